@@ -0,0 +1,408 @@
+use crate::auth::backend::Authenticator;
+use crate::config::auth_backend_config::LdapConfig;
+use crate::constants::LDAP_TIMEOUT_SECONDS;
+use crate::database::mongodb::UserRepository;
+use crate::errors::AppError;
+use crate::models::user::{fit_username, Mfa, User};
+use async_trait::async_trait;
+use ldap3::{ldap_escape, LdapConnAsync, LdapConnSettings, LdapError, Scope, SearchEntry};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use std::time::Duration;
+
+/// Binds to an LDAP directory (OpenLDAP, Active Directory) as the user.
+/// Accounts are provisioned on first login and their mapped roles follow the
+/// user's directory groups on every login.
+pub struct LdapAuthenticator {
+    cfg: LdapConfig,
+    users: UserRepository,
+}
+
+impl LdapAuthenticator {
+    pub fn new(cfg: &LdapConfig, users: &UserRepository) -> Self {
+        Self {
+            cfg: cfg.clone(),
+            users: users.clone(),
+        }
+    }
+
+    /// Finds the entry for `email` and checks `password` by binding as it.
+    async fn bind_user(
+        &self,
+        email: &str,
+        password: &str,
+    ) -> Result<Option<SearchEntry>, LdapError> {
+        let settings = LdapConnSettings::new()
+            .set_conn_timeout(Duration::from_secs(LDAP_TIMEOUT_SECONDS))
+            .set_starttls(self.cfg.starttls);
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &self.cfg.url).await?;
+        ldap3::drive!(conn);
+
+        if let Some(ref bind_dn) = self.cfg.bind_dn {
+            ldap.simple_bind(
+                bind_dn,
+                self.cfg.bind_password.as_deref().unwrap_or_default(),
+            )
+            .await?
+            .success()?;
+        }
+
+        let filter = self.cfg.user_filter.replace("{email}", &ldap_escape(email));
+        let attributes = vec![
+            self.cfg.email_attribute.as_str(),
+            self.cfg.username_attribute.as_str(),
+            self.cfg.group_attribute.as_str(),
+        ];
+        let (entries, _) = ldap
+            .search(&self.cfg.user_base_dn, Scope::Subtree, &filter, attributes)
+            .await?
+            .success()?;
+
+        // An ambiguous filter is refused rather than guessed at
+        let entry = match <[_; 1]>::try_from(entries) {
+            Ok([entry]) => SearchEntry::construct(entry),
+            Err(_) => {
+                ldap.unbind().await?;
+                return Ok(None);
+            }
+        };
+
+        let bound = ldap
+            .simple_bind(&entry.dn, password)
+            .await?
+            .success()
+            .is_ok();
+        ldap.unbind().await?;
+        Ok(bound.then_some(entry))
+    }
+}
+
+#[async_trait]
+impl Authenticator for LdapAuthenticator {
+    async fn authenticate(&self, email: &str, password: &str) -> Result<Option<User>, AppError> {
+        // An empty password makes the bind unauthenticated, which succeeds
+        if password.is_empty() {
+            return Ok(None);
+        }
+
+        let entry = match self.bind_user(email, password).await {
+            Ok(Some(entry)) => entry,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                tracing::error!("LDAP login for {} failed: {}", email, e);
+                return Err(AppError::Internal);
+            }
+        };
+
+        let attribute = |name: &str| entry.attrs.get(name).and_then(|values| values.first());
+        let email = attribute(&self.cfg.email_attribute)
+            .cloned()
+            .unwrap_or_else(|| email.to_string());
+        let groups = entry
+            .attrs
+            .get(&self.cfg.group_attribute)
+            .cloned()
+            .unwrap_or_default();
+        let mut granted: Vec<String> = Vec::new();
+        for mapping in &self.cfg.group_roles {
+            if groups
+                .iter()
+                .any(|g| g.eq_ignore_ascii_case(&mapping.group_dn))
+                && !granted.contains(&mapping.role)
+            {
+                granted.push(mapping.role.clone());
+            }
+        }
+
+        let existing = match self.users.find_by_ldap_dn(&entry.dn).await? {
+            Some(user) => Some(user),
+            None => self.users.find_by_email(&email).await?,
+        };
+
+        match existing {
+            Some(mut user) => {
+                // Local accounts are never taken over by a directory entry
+                // that happens to share their email
+                if user.ldap_dn.as_deref() != Some(entry.dn.as_str()) {
+                    tracing::warn!(
+                        "LDAP entry {} matches local account {}, refusing login",
+                        entry.dn,
+                        user.id
+                    );
+                    return Ok(None);
+                }
+
+                // Mapped roles follow the directory, roles assigned here stay
+                let mut roles: Vec<String> = user
+                    .roles
+                    .iter()
+                    .filter(|r| !self.cfg.group_roles.iter().any(|m| &m.role == *r))
+                    .cloned()
+                    .collect();
+                roles.extend(granted);
+                if roles != user.roles {
+                    self.users.set_roles(&user.id, &roles).await?;
+                    user.roles = roles;
+                }
+                Ok(Some(user))
+            }
+            None => {
+                let username = attribute(&self.cfg.username_attribute)
+                    .map(String::as_str)
+                    .unwrap_or_else(|| email.split('@').next().unwrap_or_default());
+                let user = User {
+                    id: ObjectId::new(),
+                    username: fit_username(username),
+                    email,
+                    password_hash: String::new(),
+                    password_history: Vec::new(),
+                    password_changed_at: None,
+                    roles: granted,
+                    token_version: 0,
+                    disabled: false,
+                    mfa: Mfa::default(),
+                    email_verified: true,
+                    email_verified_at: Some(DateTime::now()),
+                    ldap_dn: Some(entry.dn),
+                    external_id: None,
+                };
+                self.users.create(&user).await?;
+                tracing::info!("Provisioned user {} from LDAP", user.id);
+                Ok(Some(user))
+            }
+        }
+    }
+}
+
+/// Runs against the directory and database in `tests/docker-compose.yml`:
+/// `docker compose -f tests/docker-compose.yml up -d`, then
+/// `cargo test ldap -- --ignored`.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::auth_backend_config::GroupRole;
+    use crate::database::mongodb::init_mongodb;
+    use ldap3::{LdapConnAsync, Mod};
+    use mongodb::Database;
+    use std::collections::HashSet;
+
+    const LDAP_URL: &str = "ldap://localhost:3389";
+    const MONGO_URI: &str = "mongodb://localhost:27018";
+    const ADMIN_DN: &str = "cn=admin,dc=example,dc=org";
+    const ADMINS: &str = "cn=admins,ou=groups,dc=example,dc=org";
+    const AUDITORS: &str = "cn=auditors,ou=groups,dc=example,dc=org";
+    const BOB: &str = "uid=bob,ou=people,dc=example,dc=org";
+
+    /// An authenticator over a database of its own, dropped by `finish`.
+    struct Fixture {
+        db: Database,
+        users: UserRepository,
+        ldap: LdapAuthenticator,
+    }
+
+    impl Fixture {
+        async fn new() -> Self {
+            let db = init_mongodb(MONGO_URI, &format!("ldap_test_{}", ObjectId::new()))
+                .await
+                .unwrap();
+            let users = UserRepository::new(&db);
+            let cfg = LdapConfig {
+                url: LDAP_URL.into(),
+                starttls: false,
+                bind_dn: Some(ADMIN_DN.into()),
+                bind_password: Some("admin".into()),
+                user_base_dn: "ou=people,dc=example,dc=org".into(),
+                user_filter: "(mail={email})".into(),
+                email_attribute: "mail".into(),
+                username_attribute: "uid".into(),
+                group_attribute: "memberOf".into(),
+                group_roles: vec![
+                    GroupRole {
+                        group_dn: ADMINS.into(),
+                        role: "admin".into(),
+                    },
+                    GroupRole {
+                        group_dn: AUDITORS.into(),
+                        role: "auditor".into(),
+                    },
+                ],
+            };
+            let ldap = LdapAuthenticator::new(&cfg, &users);
+            Self { db, users, ldap }
+        }
+
+        async fn finish(self) {
+            self.db.drop().await.unwrap();
+        }
+    }
+
+    /// Adds or removes bob from a group as the directory admin.
+    async fn set_bob_membership(group_dn: &str, member: bool) {
+        let (conn, mut ldap) = LdapConnAsync::new(LDAP_URL).await.unwrap();
+        ldap3::drive!(conn);
+        ldap.simple_bind(ADMIN_DN, "admin")
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        let values = HashSet::from([BOB]);
+        let change = if member {
+            Mod::Add("uniqueMember", values)
+        } else {
+            Mod::Delete("uniqueMember", values)
+        };
+        // Already in the requested state when a previous run was interrupted
+        let _ = ldap.modify(group_dn, vec![change]).await.unwrap().success();
+        ldap.unbind().await.unwrap();
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs the services in tests/docker-compose.yml"]
+    async fn ldap_bind_provisions_the_account() {
+        let fixture = Fixture::new().await;
+
+        let user = fixture
+            .ldap
+            .authenticate("alice@example.org", "alice-password")
+            .await
+            .unwrap()
+            .expect("alice signs in");
+        assert_eq!(user.username, "alice");
+        assert_eq!(
+            user.ldap_dn.as_deref(),
+            Some("uid=alice,ou=people,dc=example,dc=org")
+        );
+        assert!(user.email_verified);
+        assert!(user.password_hash.is_empty());
+
+        // The second login finds the same account
+        let again = fixture
+            .ldap
+            .authenticate("alice@example.org", "alice-password")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(again.id, user.id);
+
+        fixture.finish().await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs the services in tests/docker-compose.yml"]
+    async fn ldap_wrong_or_empty_password_is_refused() {
+        let fixture = Fixture::new().await;
+
+        for password in ["wrong-password", ""] {
+            let result = fixture
+                .ldap
+                .authenticate("alice@example.org", password)
+                .await
+                .unwrap();
+            assert!(result.is_none(), "{:?} accepted", password);
+        }
+        let result = fixture
+            .ldap
+            .authenticate("nobody@example.org", "alice-password")
+            .await
+            .unwrap();
+        assert!(result.is_none());
+        assert!(fixture
+            .users
+            .find_by_email("alice@example.org")
+            .await
+            .unwrap()
+            .is_none());
+
+        fixture.finish().await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs the services in tests/docker-compose.yml"]
+    async fn ldap_ambiguous_filter_is_refused() {
+        let fixture = Fixture::new().await;
+
+        let result = fixture
+            .ldap
+            .authenticate("shared@example.org", "carol-password")
+            .await
+            .unwrap();
+        assert!(result.is_none());
+
+        fixture.finish().await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs the services in tests/docker-compose.yml"]
+    async fn ldap_does_not_take_over_local_accounts() {
+        let fixture = Fixture::new().await;
+        let local = User {
+            id: ObjectId::new(),
+            email: "dave@example.org".into(),
+            username: "dave".into(),
+            password_hash: "$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA".into(),
+            password_history: Vec::new(),
+            password_changed_at: None,
+            roles: Vec::new(),
+            token_version: 0,
+            disabled: false,
+            mfa: Mfa::default(),
+            email_verified: true,
+            email_verified_at: None,
+            ldap_dn: None,
+            external_id: None,
+        };
+        fixture.users.create(&local).await.unwrap();
+
+        let result = fixture
+            .ldap
+            .authenticate("dave@example.org", "dave-password")
+            .await
+            .unwrap();
+        assert!(result.is_none());
+        let stored = fixture.users.find_by_id(&local.id).await.unwrap().unwrap();
+        assert_eq!(stored.ldap_dn, None);
+        assert_eq!(stored.password_hash, local.password_hash);
+
+        fixture.finish().await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs the services in tests/docker-compose.yml"]
+    async fn ldap_groups_sync_mapped_roles() {
+        let fixture = Fixture::new().await;
+        set_bob_membership(AUDITORS, false).await;
+        let login = || async {
+            fixture
+                .ldap
+                .authenticate("bob@example.org", "bob-password")
+                .await
+                .unwrap()
+                .unwrap()
+        };
+
+        let alice = fixture
+            .ldap
+            .authenticate("alice@example.org", "alice-password")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(alice.roles, ["admin", "auditor"]);
+
+        let bob = login().await;
+        assert!(bob.roles.is_empty());
+
+        // Roles assigned here survive; mapped roles follow the directory
+        fixture
+            .users
+            .set_roles(&bob.id, &["support".to_string()])
+            .await
+            .unwrap();
+        set_bob_membership(AUDITORS, true).await;
+        assert_eq!(login().await.roles, ["support", "auditor"]);
+
+        set_bob_membership(AUDITORS, false).await;
+        assert_eq!(login().await.roles, ["support"]);
+
+        fixture.finish().await;
+    }
+}
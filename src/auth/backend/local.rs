@@ -0,0 +1,57 @@
+use crate::auth::backend::Authenticator;
+use crate::config::password_config::PasswordHashing;
+use crate::database::mongodb::UserRepository;
+use crate::errors::AppError;
+use crate::models::user::User;
+use crate::utils::password::{hash_password, needs_rehash, verify_password};
+use async_trait::async_trait;
+use tracing::warn;
+
+/// Accounts with a password hash stored in MongoDB.
+pub struct LocalAuthenticator {
+    users: UserRepository,
+    hashing: PasswordHashing,
+}
+
+impl LocalAuthenticator {
+    pub fn new(users: &UserRepository, hashing: &PasswordHashing) -> Self {
+        Self {
+            users: users.clone(),
+            hashing: hashing.clone(),
+        }
+    }
+
+    async fn rehash(&self, user: &mut User, password: &str) -> Result<(), AppError> {
+        let hash = hash_password(&self.hashing, password).await?;
+        self.users
+            .rehash_password(&user.id, &user.password_hash, &hash)
+            .await?;
+        user.password_hash = hash;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Authenticator for LocalAuthenticator {
+    async fn authenticate(&self, email: &str, password: &str) -> Result<Option<User>, AppError> {
+        let Some(mut user) = self.users.find_by_email(email).await? else {
+            return Ok(None);
+        };
+        if verify_password(&user.password_hash, password)
+            .await
+            .is_err()
+        {
+            return Ok(None);
+        }
+
+        // The plain password is only at hand now, so hashes from an older
+        // policy are upgraded here. Failing to do so does not fail the login.
+        if needs_rehash(&self.hashing, &user.password_hash) {
+            if let Err(e) = self.rehash(&mut user, password).await {
+                warn!("Failed to rehash password of user {}: {:?}", user.id, e);
+            }
+        }
+
+        Ok(Some(user))
+    }
+}
@@ -0,0 +1,67 @@
+pub mod ldap;
+pub mod local;
+
+use crate::config::app_config::AppConfig;
+use crate::config::auth_backend_config::AuthBackend;
+use crate::database::mongodb::UserRepository;
+use crate::errors::AppError;
+use crate::models::user::User;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// Checks login credentials against one source of accounts.
+#[async_trait]
+pub trait Authenticator: Send + Sync {
+    /// The user the credentials belong to, or `None` when they don't match.
+    async fn authenticate(&self, email: &str, password: &str) -> Result<Option<User>, AppError>;
+}
+
+/// The configured backends, tried in order until one accepts the credentials.
+#[derive(Clone)]
+pub struct Authenticators {
+    backends: Arc<Vec<Box<dyn Authenticator>>>,
+}
+
+impl Authenticators {
+    pub fn new(cfg: &AppConfig, users: &UserRepository) -> Self {
+        let backends = cfg
+            .auth_backends
+            .iter()
+            .map(|backend| -> Box<dyn Authenticator> {
+                match backend {
+                    AuthBackend::Local => {
+                        Box::new(local::LocalAuthenticator::new(users, &cfg.password_hashing))
+                    }
+                    AuthBackend::Ldap(ldap) => Box::new(ldap::LdapAuthenticator::new(ldap, users)),
+                }
+            })
+            .collect();
+
+        Self {
+            backends: Arc::new(backends),
+        }
+    }
+
+    /// A backend that fails (e.g. the directory is down) does not stop the
+    /// others; its error is only returned when no backend accepts, so an
+    /// outage is not counted as a failed login.
+    pub async fn authenticate(
+        &self,
+        email: &str,
+        password: &str,
+    ) -> Result<Option<User>, AppError> {
+        let mut failure = None;
+        for backend in self.backends.iter() {
+            match backend.authenticate(email, password).await {
+                Ok(Some(user)) => return Ok(Some(user)),
+                Ok(None) => {}
+                Err(e) => failure = Some(e),
+            }
+        }
+
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(None),
+        }
+    }
+}
@@ -1,5 +1,20 @@
-mod admin;
+mod backend;
+mod organization;
+mod password_change;
+mod permission;
+mod scim;
+mod service;
 mod user;
 
-pub use admin::AdminUser;
-pub use user::AuthenticatedUser;
+pub use backend::Authenticators;
+pub use organization::OrgMember;
+pub use password_change::{PasswordChanger, PendingPasswordChange};
+pub use permission::{
+    ClientsManage, KeysManage, Principal, RequirePermission, RolesManage, UsersDelete, UsersRead,
+    UsersWrite,
+};
+pub use scim::ScimClient;
+pub use service::{verify_service_token, ServicePrincipal};
+pub use user::{
+    api_key, bearer_token, verify_user_token, AuthenticatedUser, OAuthUser, UserOrApiKey,
+};
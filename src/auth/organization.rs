@@ -0,0 +1,73 @@
+use crate::auth::AuthenticatedUser;
+use crate::constants::{
+    AUTH_REQUIRED, NOT_ORG_MEMBER, NO_ACTIVE_ORGANIZATION, ORG_PERMISSION_DENIED,
+};
+use crate::database::mongodb::MembershipRepository;
+use crate::errors::AppError;
+use crate::models::organization::OrgRole;
+use actix_web::dev::Payload;
+use actix_web::web::Data;
+use actix_web::{Error as ActixError, FromRequest, HttpRequest};
+use mongodb::bson::oid::ObjectId;
+use std::future::Future;
+use std::pin::Pin;
+
+/// An authenticated member of the organization selected by the token's `org`
+/// claim. Membership is checked on every request, so removed members lose
+/// access even while their token still names the organization.
+#[derive(Clone)]
+pub struct OrgMember {
+    pub user_id: ObjectId,
+    pub org_id: ObjectId,
+    pub role: OrgRole,
+}
+
+impl OrgMember {
+    pub fn ensure_manager(&self) -> Result<(), AppError> {
+        if !self.role.can_manage_members() {
+            return Err(AppError::Forbidden(ORG_PERMISSION_DENIED.into()));
+        }
+        Ok(())
+    }
+
+    pub fn ensure_owner(&self) -> Result<(), AppError> {
+        if self.role != OrgRole::Owner {
+            return Err(AppError::Forbidden(ORG_PERMISSION_DENIED.into()));
+        }
+        Ok(())
+    }
+}
+
+impl FromRequest for OrgMember {
+    type Error = ActixError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, ActixError>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let user = AuthenticatedUser::from_request(req, payload);
+        let memberships = req.app_data::<Data<MembershipRepository>>().cloned();
+
+        Box::pin(async move {
+            let user = user.await?;
+            let memberships = memberships.ok_or(AppError::Internal)?;
+
+            let user_id = ObjectId::parse_str(&user.user_id)
+                .map_err(|_| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+            let org_id = user
+                .org_id
+                .as_deref()
+                .and_then(|org| ObjectId::parse_str(org).ok())
+                .ok_or_else(|| AppError::Forbidden(NO_ACTIVE_ORGANIZATION.into()))?;
+
+            let membership = memberships
+                .find(&org_id, &user_id)
+                .await?
+                .ok_or_else(|| AppError::Forbidden(NOT_ORG_MEMBER.into()))?;
+
+            Ok(OrgMember {
+                user_id,
+                org_id,
+                role: membership.role,
+            })
+        })
+    }
+}
@@ -0,0 +1,117 @@
+use crate::auth::{bearer_token, AuthenticatedUser};
+use crate::constants::{INVALID_PASSWORD_CHANGE_TOKEN, PASSWORD_CHANGE_TTL_SECONDS};
+use crate::database::redis::OneTimeTokenStore;
+use crate::errors::AppError;
+use crate::models::response::PasswordChangeChallenge;
+use crate::utils::token::{generate_opaque_token, hash_token};
+use actix_web::dev::Payload;
+use actix_web::web::Data;
+use actix_web::{Error as ActixError, FromRequest, HttpRequest};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+
+const PASSWORD_CHANGE_PURPOSE: &str = "password_change";
+
+/// A login held back because the account's password expired. Instead of a
+/// session, the user gets a password change token that only works for
+/// `PUT /user/password`.
+#[derive(Serialize, Deserialize)]
+pub struct PendingPasswordChange {
+    pub user_id: String,
+    pub device: Option<String>,
+}
+
+impl PendingPasswordChange {
+    pub async fn issue(
+        &self,
+        one_time_tokens: &OneTimeTokenStore,
+    ) -> Result<PasswordChangeChallenge, AppError> {
+        let token = generate_opaque_token();
+        one_time_tokens
+            .issue_for_user(
+                PASSWORD_CHANGE_PURPOSE,
+                &hash_token(&token),
+                &serde_json::to_string(self).map_err(|_| AppError::Internal)?,
+                PASSWORD_CHANGE_TTL_SECONDS,
+                &self.user_id,
+            )
+            .await?;
+
+        Ok(PasswordChangeChallenge {
+            password_change_token: token,
+            expires_in: PASSWORD_CHANGE_TTL_SECONDS,
+        })
+    }
+}
+
+/// Someone allowed to change a password: a signed-in user, or a user whose
+/// login was held back by an expired password, presenting the password
+/// change token as a bearer token.
+pub enum PasswordChanger {
+    User(AuthenticatedUser),
+    Expired {
+        pending: PendingPasswordChange,
+        token_hash: String,
+    },
+}
+
+impl PasswordChanger {
+    pub fn user_id(&self) -> &str {
+        match self {
+            PasswordChanger::User(user) => &user.user_id,
+            PasswordChanger::Expired { pending, .. } => &pending.user_id,
+        }
+    }
+
+    /// Uses up the password change token, if any, so it changes the password
+    /// only once.
+    pub async fn consume(&self, one_time_tokens: &OneTimeTokenStore) -> Result<(), AppError> {
+        if let PasswordChanger::Expired { token_hash, .. } = self {
+            if one_time_tokens
+                .take(PASSWORD_CHANGE_PURPOSE, token_hash)
+                .await?
+                .is_none()
+            {
+                return Err(AppError::Unauthorized(INVALID_PASSWORD_CHANGE_TOKEN.into()));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromRequest for PasswordChanger {
+    type Error = ActixError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, ActixError>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let authenticated = AuthenticatedUser::from_request(req, payload);
+        let one_time_tokens = req.app_data::<Data<OneTimeTokenStore>>().cloned();
+        let token = bearer_token(req);
+
+        Box::pin(async move {
+            let error = match authenticated.await {
+                Ok(user) => return Ok(PasswordChanger::User(user)),
+                Err(error) => error,
+            };
+
+            // Not an access token, but it may be a password change token
+            let (Some(token), Some(one_time_tokens)) = (token, one_time_tokens) else {
+                return Err(error);
+            };
+            let token_hash = hash_token(&token);
+            let pending = one_time_tokens
+                .peek(PASSWORD_CHANGE_PURPOSE, &token_hash)
+                .await?
+                .and_then(|v| serde_json::from_str(&v).ok());
+
+            match pending {
+                Some(pending) => Ok(PasswordChanger::Expired {
+                    pending,
+                    token_hash,
+                }),
+                None => Err(error),
+            }
+        })
+    }
+}
@@ -0,0 +1,141 @@
+use crate::auth::user::bearer_token;
+use crate::auth::{ServicePrincipal, UserOrApiKey};
+use crate::config::app_config::AppConfig;
+use crate::constants::*;
+use crate::database::mongodb::{RoleRepository, UserRepository};
+use crate::errors::AppError;
+use crate::utils::token::{decode_token, PrincipalKind};
+use actix_web::dev::Payload;
+use actix_web::web::Data;
+use actix_web::{Error as ActixError, FromRequest, HttpRequest};
+use mongodb::bson::oid::ObjectId;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+
+/// A permission that can be demanded through [`RequirePermission`].
+pub trait Permission {
+    const NAME: &'static str;
+}
+
+pub struct UsersRead;
+pub struct UsersWrite;
+pub struct UsersDelete;
+pub struct RolesManage;
+pub struct KeysManage;
+pub struct ClientsManage;
+
+impl Permission for UsersRead {
+    const NAME: &'static str = PERMISSION_USERS_READ;
+}
+
+impl Permission for UsersWrite {
+    const NAME: &'static str = PERMISSION_USERS_WRITE;
+}
+
+impl Permission for UsersDelete {
+    const NAME: &'static str = PERMISSION_USERS_DELETE;
+}
+
+impl Permission for RolesManage {
+    const NAME: &'static str = PERMISSION_ROLES_MANAGE;
+}
+
+impl Permission for KeysManage {
+    const NAME: &'static str = PERMISSION_KEYS_MANAGE;
+}
+
+impl Permission for ClientsManage {
+    const NAME: &'static str = PERMISSION_CLIENTS_MANAGE;
+}
+
+/// Who is exercising a permission.
+pub enum Principal {
+    User(String), // user id
+    Service(ServicePrincipal),
+}
+
+impl Principal {
+    pub fn user_id(&self) -> Option<&str> {
+        match self {
+            Principal::User(id) => Some(id),
+            Principal::Service(_) => None,
+        }
+    }
+}
+
+/// A principal allowed to use `P`: a user whose roles grant it (through an API
+/// key only if the key is scoped to it), or a service account holding it as a
+/// scope. Roles are read per request, so revocations apply at once; tokens
+/// issued to OAuth clients are always refused.
+pub struct RequirePermission<P: Permission> {
+    pub principal: Principal,
+    _permission: PhantomData<P>,
+}
+
+impl<P: Permission> RequirePermission<P> {
+    fn new(principal: Principal) -> Self {
+        Self {
+            principal,
+            _permission: PhantomData,
+        }
+    }
+}
+
+impl<P: Permission> FromRequest for RequirePermission<P> {
+    type Error = ActixError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, ActixError>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        // Peek at the token to pick the extractor; either one verifies it fully
+        let kind = req
+            .app_data::<Data<AppConfig>>()
+            .zip(bearer_token(req))
+            .and_then(|(cfg, token)| decode_token(cfg, &token).ok())
+            .map(|claims| claims.kind)
+            .unwrap_or_default();
+
+        if kind == PrincipalKind::Service {
+            let service = ServicePrincipal::from_request(req, payload);
+            return Box::pin(async move {
+                let service = service.await?;
+                if !service.has_scope(P::NAME) {
+                    return Err(AppError::Forbidden(PERMISSION_DENIED.into()).into());
+                }
+                Ok(Self::new(Principal::Service(service)))
+            });
+        }
+
+        let user = UserOrApiKey::from_request(req, payload);
+        let repo = req.app_data::<Data<UserRepository>>().cloned();
+        let role_repo = req.app_data::<Data<RoleRepository>>().cloned();
+
+        Box::pin(async move {
+            let user = user.await?;
+            let repo = repo.ok_or(AppError::Internal)?;
+            let role_repo = role_repo.ok_or(AppError::Internal)?;
+
+            let object_id = ObjectId::parse_str(user.user_id())
+                .map_err(|_| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+            let account = repo
+                .find_by_id(&object_id)
+                .await?
+                .ok_or_else(|| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+
+            if !role_repo.grants(&account.roles, P::NAME).await? {
+                return Err(AppError::Forbidden(PERMISSION_DENIED.into()).into());
+            }
+
+            // An API key only carries the permissions it was scoped to
+            let user_id = match user {
+                UserOrApiKey::ApiKey(key) if !key.scopes.iter().any(|s| s == P::NAME) => {
+                    return Err(AppError::Forbidden(PERMISSION_DENIED.into()).into());
+                }
+                UserOrApiKey::ApiKey(key) => key.user_id,
+                UserOrApiKey::User(user) => user.user_id,
+            };
+
+            Ok(Self::new(Principal::User(user_id)))
+        })
+    }
+}
@@ -0,0 +1,37 @@
+use crate::auth::bearer_token;
+use crate::config::app_config::AppConfig;
+use crate::constants::{AUTH_REQUIRED, SCIM_NOT_CONFIGURED};
+use crate::errors::{AppError, ScimError};
+use crate::utils::token::{hash_token, secrets_match};
+use actix_web::dev::Payload;
+use actix_web::http::StatusCode;
+use actix_web::web::Data;
+use actix_web::{Error as ActixError, FromRequest, HttpRequest};
+use std::future::{ready, Ready};
+
+/// A provisioning client presenting the `SCIM_TOKEN` bearer token.
+pub struct ScimClient;
+
+fn check(req: &HttpRequest) -> Result<ScimClient, ScimError> {
+    let cfg = req
+        .app_data::<Data<AppConfig>>()
+        .ok_or(ScimError::from(AppError::Internal))?;
+    let expected = cfg
+        .scim_token_hash
+        .as_deref()
+        .ok_or_else(|| ScimError::new(StatusCode::UNAUTHORIZED, SCIM_NOT_CONFIGURED))?;
+
+    match bearer_token(req) {
+        Some(token) if secrets_match(&hash_token(&token), expected) => Ok(ScimClient),
+        _ => Err(ScimError::new(StatusCode::UNAUTHORIZED, AUTH_REQUIRED)),
+    }
+}
+
+impl FromRequest for ScimClient {
+    type Error = ActixError;
+    type Future = Ready<Result<Self, ActixError>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(check(req).map_err(Into::into))
+    }
+}
@@ -0,0 +1,102 @@
+use crate::auth::user::bearer_token;
+use crate::config::app_config::AppConfig;
+use crate::constants::{AUTH_REQUIRED, SERVICE_PRINCIPAL_REQUIRED, TOKEN_BLACKLISTED};
+use crate::database::mongodb::ServiceAccountRepository;
+use crate::database::redis::TokenBlacklist;
+use crate::errors::AppError;
+use crate::utils::token::{decode_token, Claims, PrincipalKind};
+use actix_web::dev::Payload;
+use actix_web::web::Data;
+use actix_web::{Error as ActixError, FromRequest, HttpRequest};
+use std::future::Future;
+use std::pin::Pin;
+
+/// A service account authenticated by a client credentials token. Disabling
+/// the account or rotating its secret invalidates the token immediately.
+#[derive(Clone)]
+pub struct ServicePrincipal {
+    pub client_id: String,
+    pub scopes: Vec<String>,
+}
+
+impl ServicePrincipal {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+}
+
+/// Checks a client credentials token: its signature and expiry, the
+/// blacklist, and that the account still exists, is enabled and has not had
+/// its secret rotated or scopes changed since.
+pub async fn verify_service_token(
+    cfg: &AppConfig,
+    blacklist: Option<&TokenBlacklist>,
+    accounts: &ServiceAccountRepository,
+    token: &str,
+) -> Result<Claims, AppError> {
+    let claims = decode_token(cfg, token)?;
+
+    if claims.kind != PrincipalKind::Service {
+        return Err(AppError::Forbidden(SERVICE_PRINCIPAL_REQUIRED.into()));
+    }
+
+    if let Some(bl) = blacklist {
+        if bl.is_blacklisted(token).await? {
+            return Err(AppError::Unauthorized(TOKEN_BLACKLISTED.into()));
+        }
+    }
+
+    let client_id = claims
+        .client_id
+        .as_deref()
+        .ok_or_else(|| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+    let account = accounts
+        .find_by_client_id(client_id)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+
+    if account.id.to_hex() != claims.sub || account.disabled || account.token_version != claims.ver
+    {
+        return Err(AppError::Unauthorized(AUTH_REQUIRED.into()));
+    }
+
+    Ok(claims)
+}
+
+impl FromRequest for ServicePrincipal {
+    type Error = ActixError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, ActixError>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let cfg = req.app_data::<Data<AppConfig>>().cloned();
+        let blacklist = req.app_data::<Data<TokenBlacklist>>().cloned();
+        let accounts = req.app_data::<Data<ServiceAccountRepository>>().cloned();
+        let token = bearer_token(req);
+
+        Box::pin(async move {
+            let cfg = cfg.ok_or(AppError::Internal)?;
+            let accounts = accounts.ok_or(AppError::Internal)?;
+            let token = token.ok_or(AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+            let claims = verify_service_token(
+                &cfg,
+                blacklist.as_ref().map(|b| b.get_ref()),
+                &accounts,
+                &token,
+            )
+            .await?;
+            let client_id = claims
+                .client_id
+                .ok_or_else(|| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+
+            Ok(ServicePrincipal {
+                client_id,
+                scopes: claims
+                    .scope
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .map(String::from)
+                    .collect(),
+            })
+        })
+    }
+}
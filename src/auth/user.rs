@@ -1,67 +1,276 @@
 use crate::config::app_config::AppConfig;
-use crate::constants::{AUTH_REQUIRED, TOKEN_BLACKLISTED};
-use crate::database::mongodb::UserRepository;
+use crate::constants::{
+    API_KEY_HEADER, AUTH_REQUIRED, FIRST_PARTY_SESSION_REQUIRED, INVALID_API_KEY, SESSION_REQUIRED,
+    TOKEN_BLACKLISTED, USER_PRINCIPAL_REQUIRED,
+};
+use crate::database::mongodb::{ApiKeyRepository, SessionRepository, UserRepository};
 use crate::database::redis::TokenBlacklist;
 use crate::errors::AppError;
-use crate::utils::token::decode_token;
+use crate::models::api_key::ApiKey;
+use crate::utils::token::{decode_token, hash_token, Claims, PrincipalKind};
 use actix_web::dev::Payload;
 use actix_web::web::Data;
 use actix_web::{Error as ActixError, FromRequest, HttpRequest};
 use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
 use std::future::Future;
 use std::pin::Pin;
 
+pub fn bearer_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .filter(|h| h.starts_with("Bearer "))
+        .map(|h| h.trim_start_matches("Bearer ").trim().to_string())
+}
+
+/// Checks a user access token the way every protected endpoint does: its
+/// signature and expiry, the blacklist, the user's token version and that its
+/// session is still open. The session is marked as seen.
+pub async fn verify_user_token(
+    cfg: &AppConfig,
+    blacklist: Option<&TokenBlacklist>,
+    users: &UserRepository,
+    sessions: &SessionRepository,
+    token: &str,
+) -> Result<Claims, AppError> {
+    let claims = decode_token(cfg, token)?;
+
+    if claims.kind != PrincipalKind::User {
+        return Err(AppError::Forbidden(USER_PRINCIPAL_REQUIRED.into()));
+    }
+
+    if let Some(bl) = blacklist {
+        if bl.is_blacklisted(token).await? {
+            return Err(AppError::Unauthorized(TOKEN_BLACKLISTED.into()));
+        }
+    }
+
+    let object_id = ObjectId::parse_str(&claims.sub)
+        .map_err(|_| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+
+    let user = users
+        .find_by_id(&object_id)
+        .await
+        .map_err(|_| AppError::Unauthorized(AUTH_REQUIRED.into()))?
+        .ok_or_else(|| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+
+    if user.disabled || user.token_version != claims.ver {
+        return Err(AppError::Unauthorized(AUTH_REQUIRED.into()));
+    }
+
+    let session_oid = claims
+        .sid
+        .as_deref()
+        .and_then(|sid| ObjectId::parse_str(sid).ok())
+        .ok_or_else(|| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+
+    if !sessions.touch(&session_oid, &object_id).await? {
+        return Err(AppError::Unauthorized(AUTH_REQUIRED.into()));
+    }
+
+    Ok(claims)
+}
+
+/// The raw key from an `X-API-Key` or `Authorization: ApiKey` header.
+pub fn api_key(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(API_KEY_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .or_else(|| {
+            req.headers()
+                .get("Authorization")
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| h.strip_prefix("ApiKey "))
+        })
+        .map(|key| key.trim().to_string())
+}
+
+/// Checks an API key: it must exist, be unexpired and belong to an existing,
+/// enabled user. Its use is recorded.
+async fn verify_api_key(
+    keys: &ApiKeyRepository,
+    users: &UserRepository,
+    key: &str,
+) -> Result<ApiKey, AppError> {
+    let invalid = || AppError::Unauthorized(INVALID_API_KEY.into());
+    let api_key = keys
+        .find_by_hash(&hash_token(key))
+        .await?
+        .ok_or_else(invalid)?;
+
+    if api_key.expires_at.is_some_and(|t| t <= DateTime::now()) {
+        return Err(invalid());
+    }
+    users
+        .find_by_id(&api_key.user_id)
+        .await?
+        .filter(|user| !user.disabled)
+        .ok_or_else(invalid)?;
+
+    keys.record_use(&api_key.id).await?;
+    Ok(api_key)
+}
+
+/// Verifies the request's bearer token as a user access token, returning its
+/// claims along with the token.
+fn user_token_claims(
+    req: &HttpRequest,
+) -> impl Future<Output = Result<(Claims, String), AppError>> + 'static {
+    let cfg = req.app_data::<Data<AppConfig>>().cloned();
+    let blacklist = req.app_data::<Data<TokenBlacklist>>().cloned();
+    let repo = req.app_data::<Data<UserRepository>>().cloned();
+    let sessions = req.app_data::<Data<SessionRepository>>().cloned();
+    let token = bearer_token(req);
+
+    async move {
+        let cfg = cfg.ok_or(AppError::Internal)?;
+        let repo = repo.ok_or(AppError::Internal)?;
+        let sessions = sessions.ok_or(AppError::Internal)?;
+        let token = token.ok_or(AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+
+        let claims = verify_user_token(
+            &cfg,
+            blacklist.as_ref().map(|b| b.get_ref()),
+            &repo,
+            &sessions,
+            &token,
+        )
+        .await?;
+        Ok((claims, token))
+    }
+}
+
+/// The session an access token is bound to.
 #[derive(Clone)]
-pub struct AuthenticatedUser {
-    pub user_id: String,
+pub struct SessionToken {
+    pub id: String,
     pub token: String,
     pub exp: usize,
 }
 
+/// A person signed in to one of their sessions. API keys, service account
+/// tokens and tokens issued to OAuth clients are rejected: routes that accept
+/// keys take [`UserOrApiKey`] or a permission, and [`OAuthUser`] serves
+/// clients.
+#[derive(Clone)]
+pub struct AuthenticatedUser {
+    pub user_id: String,
+    pub session: SessionToken,
+    pub org_id: Option<String>, // active organization from the token
+}
+
 impl FromRequest for AuthenticatedUser {
     type Error = ActixError;
     type Future = Pin<Box<dyn Future<Output = Result<Self, ActixError>>>>;
 
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
-        let cfg = req.app_data::<Data<AppConfig>>().cloned();
-        let blacklist = req.app_data::<Data<TokenBlacklist>>().cloned();
+        if api_key(req).is_some() {
+            return Box::pin(async { Err(AppError::Forbidden(SESSION_REQUIRED.into()).into()) });
+        }
+
+        let claims = user_token_claims(req);
+        Box::pin(async move {
+            let (claims, token) = claims.await?;
+            if claims.client_id.is_some() {
+                return Err(AppError::Forbidden(FIRST_PARTY_SESSION_REQUIRED.into()).into());
+            }
+            let session_id = claims
+                .sid
+                .ok_or_else(|| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+
+            Ok(AuthenticatedUser {
+                user_id: claims.sub,
+                session: SessionToken {
+                    id: session_id,
+                    token,
+                    exp: claims.exp,
+                },
+                org_id: claims.org,
+            })
+        })
+    }
+}
+
+/// A person authenticated by one of their API keys.
+pub struct ApiKeyUser {
+    pub user_id: String,
+    pub scopes: Vec<String>, // permissions the key was scoped to
+}
+
+impl FromRequest for ApiKeyUser {
+    type Error = ActixError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, ActixError>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
         let repo = req.app_data::<Data<UserRepository>>().cloned();
-        let token = req
-            .headers()
-            .get("Authorization")
-            .and_then(|h| h.to_str().ok())
-            .filter(|h| h.starts_with("Bearer "))
-            .map(|h| h.trim_start_matches("Bearer ").trim().to_string());
+        let keys = req.app_data::<Data<ApiKeyRepository>>().cloned();
+        let key = api_key(req);
 
         Box::pin(async move {
-            let cfg = cfg.ok_or(AppError::Internal)?;
             let repo = repo.ok_or(AppError::Internal)?;
-            let token = token.ok_or(AppError::Unauthorized(AUTH_REQUIRED.into()))?;
-            let claims = decode_token(&cfg, &token)?;
+            let keys = keys.ok_or(AppError::Internal)?;
+            let key = key.ok_or(AppError::Unauthorized(AUTH_REQUIRED.into()))?;
 
-            if let Some(bl) = blacklist {
-                if bl.is_blacklisted(&token).await? {
-                    return Err(AppError::Unauthorized(TOKEN_BLACKLISTED.into()).into());
-                }
-            }
+            let api_key = verify_api_key(&keys, &repo, &key).await?;
+            Ok(ApiKeyUser {
+                user_id: api_key.user_id.to_hex(),
+                scopes: api_key.scopes,
+            })
+        })
+    }
+}
 
-            let object_id = ObjectId::parse_str(&claims.sub)
-                .map_err(|_| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+/// A signed-in user or one of their API keys, for the read-only routes a key
+/// may use whatever its scopes.
+pub enum UserOrApiKey {
+    User(AuthenticatedUser),
+    ApiKey(ApiKeyUser),
+}
 
-            let user = repo
-                .find_by_id(&object_id)
-                .await
-                .map_err(|_| AppError::Unauthorized(AUTH_REQUIRED.into()))?
-                .ok_or_else(|| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+impl UserOrApiKey {
+    pub fn user_id(&self) -> &str {
+        match self {
+            UserOrApiKey::User(user) => &user.user_id,
+            UserOrApiKey::ApiKey(key) => &key.user_id,
+        }
+    }
+}
 
-            if user.token_version != claims.ver {
-                return Err(AppError::Unauthorized(AUTH_REQUIRED.into()).into());
-            }
+impl FromRequest for UserOrApiKey {
+    type Error = ActixError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, ActixError>>>>;
 
-            Ok(AuthenticatedUser {
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        if api_key(req).is_some() {
+            let key = ApiKeyUser::from_request(req, payload);
+            return Box::pin(async move { Ok(UserOrApiKey::ApiKey(key.await?)) });
+        }
+
+        let user = AuthenticatedUser::from_request(req, payload);
+        Box::pin(async move { Ok(UserOrApiKey::User(user.await?)) })
+    }
+}
+
+/// A person authenticated by an access token that may have been issued to an
+/// OAuth client. Handlers taking it must check `scope`, which is empty for
+/// first-party tokens.
+pub struct OAuthUser {
+    pub user_id: String,
+    pub scope: String,
+}
+
+impl FromRequest for OAuthUser {
+    type Error = ActixError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, ActixError>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let claims = user_token_claims(req);
+        Box::pin(async move {
+            let (claims, _) = claims.await?;
+            Ok(OAuthUser {
                 user_id: claims.sub,
-                token,
-                exp: claims.exp,
+                scope: claims.scope.unwrap_or_default(),
             })
         })
     }
@@ -1,33 +1,108 @@
+use crate::config::auth_backend_config::{auth_backends_from_env, AuthBackend};
+use crate::config::federation_config::{federated_providers_from_env, FederatedProvider};
+use crate::config::jwt_keys::{JwtKey, Keyring};
+use crate::config::mail_config::{mail_transport_from_env, MailTransport};
+use crate::config::password_config::{
+    password_hashing_from_env, password_policy_from_env, PasswordHashing, PasswordPolicy,
+};
+use crate::config::rate_limit_config::{rate_limits_from_env, RateLimits};
 use crate::constants::*;
+use crate::utils::client_addr::parse_trusted_proxies;
+use crate::utils::key_encryption::KeyEncryptionKey;
+use crate::utils::token::hash_token;
 use dotenvy::dotenv;
+use ipnet::IpNet;
+use jsonwebtoken::Algorithm;
 use std::env;
+use std::fs;
 use std::path::Path;
+use std::str::FromStr;
+use tracing::warn;
 
 #[derive(Clone)]
 pub struct AppConfig {
     pub mongo_uri: String,
     pub mongo_db: String,
     pub redis_uri: String,
-    pub jwt_secret: String,
-    pub jwt_exp_hours: i64,
+    pub jwt_keys: Keyring,
+    pub signing_key_encryption_key: Option<KeyEncryptionKey>, // key rotation is disabled when unset
+    pub access_token_exp_minutes: i64,
+    pub refresh_token_exp_days: i64,
     pub host: String,
     pub port: u16,
     pub ssl_cert_path: Option<String>,
     pub ssl_key_path: Option<String>,
+    pub trusted_proxies: Vec<IpNet>, // peers whose X-Forwarded-For is believed
+    pub mfa_issuer: String,
+    pub webauthn_rp_id: String,
+    pub webauthn_rp_name: String,
+    pub webauthn_origin: String,
+    pub webauthn_require_user_verification: bool, // PIN or biometric, not just presence
+    pub require_email_verification: bool,
+    pub public_url: String,
+    pub oidc_issuer: String, // base url of this server as seen by OAuth clients
+    pub mail_from: String,
+    pub mail_transport: MailTransport,
+    pub mail_default_locale: String,
+    pub mail_template_dir: Option<String>,
+    pub login_max_failures: i64,
+    pub login_ip_max_failures: i64,
+    pub login_lockout_minutes: i64,
+    pub rate_limits: RateLimits,
+    pub federated_providers: Vec<FederatedProvider>,
+    pub federated_redirect_url: String, // front end page that receives federated sign-in results
+    pub auth_backends: Vec<AuthBackend>,
+    pub scim_token_hash: Option<String>, // SCIM endpoints are disabled when unset
+    pub password_hashing: PasswordHashing,
+    pub password_policy: PasswordPolicy,
 }
 
 impl AppConfig {
     pub fn from_env() -> Result<Self, String> {
         dotenv().ok();
 
-        let jwt_secret = env::var(JWT_SECRET).map_err(|_| format!("{} is required", JWT_SECRET))?;
+        let jwt_algorithm =
+            env::var(JWT_ALGORITHM).unwrap_or_else(|_| DEFAULT_JWT_ALGORITHM.into());
+        let jwt_algorithm = Algorithm::from_str(&jwt_algorithm).map_err(|_| {
+            format!(
+                "{} must be one of HS256, RS256, ES256, EdDSA",
+                JWT_ALGORITHM
+            )
+        })?;
+        let jwt_key_id = env::var(JWT_KEY_ID).ok();
 
-        if jwt_secret.len() < MIN_JWT_SECRET_LENGTH {
-            return Err(format!(
-                "{} must be at least {} characters",
-                JWT_SECRET, MIN_JWT_SECRET_LENGTH,
-            ));
-        }
+        let jwt_key = match jwt_algorithm {
+            Algorithm::HS256 => {
+                let jwt_secret =
+                    env::var(JWT_SECRET).map_err(|_| format!("{} is required", JWT_SECRET))?;
+
+                if jwt_secret.len() < MIN_JWT_SECRET_LENGTH {
+                    return Err(format!(
+                        "{} must be at least {} characters",
+                        JWT_SECRET, MIN_JWT_SECRET_LENGTH,
+                    ));
+                }
+
+                JwtKey::from_secret(jwt_secret.as_bytes(), jwt_key_id)
+            }
+            Algorithm::RS256 | Algorithm::ES256 | Algorithm::EdDSA => {
+                let key_path = env::var(JWT_PRIVATE_KEY_PATH).map_err(|_| {
+                    format!(
+                        "{} is required for {:?}",
+                        JWT_PRIVATE_KEY_PATH, jwt_algorithm
+                    )
+                })?;
+                let pem = fs::read(&key_path)
+                    .map_err(|_| format!("JWT private key file not found: {}", key_path))?;
+                JwtKey::from_pem(jwt_algorithm, &pem, jwt_key_id)?
+            }
+            _ => {
+                return Err(format!(
+                    "{} must be one of HS256, RS256, ES256, EdDSA",
+                    JWT_ALGORITHM
+                ))
+            }
+        };
 
         let mongo_uri = env::var(MONGO_URI).map_err(|_| format!("{} is required", MONGO_URI))?;
 
@@ -35,13 +110,48 @@ impl AppConfig {
 
         let redis_uri = env::var(REDIS_URI).map_err(|_| format!("{} is required", REDIS_URI))?;
 
-        let jwt_exp_hours = env::var(JWT_EXP_HOURS)
-            .unwrap_or_else(|_| DEFAULT_JWT_EXP_HOURS.to_string())
+        // JWT_EXP_HOURS set the token lifetime before refresh tokens existed
+        let legacy_exp_hours = env::var(JWT_EXP_HOURS).ok();
+        let access_token_exp_minutes: i64 = match env::var(ACCESS_TOKEN_EXP_MINUTES) {
+            Ok(minutes) => {
+                if legacy_exp_hours.is_some() {
+                    warn!(
+                        "{} is deprecated and ignored because {} is set",
+                        JWT_EXP_HOURS, ACCESS_TOKEN_EXP_MINUTES
+                    );
+                }
+                minutes
+                    .parse()
+                    .map_err(|_| format!("{} must be a valid number", ACCESS_TOKEN_EXP_MINUTES))?
+            }
+            Err(_) => match legacy_exp_hours {
+                Some(hours) => {
+                    let hours: i64 = hours
+                        .parse()
+                        .map_err(|_| format!("{} must be a valid number", JWT_EXP_HOURS))?;
+                    warn!(
+                        "{} is deprecated, set {}={} instead",
+                        JWT_EXP_HOURS,
+                        ACCESS_TOKEN_EXP_MINUTES,
+                        hours * 60
+                    );
+                    hours * 60
+                }
+                None => DEFAULT_ACCESS_TOKEN_EXP_MINUTES,
+            },
+        };
+
+        if access_token_exp_minutes <= 0 {
+            return Err(format!("{} must be positive", ACCESS_TOKEN_EXP_MINUTES));
+        }
+
+        let refresh_token_exp_days = env::var(REFRESH_TOKEN_EXP_DAYS)
+            .unwrap_or_else(|_| DEFAULT_REFRESH_TOKEN_EXP_DAYS.to_string())
             .parse()
-            .map_err(|_| format!("{} must be a valid number", JWT_EXP_HOURS))?;
+            .map_err(|_| format!("{} must be a valid number", REFRESH_TOKEN_EXP_DAYS))?;
 
-        if jwt_exp_hours <= 0 {
-            return Err(format!("{} must be positive", JWT_EXP_HOURS));
+        if refresh_token_exp_days <= 0 {
+            return Err(format!("{} must be positive", REFRESH_TOKEN_EXP_DAYS));
         }
 
         let host = env::var(APP_HOST).unwrap_or_else(|_| DEFAULT_HOST.into());
@@ -67,16 +177,148 @@ impl AppConfig {
             }
         }
 
+        let trusted_proxies = parse_trusted_proxies(&env::var(TRUSTED_PROXIES).unwrap_or_default())
+            .map_err(|e| format!("{}: {}", TRUSTED_PROXIES, e))?;
+
+        let mfa_issuer = env::var(MFA_ISSUER).unwrap_or_else(|_| DEFAULT_MFA_ISSUER.into());
+
+        if mfa_issuer.contains(':') {
+            return Err(format!("{} must not contain ':'", MFA_ISSUER));
+        }
+
+        let webauthn_rp_id =
+            env::var(WEBAUTHN_RP_ID).unwrap_or_else(|_| DEFAULT_WEBAUTHN_RP_ID.into());
+        let webauthn_rp_name =
+            env::var(WEBAUTHN_RP_NAME).unwrap_or_else(|_| DEFAULT_WEBAUTHN_RP_NAME.into());
+        let webauthn_origin =
+            env::var(WEBAUTHN_ORIGIN).unwrap_or_else(|_| DEFAULT_WEBAUTHN_ORIGIN.into());
+
+        let webauthn_require_user_verification = env::var(WEBAUTHN_REQUIRE_USER_VERIFICATION)
+            .unwrap_or_else(|_| "false".into())
+            .parse()
+            .map_err(|_| {
+                format!(
+                    "{} must be true or false",
+                    WEBAUTHN_REQUIRE_USER_VERIFICATION
+                )
+            })?;
+
+        let require_email_verification = env::var(REQUIRE_EMAIL_VERIFICATION)
+            .unwrap_or_else(|_| "false".into())
+            .parse()
+            .map_err(|_| format!("{} must be true or false", REQUIRE_EMAIL_VERIFICATION))?;
+
+        let public_url = env::var(APP_PUBLIC_URL)
+            .unwrap_or_else(|_| DEFAULT_PUBLIC_URL.into())
+            .trim_end_matches('/')
+            .to_string();
+        let oidc_issuer = env::var(OIDC_ISSUER)
+            .map(|issuer| issuer.trim_end_matches('/').to_string())
+            .unwrap_or_else(|_| public_url.clone());
+        let mail_from = env::var(MAIL_FROM).unwrap_or_else(|_| DEFAULT_MAIL_FROM.into());
+        let mail_transport = mail_transport_from_env()?;
+        let mail_default_locale =
+            env::var(MAIL_DEFAULT_LOCALE).unwrap_or_else(|_| DEFAULT_MAIL_LOCALE.into());
+        let mail_template_dir = env::var(MAIL_TEMPLATE_DIR).ok();
+
+        let login_max_failures = positive_env(LOGIN_MAX_FAILURES, DEFAULT_LOGIN_MAX_FAILURES)?;
+        let login_ip_max_failures =
+            positive_env(LOGIN_IP_MAX_FAILURES, DEFAULT_LOGIN_IP_MAX_FAILURES)?;
+        let login_lockout_minutes =
+            positive_env(LOGIN_LOCKOUT_MINUTES, DEFAULT_LOGIN_LOCKOUT_MINUTES)?;
+
+        let rate_limits = rate_limits_from_env()?;
+        let federated_providers = federated_providers_from_env()?;
+        let federated_redirect_url = match env::var(FEDERATED_REDIRECT_URL) {
+            Ok(url) => url,
+            Err(_) if federated_providers.is_empty() => String::new(),
+            Err(_) => {
+                return Err(format!(
+                    "{} is required when {} is set",
+                    FEDERATED_REDIRECT_URL, FEDERATED_PROVIDERS
+                ))
+            }
+        };
+        let auth_backends = auth_backends_from_env()?;
+        let password_hashing = password_hashing_from_env()?;
+        let password_policy = password_policy_from_env()?;
+
+        let scim_token_hash = match env::var(SCIM_TOKEN) {
+            Ok(token) if token.len() < MIN_SCIM_TOKEN_LENGTH => {
+                return Err(format!(
+                    "{} must be at least {} characters",
+                    SCIM_TOKEN, MIN_SCIM_TOKEN_LENGTH
+                ))
+            }
+            Ok(token) => Some(hash_token(&token)),
+            Err(_) => None,
+        };
+
+        let signing_key_encryption_key = env::var(SIGNING_KEY_ENCRYPTION_KEY)
+            .ok()
+            .map(|value| {
+                KeyEncryptionKey::from_base64(&value)
+                    .map_err(|e| format!("{} {}", SIGNING_KEY_ENCRYPTION_KEY, e))
+            })
+            .transpose()?;
+
+        // Superseded keys stay valid until every token they signed has expired,
+        // plus the default validation leeway.
+        let jwt_keys = Keyring::new(
+            jwt_key,
+            (access_token_exp_minutes * 60)
+                .max(EMAIL_VERIFICATION_TTL_SECONDS)
+                .max(INVITATION_TTL_SECONDS)
+                + 60,
+        );
+
         Ok(Self {
             mongo_uri,
             mongo_db,
             redis_uri,
-            jwt_secret,
-            jwt_exp_hours,
+            jwt_keys,
+            signing_key_encryption_key,
+            access_token_exp_minutes,
+            refresh_token_exp_days,
             host,
             port,
             ssl_cert_path,
             ssl_key_path,
+            trusted_proxies,
+            mfa_issuer,
+            webauthn_rp_id,
+            webauthn_rp_name,
+            webauthn_origin,
+            webauthn_require_user_verification,
+            require_email_verification,
+            public_url,
+            oidc_issuer,
+            mail_from,
+            mail_transport,
+            mail_default_locale,
+            mail_template_dir,
+            login_max_failures,
+            login_ip_max_failures,
+            login_lockout_minutes,
+            rate_limits,
+            federated_providers,
+            federated_redirect_url,
+            auth_backends,
+            scim_token_hash,
+            password_hashing,
+            password_policy,
         })
     }
 }
+
+fn positive_env(name: &str, default: i64) -> Result<i64, String> {
+    let value: i64 = env::var(name)
+        .unwrap_or_else(|_| default.to_string())
+        .parse()
+        .map_err(|_| format!("{} must be a valid number", name))?;
+
+    if value <= 0 {
+        return Err(format!("{} must be positive", name));
+    }
+    Ok(value)
+}
@@ -0,0 +1,114 @@
+use crate::constants::*;
+use std::env;
+
+/// Grants `role` to directory users who are members of `group_dn`.
+#[derive(Clone, Debug)]
+pub struct GroupRole {
+    pub group_dn: String,
+    pub role: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct LdapConfig {
+    pub url: String, // ldap:// or ldaps://
+    pub starttls: bool,
+    pub bind_dn: Option<String>, // searches anonymously when unset
+    pub bind_password: Option<String>,
+    pub user_base_dn: String,
+    pub user_filter: String, // `{email}` is replaced with the escaped login email
+    pub email_attribute: String,
+    pub username_attribute: String,
+    pub group_attribute: String,
+    pub group_roles: Vec<GroupRole>,
+}
+
+#[derive(Clone, Debug)]
+pub enum AuthBackend {
+    Local,
+    Ldap(Box<LdapConfig>),
+}
+
+/// Reads `AUTH_BACKENDS`, the comma separated backends `login` tries in
+/// order, and the settings of each.
+pub fn auth_backends_from_env() -> Result<Vec<AuthBackend>, String> {
+    let names = env::var(AUTH_BACKENDS).unwrap_or_else(|_| DEFAULT_AUTH_BACKENDS.into());
+    let mut backends: Vec<AuthBackend> = Vec::new();
+    let mut seen: Vec<&str> = Vec::new();
+
+    for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        if seen.contains(&name) {
+            return Err(format!("{} lists {} twice", AUTH_BACKENDS, name));
+        }
+        seen.push(name);
+
+        backends.push(match name {
+            "local" => AuthBackend::Local,
+            "ldap" => AuthBackend::Ldap(Box::new(ldap_config_from_env()?)),
+            _ => return Err(format!("{} entries must be local or ldap", AUTH_BACKENDS)),
+        });
+    }
+
+    if backends.is_empty() {
+        return Err(format!("{} must not be empty", AUTH_BACKENDS));
+    }
+    Ok(backends)
+}
+
+fn ldap_config_from_env() -> Result<LdapConfig, String> {
+    let required = |key: &str| env::var(key).map_err(|_| format!("{} is required for ldap", key));
+
+    let url = required(LDAP_URL)?;
+    if !url.starts_with("ldap://") && !url.starts_with("ldaps://") {
+        return Err(format!("{} must start with ldap:// or ldaps://", LDAP_URL));
+    }
+
+    let starttls = env::var(LDAP_STARTTLS)
+        .unwrap_or_else(|_| "false".into())
+        .parse()
+        .map_err(|_| format!("{} must be true or false", LDAP_STARTTLS))?;
+
+    let user_filter =
+        env::var(LDAP_USER_FILTER).unwrap_or_else(|_| DEFAULT_LDAP_USER_FILTER.into());
+    if !user_filter.contains("{email}") {
+        return Err(format!("{} must contain {{email}}", LDAP_USER_FILTER));
+    }
+
+    // `role=group dn` pairs separated by `;`, since DNs contain commas
+    let group_roles = env::var(LDAP_GROUP_ROLES)
+        .unwrap_or_default()
+        .split(';')
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+        .map(|mapping| {
+            mapping
+                .split_once('=')
+                .map(|(role, group_dn)| GroupRole {
+                    group_dn: group_dn.trim().into(),
+                    role: role.trim().into(),
+                })
+                .filter(|m| !m.role.is_empty() && !m.group_dn.is_empty())
+                .ok_or_else(|| {
+                    format!(
+                        "{} entries must look like role=cn=group,dc=example,dc=org",
+                        LDAP_GROUP_ROLES
+                    )
+                })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(LdapConfig {
+        url,
+        starttls,
+        bind_dn: env::var(LDAP_BIND_DN).ok(),
+        bind_password: env::var(LDAP_BIND_PASSWORD).ok(),
+        user_base_dn: required(LDAP_USER_BASE_DN)?,
+        user_filter,
+        email_attribute: env::var(LDAP_EMAIL_ATTRIBUTE)
+            .unwrap_or_else(|_| DEFAULT_LDAP_EMAIL_ATTRIBUTE.into()),
+        username_attribute: env::var(LDAP_USERNAME_ATTRIBUTE)
+            .unwrap_or_else(|_| DEFAULT_LDAP_USERNAME_ATTRIBUTE.into()),
+        group_attribute: env::var(LDAP_GROUP_ATTRIBUTE)
+            .unwrap_or_else(|_| DEFAULT_LDAP_GROUP_ATTRIBUTE.into()),
+        group_roles,
+    })
+}
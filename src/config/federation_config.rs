@@ -0,0 +1,59 @@
+use crate::constants::*;
+use std::env;
+
+/// An upstream OpenID Connect provider users can sign in with. Its endpoints
+/// are discovered from the issuer.
+#[derive(Clone, Debug)]
+pub struct FederatedProvider {
+    pub name: String, // used in urls, e.g. `google`
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scopes: String, // space separated, always includes openid
+}
+
+/// Reads the providers named in `FEDERATED_PROVIDERS`, each configured by
+/// `FEDERATED_{NAME}_ISSUER`, `_CLIENT_ID`, `_CLIENT_SECRET` and optionally
+/// `_SCOPES`.
+pub fn federated_providers_from_env() -> Result<Vec<FederatedProvider>, String> {
+    let names = env::var(FEDERATED_PROVIDERS).unwrap_or_default();
+    let mut providers: Vec<FederatedProvider> = Vec::new();
+
+    for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        let name = name.to_ascii_lowercase();
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!(
+                "{} names may only contain letters, digits, '-' and '_': {}",
+                FEDERATED_PROVIDERS, name
+            ));
+        }
+        if providers.iter().any(|p| p.name == name) {
+            return Err(format!("{} lists {} twice", FEDERATED_PROVIDERS, name));
+        }
+
+        let prefix = format!("FEDERATED_{}_", name.to_ascii_uppercase().replace('-', "_"));
+        let required = |suffix: &str| {
+            let key = format!("{}{}", prefix, suffix);
+            env::var(&key).map_err(|_| format!("{} is required", key))
+        };
+
+        let scopes = env::var(format!("{}SCOPES", prefix))
+            .unwrap_or_else(|_| DEFAULT_FEDERATED_SCOPES.into());
+        if !scopes.split_whitespace().any(|s| s == "openid") {
+            return Err(format!("{}SCOPES must include openid", prefix));
+        }
+
+        providers.push(FederatedProvider {
+            issuer: required("ISSUER")?.trim_end_matches('/').to_string(),
+            client_id: required("CLIENT_ID")?,
+            client_secret: required("CLIENT_SECRET")?,
+            scopes,
+            name,
+        });
+    }
+
+    Ok(providers)
+}
@@ -0,0 +1,244 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ed25519_dalek::pkcs8::{DecodePrivateKey, EncodePrivateKey};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+    OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, ThumbprintHash,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use rand_core::{OsRng, RngCore};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use time::OffsetDateTime;
+
+const RSA_KEY_BITS: usize = 2048;
+
+/// A JWT signing key together with its verification half and, for asymmetric
+/// algorithms, the public JWK published at `/.well-known/jwks.json`.
+#[derive(Clone)]
+pub struct JwtKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub encoding: EncodingKey,
+    pub decoding: DecodingKey,
+    pub jwk: Option<Jwk>,
+}
+
+impl JwtKey {
+    pub fn from_secret(secret: &[u8], kid: Option<String>) -> Self {
+        let kid = kid.unwrap_or_else(|| {
+            let jwk = Jwk::from_encoding_key(&EncodingKey::from_secret(secret), Algorithm::HS256)
+                .expect("HMAC keys always convert to a JWK");
+            jwk.thumbprint(ThumbprintHash::SHA256)
+        });
+
+        Self {
+            kid,
+            algorithm: Algorithm::HS256,
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+            jwk: None,
+        }
+    }
+
+    pub fn from_pem(algorithm: Algorithm, pem: &[u8], kid: Option<String>) -> Result<Self, String> {
+        let (encoding, mut jwk) = match algorithm {
+            Algorithm::RS256 | Algorithm::ES256 => {
+                let encoding = match algorithm {
+                    Algorithm::RS256 => EncodingKey::from_rsa_pem(pem),
+                    _ => EncodingKey::from_ec_pem(pem),
+                }
+                .map_err(|e| format!("invalid {:?} private key: {}", algorithm, e))?;
+                let jwk = Jwk::from_encoding_key(&encoding, algorithm)
+                    .map_err(|e| format!("invalid {:?} private key: {}", algorithm, e))?;
+                (encoding, jwk)
+            }
+            Algorithm::EdDSA => {
+                let encoding = EncodingKey::from_ed_pem(pem)
+                    .map_err(|e| format!("invalid EdDSA private key: {}", e))?;
+                let pem = std::str::from_utf8(pem).map_err(|_| "invalid EdDSA private key")?;
+                let signing_key = ed25519_dalek::SigningKey::from_pkcs8_pem(pem)
+                    .map_err(|e| format!("invalid EdDSA private key: {}", e))?;
+                let jwk = Jwk {
+                    common: CommonParameters {
+                        key_algorithm: Some(KeyAlgorithm::EdDSA),
+                        ..Default::default()
+                    },
+                    algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                        key_type: OctetKeyPairType::OctetKeyPair,
+                        curve: EllipticCurve::Ed25519,
+                        x: URL_SAFE_NO_PAD.encode(signing_key.verifying_key().as_bytes()),
+                    }),
+                };
+                (encoding, jwk)
+            }
+            _ => return Err(format!("unsupported JWT algorithm: {:?}", algorithm)),
+        };
+
+        let kid = kid.unwrap_or_else(|| jwk.thumbprint(ThumbprintHash::SHA256));
+        jwk.common.key_id = Some(kid.clone());
+        jwk.common.public_key_use = Some(PublicKeyUse::Signature);
+        let decoding = DecodingKey::from_jwk(&jwk).map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            kid,
+            algorithm,
+            encoding,
+            decoding,
+            jwk: Some(jwk),
+        })
+    }
+
+    /// Rebuilds a key from persisted material: the raw secret for HS256, a
+    /// PKCS#8 PEM private key otherwise.
+    pub fn from_material(algorithm: &str, material: &str, kid: &str) -> Result<Self, String> {
+        let algorithm = Algorithm::from_str(algorithm).map_err(|e| e.to_string())?;
+        match algorithm {
+            Algorithm::HS256 => Ok(Self::from_secret(material.as_bytes(), Some(kid.into()))),
+            _ => Self::from_pem(algorithm, material.as_bytes(), Some(kid.into())),
+        }
+    }
+
+    /// Generates a fresh key for `algorithm`, returning it with the material to
+    /// persist.
+    pub fn generate(algorithm: Algorithm) -> Result<(Self, String), String> {
+        let material = match algorithm {
+            Algorithm::HS256 => {
+                let mut bytes = [0u8; 48];
+                OsRng.fill_bytes(&mut bytes);
+                URL_SAFE_NO_PAD.encode(bytes)
+            }
+            Algorithm::RS256 => {
+                use rsa::pkcs8::{EncodePrivateKey as _, LineEnding};
+                rsa::RsaPrivateKey::new(&mut OsRng, RSA_KEY_BITS)
+                    .map_err(|e| e.to_string())?
+                    .to_pkcs8_pem(LineEnding::LF)
+                    .map_err(|e| e.to_string())?
+                    .to_string()
+            }
+            Algorithm::ES256 => {
+                use p256::pkcs8::{EncodePrivateKey as _, LineEnding};
+                p256::SecretKey::random(&mut OsRng)
+                    .to_pkcs8_pem(LineEnding::LF)
+                    .map_err(|e| e.to_string())?
+                    .to_string()
+            }
+            Algorithm::EdDSA => {
+                let mut bytes = [0u8; 32];
+                OsRng.fill_bytes(&mut bytes);
+                ed25519_dalek::SigningKey::from_bytes(&bytes)
+                    .to_pkcs8_pem(ed25519_dalek::pkcs8::spki::der::pem::LineEnding::LF)
+                    .map_err(|e| e.to_string())?
+                    .to_string()
+            }
+            _ => return Err(format!("unsupported JWT algorithm: {:?}", algorithm)),
+        };
+
+        let key = match algorithm {
+            Algorithm::HS256 => Self::from_secret(material.as_bytes(), None),
+            _ => Self::from_pem(algorithm, material.as_bytes(), None)?,
+        };
+        Ok((key, material))
+    }
+}
+
+#[derive(Clone)]
+struct KeyringEntry {
+    key: JwtKey,
+    activates_at: i64,
+}
+
+/// The set of JWT keys in use. The bootstrap key comes from the environment;
+/// rotated keys are loaded from the database and take over signing once their
+/// activation time passes. A superseded key keeps verifying tokens for
+/// `grace_seconds` (the access token lifetime) after its successor activates.
+#[derive(Clone)]
+pub struct Keyring {
+    bootstrap: JwtKey,
+    grace_seconds: i64,
+    rotated: Arc<RwLock<Vec<KeyringEntry>>>,
+}
+
+impl Keyring {
+    pub fn new(bootstrap: JwtKey, grace_seconds: i64) -> Self {
+        Self {
+            bootstrap,
+            grace_seconds,
+            rotated: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.bootstrap.algorithm
+    }
+
+    /// Whether third parties can verify our tokens from the JWKS. An HMAC
+    /// secret can't be shared with them, so OpenID Connect needs this.
+    pub fn is_asymmetric(&self) -> bool {
+        self.algorithm() != Algorithm::HS256
+    }
+
+    pub fn bootstrap(&self) -> &JwtKey {
+        &self.bootstrap
+    }
+
+    /// Replaces the rotated keys with `keys`, given as `(key, activates_at)`.
+    pub fn set_rotated(&self, keys: Vec<(JwtKey, i64)>) {
+        let mut entries: Vec<KeyringEntry> = keys
+            .into_iter()
+            .map(|(key, activates_at)| KeyringEntry { key, activates_at })
+            .collect();
+        entries.sort_by_key(|e| e.activates_at);
+        *self.rotated.write().unwrap_or_else(|e| e.into_inner()) = entries;
+    }
+
+    fn entries(&self) -> Vec<KeyringEntry> {
+        let rotated = self.rotated.read().unwrap_or_else(|e| e.into_inner());
+        std::iter::once(KeyringEntry {
+            key: self.bootstrap.clone(),
+            activates_at: i64::MIN,
+        })
+        .chain(rotated.iter().cloned())
+        .collect()
+    }
+
+    pub fn current(&self) -> JwtKey {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        self.entries()
+            .into_iter()
+            .rev()
+            .find(|e| e.activates_at <= now)
+            .map(|e| e.key)
+            .unwrap_or_else(|| self.bootstrap.clone())
+    }
+
+    /// Keys accepted for verification: the current key, keys pending
+    /// activation, and superseded keys still within the grace period.
+    pub fn verification_keys(&self) -> Vec<JwtKey> {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let entries = self.entries();
+        entries
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| match entries.get(i + 1) {
+                Some(next) => next.activates_at.saturating_add(self.grace_seconds) > now,
+                None => true,
+            })
+            .map(|(_, e)| e.key.clone())
+            .collect()
+    }
+
+    pub fn find(&self, kid: &str) -> Option<JwtKey> {
+        self.verification_keys().into_iter().find(|k| k.kid == kid)
+    }
+
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self
+                .verification_keys()
+                .into_iter()
+                .filter_map(|k| k.jwk)
+                .collect(),
+        }
+    }
+}
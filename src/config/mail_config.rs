@@ -0,0 +1,69 @@
+use crate::constants::*;
+use std::env;
+
+#[derive(Clone, Copy, Debug)]
+pub enum SmtpTls {
+    StartTls,
+    Tls,
+    None,
+}
+
+#[derive(Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub tls: SmtpTls,
+}
+
+#[derive(Clone)]
+pub enum MailTransport {
+    Log,
+    File(String),
+    Smtp(SmtpConfig),
+}
+
+/// Reads `MAIL_TRANSPORT` and its transport-specific settings. There is no
+/// default, so a deployment never silently drops its mail.
+pub fn mail_transport_from_env() -> Result<MailTransport, String> {
+    let transport = env::var(MAIL_TRANSPORT)
+        .map_err(|_| format!("{} is required (log, file or smtp)", MAIL_TRANSPORT))?;
+
+    match transport.as_str() {
+        "log" => Ok(MailTransport::Log),
+        "file" => env::var(MAIL_OUTBOX_DIR)
+            .ok()
+            .map(MailTransport::File)
+            .ok_or_else(|| format!("{} is required for file mail transport", MAIL_OUTBOX_DIR)),
+        "smtp" => {
+            let host =
+                env::var(SMTP_HOST).map_err(|_| format!("{} is required for smtp", SMTP_HOST))?;
+
+            let port = env::var(SMTP_PORT)
+                .ok()
+                .map(|p| p.parse())
+                .transpose()
+                .map_err(|_| format!("{} must be a valid port number", SMTP_PORT))?;
+
+            let tls = match env::var(SMTP_TLS)
+                .unwrap_or_else(|_| "starttls".into())
+                .as_str()
+            {
+                "starttls" => SmtpTls::StartTls,
+                "tls" => SmtpTls::Tls,
+                "none" => SmtpTls::None,
+                _ => return Err(format!("{} must be one of starttls, tls, none", SMTP_TLS)),
+            };
+
+            Ok(MailTransport::Smtp(SmtpConfig {
+                host,
+                port,
+                username: env::var(SMTP_USERNAME).ok(),
+                password: env::var(SMTP_PASSWORD).ok(),
+                tls,
+            }))
+        }
+        _ => Err(format!("{} must be one of log, file, smtp", MAIL_TRANSPORT)),
+    }
+}
@@ -1,2 +1,8 @@
 pub mod app_config;
+pub mod auth_backend_config;
+pub mod federation_config;
+pub mod jwt_keys;
+pub mod mail_config;
+pub mod password_config;
+pub mod rate_limit_config;
 pub mod rustls_config;
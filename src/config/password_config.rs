@@ -0,0 +1,119 @@
+use crate::constants::*;
+use argon2::Params;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// How new password hashes are made. Hashes from another policy still verify
+/// and are replaced on the user's next successful login.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PasswordHashing {
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+    /// Legacy; passwords longer than 72 bytes are rejected.
+    Bcrypt { cost: u32 },
+}
+
+/// What a new password must satisfy, wherever it is set.
+#[derive(Clone, Debug)]
+pub struct PasswordPolicy {
+    pub min_length: usize, // in characters
+    pub max_length: usize,
+    pub min_classes: usize, // of lowercase, uppercase, digits and symbols
+    pub min_score: u8,      // strength estimate from 0 (guessable) to 4
+    pub breached_dir: Option<PathBuf>, // Have I Been Pwned range files
+    pub history: usize,     // recent passwords that may not be reused, 0 to allow any
+    pub max_age_days: i64,  // 0 for passwords that never expire
+}
+
+fn number_env<T: FromStr + ToString>(name: &str, default: T) -> Result<T, String> {
+    env::var(name)
+        .unwrap_or_else(|_| default.to_string())
+        .parse()
+        .map_err(|_| format!("{} must be a valid number", name))
+}
+
+/// Reads `PASSWORD_HASH` (`argon2id` or `bcrypt`) and the parameters of the
+/// chosen algorithm.
+pub fn password_hashing_from_env() -> Result<PasswordHashing, String> {
+    let algorithm = env::var(PASSWORD_HASH).unwrap_or_else(|_| DEFAULT_PASSWORD_HASH.into());
+
+    match algorithm.as_str() {
+        "argon2id" => {
+            let memory_kib = number_env(ARGON2_MEMORY_KIB, DEFAULT_ARGON2_MEMORY_KIB)?;
+            let iterations = number_env(ARGON2_ITERATIONS, DEFAULT_ARGON2_ITERATIONS)?;
+            let parallelism = number_env(ARGON2_PARALLELISM, DEFAULT_ARGON2_PARALLELISM)?;
+            Params::new(memory_kib, iterations, parallelism, None).map_err(|e| {
+                format!(
+                    "invalid {}/{}/{}: {}",
+                    ARGON2_MEMORY_KIB, ARGON2_ITERATIONS, ARGON2_PARALLELISM, e
+                )
+            })?;
+
+            Ok(PasswordHashing::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            })
+        }
+        "bcrypt" => {
+            let cost = number_env(BCRYPT_COST, DEFAULT_BCRYPT_COST)?;
+            if !(4..=31).contains(&cost) {
+                return Err(format!("{} must be between 4 and 31", BCRYPT_COST));
+            }
+            Ok(PasswordHashing::Bcrypt { cost })
+        }
+        _ => Err(format!("{} must be argon2id or bcrypt", PASSWORD_HASH)),
+    }
+}
+
+pub fn password_policy_from_env() -> Result<PasswordPolicy, String> {
+    let min_length = number_env(PASSWORD_MIN_LENGTH, DEFAULT_PASSWORD_MIN_LENGTH)?;
+    let max_length = number_env(PASSWORD_MAX_LENGTH, DEFAULT_PASSWORD_MAX_LENGTH)?;
+    if min_length == 0 || min_length > max_length {
+        return Err(format!(
+            "{} must be positive and at most {}",
+            PASSWORD_MIN_LENGTH, PASSWORD_MAX_LENGTH
+        ));
+    }
+
+    let min_classes = number_env(PASSWORD_MIN_CLASSES, DEFAULT_PASSWORD_MIN_CLASSES)?;
+    if min_classes > 4 {
+        return Err(format!("{} must be between 0 and 4", PASSWORD_MIN_CLASSES));
+    }
+
+    let min_score = number_env(PASSWORD_MIN_SCORE, DEFAULT_PASSWORD_MIN_SCORE)?;
+    if min_score > 4 {
+        return Err(format!("{} must be between 0 and 4", PASSWORD_MIN_SCORE));
+    }
+
+    let breached_dir = env::var(PASSWORD_BREACHED_DIR).ok().map(PathBuf::from);
+    if let Some(ref dir) = breached_dir {
+        if !Path::new(dir).is_dir() {
+            return Err(format!(
+                "{} is not a directory: {}",
+                PASSWORD_BREACHED_DIR,
+                dir.display()
+            ));
+        }
+    }
+
+    let history = number_env(PASSWORD_HISTORY, DEFAULT_PASSWORD_HISTORY)?;
+    let max_age_days = number_env(PASSWORD_MAX_AGE_DAYS, DEFAULT_PASSWORD_MAX_AGE_DAYS)?;
+    if max_age_days < 0 {
+        return Err(format!("{} must not be negative", PASSWORD_MAX_AGE_DAYS));
+    }
+
+    Ok(PasswordPolicy {
+        min_length,
+        max_length,
+        min_classes,
+        min_score,
+        breached_dir,
+        history,
+        max_age_days,
+    })
+}
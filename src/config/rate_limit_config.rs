@@ -0,0 +1,77 @@
+use crate::constants::*;
+use std::env;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RateLimitAlgorithm {
+    TokenBucket,
+    SlidingWindow,
+}
+
+/// `limit` requests per `window_seconds`. A token bucket allows bursts of up
+/// to `limit` and refills evenly over the window; a sliding window counts
+/// requests over the trailing window.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimitPolicy {
+    pub algorithm: RateLimitAlgorithm,
+    pub limit: u64,
+    pub window_seconds: u64,
+}
+
+impl FromStr for RateLimitPolicy {
+    type Err = ();
+
+    /// Parses `{algorithm}:{limit}/{window_seconds}`, e.g. `token_bucket:120/60`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (algorithm, rate) = s.split_once(':').ok_or(())?;
+        let (limit, window_seconds) = rate.split_once('/').ok_or(())?;
+
+        let algorithm = match algorithm.trim() {
+            "token_bucket" => RateLimitAlgorithm::TokenBucket,
+            "sliding_window" => RateLimitAlgorithm::SlidingWindow,
+            _ => return Err(()),
+        };
+        let limit: u64 = limit.trim().parse().map_err(|_| ())?;
+        let window_seconds: u64 = window_seconds.trim().parse().map_err(|_| ())?;
+
+        if limit == 0 || window_seconds == 0 {
+            return Err(());
+        }
+
+        Ok(Self {
+            algorithm,
+            limit,
+            window_seconds,
+        })
+    }
+}
+
+/// Policies per route group; `None` leaves the group unlimited.
+#[derive(Clone, Debug)]
+pub struct RateLimits {
+    pub auth: Option<RateLimitPolicy>,
+    pub user: Option<RateLimitPolicy>,
+    pub admin: Option<RateLimitPolicy>,
+}
+
+fn policy_from_env(name: &str, default: &str) -> Result<Option<RateLimitPolicy>, String> {
+    let value = env::var(name).unwrap_or_else(|_| default.into());
+    if value == "off" {
+        return Ok(None);
+    }
+
+    value.parse().map(Some).map_err(|_| {
+        format!(
+            "{} must be off or {{token_bucket|sliding_window}}:{{limit}}/{{window_seconds}}",
+            name
+        )
+    })
+}
+
+pub fn rate_limits_from_env() -> Result<RateLimits, String> {
+    Ok(RateLimits {
+        auth: policy_from_env(RATE_LIMIT_AUTH, DEFAULT_RATE_LIMIT_AUTH)?,
+        user: policy_from_env(RATE_LIMIT_USER, DEFAULT_RATE_LIMIT_USER)?,
+        admin: policy_from_env(RATE_LIMIT_ADMIN, DEFAULT_RATE_LIMIT_ADMIN)?,
+    })
+}
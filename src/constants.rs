@@ -1,42 +1,346 @@
 pub const COLLECTION_USERS: &str = "users";
+pub const COLLECTION_SESSIONS: &str = "sessions";
+pub const SESSION_TTL_INDEX: &str = "last_seen_at_ttl";
+pub const COLLECTION_PASSKEYS: &str = "passkeys";
+pub const COLLECTION_SIGNING_KEYS: &str = "signing_keys";
+pub const COLLECTION_ROLES: &str = "roles";
+pub const COLLECTION_ORGANIZATIONS: &str = "organizations";
+pub const COLLECTION_MEMBERSHIPS: &str = "memberships";
+pub const COLLECTION_ORG_INVITATIONS: &str = "org_invitations";
+pub const COLLECTION_INVITATIONS: &str = "invitations";
+pub const COLLECTION_OAUTH_CLIENTS: &str = "oauth_clients";
+pub const COLLECTION_SERVICE_ACCOUNTS: &str = "service_accounts";
+pub const COLLECTION_API_KEYS: &str = "api_keys";
+pub const COLLECTION_FEDERATED_IDENTITIES: &str = "federated_identities";
 
-pub const DEFAULT_JWT_EXP_HOURS: i64 = 24;
+pub const BUILTIN_ADMIN_ROLE: &str = "admin";
+pub const PERMISSION_USERS_READ: &str = "users:read";
+pub const PERMISSION_USERS_WRITE: &str = "users:write";
+pub const PERMISSION_USERS_DELETE: &str = "users:delete";
+pub const PERMISSION_ROLES_MANAGE: &str = "roles:manage";
+pub const PERMISSION_KEYS_MANAGE: &str = "keys:manage";
+pub const PERMISSION_CLIENTS_MANAGE: &str = "clients:manage";
+pub const PERMISSION_TOKENS_INTROSPECT: &str = "tokens:introspect";
+pub const ALL_PERMISSIONS: [&str; 7] = [
+    PERMISSION_USERS_READ,
+    PERMISSION_USERS_WRITE,
+    PERMISSION_USERS_DELETE,
+    PERMISSION_ROLES_MANAGE,
+    PERMISSION_KEYS_MANAGE,
+    PERMISSION_CLIENTS_MANAGE,
+    PERMISSION_TOKENS_INTROSPECT,
+];
+
+pub const DEFAULT_ACCESS_TOKEN_EXP_MINUTES: i64 = 15;
+pub const DEFAULT_REFRESH_TOKEN_EXP_DAYS: i64 = 30;
 pub const MIN_JWT_SECRET_LENGTH: usize = 32;
+pub const MIN_SCIM_TOKEN_LENGTH: usize = 32;
+pub const DEFAULT_JWT_ALGORITHM: &str = "HS256";
+pub const JWT_KEY_ACTIVATION_DELAY_SECONDS: i64 = 600;
+pub const JWT_KEYRING_REFRESH_SECONDS: u64 = 30;
+
+pub const DEFAULT_MFA_ISSUER: &str = "Server";
+pub const MFA_CHALLENGE_TTL_SECONDS: i64 = 300;
+pub const MAX_MFA_ATTEMPTS: i64 = 5;
+pub const PASSWORD_CHANGE_TTL_SECONDS: i64 = 600;
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+pub const DEFAULT_WEBAUTHN_RP_ID: &str = "localhost";
+pub const DEFAULT_WEBAUTHN_RP_NAME: &str = "Server";
+pub const DEFAULT_WEBAUTHN_ORIGIN: &str = "http://localhost:8080";
+pub const WEBAUTHN_CHALLENGE_TTL_SECONDS: i64 = 300;
+
+pub const DEFAULT_PUBLIC_URL: &str = "http://localhost:8080";
+pub const DEFAULT_MAIL_FROM: &str = "Server <no-reply@localhost>";
+pub const DEFAULT_MAIL_LOCALE: &str = "en";
+pub const MAIL_RETRY_DELAYS_SECONDS: [u64; 4] = [30, 120, 480, 1920];
+pub const EMAIL_VERIFICATION_TTL_SECONDS: i64 = 24 * 60 * 60;
+pub const EMAIL_VERIFICATION_RESEND_SECONDS: i64 = 60;
+pub const PASSWORD_RESET_TTL_SECONDS: i64 = 60 * 60;
+pub const PASSWORD_RESET_REQUEST_INTERVAL_SECONDS: i64 = 60;
+pub const ORG_INVITATION_TTL_SECONDS: i64 = 7 * 24 * 60 * 60;
+pub const INVITATION_TTL_SECONDS: i64 = 7 * 24 * 60 * 60;
+pub const OAUTH_CODE_TTL_SECONDS: i64 = 60;
+pub const OAUTH_SCOPES: [&str; 3] = ["openid", "profile", "email"];
+pub const API_KEY_PREFIX: &str = "ak_";
+pub const API_KEY_HEADER: &str = "X-API-Key";
+pub const DEFAULT_FEDERATED_SCOPES: &str = "openid email profile";
+pub const FEDERATED_STATE_TTL_SECONDS: i64 = 600;
+pub const FEDERATED_HTTP_TIMEOUT_SECONDS: u64 = 10;
+pub const DEFAULT_AUTH_BACKENDS: &str = "local";
+pub const DEFAULT_LDAP_USER_FILTER: &str = "(mail={email})";
+pub const DEFAULT_LDAP_EMAIL_ATTRIBUTE: &str = "mail";
+pub const DEFAULT_LDAP_USERNAME_ATTRIBUTE: &str = "uid";
+pub const DEFAULT_LDAP_GROUP_ATTRIBUTE: &str = "memberOf";
+pub const LDAP_TIMEOUT_SECONDS: u64 = 10;
+pub const DEFAULT_PASSWORD_HASH: &str = "argon2id";
+pub const DEFAULT_ARGON2_MEMORY_KIB: u32 = 19 * 1024;
+pub const DEFAULT_ARGON2_ITERATIONS: u32 = 2;
+pub const DEFAULT_ARGON2_PARALLELISM: u32 = 1;
+pub const DEFAULT_BCRYPT_COST: u32 = 12;
+pub const DEFAULT_PASSWORD_MIN_LENGTH: usize = 8;
+pub const DEFAULT_PASSWORD_MAX_LENGTH: usize = 128;
+pub const DEFAULT_PASSWORD_MIN_CLASSES: usize = 0;
+pub const DEFAULT_PASSWORD_MIN_SCORE: u8 = 2;
+pub const DEFAULT_PASSWORD_HISTORY: usize = 0;
+pub const DEFAULT_PASSWORD_MAX_AGE_DAYS: i64 = 0;
+pub const SCIM_CONTENT_TYPE: &str = "application/scim+json";
+pub const SCIM_DEFAULT_PAGE_SIZE: i64 = 100;
+pub const SCIM_MAX_PAGE_SIZE: i64 = 200;
+pub const SCIM_SCHEMA_USER: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+pub const SCIM_SCHEMA_GROUP: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+pub const SCIM_SCHEMA_LIST_RESPONSE: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+pub const SCIM_SCHEMA_PATCH_OP: &str = "urn:ietf:params:scim:api:messages:2.0:PatchOp";
+pub const SCIM_SCHEMA_ERROR: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+pub const SCIM_SCHEMA_SERVICE_PROVIDER_CONFIG: &str =
+    "urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig";
+pub const SCIM_SCHEMA_RESOURCE_TYPE: &str = "urn:ietf:params:scim:schemas:core:2.0:ResourceType";
+pub const SCIM_SCHEMA_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Schema";
+
+pub const DEFAULT_LOGIN_MAX_FAILURES: i64 = 10;
+pub const DEFAULT_LOGIN_IP_MAX_FAILURES: i64 = 50;
+pub const DEFAULT_LOGIN_LOCKOUT_MINUTES: i64 = 15;
+pub const LOGIN_FREE_ATTEMPTS: i64 = 3;
+pub const LOGIN_MAX_DELAY_SECONDS: i64 = 60;
+
+pub const DEFAULT_RATE_LIMIT_AUTH: &str = "sliding_window:30/60";
+pub const DEFAULT_RATE_LIMIT_USER: &str = "token_bucket:120/60";
+pub const DEFAULT_RATE_LIMIT_ADMIN: &str = "token_bucket:60/60";
 
 pub const DEFAULT_HOST: &str = "0.0.0.0";
 pub const DEFAULT_PORT: &str = "8080";
 
 pub const REGISTER_SUCCESS: &str = "successfully registered";
+pub const REGISTER_VERIFY_EMAIL: &str =
+    "successfully registered, check your email to verify your address";
+pub const EMAIL_VERIFIED: &str = "successfully verified email";
+pub const VERIFICATION_EMAIL_SENT: &str =
+    "if the address belongs to an unverified account, a verification email has been sent";
 pub const LOGIN_SUCCESS: &str = "successfully logged in";
 pub const LOGOUT_SUCCESS: &str = "successfully logged out";
+pub const TOKEN_REFRESHED: &str = "successfully refreshed token";
 pub const TOKEN_BLACKLISTED: &str = "token has been blacklisted";
 pub const PROFILE_FETCHED: &str = "successfully fetched user profile";
 pub const EMAIL_UPDATED: &str = "successfully updated email";
 pub const USERNAME_UPDATED: &str = "successfully updated username";
 pub const PASSWORD_UPDATED: &str = "successfully updated password";
+pub const PASSWORD_RESET_REQUESTED: &str =
+    "if the address is registered, a password reset email has been sent";
+pub const PASSWORD_RESET_SUCCESS: &str = "successfully reset password";
+pub const SESSIONS_FETCHED: &str = "successfully fetched sessions";
+pub const SESSION_REVOKED: &str = "successfully revoked session";
+pub const ALL_SESSIONS_REVOKED: &str = "successfully logged out everywhere";
+pub const MFA_REQUIRED: &str = "second factor required";
+pub const PASSWORD_EXPIRED: &str = "password has expired and must be changed";
+pub const MFA_STATUS_FETCHED: &str = "successfully fetched mfa status";
+pub const MFA_ENROLLMENT_STARTED: &str = "scan the otpauth uri and confirm with a code";
+pub const MFA_ENABLED: &str = "successfully enabled mfa";
+pub const MFA_DISABLED: &str = "successfully disabled mfa";
+pub const RECOVERY_CODES_REGENERATED: &str = "successfully regenerated recovery codes";
+pub const MFA_RESET: &str = "successfully reset user mfa";
+pub const PASSKEY_CHALLENGE_CREATED: &str = "successfully created passkey challenge";
+pub const PASSKEY_REGISTERED: &str = "successfully registered passkey";
+pub const PASSKEYS_FETCHED: &str = "successfully fetched passkeys";
+pub const PASSKEY_DELETED: &str = "successfully deleted passkey";
+pub const SIGNING_KEYS_FETCHED: &str = "successfully fetched signing keys";
+pub const SIGNING_KEY_ROTATED: &str = "successfully rotated signing key";
+pub const SIGNING_KEY_ENCRYPTION_REQUIRED: &str =
+    "rotated signing keys are only stored encrypted; set SIGNING_KEY_ENCRYPTION_KEY";
 pub const USER_INFO_FETCHED: &str = "successfully fetched user info";
 pub const USER_INFOS_FETCHED: &str = "successfully fetched user infos";
 pub const USER_CREATED: &str = "successfully created user";
 pub const USER_UPDATED: &str = "successfully updated user";
 pub const USER_DELETED: &str = "successfully deleted user";
-pub const USER_SET_AS_ADMIN: &str = "successfully set user as admin";
-pub const ADMIN_SET_AS_USER: &str = "successfully set admin as user";
+pub const ACCOUNT_UNLOCKED: &str = "successfully unlocked account";
+pub const USER_ROLES_UPDATED: &str = "successfully updated user roles";
+pub const ROLES_FETCHED: &str = "successfully fetched roles";
+pub const ROLE_CREATED: &str = "successfully created role";
+pub const ROLE_UPDATED: &str = "successfully updated role";
+pub const ROLE_DELETED: &str = "successfully deleted role";
+pub const ORGANIZATIONS_FETCHED: &str = "successfully fetched organizations";
+pub const ORGANIZATION_FETCHED: &str = "successfully fetched organization";
+pub const ORGANIZATION_CREATED: &str = "successfully created organization";
+pub const ORGANIZATION_DELETED: &str = "successfully deleted organization";
+pub const ORGANIZATION_SWITCHED: &str = "successfully switched organization";
+pub const MEMBERS_FETCHED: &str = "successfully fetched members";
+pub const MEMBER_UPDATED: &str = "successfully updated member";
+pub const MEMBER_REMOVED: &str = "successfully removed member";
+pub const ORG_INVITATIONS_FETCHED: &str = "successfully fetched invitations";
+pub const ORG_INVITATION_SENT: &str = "successfully sent invitation";
+pub const ORG_INVITATION_REVOKED: &str = "successfully revoked invitation";
+pub const ORG_INVITATION_ACCEPTED: &str = "successfully joined organization";
+pub const INVITATIONS_FETCHED: &str = "successfully fetched invitations";
+pub const INVITATION_SENT: &str = "successfully sent invitation";
+pub const INVITATION_REVOKED: &str = "successfully revoked invitation";
+pub const INVITATION_ACCEPTED: &str = "successfully accepted invitation";
+pub const OAUTH_CLIENTS_FETCHED: &str = "successfully fetched oauth clients";
+pub const OAUTH_CLIENT_CREATED: &str = "successfully created oauth client";
+pub const OAUTH_CLIENT_UPDATED: &str = "successfully updated oauth client";
+pub const OAUTH_CLIENT_SECRET_ROTATED: &str = "successfully rotated client secret";
+pub const OAUTH_CLIENT_DELETED: &str = "successfully deleted oauth client";
+pub const SERVICE_ACCOUNTS_FETCHED: &str = "successfully fetched service accounts";
+pub const SERVICE_ACCOUNT_CREATED: &str = "successfully created service account";
+pub const SERVICE_ACCOUNT_UPDATED: &str = "successfully updated service account";
+pub const SERVICE_ACCOUNT_SECRET_ROTATED: &str = "successfully rotated service account secret";
+pub const SERVICE_ACCOUNT_DELETED: &str = "successfully deleted service account";
+pub const API_KEYS_FETCHED: &str = "successfully fetched api keys";
+pub const API_KEY_CREATED: &str = "successfully created api key";
+pub const API_KEY_REVOKED: &str = "successfully revoked api key";
+pub const FEDERATED_PROVIDERS_FETCHED: &str = "successfully fetched sign-in providers";
+pub const IDENTITIES_FETCHED: &str = "successfully fetched linked identities";
+pub const IDENTITY_LINK_STARTED: &str = "continue at the provider to link your account";
+pub const IDENTITY_UNLINKED: &str = "successfully unlinked identity";
+pub const AUTHORIZATION_DECIDED: &str = "successfully recorded authorization decision";
 
 pub const EMAIL_ALREADY_EXISTS: &str = "email already registered";
 pub const INVALID_CREDENTIALS: &str = "invalid username or password";
+pub const INVALID_REFRESH_TOKEN: &str = "invalid or expired refresh token";
+pub const REFRESH_TOKEN_REUSED: &str = "refresh token reuse detected";
 pub const INVALID_OLD_PASSWORD: &str = "invalid old password";
+pub const INVALID_MFA_CODE: &str = "invalid verification code";
+pub const INVALID_MFA_TOKEN: &str = "invalid or expired mfa token";
+pub const INVALID_PASSWORD_CHANGE_TOKEN: &str = "invalid or expired password change token";
+pub const MFA_ALREADY_ENABLED: &str = "mfa is already enabled";
+pub const MFA_NOT_ENABLED: &str = "mfa is not enabled";
+pub const MFA_NOT_PENDING: &str = "no pending mfa enrollment";
+pub const INVALID_PASSKEY: &str = "invalid passkey credential";
+pub const INVALID_PASSKEY_CHALLENGE: &str = "invalid or expired passkey challenge";
+pub const PASSKEY_ALREADY_REGISTERED: &str = "passkey already registered";
+pub const PASSKEY_NOT_FOUND: &str = "passkey not found";
+pub const INVALID_PASSKEY_ID: &str = "invalid passkey id";
+pub const INVALID_VERIFICATION_TOKEN: &str = "invalid or expired verification token";
+pub const INVALID_RESET_TOKEN: &str = "invalid or expired reset token";
+pub const EMAIL_NOT_VERIFIED: &str = "email address is not verified";
+pub const ACCOUNT_DISABLED: &str = "this account has been disabled";
+pub const LOGIN_LOCKED: &str = "too many failed login attempts, try again later";
+pub const RATE_LIMITED: &str = "too many requests, slow down";
 pub const USER_NOT_FOUND: &str = "user not found";
+pub const SESSION_NOT_FOUND: &str = "session not found";
+pub const INVALID_SESSION_ID: &str = "invalid session id";
 pub const AUTH_REQUIRED: &str = "authentication required";
 pub const INVALID_USER_ID: &str = "invalid user id";
 pub const PERMISSION_DENIED: &str = "permission denied";
+pub const ROLE_NOT_FOUND: &str = "role not found";
+pub const ROLE_ALREADY_EXISTS: &str = "role already exists";
+pub const BUILTIN_ROLE_IMMUTABLE: &str = "built-in roles cannot be modified";
+pub const INVALID_ROLE_NAME: &str =
+    "role name may only contain lowercase letters, digits, '-', '_' and ':'";
+pub const UNKNOWN_PERMISSION: &str = "unknown permission";
+pub const NO_ACTIVE_ORGANIZATION: &str = "no active organization, switch to one first";
+pub const NOT_ORG_MEMBER: &str = "not a member of this organization";
+pub const ORG_PERMISSION_DENIED: &str = "organization permission denied";
+pub const INVALID_ORGANIZATION_ID: &str = "invalid organization id";
+pub const MEMBER_NOT_FOUND: &str = "member not found";
+pub const ALREADY_ORG_MEMBER: &str = "user is already a member of this organization";
+pub const LAST_ORG_OWNER: &str = "an organization must keep at least one owner";
+pub const INVALID_INVITATION: &str = "invalid or expired invitation";
+pub const INVITATION_NOT_FOUND: &str = "invitation not found";
+pub const INVALID_INVITATION_ID: &str = "invalid invitation id";
+pub const OWN_ROLE_MANAGEMENT_REQUIRED: &str = "you cannot remove your own role management access";
+pub const OAUTH_CLIENT_NOT_FOUND: &str = "oauth client not found";
+pub const INVALID_REDIRECT_URI: &str =
+    "redirect uris must be absolute and must not contain a fragment";
+pub const UNKNOWN_SCOPE: &str = "unknown scope";
+pub const OIDC_UNAVAILABLE: &str =
+    "openid connect requires an asymmetric JWT_ALGORITHM (RS256, ES256 or EdDSA)";
+pub const PUBLIC_CLIENT_SECRET: &str = "public clients have no secret";
+pub const SERVICE_ACCOUNT_NOT_FOUND: &str = "service account not found";
+pub const OWN_SERVICE_ACCOUNT: &str = "service accounts cannot manage themselves";
+pub const SCOPE_NOT_GRANTABLE: &str = "you can only grant permissions you hold";
+pub const USER_PRINCIPAL_REQUIRED: &str = "this action requires a user account";
+pub const SERVICE_PRINCIPAL_REQUIRED: &str = "this action requires a service account";
+pub const FIRST_PARTY_SESSION_REQUIRED: &str = "this action requires a first-party session";
+pub const SESSION_REQUIRED: &str = "this action requires a signed-in session, not an api key";
+pub const API_KEY_NOT_FOUND: &str = "api key not found";
+pub const INVALID_API_KEY_ID: &str = "invalid api key id";
+pub const INVALID_API_KEY: &str = "invalid or expired api key";
+pub const UNKNOWN_FEDERATED_PROVIDER: &str = "unknown sign-in provider";
+pub const INVALID_FEDERATED_STATE: &str = "invalid or expired sign-in state";
+pub const FEDERATED_LOGIN_FAILED: &str = "sign-in with the provider failed";
+pub const FEDERATED_EMAIL_REQUIRED: &str = "the provider did not share an email address";
+pub const FEDERATED_EMAIL_EXISTS: &str =
+    "an account with this email already exists, sign in and link the provider from your profile";
+pub const IDENTITY_ALREADY_LINKED: &str = "this identity is linked to another account";
+pub const PROVIDER_ALREADY_LINKED: &str = "an identity from this provider is already linked";
+pub const IDENTITY_NOT_FOUND: &str = "linked identity not found";
+pub const LAST_SIGN_IN_METHOD: &str =
+    "cannot unlink the only way to sign in, set a password or add a passkey first";
+pub const PASSWORD_MANAGED_BY_DIRECTORY: &str =
+    "the password of this account is managed by the directory";
+pub const PASSWORD_POLICY_VIOLATED: &str = "password does not meet the requirements";
+pub const PASSWORD_TOO_LONG_FOR_BCRYPT: &str = "password must be at most 72 bytes";
+pub const SCIM_NOT_CONFIGURED: &str = "SCIM provisioning is not enabled";
+pub const INVALID_SCIM_FILTER: &str = "invalid filter";
+pub const INVALID_SCIM_PATH: &str = "unsupported attribute path";
+pub const INVALID_SCIM_VALUE: &str = "invalid attribute value";
+pub const INVALID_SCIM_OPERATION: &str = "patch op must be add, replace or remove";
+pub const SCIM_USER_NAME_REQUIRED: &str = "userName must be an email address";
+pub const SCIM_RESOURCE_NOT_FOUND: &str = "resource not found";
 pub const INTERNAL_SERVER_ERROR: &str = "internal server error";
 
 pub const MONGO_URI: &str = "MONGO_URI";
 pub const MONGO_DB: &str = "MONGO_DB";
 pub const JWT_SECRET: &str = "JWT_SECRET";
-pub const JWT_EXP_HOURS: &str = "JWT_EXP_HOURS";
+pub const JWT_ALGORITHM: &str = "JWT_ALGORITHM";
+pub const JWT_PRIVATE_KEY_PATH: &str = "JWT_PRIVATE_KEY_PATH";
+pub const JWT_KEY_ID: &str = "JWT_KEY_ID";
+pub const SIGNING_KEY_ENCRYPTION_KEY: &str = "SIGNING_KEY_ENCRYPTION_KEY";
+pub const ACCESS_TOKEN_EXP_MINUTES: &str = "ACCESS_TOKEN_EXP_MINUTES";
+pub const JWT_EXP_HOURS: &str = "JWT_EXP_HOURS"; // deprecated
+pub const REFRESH_TOKEN_EXP_DAYS: &str = "REFRESH_TOKEN_EXP_DAYS";
 pub const APP_HOST: &str = "APP_HOST";
 pub const APP_PORT: &str = "APP_PORT";
 pub const REDIS_URI: &str = "REDIS_URI";
 pub const SSL_CERT_PATH: &str = "SSL_CERT_PATH";
 pub const SSL_KEY_PATH: &str = "SSL_KEY_PATH";
+pub const TRUSTED_PROXIES: &str = "TRUSTED_PROXIES";
+pub const MFA_ISSUER: &str = "MFA_ISSUER";
+pub const WEBAUTHN_RP_ID: &str = "WEBAUTHN_RP_ID";
+pub const WEBAUTHN_RP_NAME: &str = "WEBAUTHN_RP_NAME";
+pub const WEBAUTHN_ORIGIN: &str = "WEBAUTHN_ORIGIN";
+pub const WEBAUTHN_REQUIRE_USER_VERIFICATION: &str = "WEBAUTHN_REQUIRE_USER_VERIFICATION";
+pub const REQUIRE_EMAIL_VERIFICATION: &str = "REQUIRE_EMAIL_VERIFICATION";
+pub const APP_PUBLIC_URL: &str = "APP_PUBLIC_URL";
+pub const OIDC_ISSUER: &str = "OIDC_ISSUER";
+pub const MAIL_FROM: &str = "MAIL_FROM";
+pub const MAIL_OUTBOX_DIR: &str = "MAIL_OUTBOX_DIR";
+pub const MAIL_TRANSPORT: &str = "MAIL_TRANSPORT";
+pub const MAIL_DEFAULT_LOCALE: &str = "MAIL_DEFAULT_LOCALE";
+pub const MAIL_TEMPLATE_DIR: &str = "MAIL_TEMPLATE_DIR";
+pub const SMTP_HOST: &str = "SMTP_HOST";
+pub const SMTP_PORT: &str = "SMTP_PORT";
+pub const SMTP_USERNAME: &str = "SMTP_USERNAME";
+pub const SMTP_PASSWORD: &str = "SMTP_PASSWORD";
+pub const SMTP_TLS: &str = "SMTP_TLS";
+pub const LOGIN_MAX_FAILURES: &str = "LOGIN_MAX_FAILURES";
+pub const LOGIN_IP_MAX_FAILURES: &str = "LOGIN_IP_MAX_FAILURES";
+pub const LOGIN_LOCKOUT_MINUTES: &str = "LOGIN_LOCKOUT_MINUTES";
+pub const RATE_LIMIT_AUTH: &str = "RATE_LIMIT_AUTH";
+pub const RATE_LIMIT_USER: &str = "RATE_LIMIT_USER";
+pub const RATE_LIMIT_ADMIN: &str = "RATE_LIMIT_ADMIN";
+pub const FEDERATED_PROVIDERS: &str = "FEDERATED_PROVIDERS";
+pub const FEDERATED_REDIRECT_URL: &str = "FEDERATED_REDIRECT_URL";
+pub const AUTH_BACKENDS: &str = "AUTH_BACKENDS";
+pub const LDAP_URL: &str = "LDAP_URL";
+pub const LDAP_STARTTLS: &str = "LDAP_STARTTLS";
+pub const LDAP_BIND_DN: &str = "LDAP_BIND_DN";
+pub const LDAP_BIND_PASSWORD: &str = "LDAP_BIND_PASSWORD";
+pub const LDAP_USER_BASE_DN: &str = "LDAP_USER_BASE_DN";
+pub const LDAP_USER_FILTER: &str = "LDAP_USER_FILTER";
+pub const LDAP_EMAIL_ATTRIBUTE: &str = "LDAP_EMAIL_ATTRIBUTE";
+pub const LDAP_USERNAME_ATTRIBUTE: &str = "LDAP_USERNAME_ATTRIBUTE";
+pub const LDAP_GROUP_ATTRIBUTE: &str = "LDAP_GROUP_ATTRIBUTE";
+pub const LDAP_GROUP_ROLES: &str = "LDAP_GROUP_ROLES";
+pub const SCIM_TOKEN: &str = "SCIM_TOKEN";
+pub const PASSWORD_HASH: &str = "PASSWORD_HASH";
+pub const ARGON2_MEMORY_KIB: &str = "ARGON2_MEMORY_KIB";
+pub const ARGON2_ITERATIONS: &str = "ARGON2_ITERATIONS";
+pub const ARGON2_PARALLELISM: &str = "ARGON2_PARALLELISM";
+pub const BCRYPT_COST: &str = "BCRYPT_COST";
+pub const PASSWORD_MIN_LENGTH: &str = "PASSWORD_MIN_LENGTH";
+pub const PASSWORD_MAX_LENGTH: &str = "PASSWORD_MAX_LENGTH";
+pub const PASSWORD_MIN_CLASSES: &str = "PASSWORD_MIN_CLASSES";
+pub const PASSWORD_MIN_SCORE: &str = "PASSWORD_MIN_SCORE";
+pub const PASSWORD_BREACHED_DIR: &str = "PASSWORD_BREACHED_DIR";
+pub const PASSWORD_HISTORY: &str = "PASSWORD_HISTORY";
+pub const PASSWORD_MAX_AGE_DAYS: &str = "PASSWORD_MAX_AGE_DAYS";
@@ -1,10 +1,29 @@
-use crate::constants::COLLECTION_USERS;
+use crate::constants::{
+    BUILTIN_ADMIN_ROLE, COLLECTION_API_KEYS, COLLECTION_FEDERATED_IDENTITIES,
+    COLLECTION_INVITATIONS, COLLECTION_MEMBERSHIPS, COLLECTION_OAUTH_CLIENTS,
+    COLLECTION_ORGANIZATIONS, COLLECTION_ORG_INVITATIONS, COLLECTION_PASSKEYS, COLLECTION_ROLES,
+    COLLECTION_SERVICE_ACCOUNTS, COLLECTION_SESSIONS, COLLECTION_SIGNING_KEYS, COLLECTION_USERS,
+    SESSION_TTL_INDEX,
+};
 use crate::errors::AppError;
+use crate::models::api_key::ApiKey;
+use crate::models::federated_identity::FederatedIdentity;
+use crate::models::invitation::Invitation;
+use crate::models::oauth_client::OAuthClient;
+use crate::models::organization::{Membership, OrgInvitation, OrgRole, Organization};
+use crate::models::passkey::Passkey;
+use crate::models::role::Role;
+use crate::models::service_account::ServiceAccount;
+use crate::models::session::Session;
+use crate::models::signing_key::SigningKey;
 use crate::models::user::User;
-use mongodb::bson::doc;
+use futures::stream::TryStreamExt;
 use mongodb::bson::oid::ObjectId;
-use mongodb::options::ClientOptions;
-use mongodb::{Client, Collection, Database};
+use mongodb::bson::DateTime;
+use mongodb::bson::{doc, Document};
+use mongodb::options::{ClientOptions, FindOptions, IndexOptions};
+use mongodb::{Client, Collection, Database, IndexModel};
+use std::time::Duration;
 
 pub async fn init_mongodb(uri: &str, db_name: &str) -> mongodb::error::Result<Database> {
     let mut client_options = ClientOptions::parse(uri).await?;
@@ -25,6 +44,15 @@ impl UserRepository {
         }
     }
 
+    /// Indexes the fields accounts are looked up by.
+    pub async fn ensure_indexes(&self) -> Result<(), AppError> {
+        let indexes = ["email", "ldap_dn", "external_id"]
+            .into_iter()
+            .map(|field| IndexModel::builder().keys(doc! { field: 1 }).build());
+        self.collection.create_indexes(indexes).await?;
+        Ok(())
+    }
+
     pub async fn find_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
         Ok(self.collection.find_one(doc! { "email": email }).await?)
     }
@@ -33,6 +61,15 @@ impl UserRepository {
         Ok(self.collection.find_one(doc! { "_id": id }).await?)
     }
 
+    pub async fn find_by_ldap_dn(&self, dn: &str) -> Result<Option<User>, AppError> {
+        Ok(self.collection.find_one(doc! { "ldap_dn": dn }).await?)
+    }
+
+    pub async fn find_by_ids(&self, ids: &[ObjectId]) -> Result<Vec<User>, AppError> {
+        let cursor = self.collection.find(doc! { "_id": { "$in": ids } }).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
     pub async fn find_all(&self) -> Result<Vec<User>, AppError> {
         use mongodb::bson::doc;
         let mut cursor = self.collection.find(doc! {}).await?;
@@ -44,21 +81,106 @@ impl UserRepository {
         Ok(users)
     }
 
+    /// Users matching `filter` in creation order, for paged listings.
+    pub async fn find_page(
+        &self,
+        filter: Document,
+        skip: u64,
+        limit: i64,
+    ) -> Result<Vec<User>, AppError> {
+        let options = FindOptions::builder()
+            .sort(doc! { "_id": 1 })
+            .skip(skip)
+            .limit(limit)
+            .build();
+        let cursor = self.collection.find(filter).with_options(options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    pub async fn count(&self, filter: Document) -> Result<u64, AppError> {
+        Ok(self.collection.count_documents(filter).await?)
+    }
+
+    pub async fn find_by_role(&self, role: &str) -> Result<Vec<User>, AppError> {
+        let cursor = self.collection.find(doc! { "roles": role }).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    pub async fn replace(&self, user: &User) -> Result<(), AppError> {
+        self.collection
+            .replace_one(doc! { "_id": user.id }, user)
+            .await?;
+        Ok(())
+    }
+
     pub async fn delete_by_id(&self, id: &ObjectId) -> Result<(), AppError> {
         self.collection.delete_one(doc! { "_id": id }).await?;
         Ok(())
     }
 
-    pub async fn set_admin(&self, id: &ObjectId, is_admin: bool) -> Result<(), AppError> {
+    pub async fn set_roles(&self, id: &ObjectId, roles: &[String]) -> Result<(), AppError> {
         self.collection
-            .update_one(
-                doc! { "_id": id },
-                doc! { "$set": { "is_admin": is_admin } },
+            .update_one(doc! { "_id": id }, doc! { "$set": { "roles": roles } })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_role(&self, role: &str) -> Result<(), AppError> {
+        self.collection
+            .update_many(doc! { "roles": role }, doc! { "$pull": { "roles": role } })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn add_role_to(&self, ids: &[ObjectId], role: &str) -> Result<(), AppError> {
+        self.collection
+            .update_many(
+                doc! { "_id": { "$in": ids } },
+                doc! { "$addToSet": { "roles": role } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_role_from(&self, ids: &[ObjectId], role: &str) -> Result<(), AppError> {
+        self.collection
+            .update_many(
+                doc! { "_id": { "$in": ids } },
+                doc! { "$pull": { "roles": role } },
             )
             .await?;
         Ok(())
     }
 
+    pub async fn rename_role(&self, from: &str, to: &str) -> Result<(), AppError> {
+        self.collection
+            .update_many(doc! { "roles": from }, doc! { "$set": { "roles.$": to } })
+            .await?;
+        Ok(())
+    }
+
+    /// Replaces the legacy `is_admin` flag with membership of the built-in
+    /// admin role. Returns how many admins were migrated.
+    pub async fn migrate_admin_flag(&self) -> Result<u64, AppError> {
+        let admins = self
+            .collection
+            .update_many(
+                doc! { "is_admin": true },
+                doc! {
+                    "$addToSet": { "roles": BUILTIN_ADMIN_ROLE },
+                    "$unset": { "is_admin": "" },
+                },
+            )
+            .await?;
+        self.collection
+            .update_many(
+                doc! { "is_admin": { "$exists": true } },
+                doc! { "$unset": { "is_admin": "" } },
+            )
+            .await?;
+        Ok(admins.modified_count)
+    }
+
     pub async fn create(&self, user: &User) -> Result<(), AppError> {
         self.collection.insert_one(user).await?;
         Ok(())
@@ -66,11 +188,30 @@ impl UserRepository {
 
     pub async fn update_email(&self, id: &ObjectId, new_email: &str) -> Result<(), AppError> {
         self.collection
-            .update_one(doc! { "_id": id }, doc! { "$set": { "email": new_email } })
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": {
+                    "email": new_email,
+                    "email_verified": false,
+                    "email_verified_at": null,
+                } },
+            )
             .await?;
         Ok(())
     }
 
+    /// Marks the email as verified, provided it is still the user's address.
+    pub async fn mark_email_verified(&self, id: &ObjectId, email: &str) -> Result<bool, AppError> {
+        let result = self
+            .collection
+            .update_one(
+                doc! { "_id": id, "email": email },
+                doc! { "$set": { "email_verified": true, "email_verified_at": DateTime::now() } },
+            )
+            .await?;
+        Ok(result.matched_count > 0)
+    }
+
     pub async fn update_username(&self, id: &ObjectId, new_username: &str) -> Result<(), AppError> {
         self.collection
             .update_one(
@@ -81,15 +222,33 @@ impl UserRepository {
         Ok(())
     }
 
-    pub async fn update_password(
+    /// Saves a changed password along with its history and change time.
+    pub async fn update_password(&self, user: &User) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": user.id },
+                doc! { "$set": {
+                    "password_hash": &user.password_hash,
+                    "password_history": &user.password_history,
+                    "password_changed_at": user.password_changed_at,
+                } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Swaps in a rehash of the same password, unless the password was
+    /// changed in the meantime.
+    pub async fn rehash_password(
         &self,
         id: &ObjectId,
-        password_hash: &str,
+        old_hash: &str,
+        new_hash: &str,
     ) -> Result<(), AppError> {
         self.collection
             .update_one(
-                doc! { "_id": id },
-                doc! { "$set": { "password_hash": password_hash } },
+                doc! { "_id": id, "password_hash": old_hash },
+                doc! { "$set": { "password_hash": new_hash } },
             )
             .await?;
         Ok(())
@@ -108,4 +267,1053 @@ impl UserRepository {
             .await?;
         Ok(())
     }
+
+    pub async fn set_pending_totp(
+        &self,
+        id: &ObjectId,
+        secret: &str,
+        encrypted: bool,
+    ) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": {
+                    "mfa.totp_secret": secret,
+                    "mfa.totp_secret_encrypted": encrypted,
+                    "mfa.totp_enabled": false,
+                } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Users whose TOTP secret is stored in plaintext.
+    pub async fn find_plaintext_totp_secrets(&self) -> Result<Vec<User>, AppError> {
+        let cursor = self
+            .collection
+            .find(doc! {
+                "mfa.totp_secret": { "$type": "string" },
+                "mfa.totp_secret_encrypted": { "$ne": true },
+            })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Replaces a plaintext TOTP secret with its encrypted form, unless it
+    /// changed in the meantime.
+    pub async fn seal_totp_secret(
+        &self,
+        id: &ObjectId,
+        plaintext: &str,
+        sealed: &str,
+    ) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! {
+                    "_id": id,
+                    "mfa.totp_secret": plaintext,
+                    "mfa.totp_secret_encrypted": { "$ne": true },
+                },
+                doc! { "$set": {
+                    "mfa.totp_secret": sealed,
+                    "mfa.totp_secret_encrypted": true,
+                } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn enable_totp(
+        &self,
+        id: &ObjectId,
+        step: i64,
+        recovery_codes: &[String],
+    ) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": {
+                    "mfa.totp_enabled": true,
+                    "mfa.totp_last_step": step,
+                    "mfa.recovery_codes": recovery_codes,
+                } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Records the last accepted TOTP step. Returns `false` if an equal or later
+    /// step was already recorded, i.e. the code is being replayed.
+    pub async fn advance_totp_step(&self, id: &ObjectId, step: i64) -> Result<bool, AppError> {
+        let result = self
+            .collection
+            .update_one(
+                doc! {
+                    "_id": id,
+                    "$or": [
+                        { "mfa.totp_last_step": null },
+                        { "mfa.totp_last_step": { "$lt": step } },
+                    ],
+                },
+                doc! { "$set": { "mfa.totp_last_step": step } },
+            )
+            .await?;
+        Ok(result.modified_count > 0)
+    }
+
+    pub async fn consume_recovery_code(
+        &self,
+        id: &ObjectId,
+        code_hash: &str,
+    ) -> Result<bool, AppError> {
+        let result = self
+            .collection
+            .update_one(
+                doc! { "_id": id, "mfa.recovery_codes": code_hash },
+                doc! { "$pull": { "mfa.recovery_codes": code_hash } },
+            )
+            .await?;
+        Ok(result.modified_count > 0)
+    }
+
+    pub async fn set_recovery_codes(
+        &self,
+        id: &ObjectId,
+        recovery_codes: &[String],
+    ) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "mfa.recovery_codes": recovery_codes } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn clear_mfa(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "mfa": {
+                    "totp_secret": null,
+                    "totp_secret_encrypted": false,
+                    "totp_enabled": false,
+                    "totp_last_step": null,
+                    "recovery_codes": [],
+                } } },
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct SessionRepository {
+    collection: Collection<Session>,
+}
+
+impl SessionRepository {
+    pub fn new(db: &Database) -> Self {
+        Self {
+            collection: db.collection::<Session>(COLLECTION_SESSIONS),
+        }
+    }
+
+    /// Indexes sessions by user and has MongoDB delete those idle for longer
+    /// than `idle_ttl`, by which time their refresh tokens have expired.
+    pub async fn ensure_indexes(&self, idle_ttl: Duration) -> Result<(), AppError> {
+        self.collection
+            .create_index(IndexModel::builder().keys(doc! { "user_id": 1 }).build())
+            .await?;
+
+        let ttl_index = IndexModel::builder()
+            .keys(doc! { "last_seen_at": 1 })
+            .options(
+                IndexOptions::builder()
+                    .name(SESSION_TTL_INDEX.to_string())
+                    .expire_after(idle_ttl)
+                    .build(),
+            )
+            .build();
+        if self
+            .collection
+            .create_index(ttl_index.clone())
+            .await
+            .is_err()
+        {
+            // The index exists with another expiry, from an earlier setting
+            self.collection.drop_index(SESSION_TTL_INDEX).await?;
+            self.collection.create_index(ttl_index).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn create(&self, session: &Session) -> Result<(), AppError> {
+        self.collection.insert_one(session).await?;
+        Ok(())
+    }
+
+    pub async fn find_by_user(&self, user_id: &ObjectId) -> Result<Vec<Session>, AppError> {
+        let cursor = self.collection.find(doc! { "user_id": user_id }).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Updates `last_seen_at` and reports whether the session still exists.
+    pub async fn touch(&self, id: &ObjectId, user_id: &ObjectId) -> Result<bool, AppError> {
+        let result = self
+            .collection
+            .update_one(
+                doc! { "_id": id, "user_id": user_id },
+                doc! { "$set": { "last_seen_at": DateTime::now() } },
+            )
+            .await?;
+        Ok(result.matched_count > 0)
+    }
+
+    pub async fn delete(&self, id: &ObjectId, user_id: &ObjectId) -> Result<bool, AppError> {
+        let result = self
+            .collection
+            .delete_one(doc! { "_id": id, "user_id": user_id })
+            .await?;
+        Ok(result.deleted_count > 0)
+    }
+
+    pub async fn delete_by_user(&self, user_id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .delete_many(doc! { "user_id": user_id })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn find(
+        &self,
+        id: &ObjectId,
+        user_id: &ObjectId,
+    ) -> Result<Option<Session>, AppError> {
+        Ok(self
+            .collection
+            .find_one(doc! { "_id": id, "user_id": user_id })
+            .await?)
+    }
+
+    /// Ends every session granted to an OAuth client.
+    pub async fn delete_by_client(&self, client_id: &str) -> Result<(), AppError> {
+        self.collection
+            .delete_many(doc! { "client_id": client_id })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_org(
+        &self,
+        id: &ObjectId,
+        user_id: &ObjectId,
+        org_id: Option<&ObjectId>,
+    ) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id, "user_id": user_id },
+                doc! { "$set": { "org_id": org_id } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Drops `org_id` as the active organization of the user's sessions, or
+    /// of every session when `user_id` is `None`.
+    pub async fn clear_org(
+        &self,
+        org_id: &ObjectId,
+        user_id: Option<&ObjectId>,
+    ) -> Result<(), AppError> {
+        let mut filter = doc! { "org_id": org_id };
+        if let Some(user_id) = user_id {
+            filter.insert("user_id", user_id);
+        }
+        self.collection
+            .update_many(filter, doc! { "$set": { "org_id": null } })
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct PasskeyRepository {
+    collection: Collection<Passkey>,
+}
+
+impl PasskeyRepository {
+    pub fn new(db: &Database) -> Self {
+        Self {
+            collection: db.collection::<Passkey>(COLLECTION_PASSKEYS),
+        }
+    }
+
+    pub async fn create(&self, passkey: &Passkey) -> Result<(), AppError> {
+        self.collection.insert_one(passkey).await?;
+        Ok(())
+    }
+
+    pub async fn find_by_credential_id(
+        &self,
+        credential_id: &str,
+    ) -> Result<Option<Passkey>, AppError> {
+        Ok(self
+            .collection
+            .find_one(doc! { "credential_id": credential_id })
+            .await?)
+    }
+
+    pub async fn find_by_user(&self, user_id: &ObjectId) -> Result<Vec<Passkey>, AppError> {
+        let cursor = self.collection.find(doc! { "user_id": user_id }).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    pub async fn record_use(&self, id: &ObjectId, sign_count: i64) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "sign_count": sign_count, "last_used_at": DateTime::now() } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete(&self, id: &ObjectId, user_id: &ObjectId) -> Result<bool, AppError> {
+        let result = self
+            .collection
+            .delete_one(doc! { "_id": id, "user_id": user_id })
+            .await?;
+        Ok(result.deleted_count > 0)
+    }
+
+    pub async fn delete_by_user(&self, user_id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .delete_many(doc! { "user_id": user_id })
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct SigningKeyRepository {
+    collection: Collection<SigningKey>,
+}
+
+impl SigningKeyRepository {
+    pub fn new(db: &Database) -> Self {
+        Self {
+            collection: db.collection::<SigningKey>(COLLECTION_SIGNING_KEYS),
+        }
+    }
+
+    pub async fn create(&self, key: &SigningKey) -> Result<(), AppError> {
+        self.collection.insert_one(key).await?;
+        Ok(())
+    }
+
+    pub async fn find_all(&self) -> Result<Vec<SigningKey>, AppError> {
+        let options = FindOptions::builder()
+            .sort(doc! { "activates_at": 1 })
+            .build();
+        let cursor = self.collection.find(doc! {}).with_options(options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Replaces plaintext material with its encrypted form.
+    pub async fn seal_material(&self, id: &ObjectId, sealed: &str) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id, "encrypted": { "$ne": true } },
+                doc! { "$set": { "material": sealed, "encrypted": true } },
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct RoleRepository {
+    collection: Collection<Role>,
+}
+
+impl RoleRepository {
+    pub fn new(db: &Database) -> Self {
+        Self {
+            collection: db.collection::<Role>(COLLECTION_ROLES),
+        }
+    }
+
+    /// Creates a built-in role or resets its permissions to `permissions`.
+    pub async fn ensure_built_in(&self, name: &str, permissions: &[&str]) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "name": name },
+                doc! {
+                    "$set": { "permissions": permissions, "built_in": true },
+                    "$setOnInsert": {
+                        "_id": ObjectId::new(),
+                        "description": null,
+                        "created_at": DateTime::now(),
+                    },
+                },
+            )
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn create(&self, role: &Role) -> Result<(), AppError> {
+        self.collection.insert_one(role).await?;
+        Ok(())
+    }
+
+    pub async fn find_all(&self) -> Result<Vec<Role>, AppError> {
+        let options = FindOptions::builder().sort(doc! { "name": 1 }).build();
+        let cursor = self.collection.find(doc! {}).with_options(options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    pub async fn find_by_id(&self, id: &ObjectId) -> Result<Option<Role>, AppError> {
+        Ok(self.collection.find_one(doc! { "_id": id }).await?)
+    }
+
+    pub async fn find_by_name(&self, name: &str) -> Result<Option<Role>, AppError> {
+        Ok(self.collection.find_one(doc! { "name": name }).await?)
+    }
+
+    /// Roles matching `filter` in creation order, for paged listings.
+    pub async fn find_page(
+        &self,
+        filter: Document,
+        skip: u64,
+        limit: i64,
+    ) -> Result<Vec<Role>, AppError> {
+        let options = FindOptions::builder()
+            .sort(doc! { "_id": 1 })
+            .skip(skip)
+            .limit(limit)
+            .build();
+        let cursor = self.collection.find(filter).with_options(options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    pub async fn count(&self, filter: Document) -> Result<u64, AppError> {
+        Ok(self.collection.count_documents(filter).await?)
+    }
+
+    /// Renames the role itself; user assignments are renamed separately.
+    pub async fn rename(&self, id: &ObjectId, name: &str) -> Result<(), AppError> {
+        self.collection
+            .update_one(doc! { "_id": id }, doc! { "$set": { "name": name } })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn find_by_names(&self, names: &[String]) -> Result<Vec<Role>, AppError> {
+        let cursor = self
+            .collection
+            .find(doc! { "name": { "$in": names } })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Whether any of `roles` grants `permission`.
+    pub async fn grants(&self, roles: &[String], permission: &str) -> Result<bool, AppError> {
+        if roles.is_empty() {
+            return Ok(false);
+        }
+        let count = self
+            .collection
+            .count_documents(doc! { "name": { "$in": roles }, "permissions": permission })
+            .await?;
+        Ok(count > 0)
+    }
+
+    pub async fn update(
+        &self,
+        name: &str,
+        description: Option<&str>,
+        permissions: Option<&[String]>,
+    ) -> Result<(), AppError> {
+        let mut set = doc! {};
+        if let Some(description) = description {
+            set.insert("description", description);
+        }
+        if let Some(permissions) = permissions {
+            set.insert("permissions", permissions);
+        }
+        if set.is_empty() {
+            return Ok(());
+        }
+        self.collection
+            .update_one(doc! { "name": name }, doc! { "$set": set })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete(&self, name: &str) -> Result<(), AppError> {
+        self.collection.delete_one(doc! { "name": name }).await?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct OrganizationRepository {
+    collection: Collection<Organization>,
+}
+
+impl OrganizationRepository {
+    pub fn new(db: &Database) -> Self {
+        Self {
+            collection: db.collection::<Organization>(COLLECTION_ORGANIZATIONS),
+        }
+    }
+
+    pub async fn create(&self, org: &Organization) -> Result<(), AppError> {
+        self.collection.insert_one(org).await?;
+        Ok(())
+    }
+
+    pub async fn find_by_id(&self, id: &ObjectId) -> Result<Option<Organization>, AppError> {
+        Ok(self.collection.find_one(doc! { "_id": id }).await?)
+    }
+
+    pub async fn find_by_ids(&self, ids: &[ObjectId]) -> Result<Vec<Organization>, AppError> {
+        let options = FindOptions::builder().sort(doc! { "name": 1 }).build();
+        let cursor = self
+            .collection
+            .find(doc! { "_id": { "$in": ids } })
+            .with_options(options)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    pub async fn delete(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.collection.delete_one(doc! { "_id": id }).await?;
+        Ok(())
+    }
+}
+
+/// Every query is scoped by `org_id`, so one tenant can never read or change
+/// another tenant's members.
+#[derive(Clone)]
+pub struct MembershipRepository {
+    collection: Collection<Membership>,
+}
+
+impl MembershipRepository {
+    pub fn new(db: &Database) -> Self {
+        Self {
+            collection: db.collection::<Membership>(COLLECTION_MEMBERSHIPS),
+        }
+    }
+
+    pub async fn create(&self, membership: &Membership) -> Result<(), AppError> {
+        self.collection.insert_one(membership).await?;
+        Ok(())
+    }
+
+    pub async fn find(
+        &self,
+        org_id: &ObjectId,
+        user_id: &ObjectId,
+    ) -> Result<Option<Membership>, AppError> {
+        Ok(self
+            .collection
+            .find_one(doc! { "org_id": org_id, "user_id": user_id })
+            .await?)
+    }
+
+    pub async fn find_by_org(&self, org_id: &ObjectId) -> Result<Vec<Membership>, AppError> {
+        let options = FindOptions::builder().sort(doc! { "joined_at": 1 }).build();
+        let cursor = self
+            .collection
+            .find(doc! { "org_id": org_id })
+            .with_options(options)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    pub async fn find_by_user(&self, user_id: &ObjectId) -> Result<Vec<Membership>, AppError> {
+        let cursor = self.collection.find(doc! { "user_id": user_id }).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    pub async fn count_owners(&self, org_id: &ObjectId) -> Result<u64, AppError> {
+        Ok(self
+            .collection
+            .count_documents(doc! { "org_id": org_id, "role": "owner" })
+            .await?)
+    }
+
+    pub async fn update_role(
+        &self,
+        org_id: &ObjectId,
+        user_id: &ObjectId,
+        role: OrgRole,
+    ) -> Result<(), AppError> {
+        let role = mongodb::bson::to_bson(&role).map_err(|_| AppError::Internal)?;
+        self.collection
+            .update_one(
+                doc! { "org_id": org_id, "user_id": user_id },
+                doc! { "$set": { "role": role } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete(&self, org_id: &ObjectId, user_id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .delete_one(doc! { "org_id": org_id, "user_id": user_id })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_by_org(&self, org_id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .delete_many(doc! { "org_id": org_id })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_by_user(&self, user_id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .delete_many(doc! { "user_id": user_id })
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct OrgInvitationRepository {
+    collection: Collection<OrgInvitation>,
+}
+
+impl OrgInvitationRepository {
+    pub fn new(db: &Database) -> Self {
+        Self {
+            collection: db.collection::<OrgInvitation>(COLLECTION_ORG_INVITATIONS),
+        }
+    }
+
+    pub async fn create(&self, invitation: &OrgInvitation) -> Result<(), AppError> {
+        self.collection.insert_one(invitation).await?;
+        Ok(())
+    }
+
+    /// Pending invitations of the organization, newest first.
+    pub async fn find_by_org(&self, org_id: &ObjectId) -> Result<Vec<OrgInvitation>, AppError> {
+        let options = FindOptions::builder()
+            .sort(doc! { "created_at": -1 })
+            .build();
+        let cursor = self
+            .collection
+            .find(doc! { "org_id": org_id, "expires_at": { "$gt": DateTime::now() } })
+            .with_options(options)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    pub async fn find_by_token_hash(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<OrgInvitation>, AppError> {
+        Ok(self
+            .collection
+            .find_one(doc! { "token_hash": token_hash, "expires_at": { "$gt": DateTime::now() } })
+            .await?)
+    }
+
+    pub async fn delete(&self, org_id: &ObjectId, id: &ObjectId) -> Result<bool, AppError> {
+        let result = self
+            .collection
+            .delete_one(doc! { "_id": id, "org_id": org_id })
+            .await?;
+        Ok(result.deleted_count > 0)
+    }
+
+    pub async fn delete_by_org(&self, org_id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .delete_many(doc! { "org_id": org_id })
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct InvitationRepository {
+    collection: Collection<Invitation>,
+}
+
+impl InvitationRepository {
+    pub fn new(db: &Database) -> Self {
+        Self {
+            collection: db.collection::<Invitation>(COLLECTION_INVITATIONS),
+        }
+    }
+
+    pub async fn create(&self, invitation: &Invitation) -> Result<(), AppError> {
+        self.collection.insert_one(invitation).await?;
+        Ok(())
+    }
+
+    /// Unexpired invitations, newest first.
+    pub async fn find_pending(&self) -> Result<Vec<Invitation>, AppError> {
+        let options = FindOptions::builder()
+            .sort(doc! { "created_at": -1 })
+            .build();
+        let cursor = self
+            .collection
+            .find(doc! { "expires_at": { "$gt": DateTime::now() } })
+            .with_options(options)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    /// Removes and returns the invitation if it is still pending and was
+    /// issued with this token id, so each invitation is accepted at most once.
+    pub async fn take(&self, id: &ObjectId, jti: &str) -> Result<Option<Invitation>, AppError> {
+        Ok(self
+            .collection
+            .find_one_and_delete(doc! {
+                "_id": id,
+                "jti": jti,
+                "expires_at": { "$gt": DateTime::now() },
+            })
+            .await?)
+    }
+
+    pub async fn delete(&self, id: &ObjectId) -> Result<bool, AppError> {
+        let result = self.collection.delete_one(doc! { "_id": id }).await?;
+        Ok(result.deleted_count > 0)
+    }
+
+    pub async fn delete_by_email(&self, email: &str) -> Result<(), AppError> {
+        self.collection.delete_many(doc! { "email": email }).await?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct OAuthClientRepository {
+    collection: Collection<OAuthClient>,
+}
+
+impl OAuthClientRepository {
+    pub fn new(db: &Database) -> Self {
+        Self {
+            collection: db.collection::<OAuthClient>(COLLECTION_OAUTH_CLIENTS),
+        }
+    }
+
+    pub async fn create(&self, client: &OAuthClient) -> Result<(), AppError> {
+        self.collection.insert_one(client).await?;
+        Ok(())
+    }
+
+    pub async fn find_all(&self) -> Result<Vec<OAuthClient>, AppError> {
+        let options = FindOptions::builder().sort(doc! { "name": 1 }).build();
+        let cursor = self.collection.find(doc! {}).with_options(options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    pub async fn find_by_client_id(
+        &self,
+        client_id: &str,
+    ) -> Result<Option<OAuthClient>, AppError> {
+        Ok(self
+            .collection
+            .find_one(doc! { "client_id": client_id })
+            .await?)
+    }
+
+    pub async fn update(
+        &self,
+        client_id: &str,
+        name: Option<&str>,
+        redirect_uris: Option<&[String]>,
+        scopes: Option<&[String]>,
+    ) -> Result<(), AppError> {
+        let mut set = doc! {};
+        if let Some(name) = name {
+            set.insert("name", name);
+        }
+        if let Some(redirect_uris) = redirect_uris {
+            set.insert("redirect_uris", redirect_uris);
+        }
+        if let Some(scopes) = scopes {
+            set.insert("scopes", scopes);
+        }
+        if set.is_empty() {
+            return Ok(());
+        }
+        self.collection
+            .update_one(doc! { "client_id": client_id }, doc! { "$set": set })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_secret_hash(
+        &self,
+        client_id: &str,
+        secret_hash: &str,
+    ) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "client_id": client_id },
+                doc! { "$set": { "secret_hash": secret_hash } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete(&self, client_id: &str) -> Result<bool, AppError> {
+        let result = self
+            .collection
+            .delete_one(doc! { "client_id": client_id })
+            .await?;
+        Ok(result.deleted_count > 0)
+    }
+}
+
+#[derive(Clone)]
+pub struct ServiceAccountRepository {
+    collection: Collection<ServiceAccount>,
+}
+
+impl ServiceAccountRepository {
+    pub fn new(db: &Database) -> Self {
+        Self {
+            collection: db.collection::<ServiceAccount>(COLLECTION_SERVICE_ACCOUNTS),
+        }
+    }
+
+    pub async fn create(&self, account: &ServiceAccount) -> Result<(), AppError> {
+        self.collection.insert_one(account).await?;
+        Ok(())
+    }
+
+    pub async fn find_all(&self) -> Result<Vec<ServiceAccount>, AppError> {
+        let options = FindOptions::builder().sort(doc! { "name": 1 }).build();
+        let cursor = self.collection.find(doc! {}).with_options(options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    pub async fn find_by_client_id(
+        &self,
+        client_id: &str,
+    ) -> Result<Option<ServiceAccount>, AppError> {
+        Ok(self
+            .collection
+            .find_one(doc! { "client_id": client_id })
+            .await?)
+    }
+
+    /// Applies the changes. Changing the scopes or disabling the account
+    /// revokes its outstanding tokens.
+    pub async fn update(
+        &self,
+        client_id: &str,
+        name: Option<&str>,
+        scopes: Option<&[String]>,
+        disabled: Option<bool>,
+    ) -> Result<(), AppError> {
+        let mut set = doc! {};
+        if let Some(name) = name {
+            set.insert("name", name);
+        }
+        if let Some(scopes) = scopes {
+            set.insert("scopes", scopes);
+        }
+        if let Some(disabled) = disabled {
+            set.insert("disabled", disabled);
+        }
+        if set.is_empty() {
+            return Ok(());
+        }
+        let mut update = doc! { "$set": set };
+        if scopes.is_some() || disabled == Some(true) {
+            update.insert("$inc", doc! { "token_version": 1 });
+        }
+        self.collection
+            .update_one(doc! { "client_id": client_id }, update)
+            .await?;
+        Ok(())
+    }
+
+    /// Replaces the secret and revokes every token issued with the old one.
+    pub async fn rotate_secret(&self, client_id: &str, secret_hash: &str) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "client_id": client_id },
+                doc! {
+                    "$set": { "secret_hash": secret_hash },
+                    "$inc": { "token_version": 1 },
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn record_use(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "last_used_at": DateTime::now() } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete(&self, client_id: &str) -> Result<bool, AppError> {
+        let result = self
+            .collection
+            .delete_one(doc! { "client_id": client_id })
+            .await?;
+        Ok(result.deleted_count > 0)
+    }
+}
+
+#[derive(Clone)]
+pub struct ApiKeyRepository {
+    collection: Collection<ApiKey>,
+}
+
+impl ApiKeyRepository {
+    pub fn new(db: &Database) -> Self {
+        Self {
+            collection: db.collection::<ApiKey>(COLLECTION_API_KEYS),
+        }
+    }
+
+    pub async fn create(&self, key: &ApiKey) -> Result<(), AppError> {
+        self.collection.insert_one(key).await?;
+        Ok(())
+    }
+
+    pub async fn find_all(&self) -> Result<Vec<ApiKey>, AppError> {
+        let options = FindOptions::builder()
+            .sort(doc! { "created_at": -1 })
+            .build();
+        let cursor = self.collection.find(doc! {}).with_options(options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    pub async fn find_by_user(&self, user_id: &ObjectId) -> Result<Vec<ApiKey>, AppError> {
+        let options = FindOptions::builder()
+            .sort(doc! { "created_at": -1 })
+            .build();
+        let cursor = self
+            .collection
+            .find(doc! { "user_id": user_id })
+            .with_options(options)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    pub async fn find_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>, AppError> {
+        Ok(self
+            .collection
+            .find_one(doc! { "key_hash": key_hash })
+            .await?)
+    }
+
+    pub async fn record_use(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "last_used_at": DateTime::now() } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Deletes the key, restricted to one owner when `user_id` is given.
+    pub async fn delete(
+        &self,
+        id: &ObjectId,
+        user_id: Option<&ObjectId>,
+    ) -> Result<bool, AppError> {
+        let mut filter = doc! { "_id": id };
+        if let Some(user_id) = user_id {
+            filter.insert("user_id", user_id);
+        }
+        let result = self.collection.delete_one(filter).await?;
+        Ok(result.deleted_count > 0)
+    }
+
+    pub async fn delete_by_user(&self, user_id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .delete_many(doc! { "user_id": user_id })
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct FederatedIdentityRepository {
+    collection: Collection<FederatedIdentity>,
+}
+
+impl FederatedIdentityRepository {
+    pub fn new(db: &Database) -> Self {
+        Self {
+            collection: db.collection::<FederatedIdentity>(COLLECTION_FEDERATED_IDENTITIES),
+        }
+    }
+
+    pub async fn create(&self, identity: &FederatedIdentity) -> Result<(), AppError> {
+        self.collection.insert_one(identity).await?;
+        Ok(())
+    }
+
+    pub async fn find(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<FederatedIdentity>, AppError> {
+        Ok(self
+            .collection
+            .find_one(doc! { "provider": provider, "subject": subject })
+            .await?)
+    }
+
+    pub async fn find_by_user(
+        &self,
+        user_id: &ObjectId,
+    ) -> Result<Vec<FederatedIdentity>, AppError> {
+        let options = FindOptions::builder().sort(doc! { "provider": 1 }).build();
+        let cursor = self
+            .collection
+            .find(doc! { "user_id": user_id })
+            .with_options(options)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    pub async fn record_login(&self, id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "last_login_at": DateTime::now() } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete(&self, user_id: &ObjectId, provider: &str) -> Result<bool, AppError> {
+        let result = self
+            .collection
+            .delete_one(doc! { "user_id": user_id, "provider": provider })
+            .await?;
+        Ok(result.deleted_count > 0)
+    }
+
+    pub async fn delete_by_user(&self, user_id: &ObjectId) -> Result<(), AppError> {
+        self.collection
+            .delete_many(doc! { "user_id": user_id })
+            .await?;
+        Ok(())
+    }
 }
@@ -1,5 +1,7 @@
+use crate::config::rate_limit_config::{RateLimitAlgorithm, RateLimitPolicy};
 use crate::errors::AppError;
-use redis::{Client, aio::ConnectionManager};
+use redis::{aio::ConnectionManager, Client};
+use serde::{Deserialize, Serialize};
 
 pub async fn init_redis(uri: &str) -> Result<ConnectionManager, AppError> {
     let client = Client::open(uri).map_err(|_| AppError::Internal)?;
@@ -40,3 +42,446 @@ impl TokenBlacklist {
         Ok(result.is_some())
     }
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshTokenRecord {
+    pub user_id: String,
+    pub family_id: String,
+    pub token_version: i32,
+}
+
+pub enum RefreshOutcome {
+    Valid(RefreshTokenRecord),
+    Reused(RefreshTokenRecord),
+    Invalid,
+}
+
+#[derive(Clone)]
+pub struct RefreshTokenStore {
+    conn: ConnectionManager,
+}
+
+impl RefreshTokenStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+
+    /// Stores a refresh token. The family's lifetime starts with its first
+    /// token and is not extended by rotation, so no token outlives it.
+    pub async fn store(
+        &self,
+        token_hash: &str,
+        record: &RefreshTokenRecord,
+        exp_seconds: i64,
+    ) -> Result<(), AppError> {
+        let mut conn = self.conn.clone();
+        let family_key = format!("refresh_family:{}", record.family_id);
+        let (family_ttl,): (i64,) = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(&family_key)
+            .arg(&record.user_id)
+            .arg("NX")
+            .arg("EX")
+            .arg(exp_seconds)
+            .ignore()
+            .cmd("TTL")
+            .arg(&family_key)
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)?;
+
+        let value = serde_json::to_string(record).map_err(|_| AppError::Internal)?;
+        redis::cmd("SETEX")
+            .arg(format!("refresh:{}", token_hash))
+            .arg(exp_seconds.min(family_ttl).max(1))
+            .arg(value)
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)
+    }
+
+    /// Marks the token as used and returns its record. A token that has already
+    /// been used signals theft, so its whole family is revoked.
+    pub async fn consume(&self, token_hash: &str) -> Result<RefreshOutcome, AppError> {
+        let mut conn = self.conn.clone();
+        let value: Option<String> = redis::cmd("GET")
+            .arg(format!("refresh:{}", token_hash))
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)?;
+
+        let record: RefreshTokenRecord = match value {
+            Some(v) => serde_json::from_str(&v).map_err(|_| AppError::Internal)?,
+            None => return Ok(RefreshOutcome::Invalid),
+        };
+
+        let ttl: i64 = redis::cmd("TTL")
+            .arg(format!("refresh:{}", token_hash))
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)?;
+
+        let first_use: Option<String> = redis::cmd("SET")
+            .arg(format!("refresh_used:{}", token_hash))
+            .arg("1")
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl.max(1))
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)?;
+
+        if first_use.is_none() {
+            self.revoke_family(&record.family_id).await?;
+            return Ok(RefreshOutcome::Reused(record));
+        }
+
+        let family_active: bool = redis::cmd("EXISTS")
+            .arg(format!("refresh_family:{}", record.family_id))
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)?;
+
+        if !family_active {
+            return Ok(RefreshOutcome::Invalid);
+        }
+
+        Ok(RefreshOutcome::Valid(record))
+    }
+
+    /// Looks a token up without using it.
+    pub async fn find(&self, token_hash: &str) -> Result<Option<RefreshTokenRecord>, AppError> {
+        let mut conn = self.conn.clone();
+        let value: Option<String> = redis::cmd("GET")
+            .arg(format!("refresh:{}", token_hash))
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)?;
+
+        value
+            .map(|v| serde_json::from_str(&v).map_err(|_| AppError::Internal))
+            .transpose()
+    }
+
+    pub async fn revoke_family(&self, family_id: &str) -> Result<(), AppError> {
+        let mut conn = self.conn.clone();
+        redis::cmd("DEL")
+            .arg(format!("refresh_family:{}", family_id))
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)
+    }
+}
+
+/// Short-lived, single-use tokens (e.g. MFA challenges) keyed by purpose and
+/// token hash.
+#[derive(Clone)]
+pub struct OneTimeTokenStore {
+    conn: ConnectionManager,
+}
+
+fn user_tokens_key(user_id: &str) -> String {
+    format!("one_time_user:{}", user_id)
+}
+
+impl OneTimeTokenStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+
+    pub async fn issue(
+        &self,
+        purpose: &str,
+        token_hash: &str,
+        value: &str,
+        exp_seconds: i64,
+    ) -> Result<(), AppError> {
+        let mut conn = self.conn.clone();
+        redis::cmd("SETEX")
+            .arg(format!("{}:{}", purpose, token_hash))
+            .arg(exp_seconds)
+            .arg(value)
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)
+    }
+
+    /// Issues a token acting for `user_id`, recording it so that
+    /// [`revoke_user`](Self::revoke_user) can withdraw it.
+    pub async fn issue_for_user(
+        &self,
+        purpose: &str,
+        token_hash: &str,
+        value: &str,
+        exp_seconds: i64,
+        user_id: &str,
+    ) -> Result<(), AppError> {
+        let mut conn = self.conn.clone();
+        let key = format!("{}:{}", purpose, token_hash);
+        let index = user_tokens_key(user_id);
+        let (index_ttl,): (i64,) = redis::pipe()
+            .atomic()
+            .cmd("SETEX")
+            .arg(&key)
+            .arg(exp_seconds)
+            .arg(value)
+            .ignore()
+            .cmd("SADD")
+            .arg(&index)
+            .arg(&key)
+            .ignore()
+            .cmd("TTL")
+            .arg(&index)
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)?;
+
+        // The index lives as long as the longest-lived token in it
+        if index_ttl < exp_seconds {
+            redis::cmd("EXPIRE")
+                .arg(&index)
+                .arg(exp_seconds)
+                .query_async::<()>(&mut conn)
+                .await
+                .map_err(|_| AppError::Internal)?;
+        }
+        Ok(())
+    }
+
+    /// Withdraws every token issued for the user that is still pending.
+    pub async fn revoke_user(&self, user_id: &str) -> Result<(), AppError> {
+        let mut conn = self.conn.clone();
+        let index = user_tokens_key(user_id);
+        let keys: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(&index)
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        redis::cmd("DEL")
+            .arg(keys)
+            .arg(&index)
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)
+    }
+
+    pub async fn peek(&self, purpose: &str, token_hash: &str) -> Result<Option<String>, AppError> {
+        let mut conn = self.conn.clone();
+        redis::cmd("GET")
+            .arg(format!("{}:{}", purpose, token_hash))
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)
+    }
+
+    pub async fn take(&self, purpose: &str, token_hash: &str) -> Result<Option<String>, AppError> {
+        let mut conn = self.conn.clone();
+        redis::cmd("GETDEL")
+            .arg(format!("{}:{}", purpose, token_hash))
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)
+    }
+
+    /// Counts a failed attempt against the token and returns the new total.
+    pub async fn record_failure(
+        &self,
+        purpose: &str,
+        token_hash: &str,
+        exp_seconds: i64,
+    ) -> Result<i64, AppError> {
+        let mut conn = self.conn.clone();
+        let key = format!("{}_failures:{}", purpose, token_hash);
+        let (count,): (i64,) = redis::pipe()
+            .atomic()
+            .cmd("INCR")
+            .arg(&key)
+            .cmd("EXPIRE")
+            .arg(&key)
+            .arg(exp_seconds)
+            .ignore()
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        Ok(count)
+    }
+}
+
+/// Failed login counters and temporary locks, keyed by a subject such as
+/// `account:{email}` or `ip:{address}`.
+#[derive(Clone)]
+pub struct LoginAttemptStore {
+    conn: ConnectionManager,
+}
+
+impl LoginAttemptStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+
+    pub fn account(email: &str) -> String {
+        format!("account:{}", email.to_lowercase())
+    }
+
+    pub fn ip(address: &str) -> String {
+        format!("ip:{}", address)
+    }
+
+    /// Counts a failed attempt and returns the number of failures seen within
+    /// `window_seconds` of each other.
+    pub async fn record_failure(
+        &self,
+        subject: &str,
+        window_seconds: i64,
+    ) -> Result<i64, AppError> {
+        let mut conn = self.conn.clone();
+        let key = format!("login_failures:{}", subject);
+        let (count,): (i64,) = redis::pipe()
+            .atomic()
+            .cmd("INCR")
+            .arg(&key)
+            .cmd("EXPIRE")
+            .arg(&key)
+            .arg(window_seconds)
+            .ignore()
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        Ok(count)
+    }
+
+    pub async fn lock(&self, subject: &str, seconds: i64) -> Result<(), AppError> {
+        let mut conn = self.conn.clone();
+        redis::cmd("SETEX")
+            .arg(format!("login_lock:{}", subject))
+            .arg(seconds)
+            .arg("1")
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)
+    }
+
+    /// Returns the remaining lock time in seconds, if the subject is locked.
+    pub async fn locked_for(&self, subject: &str) -> Result<Option<i64>, AppError> {
+        let mut conn = self.conn.clone();
+        let ttl: i64 = redis::cmd("TTL")
+            .arg(format!("login_lock:{}", subject))
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        Ok((ttl > 0).then_some(ttl))
+    }
+
+    /// Clears both the failure counter and any lock.
+    pub async fn reset(&self, subject: &str) -> Result<(), AppError> {
+        let mut conn = self.conn.clone();
+        redis::cmd("DEL")
+            .arg(format!("login_failures:{}", subject))
+            .arg(format!("login_lock:{}", subject))
+            .query_async(&mut conn)
+            .await
+            .map_err(|_| AppError::Internal)
+    }
+}
+
+/// Outcome of counting one request against a rate limit. Times are in
+/// milliseconds.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub remaining: u64,
+    pub reset_ms: u64,       // until the full quota is available again
+    pub retry_after_ms: u64, // until the next request is allowed
+}
+
+// Both scripts take KEYS[1] = key, ARGV = [limit, window_ms] and return
+// {allowed, remaining, reset_ms, retry_after_ms}, using the Redis clock so all
+// instances agree on time.
+const TOKEN_BUCKET_SCRIPT: &str = r"
+redis.replicate_commands()
+local limit = tonumber(ARGV[1])
+local window_ms = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = time[1] * 1000 + math.floor(time[2] / 1000)
+local rate = limit / window_ms
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(state[1]) or limit
+local ts = tonumber(state[2]) or now
+tokens = math.min(limit, tokens + math.max(0, now - ts) * rate)
+local allowed = 0
+local wait = 0
+if tokens >= 1 then
+  tokens = tokens - 1
+  allowed = 1
+else
+  wait = math.ceil((1 - tokens) / rate)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', now)
+redis.call('PEXPIRE', KEYS[1], window_ms)
+return {allowed, math.floor(tokens), math.ceil((limit - tokens) / rate), wait}
+";
+
+const SLIDING_WINDOW_SCRIPT: &str = r"
+redis.replicate_commands()
+local limit = tonumber(ARGV[1])
+local window_ms = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = time[1] * 1000 + math.floor(time[2] / 1000)
+local window = math.floor(now / window_ms)
+local into_window = now - window * window_ms
+local elapsed = into_window / window_ms
+local current_key = KEYS[1] .. ':' .. string.format('%d', window)
+local previous = tonumber(redis.call('GET', KEYS[1] .. ':' .. string.format('%d', window - 1))) or 0
+local current = tonumber(redis.call('GET', current_key)) or 0
+local estimate = previous * (1 - elapsed) + current
+local until_next = window_ms - into_window
+if estimate + 1 > limit then
+  local wait = until_next
+  if current + 1 <= limit and previous > 0 then
+    wait = math.ceil((1 - (limit - current - 1) / previous - elapsed) * window_ms)
+  end
+  return {0, 0, until_next, math.max(wait, 1)}
+end
+redis.call('INCR', current_key)
+redis.call('PEXPIRE', current_key, window_ms * 2)
+return {1, math.floor(limit - estimate - 1), until_next, 0}
+";
+
+#[derive(Clone)]
+pub struct RateLimitStore {
+    conn: ConnectionManager,
+}
+
+impl RateLimitStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+
+    /// Counts a request against `policy` under `key`.
+    pub async fn hit(
+        &self,
+        key: &str,
+        policy: &RateLimitPolicy,
+    ) -> Result<RateLimitDecision, AppError> {
+        let mut conn = self.conn.clone();
+        let script = match policy.algorithm {
+            RateLimitAlgorithm::TokenBucket => TOKEN_BUCKET_SCRIPT,
+            RateLimitAlgorithm::SlidingWindow => SLIDING_WINDOW_SCRIPT,
+        };
+        let (allowed, remaining, reset_ms, retry_after_ms): (i64, i64, i64, i64) =
+            redis::Script::new(script)
+                .key(format!("ratelimit:{}", key))
+                .arg(policy.limit)
+                .arg(policy.window_seconds * 1000)
+                .invoke_async(&mut conn)
+                .await?;
+
+        Ok(RateLimitDecision {
+            allowed: allowed == 1,
+            remaining: remaining.max(0) as u64,
+            reset_ms: reset_ms.max(0) as u64,
+            retry_after_ms: retry_after_ms.max(0) as u64,
+        })
+    }
+}
@@ -1,5 +1,8 @@
-use crate::constants::{INTERNAL_SERVER_ERROR, INVALID_USER_ID};
-use crate::models::response::Response;
+use crate::constants::{
+    INTERNAL_SERVER_ERROR, INVALID_USER_ID, PASSWORD_POLICY_VIOLATED, SCIM_CONTENT_TYPE,
+    SCIM_SCHEMA_ERROR,
+};
+use crate::models::response::{PasswordPolicyErrors, PasswordViolation, Response};
 
 use actix_web::{http::StatusCode, HttpResponse, ResponseError};
 use thiserror::Error;
@@ -17,6 +20,12 @@ pub enum AppError {
     NotFound(String),
     #[error("Conflict: {0}")]
     Conflict(String),
+    #[error("Locked: {0}")]
+    Locked(String, i64), // message, seconds until unlocked
+    #[error("TooManyRequests: {0}")]
+    TooManyRequests(String, i64), // message, seconds until retry
+    #[error("PasswordPolicy: {0:?}")]
+    PasswordPolicy(Vec<PasswordViolation>),
     #[error("Database error: {0}")]
     Database(#[from] mongodb::error::Error),
     #[error("Redis error: {0}")]
@@ -37,12 +46,34 @@ impl ResponseError for AppError {
             HttpResponse::build(status).json(Response::<()> { msg, data: None })
         }
 
+        fn with_retry_after(mut response: HttpResponse, seconds: i64) -> HttpResponse {
+            if let Ok(value) = seconds.to_string().parse() {
+                response
+                    .headers_mut()
+                    .insert(actix_web::http::header::RETRY_AFTER, value);
+            }
+            response
+        }
+
         match self {
             AppError::BadRequest(msg) => json_error(StatusCode::BAD_REQUEST, msg.into()),
             AppError::Unauthorized(msg) => json_error(StatusCode::UNAUTHORIZED, msg.into()),
             AppError::Forbidden(msg) => json_error(StatusCode::FORBIDDEN, msg.into()),
             AppError::NotFound(msg) => json_error(StatusCode::NOT_FOUND, msg.into()),
             AppError::Conflict(msg) => json_error(StatusCode::CONFLICT, msg.into()),
+            AppError::Locked(msg, retry_after) => {
+                with_retry_after(json_error(StatusCode::LOCKED, msg.into()), *retry_after)
+            }
+            AppError::TooManyRequests(msg, retry_after) => with_retry_after(
+                json_error(StatusCode::TOO_MANY_REQUESTS, msg.into()),
+                *retry_after,
+            ),
+            AppError::PasswordPolicy(violations) => HttpResponse::BadRequest().json(Response {
+                msg: PASSWORD_POLICY_VIOLATED.into(),
+                data: Some(PasswordPolicyErrors {
+                    violations: violations.clone(),
+                }),
+            }),
             AppError::Database(e) => {
                 error!("Database error: {:?}", e);
                 json_error(
@@ -67,3 +98,150 @@ impl ResponseError for AppError {
         }
     }
 }
+
+/// An error from the OAuth endpoints, reported in the RFC 6749 shape
+/// (`{"error", "error_description"}`) instead of the response envelope.
+#[derive(Debug, Error)]
+#[error("{error}: {description}")]
+pub struct OAuthError {
+    pub error: &'static str,
+    pub description: String,
+}
+
+impl OAuthError {
+    pub fn new(error: &'static str, description: impl Into<String>) -> Self {
+        Self {
+            error,
+            description: description.into(),
+        }
+    }
+}
+
+impl From<AppError> for OAuthError {
+    fn from(e: AppError) -> Self {
+        match e {
+            AppError::BadRequest(msg) => OAuthError::new("invalid_request", msg),
+            AppError::Unauthorized(msg)
+            | AppError::Forbidden(msg)
+            | AppError::NotFound(msg)
+            | AppError::Conflict(msg)
+            | AppError::Locked(msg, _)
+            | AppError::TooManyRequests(msg, _) => OAuthError::new("invalid_grant", msg),
+            e => {
+                error!("OAuth endpoint failed: {:?}", e);
+                OAuthError::new("server_error", INTERNAL_SERVER_ERROR)
+            }
+        }
+    }
+}
+
+impl ResponseError for OAuthError {
+    fn status_code(&self) -> StatusCode {
+        match self.error {
+            "invalid_client" | "invalid_token" => StatusCode::UNAUTHORIZED,
+            "insufficient_scope" => StatusCode::FORBIDDEN,
+            "server_error" => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        response.insert_header((actix_web::http::header::CACHE_CONTROL, "no-store"));
+        match self.error {
+            "invalid_client" => {
+                response.insert_header((
+                    actix_web::http::header::WWW_AUTHENTICATE,
+                    "Basic realm=\"oauth\"",
+                ));
+            }
+            "invalid_token" | "insufficient_scope" => {
+                response.insert_header((
+                    actix_web::http::header::WWW_AUTHENTICATE,
+                    format!("Bearer error=\"{}\"", self.error),
+                ));
+            }
+            _ => {}
+        }
+        response.json(serde_json::json!({
+            "error": self.error,
+            "error_description": self.description,
+        }))
+    }
+}
+
+/// An error from the SCIM endpoints, reported in the RFC 7644 shape
+/// (`{"schemas", "status", "scimType", "detail"}`) instead of the response
+/// envelope.
+#[derive(Debug, Error)]
+#[error("{status}: {detail}")]
+pub struct ScimError {
+    pub status: StatusCode,
+    pub scim_type: Option<&'static str>,
+    pub detail: String,
+}
+
+impl ScimError {
+    pub fn new(status: StatusCode, detail: impl Into<String>) -> Self {
+        Self {
+            status,
+            scim_type: None,
+            detail: detail.into(),
+        }
+    }
+
+    pub fn bad_request(scim_type: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            scim_type: Some(scim_type),
+            detail: detail.into(),
+        }
+    }
+}
+
+impl From<AppError> for ScimError {
+    fn from(e: AppError) -> Self {
+        match e {
+            AppError::BadRequest(msg) => ScimError::bad_request("invalidValue", msg),
+            AppError::PasswordPolicy(violations) => {
+                let messages: Vec<String> = violations.into_iter().map(|v| v.message).collect();
+                ScimError::bad_request("invalidValue", messages.join("; "))
+            }
+            AppError::Unauthorized(msg) => ScimError::new(StatusCode::UNAUTHORIZED, msg),
+            AppError::Forbidden(msg) => ScimError::new(StatusCode::FORBIDDEN, msg),
+            AppError::NotFound(msg) => ScimError::new(StatusCode::NOT_FOUND, msg),
+            AppError::Conflict(msg) => ScimError {
+                status: StatusCode::CONFLICT,
+                scim_type: Some("uniqueness"),
+                detail: msg,
+            },
+            AppError::Locked(msg, _) | AppError::TooManyRequests(msg, _) => {
+                ScimError::new(StatusCode::TOO_MANY_REQUESTS, msg)
+            }
+            e => {
+                error!("SCIM endpoint failed: {:?}", e);
+                ScimError::new(StatusCode::INTERNAL_SERVER_ERROR, INTERNAL_SERVER_ERROR)
+            }
+        }
+    }
+}
+
+impl ResponseError for ScimError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut body = serde_json::json!({
+            "schemas": [SCIM_SCHEMA_ERROR],
+            "status": self.status.as_u16().to_string(),
+            "detail": self.detail,
+        });
+        if let Some(scim_type) = self.scim_type {
+            body["scimType"] = scim_type.into();
+        }
+        HttpResponse::build(self.status)
+            .content_type(SCIM_CONTENT_TYPE)
+            .json(body)
+    }
+}
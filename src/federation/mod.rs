@@ -0,0 +1,468 @@
+use crate::config::app_config::AppConfig;
+use crate::config::federation_config::FederatedProvider;
+use crate::constants::{
+    FEDERATED_HTTP_TIMEOUT_SECONDS, FEDERATED_LOGIN_FAILED, UNKNOWN_FEDERATED_PROVIDER,
+};
+use crate::errors::AppError;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// The endpoints we use from a provider's discovery document.
+#[derive(Clone, Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UpstreamClaims {
+    sub: String,
+    nonce: Option<String>,
+    email: Option<String>,
+    email_verified: Option<Value>, // some providers send "true" as a string
+    name: Option<String>,
+    preferred_username: Option<String>,
+}
+
+/// Who the provider says signed in, taken from a verified id token.
+pub struct UpstreamIdentity {
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub username: Option<String>, // preferred_username, else name
+}
+
+fn failed(context: &str, error: impl std::fmt::Display) -> AppError {
+    tracing::warn!("Federated sign-in failed, {}: {}", context, error);
+    AppError::Unauthorized(FEDERATED_LOGIN_FAILED.into())
+}
+
+/// Talks to upstream OpenID Connect providers. Discovery documents are
+/// cached; signing keys are fetched for every sign-in so that key rotations
+/// are picked up.
+#[derive(Clone)]
+pub struct FederationClient {
+    http: reqwest::Client,
+    public_url: String,
+    providers: Arc<Vec<FederatedProvider>>,
+    metadata: Arc<RwLock<HashMap<String, ProviderMetadata>>>,
+}
+
+impl FederationClient {
+    pub fn new(cfg: &AppConfig) -> Result<Self, String> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(FEDERATED_HTTP_TIMEOUT_SECONDS))
+            .build()
+            .map_err(|e| format!("Failed to build HTTP client: {}", e))?;
+
+        Ok(Self {
+            http,
+            public_url: cfg.public_url.clone(),
+            providers: Arc::new(cfg.federated_providers.clone()),
+            metadata: Arc::new(RwLock::new(HashMap::new())),
+        })
+    }
+
+    pub fn providers(&self) -> &[FederatedProvider] {
+        &self.providers
+    }
+
+    pub fn provider(&self, name: &str) -> Result<&FederatedProvider, AppError> {
+        self.providers
+            .iter()
+            .find(|p| p.name == name)
+            .ok_or_else(|| AppError::NotFound(UNKNOWN_FEDERATED_PROVIDER.into()))
+    }
+
+    /// Where the provider sends the browser back to.
+    pub fn callback_url(&self, provider: &FederatedProvider) -> String {
+        format!(
+            "{}/auth/federated/{}/callback",
+            self.public_url, provider.name
+        )
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, AppError> {
+        self.http
+            .get(url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| failed(url, e))?
+            .json()
+            .await
+            .map_err(|e| failed(url, e))
+    }
+
+    async fn metadata(&self, provider: &FederatedProvider) -> Result<ProviderMetadata, AppError> {
+        if let Some(metadata) = self
+            .metadata
+            .read()
+            .map_err(|_| AppError::Internal)?
+            .get(&provider.name)
+        {
+            return Ok(metadata.clone());
+        }
+
+        let url = format!("{}/.well-known/openid-configuration", provider.issuer);
+        let metadata: ProviderMetadata = self.get_json(&url).await?;
+        if metadata.issuer.trim_end_matches('/') != provider.issuer {
+            return Err(failed(
+                &url,
+                format!("issuer {} does not match", metadata.issuer),
+            ));
+        }
+
+        self.metadata
+            .write()
+            .map_err(|_| AppError::Internal)?
+            .insert(provider.name.clone(), metadata.clone());
+        Ok(metadata)
+    }
+
+    /// The provider's authorization url for a code flow request with PKCE.
+    pub async fn authorization_url(
+        &self,
+        provider: &FederatedProvider,
+        state: &str,
+        nonce: &str,
+        code_challenge: &str,
+    ) -> Result<String, AppError> {
+        let metadata = self.metadata(provider).await?;
+        let query = serde_urlencoded::to_string([
+            ("response_type", "code"),
+            ("client_id", provider.client_id.as_str()),
+            ("redirect_uri", &self.callback_url(provider)),
+            ("scope", provider.scopes.as_str()),
+            ("state", state),
+            ("nonce", nonce),
+            ("code_challenge", code_challenge),
+            ("code_challenge_method", "S256"),
+        ])
+        .map_err(|_| AppError::Internal)?;
+
+        let separator = if metadata.authorization_endpoint.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        Ok(format!(
+            "{}{}{}",
+            metadata.authorization_endpoint, separator, query
+        ))
+    }
+
+    /// Redeems the authorization code and verifies the returned id token:
+    /// its signature against the provider's keys, issuer, audience, expiry
+    /// and the nonce sent with the authorization request.
+    pub async fn sign_in(
+        &self,
+        provider: &FederatedProvider,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<UpstreamIdentity, AppError> {
+        let metadata = self.metadata(provider).await?;
+        let response: TokenResponse = self
+            .http
+            .post(&metadata.token_endpoint)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.callback_url(provider)),
+                ("client_id", &provider.client_id),
+                ("client_secret", &provider.client_secret),
+                ("code_verifier", code_verifier),
+            ])
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| failed(&metadata.token_endpoint, e))?
+            .json()
+            .await
+            .map_err(|e| failed(&metadata.token_endpoint, e))?;
+        let id_token = response
+            .id_token
+            .ok_or_else(|| failed(&metadata.token_endpoint, "no id_token returned"))?;
+
+        let header = decode_header(&id_token).map_err(|e| failed("id_token", e))?;
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(failed("id_token", "symmetric signatures are not accepted"));
+        }
+
+        let jwks: JwkSet = self.get_json(&metadata.jwks_uri).await?;
+        let jwk = match header.kid {
+            Some(ref kid) => jwks.find(kid),
+            None if jwks.keys.len() == 1 => jwks.keys.first(),
+            None => None,
+        }
+        .ok_or_else(|| failed("id_token", "signing key not found"))?;
+        let key = DecodingKey::from_jwk(jwk).map_err(|e| failed("id_token", e))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&provider.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let claims = decode::<UpstreamClaims>(&id_token, &key, &validation)
+            .map_err(|e| failed("id_token", e))?
+            .claims;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(failed("id_token", "nonce mismatch"));
+        }
+
+        let email_verified = match claims.email_verified {
+            Some(Value::Bool(verified)) => verified,
+            Some(Value::String(ref verified)) => verified == "true",
+            _ => false,
+        };
+        Ok(UpstreamIdentity {
+            subject: claims.sub,
+            email: claims.email,
+            email_verified,
+            username: claims.preferred_username.or(claims.name),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::jwt_keys::JwtKey;
+    use actix_web::web::{Data, Form};
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+    use std::net::TcpListener;
+    use std::sync::Mutex;
+
+    const CODE: &str = "issued-code";
+    const NONCE: &str = "expected-nonce";
+
+    /// A local OpenID provider serving discovery, its keys and a token
+    /// endpoint that redeems `CODE` for whatever id token the test queued.
+    struct MockIssuer {
+        url: String,
+        key: JwtKey,
+        id_token: Arc<Mutex<String>>,
+    }
+
+    async fn discovery(issuer: Data<String>) -> HttpResponse {
+        HttpResponse::Ok().json(json!({
+            "issuer": issuer.as_str(),
+            "authorization_endpoint": format!("{}/authorize", issuer.as_str()),
+            "token_endpoint": format!("{}/token", issuer.as_str()),
+            "jwks_uri": format!("{}/jwks", issuer.as_str()),
+        }))
+    }
+
+    async fn jwks(keys: Data<JwkSet>) -> HttpResponse {
+        HttpResponse::Ok().json(keys.get_ref())
+    }
+
+    async fn token(
+        id_token: Data<Arc<Mutex<String>>>,
+        form: Form<HashMap<String, String>>,
+    ) -> HttpResponse {
+        let field = |name: &str| form.get(name).map(String::as_str);
+        if field("grant_type") != Some("authorization_code")
+            || field("code") != Some(CODE)
+            || field("code_verifier").is_none()
+        {
+            return HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" }));
+        }
+        let id_token = id_token.lock().unwrap().clone();
+        HttpResponse::Ok().json(json!({ "id_token": id_token, "token_type": "Bearer" }))
+    }
+
+    impl MockIssuer {
+        async fn start() -> Self {
+            let key = JwtKey::generate(Algorithm::ES256).unwrap().0;
+            let id_token = Arc::new(Mutex::new(String::new()));
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+
+            let issuer = Data::new(url.clone());
+            let keys = Data::new(JwkSet {
+                keys: vec![key.jwk.clone().unwrap()],
+            });
+            let queued = Data::new(id_token.clone());
+            let server = HttpServer::new(move || {
+                App::new()
+                    .app_data(issuer.clone())
+                    .app_data(keys.clone())
+                    .app_data(queued.clone())
+                    .route(
+                        "/.well-known/openid-configuration",
+                        web::get().to(discovery),
+                    )
+                    .route("/jwks", web::get().to(jwks))
+                    .route("/token", web::post().to(token))
+            })
+            .workers(1)
+            .listen(listener)
+            .unwrap()
+            .run();
+            actix_web::rt::spawn(server);
+
+            Self { url, key, id_token }
+        }
+
+        fn provider(&self) -> FederatedProvider {
+            FederatedProvider {
+                name: "mock".into(),
+                issuer: self.url.clone(),
+                client_id: "server".into(),
+                client_secret: "secret".into(),
+                scopes: "openid email".into(),
+            }
+        }
+
+        fn claims(&self) -> Value {
+            json!({
+                "iss": self.url,
+                "aud": "server",
+                "sub": "upstream-user",
+                "exp": time::OffsetDateTime::now_utc().unix_timestamp() + 300,
+                "nonce": NONCE,
+                "email": "alice@example.com",
+                "email_verified": "true",
+                "preferred_username": "alice",
+            })
+        }
+
+        fn queue(&self, claims: Value) {
+            let mut header = Header::new(Algorithm::ES256);
+            header.kid = Some(self.key.kid.clone());
+            *self.id_token.lock().unwrap() = encode(&header, &claims, &self.key.encoding).unwrap();
+        }
+    }
+
+    fn client() -> FederationClient {
+        // Done once in main; reqwest is built without a default provider
+        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+        FederationClient {
+            http: reqwest::Client::new(),
+            public_url: "https://auth.example.com".into(),
+            providers: Arc::new(Vec::new()),
+            metadata: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    #[actix_web::test]
+    async fn code_is_redeemed_for_a_verified_identity() {
+        let issuer = MockIssuer::start().await;
+        let provider = issuer.provider();
+        let client = client();
+
+        let url = client
+            .authorization_url(&provider, "the-state", NONCE, "the-challenge")
+            .await
+            .unwrap();
+        assert!(url.starts_with(&format!("{}/authorize?response_type=code", issuer.url)));
+        assert!(url.contains("state=the-state"));
+        assert!(url.contains("code_challenge=the-challenge&code_challenge_method=S256"));
+        assert!(url.contains(
+            "redirect_uri=https%3A%2F%2Fauth.example.com%2Fauth%2Ffederated%2Fmock%2Fcallback"
+        ));
+
+        issuer.queue(issuer.claims());
+        let identity = client
+            .sign_in(&provider, CODE, "verifier", NONCE)
+            .await
+            .unwrap();
+        assert_eq!(identity.subject, "upstream-user");
+        assert_eq!(identity.email.as_deref(), Some("alice@example.com"));
+        assert!(identity.email_verified);
+        assert_eq!(identity.username.as_deref(), Some("alice"));
+
+        let unknown_code = client.sign_in(&provider, "other", "verifier", NONCE).await;
+        assert!(matches!(unknown_code, Err(AppError::Unauthorized(_))));
+    }
+
+    #[actix_web::test]
+    async fn nonce_must_match() {
+        let issuer = MockIssuer::start().await;
+        issuer.queue(issuer.claims());
+
+        let result = client()
+            .sign_in(&issuer.provider(), CODE, "verifier", "another-nonce")
+            .await;
+        assert!(matches!(result, Err(AppError::Unauthorized(_))));
+
+        let mut claims = issuer.claims();
+        claims.as_object_mut().unwrap().remove("nonce");
+        issuer.queue(claims);
+        let result = client()
+            .sign_in(&issuer.provider(), CODE, "verifier", NONCE)
+            .await;
+        assert!(matches!(result, Err(AppError::Unauthorized(_))));
+    }
+
+    #[actix_web::test]
+    async fn audience_and_issuer_must_match() {
+        let issuer = MockIssuer::start().await;
+
+        for (claim, value) in [
+            ("aud", json!("another-client")),
+            ("iss", json!("https://evil.example.com")),
+        ] {
+            let mut claims = issuer.claims();
+            claims[claim] = value;
+            issuer.queue(claims);
+
+            let result = client()
+                .sign_in(&issuer.provider(), CODE, "verifier", NONCE)
+                .await;
+            assert!(
+                matches!(result, Err(AppError::Unauthorized(_))),
+                "{}",
+                claim
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn symmetric_and_foreign_signatures_are_refused() {
+        let issuer = MockIssuer::start().await;
+        let claims = issuer.claims();
+
+        // Signed with the client secret, which the provider does not publish
+        *issuer.id_token.lock().unwrap() = encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+        let result = client()
+            .sign_in(&issuer.provider(), CODE, "verifier", NONCE)
+            .await;
+        assert!(matches!(result, Err(AppError::Unauthorized(_))));
+
+        // Right kid, wrong key
+        let other = JwtKey::generate(Algorithm::ES256).unwrap().0;
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some(issuer.key.kid.clone());
+        *issuer.id_token.lock().unwrap() = encode(&header, &claims, &other.encoding).unwrap();
+        let result = client()
+            .sign_in(&issuer.provider(), CODE, "verifier", NONCE)
+            .await;
+        assert!(matches!(result, Err(AppError::Unauthorized(_))));
+    }
+}
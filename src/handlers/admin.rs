@@ -1,19 +1,36 @@
-use crate::auth::AdminUser;
+use crate::auth::{KeysManage, RequirePermission, RolesManage, UsersDelete, UsersRead, UsersWrite};
+use crate::config::app_config::AppConfig;
+use crate::config::jwt_keys::JwtKey;
 use crate::constants::*;
-use crate::database::mongodb::UserRepository;
+use crate::database::mongodb::{
+    ApiKeyRepository, FederatedIdentityRepository, MembershipRepository, PasskeyRepository,
+    RoleRepository, SessionRepository, SigningKeyRepository, UserRepository,
+};
+use crate::database::redis::{LoginAttemptStore, OneTimeTokenStore, RefreshTokenStore};
 use crate::errors::AppError;
-use crate::models::request::{CreateUserRequest, SetRoleRequest, UpdateUserRequest};
-use crate::models::response::{Response, UserInfo};
-use crate::models::user::User;
+use crate::handlers::api_key::admin_api_key_scope;
+use crate::handlers::auth::{end_sessions, sign_out_everywhere};
+use crate::handlers::invitation::invitation_scope;
+use crate::handlers::oauth_client::oauth_client_scope;
+use crate::handlers::role::{check_roles, role_scope};
+use crate::handlers::service_account::service_account_scope;
+use crate::models::request::{CreateUserRequest, SetUserRolesRequest, UpdateUserRequest};
+use crate::models::response::{Response, SigningKeyInfo, UserInfo};
+use crate::models::signing_key::SigningKey;
+use crate::models::user::{Mfa, User};
 use crate::utils::password::hash_password;
+use crate::utils::password_policy::{check_password, check_password_change, set_password_hash};
+use crate::utils::token::reload_keyring;
 use actix_web::web::{Data, Json, Path};
 use actix_web::{delete, get, post, put, HttpResponse, Scope};
 use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use time::OffsetDateTime;
 use validator::Validate;
 
 #[get("/users")]
 async fn get_all_users(
-    _admin: AdminUser,
+    _admin: RequirePermission<UsersRead>,
     user_repo: Data<UserRepository>,
 ) -> Result<HttpResponse, AppError> {
     let users = user_repo.find_all().await?;
@@ -24,7 +41,8 @@ async fn get_all_users(
             id: u.id.to_hex(),
             email: u.email,
             username: u.username,
-            is_admin: u.is_admin,
+            roles: u.roles,
+            email_verified: u.email_verified,
         })
         .collect();
 
@@ -36,8 +54,10 @@ async fn get_all_users(
 
 #[post("/users")]
 async fn create_user(
-    _admin: AdminUser,
+    _admin: RequirePermission<UsersWrite>,
+    cfg: Data<AppConfig>,
     user_repo: Data<UserRepository>,
+    role_repo: Data<RoleRepository>,
     payload: Json<CreateUserRequest>,
 ) -> Result<HttpResponse, AppError> {
     payload
@@ -47,16 +67,32 @@ async fn create_user(
     if user_repo.find_by_email(&payload.email).await?.is_some() {
         return Err(AppError::Conflict(EMAIL_ALREADY_EXISTS.into()));
     }
+    check_roles(&role_repo, &payload.roles).await?;
+    check_password(
+        &cfg.password_policy,
+        &payload.password,
+        &[&payload.email, &payload.username],
+    )
+    .await?;
 
-    let password_hash = hash_password(&payload.password)?;
+    let password_hash = hash_password(&cfg.password_hashing, &payload.password).await?;
 
     let user = User {
         id: ObjectId::new(),
         email: payload.email.clone(),
         username: payload.username.clone(),
         password_hash,
-        is_admin: payload.is_admin,
+        password_history: Vec::new(),
+        password_changed_at: Some(DateTime::now()),
+        roles: payload.roles.clone(),
         token_version: 0,
+        disabled: false,
+        mfa: Mfa::default(),
+        // the admin vouches for addresses they enter
+        email_verified: true,
+        email_verified_at: Some(DateTime::now()),
+        ldap_dn: None,
+        external_id: None,
     };
 
     user_repo.create(&user).await?;
@@ -69,7 +105,7 @@ async fn create_user(
 
 #[get("/users/{id}")]
 async fn get_user_by_id(
-    _admin: AdminUser,
+    _admin: RequirePermission<UsersRead>,
     user_repo: Data<UserRepository>,
     id: Path<String>,
 ) -> Result<HttpResponse, AppError> {
@@ -87,14 +123,16 @@ async fn get_user_by_id(
             id: user.id.to_hex(),
             email: user.email,
             username: user.username,
-            is_admin: user.is_admin,
+            roles: user.roles,
+            email_verified: user.email_verified,
         }),
     }))
 }
 
 #[put("/users/{id}")]
 async fn update_user(
-    _admin: AdminUser,
+    _admin: RequirePermission<UsersWrite>,
+    cfg: Data<AppConfig>,
     user_repo: Data<UserRepository>,
     id: Path<String>,
     payload: Json<UpdateUserRequest>,
@@ -111,6 +149,18 @@ async fn update_user(
         .await?
         .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
 
+    // The password is checked against the details it will be stored with
+    let mut updated = user.clone();
+    if let Some(ref email) = payload.email {
+        updated.email = email.clone();
+    }
+    if let Some(ref username) = payload.username {
+        updated.username = username.clone();
+    }
+    if let Some(ref password) = payload.password {
+        check_password_change(&cfg.password_policy, &updated, password).await?;
+    }
+
     if let Some(ref email) = payload.email {
         if email != &user.email {
             if user_repo.find_by_email(email).await?.is_some() {
@@ -125,10 +175,9 @@ async fn update_user(
     }
 
     if let Some(ref password) = payload.password {
-        let password_hash = hash_password(password)?;
-        user_repo
-            .update_password(&object_id, &password_hash)
-            .await?;
+        let password_hash = hash_password(&cfg.password_hashing, password).await?;
+        set_password_hash(&cfg.password_policy, &mut updated, password_hash);
+        user_repo.update_password(&updated).await?;
     }
 
     Ok(HttpResponse::Ok().json(Response::<()> {
@@ -137,10 +186,43 @@ async fn update_user(
     }))
 }
 
+/// Deletes a user along with everything that belongs to them. Sessions,
+/// refresh tokens and pending one-time tokens go first, so nothing the user
+/// holds keeps working; the account itself goes last.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn delete_account(
+    user_repo: &UserRepository,
+    memberships: &MembershipRepository,
+    api_keys: &ApiKeyRepository,
+    identities: &FederatedIdentityRepository,
+    passkeys: &PasskeyRepository,
+    sessions: &SessionRepository,
+    refresh_store: &RefreshTokenStore,
+    one_time_tokens: &OneTimeTokenStore,
+    id: &ObjectId,
+) -> Result<(), AppError> {
+    end_sessions(sessions, refresh_store, id).await?;
+    one_time_tokens.revoke_user(&id.to_hex()).await?;
+    api_keys.delete_by_user(id).await?;
+    passkeys.delete_by_user(id).await?;
+    identities.delete_by_user(id).await?;
+    memberships.delete_by_user(id).await?;
+    user_repo.delete_by_id(id).await?;
+    Ok(())
+}
+
 #[delete("/users/{id}")]
+#[allow(clippy::too_many_arguments)]
 async fn delete_user(
-    _admin: AdminUser,
+    _admin: RequirePermission<UsersDelete>,
     user_repo: Data<UserRepository>,
+    memberships: Data<MembershipRepository>,
+    api_keys: Data<ApiKeyRepository>,
+    identities: Data<FederatedIdentityRepository>,
+    passkeys: Data<PasskeyRepository>,
+    sessions: Data<SessionRepository>,
+    refresh_store: Data<RefreshTokenStore>,
+    one_time_tokens: Data<OneTimeTokenStore>,
     id: Path<String>,
 ) -> Result<HttpResponse, AppError> {
     let object_id = ObjectId::parse_str(id.as_str())
@@ -151,7 +233,18 @@ async fn delete_user(
         .await?
         .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
 
-    user_repo.delete_by_id(&object_id).await?;
+    delete_account(
+        &user_repo,
+        &memberships,
+        &api_keys,
+        &identities,
+        &passkeys,
+        &sessions,
+        &refresh_store,
+        &one_time_tokens,
+        &object_id,
+    )
+    .await?;
 
     Ok(HttpResponse::Ok().json(Response::<()> {
         msg: USER_DELETED.into(),
@@ -159,12 +252,15 @@ async fn delete_user(
     }))
 }
 
-#[put("/users/{id}/admin")]
-async fn set_admin(
-    _admin: AdminUser,
+/// Replaces the user's roles. Permissions are checked per request, so the
+/// change applies to existing sessions straight away.
+#[put("/users/{id}/roles")]
+async fn set_user_roles(
+    admin: RequirePermission<RolesManage>,
     user_repo: Data<UserRepository>,
+    role_repo: Data<RoleRepository>,
     id: Path<String>,
-    payload: Json<SetRoleRequest>,
+    payload: Json<SetUserRolesRequest>,
 ) -> Result<HttpResponse, AppError> {
     let object_id = ObjectId::parse_str(id.as_str())
         .map_err(|_| AppError::BadRequest(INVALID_USER_ID.into()))?;
@@ -174,20 +270,173 @@ async fn set_admin(
         .await?
         .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
 
-    user_repo.set_admin(&object_id, payload.is_admin).await?;
+    let mut roles = payload.roles.clone();
+    roles.sort();
+    roles.dedup();
+    check_roles(&role_repo, &roles).await?;
 
-    let msg = if payload.is_admin {
-        USER_SET_AS_ADMIN
-    } else {
-        ADMIN_SET_AS_USER
-    };
+    // Don't let an admin lock themselves out of role management
+    if admin.principal.user_id() == Some(object_id.to_hex().as_str())
+        && !role_repo.grants(&roles, PERMISSION_ROLES_MANAGE).await?
+    {
+        return Err(AppError::BadRequest(OWN_ROLE_MANAGEMENT_REQUIRED.into()));
+    }
+
+    user_repo.set_roles(&object_id, &roles).await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: USER_ROLES_UPDATED.into(),
+        data: None,
+    }))
+}
+
+#[delete("/users/{id}/mfa")]
+async fn reset_mfa(
+    _admin: RequirePermission<UsersWrite>,
+    user_repo: Data<UserRepository>,
+    sessions: Data<SessionRepository>,
+    refresh_store: Data<RefreshTokenStore>,
+    id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let object_id = ObjectId::parse_str(id.as_str())
+        .map_err(|_| AppError::BadRequest(INVALID_USER_ID.into()))?;
+
+    let user = user_repo
+        .find_by_id(&object_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
+
+    // Sessions opened by whoever holds the old factor end with it
+    user_repo.clear_mfa(&object_id).await?;
+    sign_out_everywhere(&user_repo, &sessions, &refresh_store, &user).await?;
 
     Ok(HttpResponse::Ok().json(Response::<()> {
-        msg: msg.into(),
+        msg: MFA_RESET.into(),
         data: None,
     }))
 }
 
+#[post("/users/{id}/unlock")]
+async fn unlock_user(
+    _admin: RequirePermission<UsersWrite>,
+    user_repo: Data<UserRepository>,
+    attempts: Data<LoginAttemptStore>,
+    id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let object_id = ObjectId::parse_str(id.as_str())
+        .map_err(|_| AppError::BadRequest(INVALID_USER_ID.into()))?;
+
+    let user = user_repo
+        .find_by_id(&object_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(USER_NOT_FOUND.into()))?;
+
+    attempts
+        .reset(&LoginAttemptStore::account(&user.email))
+        .await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: ACCOUNT_UNLOCKED.into(),
+        data: None,
+    }))
+}
+
+fn signing_key_infos(cfg: &AppConfig, records: Vec<SigningKey>) -> Vec<SigningKeyInfo> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let current = cfg.jwt_keys.current().kid;
+    let accepted: Vec<String> = cfg
+        .jwt_keys
+        .verification_keys()
+        .into_iter()
+        .map(|k| k.kid)
+        .collect();
+
+    records
+        .into_iter()
+        .map(|k| {
+            let activates_at = k.activates_at.timestamp_millis() / 1000;
+            let status = if k.kid == current {
+                "current"
+            } else if activates_at > now {
+                "pending"
+            } else if accepted.contains(&k.kid) {
+                "retired"
+            } else {
+                "expired"
+            };
+
+            SigningKeyInfo {
+                kid: k.kid,
+                algorithm: k.algorithm,
+                activates_at,
+                created_at: k.created_at.timestamp_millis() / 1000,
+                status: status.into(),
+            }
+        })
+        .collect()
+}
+
+#[get("/keys")]
+async fn get_signing_keys(
+    _admin: RequirePermission<KeysManage>,
+    cfg: Data<AppConfig>,
+    signing_key_repo: Data<SigningKeyRepository>,
+) -> Result<HttpResponse, AppError> {
+    reload_keyring(&cfg, &signing_key_repo).await?;
+    let records = signing_key_repo.find_all().await?;
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: SIGNING_KEYS_FETCHED.into(),
+        data: Some(signing_key_infos(&cfg, records)),
+    }))
+}
+
+/// Adds a new signing key. It only takes over signing after
+/// `JWT_KEY_ACTIVATION_DELAY_SECONDS`, giving every instance and every JWKS
+/// consumer time to learn it first; the previous key keeps verifying tokens
+/// until they expire. Private material is only persisted encrypted with
+/// `SIGNING_KEY_ENCRYPTION_KEY`.
+#[post("/keys/rotate")]
+async fn rotate_signing_key(
+    _admin: RequirePermission<KeysManage>,
+    cfg: Data<AppConfig>,
+    signing_key_repo: Data<SigningKeyRepository>,
+) -> Result<HttpResponse, AppError> {
+    let kek = cfg
+        .signing_key_encryption_key
+        .as_ref()
+        .ok_or_else(|| AppError::BadRequest(SIGNING_KEY_ENCRYPTION_REQUIRED.into()))?;
+
+    let algorithm = cfg.jwt_keys.algorithm();
+    let (key, material) = JwtKey::generate(algorithm).map_err(|e| {
+        tracing::error!("Failed to generate signing key: {}", e);
+        AppError::Internal
+    })?;
+
+    let material = kek.seal(&material, &key.kid).map_err(|e| {
+        tracing::error!("Failed to encrypt signing key: {}", e);
+        AppError::Internal
+    })?;
+
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let record = SigningKey {
+        id: ObjectId::new(),
+        kid: key.kid,
+        algorithm: format!("{:?}", algorithm),
+        material,
+        encrypted: true,
+        activates_at: DateTime::from_millis((now + JWT_KEY_ACTIVATION_DELAY_SECONDS) * 1000),
+        created_at: DateTime::now(),
+    };
+    signing_key_repo.create(&record).await?;
+    reload_keyring(&cfg, &signing_key_repo).await?;
+
+    Ok(HttpResponse::Created().json(Response {
+        msg: SIGNING_KEY_ROTATED.into(),
+        data: signing_key_infos(&cfg, vec![record]).pop(),
+    }))
+}
+
 pub fn admin_scope() -> Scope {
     Scope::new("/admin")
         .service(get_all_users)
@@ -195,5 +444,121 @@ pub fn admin_scope() -> Scope {
         .service(create_user)
         .service(update_user)
         .service(delete_user)
-        .service(set_admin)
+        .service(set_user_roles)
+        .service(reset_mfa)
+        .service(unlock_user)
+        .service(get_signing_keys)
+        .service(rotate_signing_key)
+        .service(role_scope())
+        .service(invitation_scope())
+        .service(oauth_client_scope())
+        .service(service_account_scope())
+        .service(admin_api_key_scope())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::mongodb::init_mongodb;
+    use crate::database::redis::{init_redis, RefreshOutcome, RefreshTokenRecord};
+    use crate::models::session::Session;
+
+    const MONGO_URI: &str = "mongodb://localhost:27018";
+    const REDIS_URI: &str = "redis://localhost:6380";
+
+    #[actix_web::test]
+    #[ignore = "needs the services in tests/docker-compose.yml"]
+    async fn deleting_an_account_revokes_its_tokens() {
+        let db = init_mongodb(MONGO_URI, &format!("admin_test_{}", ObjectId::new()))
+            .await
+            .unwrap();
+        let redis = init_redis(REDIS_URI).await.unwrap();
+        let user_repo = UserRepository::new(&db);
+        let sessions = SessionRepository::new(&db);
+        let refresh_store = RefreshTokenStore::new(redis.clone());
+        let one_time_tokens = OneTimeTokenStore::new(redis);
+
+        let user = User {
+            id: ObjectId::new(),
+            email: "carol@example.org".into(),
+            username: "carol".into(),
+            password_hash: String::new(),
+            password_history: Vec::new(),
+            password_changed_at: Some(DateTime::now()),
+            roles: Vec::new(),
+            token_version: 0,
+            disabled: false,
+            mfa: Mfa::default(),
+            email_verified: true,
+            email_verified_at: Some(DateTime::now()),
+            ldap_dn: None,
+            external_id: None,
+        };
+        user_repo.create(&user).await.unwrap();
+
+        let session = Session {
+            id: ObjectId::new(),
+            user_id: user.id,
+            device: None,
+            ip: None,
+            user_agent: None,
+            created_at: DateTime::now(),
+            last_seen_at: DateTime::now(),
+            org_id: None,
+            client_id: None,
+            scope: None,
+        };
+        sessions.create(&session).await.unwrap();
+
+        let refresh_hash = format!("refresh_{}", session.id.to_hex());
+        let record = RefreshTokenRecord {
+            user_id: user.id.to_hex(),
+            family_id: session.id.to_hex(),
+            token_version: 0,
+        };
+        refresh_store
+            .store(&refresh_hash, &record, 3600)
+            .await
+            .unwrap();
+
+        let reset_hash = format!("reset_{}", user.id.to_hex());
+        one_time_tokens
+            .issue_for_user(
+                "password_reset",
+                &reset_hash,
+                "pending",
+                3600,
+                &user.id.to_hex(),
+            )
+            .await
+            .unwrap();
+
+        delete_account(
+            &user_repo,
+            &MembershipRepository::new(&db),
+            &ApiKeyRepository::new(&db),
+            &FederatedIdentityRepository::new(&db),
+            &PasskeyRepository::new(&db),
+            &sessions,
+            &refresh_store,
+            &one_time_tokens,
+            &user.id,
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(
+            refresh_store.consume(&refresh_hash).await.unwrap(),
+            RefreshOutcome::Invalid
+        ));
+        assert!(one_time_tokens
+            .peek("password_reset", &reset_hash)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(sessions.find_by_user(&user.id).await.unwrap().is_empty());
+        assert!(user_repo.find_by_id(&user.id).await.unwrap().is_none());
+
+        db.drop().await.unwrap();
+    }
 }
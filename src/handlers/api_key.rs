@@ -0,0 +1,166 @@
+use crate::auth::{AuthenticatedUser, RequirePermission, UsersRead, UsersWrite};
+use crate::constants::*;
+use crate::database::mongodb::{ApiKeyRepository, RoleRepository, UserRepository};
+use crate::errors::AppError;
+use crate::models::api_key::ApiKey;
+use crate::models::request::CreateApiKeyRequest;
+use crate::models::response::{ApiKeyCredentials, ApiKeyInfo, Response};
+use crate::utils::token::{generate_opaque_token, hash_token};
+use actix_web::web::{Data, Json, Path};
+use actix_web::{delete, get, post, HttpResponse, Scope};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use validator::Validate;
+
+fn key_info(key: ApiKey) -> ApiKeyInfo {
+    ApiKeyInfo {
+        id: key.id.to_hex(),
+        user_id: key.user_id.to_hex(),
+        name: key.name,
+        prefix: key.prefix,
+        scopes: key.scopes,
+        expires_at: key.expires_at.map(|t| t.timestamp_millis() / 1000),
+        created_at: key.created_at.timestamp_millis() / 1000,
+        last_used_at: key.last_used_at.map(|t| t.timestamp_millis() / 1000),
+    }
+}
+
+fn parse_key_id(id: &str) -> Result<ObjectId, AppError> {
+    ObjectId::parse_str(id).map_err(|_| AppError::BadRequest(INVALID_API_KEY_ID.into()))
+}
+
+#[get("")]
+async fn get_api_keys(
+    user: AuthenticatedUser,
+    keys: Data<ApiKeyRepository>,
+) -> Result<HttpResponse, AppError> {
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    let infos: Vec<ApiKeyInfo> = keys
+        .find_by_user(&uid)
+        .await?
+        .into_iter()
+        .map(key_info)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: API_KEYS_FETCHED.into(),
+        data: Some(infos),
+    }))
+}
+
+/// Creates a key. It is returned once and only its hash is stored; scopes are
+/// limited to permissions the user currently holds.
+#[post("")]
+async fn create_api_key(
+    user: AuthenticatedUser,
+    user_repo: Data<UserRepository>,
+    role_repo: Data<RoleRepository>,
+    keys: Data<ApiKeyRepository>,
+    payload: Json<CreateApiKeyRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    let account = user_repo
+        .find_by_id(&uid)
+        .await?
+        .ok_or(AppError::Unauthorized(USER_NOT_FOUND.into()))?;
+    for scope in &payload.scopes {
+        if !ALL_PERMISSIONS.contains(&scope.as_str()) {
+            return Err(AppError::BadRequest(format!(
+                "{}: {}",
+                UNKNOWN_PERMISSION, scope
+            )));
+        }
+        if !role_repo.grants(&account.roles, scope).await? {
+            return Err(AppError::Forbidden(SCOPE_NOT_GRANTABLE.into()));
+        }
+    }
+
+    let secret = format!("{}{}", API_KEY_PREFIX, generate_opaque_token());
+    let now = DateTime::now();
+    let key = ApiKey {
+        id: ObjectId::new(),
+        user_id: uid,
+        name: payload.name.clone(),
+        prefix: secret[..API_KEY_PREFIX.len() + 8].to_string(),
+        key_hash: hash_token(&secret),
+        scopes: payload.scopes.clone(),
+        expires_at: payload
+            .expires_in_days
+            .map(|days| DateTime::from_millis(now.timestamp_millis() + days * 24 * 60 * 60 * 1000)),
+        created_at: now,
+        last_used_at: None,
+    };
+    keys.create(&key).await?;
+
+    Ok(HttpResponse::Created().json(Response {
+        msg: API_KEY_CREATED.into(),
+        data: Some(ApiKeyCredentials {
+            api_key: key_info(key),
+            key: secret,
+        }),
+    }))
+}
+
+#[delete("/{id}")]
+async fn revoke_api_key(
+    user: AuthenticatedUser,
+    keys: Data<ApiKeyRepository>,
+    id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    if !keys.delete(&parse_key_id(&id)?, Some(&uid)).await? {
+        return Err(AppError::NotFound(API_KEY_NOT_FOUND.into()));
+    }
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: API_KEY_REVOKED.into(),
+        data: None,
+    }))
+}
+
+pub fn api_key_scope() -> Scope {
+    Scope::new("/api-keys")
+        .service(get_api_keys)
+        .service(create_api_key)
+        .service(revoke_api_key)
+}
+
+#[get("")]
+async fn get_all_api_keys(
+    _admin: RequirePermission<UsersRead>,
+    keys: Data<ApiKeyRepository>,
+) -> Result<HttpResponse, AppError> {
+    let infos: Vec<ApiKeyInfo> = keys.find_all().await?.into_iter().map(key_info).collect();
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: API_KEYS_FETCHED.into(),
+        data: Some(infos),
+    }))
+}
+
+#[delete("/{id}")]
+async fn revoke_any_api_key(
+    _admin: RequirePermission<UsersWrite>,
+    keys: Data<ApiKeyRepository>,
+    id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    if !keys.delete(&parse_key_id(&id)?, None).await? {
+        return Err(AppError::NotFound(API_KEY_NOT_FOUND.into()));
+    }
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: API_KEY_REVOKED.into(),
+        data: None,
+    }))
+}
+
+/// Every user's keys, nested under `/admin`.
+pub fn admin_api_key_scope() -> Scope {
+    Scope::new("/api-keys")
+        .service(get_all_api_keys)
+        .service(revoke_any_api_key)
+}
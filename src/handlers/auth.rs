@@ -1,23 +1,233 @@
-use crate::auth::AuthenticatedUser;
+use crate::auth::{AuthenticatedUser, Authenticators, PendingPasswordChange};
 use crate::config::app_config::AppConfig;
 use crate::constants::*;
-use crate::database::mongodb::UserRepository;
-use crate::database::redis::TokenBlacklist;
+use crate::database::mongodb::{SessionRepository, UserRepository};
+use crate::database::redis::{
+    LoginAttemptStore, OneTimeTokenStore, RefreshOutcome, RefreshTokenRecord, RefreshTokenStore,
+    TokenBlacklist,
+};
 use crate::errors::AppError;
-use crate::models::request::{LoginRequest, RegisterRequest};
-use crate::models::response::{Response, Token};
-use crate::models::user::User;
-use crate::utils::password::{hash_password, verify_password};
-use crate::utils::token::generate_token;
+use crate::handlers::federated::federated_scope;
+use crate::handlers::invitation::accept_invite;
+use crate::handlers::mfa::verify_second_factor;
+use crate::handlers::password_reset::password_reset_scope;
+use crate::handlers::verification::{resend_verification, send_verification_email, verify_email};
+use crate::handlers::webauthn::webauthn_scope;
+use crate::mail::MailService;
+use crate::models::request::{LoginRequest, MfaVerifyRequest, RefreshRequest, RegisterRequest};
+use crate::models::response::{MfaChallenge, Response, Token};
+use crate::models::session::Session;
+use crate::models::user::{Mfa, User};
+use crate::utils::client_addr::client_ip;
+use crate::utils::password::hash_password;
+use crate::utils::password_policy::{check_password, password_expired};
+use crate::utils::token::{generate_opaque_token, generate_token, hash_token};
 use actix_web::web::{scope, Data, Json};
-use actix_web::{post, HttpResponse};
+use actix_web::{post, HttpRequest, HttpResponse};
 use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
 use validator::Validate;
 
+const MFA_CHALLENGE_PURPOSE: &str = "mfa_challenge";
+
+#[derive(Serialize, Deserialize)]
+struct MfaChallengeState {
+    user_id: String,
+    device: Option<String>,
+    #[serde(default)]
+    password_expired: bool, // the password must be changed once verified
+}
+
+async fn issue_tokens(
+    cfg: &AppConfig,
+    refresh_store: &RefreshTokenStore,
+    session: &Session,
+    token_version: i32,
+) -> Result<Token, AppError> {
+    let token = generate_token(cfg, session, token_version)?;
+    let refresh_token = generate_opaque_token();
+    let record = RefreshTokenRecord {
+        user_id: session.user_id.to_hex(),
+        family_id: session.id.to_hex(),
+        token_version,
+    };
+    refresh_store
+        .store(
+            &hash_token(&refresh_token),
+            &record,
+            cfg.refresh_token_exp_days * 24 * 60 * 60,
+        )
+        .await?;
+
+    Ok(Token {
+        token,
+        refresh_token,
+        expires_in: cfg.access_token_exp_minutes * 60,
+    })
+}
+
+/// Checked on every way of signing in: the account is enabled and, when
+/// required, its email address is verified.
+pub(super) fn ensure_can_sign_in(cfg: &AppConfig, user: &User) -> Result<(), AppError> {
+    if user.disabled {
+        return Err(AppError::Forbidden(ACCOUNT_DISABLED.into()));
+    }
+    if cfg.require_email_verification && !user.email_verified {
+        return Err(AppError::Forbidden(EMAIL_NOT_VERIFIED.into()));
+    }
+    Ok(())
+}
+
+/// Describes a new session for the user on the requesting device.
+pub(super) fn new_session(
+    cfg: &AppConfig,
+    req: &HttpRequest,
+    user: &User,
+    device: Option<String>,
+) -> Session {
+    let now = DateTime::now();
+    Session {
+        id: ObjectId::new(),
+        user_id: user.id,
+        device,
+        ip: client_ip(&cfg.trusted_proxies, req).map(|ip| ip.to_string()),
+        user_agent: req
+            .headers()
+            .get("User-Agent")
+            .and_then(|h| h.to_str().ok())
+            .map(|ua| ua.to_string()),
+        created_at: now,
+        last_seen_at: now,
+        org_id: None,
+        client_id: None,
+        scope: None,
+    }
+}
+
+/// Records the session and issues its first token pair. The session id
+/// doubles as the refresh token family id.
+pub(super) async fn open_session(
+    cfg: &AppConfig,
+    sessions: &SessionRepository,
+    refresh_store: &RefreshTokenStore,
+    user: &User,
+    session: &Session,
+) -> Result<Token, AppError> {
+    sessions.create(session).await?;
+    issue_tokens(cfg, refresh_store, session, user.token_version).await
+}
+
+/// Signs the user in on the requesting device.
+pub(super) async fn start_session(
+    cfg: &AppConfig,
+    sessions: &SessionRepository,
+    refresh_store: &RefreshTokenStore,
+    req: &HttpRequest,
+    user: &User,
+    device: Option<String>,
+) -> Result<Token, AppError> {
+    ensure_can_sign_in(cfg, user)?;
+
+    let session = new_session(cfg, req, user, device);
+    open_session(cfg, sessions, refresh_store, user, &session).await
+}
+
+/// Signs the user out on every device: their access tokens stop verifying
+/// with the bumped token version, and their sessions end along with the
+/// sessions' refresh tokens.
+pub(super) async fn sign_out_everywhere(
+    user_repo: &UserRepository,
+    sessions: &SessionRepository,
+    refresh_store: &RefreshTokenStore,
+    user: &User,
+) -> Result<(), AppError> {
+    user_repo
+        .update_token_version(&user.id, user.token_version + 1)
+        .await?;
+    end_sessions(sessions, refresh_store, &user.id).await
+}
+
+/// Ends all of the user's sessions along with their refresh tokens.
+pub(super) async fn end_sessions(
+    sessions: &SessionRepository,
+    refresh_store: &RefreshTokenStore,
+    user_id: &ObjectId,
+) -> Result<(), AppError> {
+    for session in sessions.find_by_user(user_id).await? {
+        refresh_store.revoke_family(&session.id.to_hex()).await?;
+    }
+    sessions.delete_by_user(user_id).await
+}
+
+/// Exchanges a refresh token for a new token pair. A refresh token only works
+/// for the OAuth client its session was granted to (`None` for first-party
+/// logins); presenting it anywhere else revokes the session's tokens. The
+/// session is returned along with the new pair.
+pub(super) async fn rotate_refresh_token(
+    cfg: &AppConfig,
+    user_repo: &UserRepository,
+    sessions: &SessionRepository,
+    refresh_store: &RefreshTokenStore,
+    refresh_token: &str,
+    client_id: Option<&str>,
+) -> Result<(Token, Session), AppError> {
+    let record = match refresh_store.consume(&hash_token(refresh_token)).await? {
+        RefreshOutcome::Valid(record) => record,
+        RefreshOutcome::Reused(record) => {
+            if let (Ok(session_id), Ok(user_id)) = (
+                ObjectId::parse_str(&record.family_id),
+                ObjectId::parse_str(&record.user_id),
+            ) {
+                sessions.delete(&session_id, &user_id).await?;
+            }
+            return Err(AppError::Unauthorized(REFRESH_TOKEN_REUSED.into()));
+        }
+        RefreshOutcome::Invalid => {
+            return Err(AppError::Unauthorized(INVALID_REFRESH_TOKEN.into()));
+        }
+    };
+
+    let object_id = ObjectId::parse_str(&record.user_id)
+        .map_err(|_| AppError::Unauthorized(INVALID_REFRESH_TOKEN.into()))?;
+    let user = user_repo
+        .find_by_id(&object_id)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized(INVALID_REFRESH_TOKEN.into()))?;
+
+    let session_id = ObjectId::parse_str(&record.family_id)
+        .map_err(|_| AppError::Unauthorized(INVALID_REFRESH_TOKEN.into()))?;
+
+    let session = if user.token_version == record.token_version
+        && sessions.touch(&session_id, &object_id).await?
+    {
+        sessions.find(&session_id, &object_id).await?
+    } else {
+        None
+    };
+
+    let session = match session {
+        Some(session) if session.client_id.as_deref() == client_id => session,
+        _ => {
+            refresh_store.revoke_family(&record.family_id).await?;
+            return Err(AppError::Unauthorized(INVALID_REFRESH_TOKEN.into()));
+        }
+    };
+
+    let token = issue_tokens(cfg, refresh_store, &session, user.token_version).await?;
+    Ok((token, session))
+}
+
 #[post("/register")]
+#[allow(clippy::too_many_arguments)]
 async fn register(
+    req: HttpRequest,
     user_repo: Data<UserRepository>,
+    sessions: Data<SessionRepository>,
+    refresh_store: Data<RefreshTokenStore>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    mail: Data<MailService>,
     cfg: Data<AppConfig>,
     payload: Json<RegisterRequest>,
 ) -> Result<HttpResponse, AppError> {
@@ -28,29 +238,145 @@ async fn register(
     if user_repo.find_by_email(&payload.email).await?.is_some() {
         return Err(AppError::Conflict(EMAIL_ALREADY_EXISTS.into()));
     }
+    check_password(
+        &cfg.password_policy,
+        &payload.password,
+        &[&payload.email, &payload.username],
+    )
+    .await?;
 
-    let hash = hash_password(&payload.password)?;
+    let hash = hash_password(&cfg.password_hashing, &payload.password).await?;
     let user_id = ObjectId::new();
     let new_user = User {
         id: user_id,
         email: payload.email.clone(),
         username: payload.username.clone(),
         password_hash: hash,
-        is_admin: false,
+        password_history: Vec::new(),
+        password_changed_at: Some(DateTime::now()),
+        roles: Vec::new(),
         token_version: 0,
+        disabled: false,
+        mfa: Mfa::default(),
+        email_verified: false,
+        email_verified_at: None,
+        ldap_dn: None,
+        external_id: None,
     };
     user_repo.create(&new_user).await?;
+    send_verification_email(&cfg, &one_time_tokens, &mail, &req, &new_user).await?;
 
-    let token = generate_token(&cfg, &user_id.to_hex(), new_user.token_version)?;
+    if cfg.require_email_verification {
+        return Ok(HttpResponse::Ok().json(Response::<()> {
+            msg: REGISTER_VERIFY_EMAIL.into(),
+            data: None,
+        }));
+    }
+
+    let token = start_session(
+        &cfg,
+        &sessions,
+        &refresh_store,
+        &req,
+        &new_user,
+        payload.device.clone(),
+    )
+    .await?;
     Ok(HttpResponse::Ok().json(Response {
         msg: REGISTER_SUCCESS.into(),
-        data: Some(Token { token }),
+        data: Some(token),
+    }))
+}
+
+/// Defers the sign-in until `/auth/mfa/verify` confirms the second factor.
+/// With `password_expired`, that confirmation yields a password change token
+/// rather than a session.
+pub(super) async fn mfa_challenge(
+    one_time_tokens: &OneTimeTokenStore,
+    user: &User,
+    device: Option<String>,
+    password_expired: bool,
+) -> Result<MfaChallenge, AppError> {
+    let mfa_token = generate_opaque_token();
+    let state = MfaChallengeState {
+        user_id: user.id.to_hex(),
+        device,
+        password_expired,
+    };
+    one_time_tokens
+        .issue_for_user(
+            MFA_CHALLENGE_PURPOSE,
+            &hash_token(&mfa_token),
+            &serde_json::to_string(&state).map_err(|_| AppError::Internal)?,
+            MFA_CHALLENGE_TTL_SECONDS,
+            &state.user_id,
+        )
+        .await?;
+
+    Ok(MfaChallenge {
+        mfa_token,
+        expires_in: MFA_CHALLENGE_TTL_SECONDS,
+    })
+}
+
+/// Holds back a sign-in whose password expired, handing out a token that
+/// only works for `PUT /user/password`.
+async fn require_password_change(
+    one_time_tokens: &OneTimeTokenStore,
+    user_id: String,
+    device: Option<String>,
+) -> Result<HttpResponse, AppError> {
+    let challenge = PendingPasswordChange { user_id, device }
+        .issue(one_time_tokens)
+        .await?;
+    Ok(HttpResponse::Ok().json(Response {
+        msg: PASSWORD_EXPIRED.into(),
+        data: Some(challenge),
     }))
 }
 
+/// Records a failed password check. Past `LOGIN_FREE_ATTEMPTS`, each failure
+/// locks the account for an exponentially growing delay, and reaching
+/// `login_max_failures` locks it for the full lockout period. Addresses only
+/// get the hard lockout, since many users may share one.
+async fn record_login_failure(
+    cfg: &AppConfig,
+    attempts: &LoginAttemptStore,
+    subjects: &[String],
+) -> Result<(), AppError> {
+    let lockout_seconds = cfg.login_lockout_minutes * 60;
+
+    for subject in subjects {
+        let failures = attempts.record_failure(subject, lockout_seconds).await?;
+        let is_account = subject.starts_with("account:");
+        let max_failures = if is_account {
+            cfg.login_max_failures
+        } else {
+            cfg.login_ip_max_failures
+        };
+
+        if failures >= max_failures {
+            tracing::warn!("Locking {} after {} failed logins", subject, failures);
+            attempts.lock(subject, lockout_seconds).await?;
+        } else if is_account && failures > LOGIN_FREE_ATTEMPTS {
+            let exponent = (failures - LOGIN_FREE_ATTEMPTS - 1).min(16) as u32;
+            let delay = 2i64.pow(exponent).min(LOGIN_MAX_DELAY_SECONDS);
+            attempts.lock(subject, delay).await?;
+        }
+    }
+
+    Ok(())
+}
+
 #[post("/login")]
+#[allow(clippy::too_many_arguments)]
 async fn login(
-    user_repo: Data<UserRepository>,
+    req: HttpRequest,
+    authenticators: Data<Authenticators>,
+    sessions: Data<SessionRepository>,
+    refresh_store: Data<RefreshTokenStore>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    attempts: Data<LoginAttemptStore>,
     cfg: Data<AppConfig>,
     payload: Json<LoginRequest>,
 ) -> Result<HttpResponse, AppError> {
@@ -58,40 +384,171 @@ async fn login(
         .validate()
         .map_err(|e| AppError::BadRequest(e.to_string()))?;
 
+    let mut subjects = vec![LoginAttemptStore::account(&payload.email)];
+    if let Some(ip) = client_ip(&cfg.trusted_proxies, &req) {
+        subjects.push(LoginAttemptStore::ip(&ip.to_string()));
+    }
+
+    for subject in &subjects {
+        if let Some(seconds) = attempts.locked_for(subject).await? {
+            return Err(AppError::Locked(LOGIN_LOCKED.into(), seconds));
+        }
+    }
+
+    // Unknown emails count as failures too, so lockouts reveal nothing
+    let user = match authenticators
+        .authenticate(&payload.email, &payload.password)
+        .await?
+    {
+        Some(user) => user,
+        None => {
+            record_login_failure(&cfg, &attempts, &subjects).await?;
+            return Err(AppError::Unauthorized(INVALID_CREDENTIALS.into()));
+        }
+    };
+    attempts.reset(&subjects[0]).await?;
+    ensure_can_sign_in(&cfg, &user)?;
+
+    // An expired password is only dealt with after the second factor, so the
+    // password alone cannot replace it
+    let expired = password_expired(&cfg.password_policy, &user);
+    if user.mfa.totp_enabled {
+        let challenge =
+            mfa_challenge(&one_time_tokens, &user, payload.device.clone(), expired).await?;
+        return Ok(HttpResponse::Ok().json(Response {
+            msg: MFA_REQUIRED.into(),
+            data: Some(challenge),
+        }));
+    }
+    if expired {
+        return require_password_change(&one_time_tokens, user.id.to_hex(), payload.device.clone())
+            .await;
+    }
+
+    let token = start_session(
+        &cfg,
+        &sessions,
+        &refresh_store,
+        &req,
+        &user,
+        payload.device.clone(),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(Response {
+        msg: LOGIN_SUCCESS.into(),
+        data: Some(token),
+    }))
+}
+
+#[post("/mfa/verify")]
+async fn verify_mfa(
+    req: HttpRequest,
+    user_repo: Data<UserRepository>,
+    sessions: Data<SessionRepository>,
+    refresh_store: Data<RefreshTokenStore>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    cfg: Data<AppConfig>,
+    payload: Json<MfaVerifyRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let token_hash = hash_token(&payload.mfa_token);
+    let state: MfaChallengeState = one_time_tokens
+        .peek(MFA_CHALLENGE_PURPOSE, &token_hash)
+        .await?
+        .and_then(|v| serde_json::from_str(&v).ok())
+        .ok_or_else(|| AppError::Unauthorized(INVALID_MFA_TOKEN.into()))?;
+
+    let user_id = ObjectId::parse_str(&state.user_id)?;
     let user = user_repo
-        .find_by_email(&payload.email)
+        .find_by_id(&user_id)
         .await?
-        .ok_or(AppError::Unauthorized(INVALID_CREDENTIALS.into()))?;
+        .ok_or_else(|| AppError::Unauthorized(INVALID_MFA_TOKEN.into()))?;
 
-    verify_password(&user.password_hash, &payload.password)?;
+    if !verify_second_factor(&cfg, &user_repo, &user, &payload.code).await? {
+        let failures = one_time_tokens
+            .record_failure(
+                MFA_CHALLENGE_PURPOSE,
+                &token_hash,
+                MFA_CHALLENGE_TTL_SECONDS,
+            )
+            .await?;
+        if failures >= MAX_MFA_ATTEMPTS {
+            one_time_tokens
+                .take(MFA_CHALLENGE_PURPOSE, &token_hash)
+                .await?;
+        }
+        return Err(AppError::Unauthorized(INVALID_MFA_CODE.into()));
+    }
 
-    let user_id = user.id.clone();
-    let new_token_version = user.token_version + 1;
-    user_repo
-        .update_token_version(&user_id, new_token_version)
-        .await?;
+    if one_time_tokens
+        .take(MFA_CHALLENGE_PURPOSE, &token_hash)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::Unauthorized(INVALID_MFA_TOKEN.into()));
+    }
+
+    if state.password_expired {
+        return require_password_change(&one_time_tokens, state.user_id, state.device).await;
+    }
 
-    let id = user_id.to_hex();
-    let token = generate_token(&cfg, &id, new_token_version)?;
+    let token = start_session(&cfg, &sessions, &refresh_store, &req, &user, state.device).await?;
     Ok(HttpResponse::Ok().json(Response {
         msg: LOGIN_SUCCESS.into(),
-        data: Some(Token { token }),
+        data: Some(token),
+    }))
+}
+
+#[post("/refresh")]
+async fn refresh(
+    user_repo: Data<UserRepository>,
+    sessions: Data<SessionRepository>,
+    refresh_store: Data<RefreshTokenStore>,
+    cfg: Data<AppConfig>,
+    payload: Json<RefreshRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let (token, _) = rotate_refresh_token(
+        &cfg,
+        &user_repo,
+        &sessions,
+        &refresh_store,
+        &payload.refresh_token,
+        None,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(Response {
+        msg: TOKEN_REFRESHED.into(),
+        data: Some(token),
     }))
 }
 
 #[post("/logout")]
 async fn logout(
     user: AuthenticatedUser,
+    sessions: Data<SessionRepository>,
     blacklist: Data<TokenBlacklist>,
+    refresh_store: Data<RefreshTokenStore>,
 ) -> Result<HttpResponse, AppError> {
-    let token = &user.token;
+    let session = &user.session;
     let now = OffsetDateTime::now_utc().unix_timestamp();
-    let exp_seconds = (user.exp as i64) - now;
+    let exp_seconds = (session.exp as i64) - now;
 
     if exp_seconds > 0 {
-        blacklist.add_token(token, exp_seconds).await?;
+        blacklist.add_token(&session.token, exp_seconds).await?;
     }
 
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    let session_id = ObjectId::parse_str(&session.id)?;
+    sessions.delete(&session_id, &uid).await?;
+    refresh_store.revoke_family(&session.id).await?;
+
     Ok(HttpResponse::Ok().json(Response::<()> {
         msg: LOGOUT_SUCCESS.into(),
         data: None,
@@ -102,5 +559,110 @@ pub fn auth_scope() -> actix_web::Scope {
     scope("/auth")
         .service(register)
         .service(login)
+        .service(verify_mfa)
+        .service(refresh)
         .service(logout)
+        .service(verify_email)
+        .service(resend_verification)
+        .service(accept_invite)
+        .service(password_reset_scope())
+        .service(webauthn_scope())
+        .service(federated_scope())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{JWT_SECRET, MONGO_DB, MONGO_URI, REDIS_URI};
+    use crate::database::mongodb::init_mongodb;
+    use crate::database::redis::init_redis;
+    use actix_web::{test, App};
+    use serde_json::{json, Value};
+
+    const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+    #[actix_web::test]
+    #[ignore = "needs the services in tests/docker-compose.yml"]
+    async fn expired_passwords_are_changed_only_after_the_second_factor() {
+        std::env::set_var(JWT_SECRET, "a".repeat(MIN_JWT_SECRET_LENGTH));
+        std::env::set_var(MONGO_URI, "mongodb://localhost:27018");
+        std::env::set_var(MONGO_DB, format!("auth_test_{}", ObjectId::new()));
+        std::env::set_var(REDIS_URI, "redis://localhost:6380");
+        let mut cfg = AppConfig::from_env().unwrap();
+        cfg.password_policy.max_age_days = 1;
+
+        let db = init_mongodb(&cfg.mongo_uri, &cfg.mongo_db).await.unwrap();
+        let redis = init_redis(&cfg.redis_uri).await.unwrap();
+        let users = UserRepository::new(&db);
+
+        let password = "correct horse battery staple";
+        let user = User {
+            id: ObjectId::new(),
+            email: "dave@example.org".into(),
+            username: "dave".into(),
+            password_hash: hash_password(&cfg.password_hashing, password)
+                .await
+                .unwrap(),
+            password_history: Vec::new(),
+            password_changed_at: Some(DateTime::from_millis(
+                DateTime::now().timestamp_millis() - 2 * DAY_MS,
+            )),
+            roles: Vec::new(),
+            token_version: 0,
+            disabled: false,
+            mfa: Mfa {
+                totp_enabled: true,
+                recovery_codes: vec![hash_token("recovery-code")],
+                ..Mfa::default()
+            },
+            email_verified: true,
+            email_verified_at: Some(DateTime::now()),
+            ldap_dn: None,
+            external_id: None,
+        };
+        users.create(&user).await.unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(Authenticators::new(&cfg, &users)))
+                .app_data(Data::new(users.clone()))
+                .app_data(Data::new(SessionRepository::new(&db)))
+                .app_data(Data::new(RefreshTokenStore::new(redis.clone())))
+                .app_data(Data::new(OneTimeTokenStore::new(redis.clone())))
+                .app_data(Data::new(LoginAttemptStore::new(redis)))
+                .app_data(Data::new(cfg))
+                .service(login)
+                .service(verify_mfa),
+        )
+        .await;
+
+        // The password alone only leads to the second factor
+        let req = test::TestRequest::post()
+            .uri("/login")
+            .set_json(json!({ "email": user.email, "password": password }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["msg"], MFA_REQUIRED);
+        assert!(body["data"]["password_change_token"].is_null());
+        let mfa_token = body["data"]["mfa_token"].as_str().unwrap().to_string();
+
+        let req = test::TestRequest::post()
+            .uri("/mfa/verify")
+            .set_json(json!({ "mfa_token": mfa_token, "code": "wrong-code" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
+        // Once verified, the expired password must be changed before signing in
+        let req = test::TestRequest::post()
+            .uri("/mfa/verify")
+            .set_json(json!({ "mfa_token": mfa_token, "code": "recovery-code" }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["msg"], PASSWORD_EXPIRED);
+        assert!(body["data"]["password_change_token"].is_string());
+        assert!(body["data"]["token"].is_null());
+
+        db.drop().await.unwrap();
+    }
 }
@@ -0,0 +1,476 @@
+use crate::auth::AuthenticatedUser;
+use crate::config::app_config::AppConfig;
+use crate::config::federation_config::FederatedProvider;
+use crate::constants::*;
+use crate::database::mongodb::{
+    FederatedIdentityRepository, PasskeyRepository, SessionRepository, UserRepository,
+};
+use crate::database::redis::{OneTimeTokenStore, RefreshTokenStore};
+use crate::errors::AppError;
+use crate::federation::{FederationClient, UpstreamIdentity};
+use crate::handlers::auth::{ensure_can_sign_in, mfa_challenge, start_session};
+use crate::handlers::verification::send_verification_email;
+use crate::mail::MailService;
+use crate::models::federated_identity::FederatedIdentity;
+use crate::models::request::{FederatedCallbackQuery, FederatedStartQuery};
+use crate::models::response::{
+    AuthorizationRedirect, FederatedProviderInfo, IdentityInfo, Response,
+};
+use crate::models::user::{fit_username, Mfa, User};
+use crate::utils::token::{generate_opaque_token, hash_token, secrets_match};
+use actix_web::cookie::time::Duration;
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::http::header::LOCATION;
+use actix_web::web::{Data, Path, Query};
+use actix_web::{delete, get, post, HttpRequest, HttpResponse, Scope};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::error;
+
+const STATE_PURPOSE: &str = "federated_state";
+const STATE_COOKIE: &str = "federated_state";
+const STATE_COOKIE_PATH: &str = "/auth/federated";
+
+/// A sign-in or link request in flight, kept in Redis until the provider
+/// redirects back.
+#[derive(Serialize, Deserialize)]
+struct FederatedState {
+    provider: String,
+    nonce: String,
+    code_verifier: String,
+    link_user_id: Option<String>, // set when linking to a signed-in user
+    device: Option<String>,
+}
+
+/// Ties a request to the browser that started it: the callback is refused
+/// unless the browser presents the hash of the state it was sent back with.
+fn state_cookie(cfg: &AppConfig, state: &str) -> Cookie<'static> {
+    Cookie::build(STATE_COOKIE, hash_token(state))
+        .path(STATE_COOKIE_PATH)
+        .http_only(true)
+        .secure(cfg.public_url.starts_with("https://"))
+        .same_site(SameSite::Lax)
+        .max_age(Duration::seconds(FEDERATED_STATE_TTL_SECONDS))
+        .finish()
+}
+
+/// Stores the request state and returns the provider's authorization url
+/// with the cookie binding the request to the browser.
+async fn begin(
+    cfg: &AppConfig,
+    federation: &FederationClient,
+    one_time_tokens: &OneTimeTokenStore,
+    provider: &FederatedProvider,
+    link_user_id: Option<String>,
+    device: Option<String>,
+) -> Result<(String, Cookie<'static>), AppError> {
+    let state = generate_opaque_token();
+    let pending = FederatedState {
+        provider: provider.name.clone(),
+        nonce: generate_opaque_token(),
+        code_verifier: generate_opaque_token(),
+        link_user_id,
+        device,
+    };
+    let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(pending.code_verifier.as_bytes()));
+
+    one_time_tokens
+        .issue(
+            STATE_PURPOSE,
+            &hash_token(&state),
+            &serde_json::to_string(&pending).map_err(|_| AppError::Internal)?,
+            FEDERATED_STATE_TTL_SECONDS,
+        )
+        .await?;
+
+    let url = federation
+        .authorization_url(provider, &state, &pending.nonce, &code_challenge)
+        .await?;
+    Ok((url, state_cookie(cfg, &state)))
+}
+
+/// The message passed to the front end when a callback fails. Server errors
+/// are only logged.
+fn error_message(error: AppError) -> String {
+    match error {
+        AppError::BadRequest(msg)
+        | AppError::Unauthorized(msg)
+        | AppError::Forbidden(msg)
+        | AppError::NotFound(msg)
+        | AppError::Conflict(msg)
+        | AppError::Locked(msg, _)
+        | AppError::TooManyRequests(msg, _) => msg,
+        error => {
+            error!("Federated sign-in failed: {}", error);
+            INTERNAL_SERVER_ERROR.into()
+        }
+    }
+}
+
+/// Finds the user behind an upstream identity, creating an account on first
+/// sign-in. Existing accounts are never taken over by email: their owner has
+/// to link the provider while signed in.
+#[allow(clippy::too_many_arguments)]
+async fn find_or_create_user(
+    req: &HttpRequest,
+    cfg: &AppConfig,
+    users: &UserRepository,
+    identities: &FederatedIdentityRepository,
+    one_time_tokens: &OneTimeTokenStore,
+    mail: &MailService,
+    provider: &FederatedProvider,
+    upstream: &UpstreamIdentity,
+) -> Result<User, AppError> {
+    if let Some(identity) = identities.find(&provider.name, &upstream.subject).await? {
+        identities.record_login(&identity.id).await?;
+        return users
+            .find_by_id(&identity.user_id)
+            .await?
+            .ok_or_else(|| AppError::Unauthorized(FEDERATED_LOGIN_FAILED.into()));
+    }
+
+    let email = upstream
+        .email
+        .clone()
+        .ok_or_else(|| AppError::BadRequest(FEDERATED_EMAIL_REQUIRED.into()))?;
+    if users.find_by_email(&email).await?.is_some() {
+        return Err(AppError::Conflict(FEDERATED_EMAIL_EXISTS.into()));
+    }
+
+    let now = DateTime::now();
+    let user = User {
+        id: ObjectId::new(),
+        email: email.clone(),
+        username: fit_username(
+            upstream
+                .username
+                .as_deref()
+                .unwrap_or_else(|| email.split('@').next().unwrap_or_default()),
+        ),
+        password_hash: String::new(),
+        password_history: Vec::new(),
+        password_changed_at: None,
+        roles: Vec::new(),
+        token_version: 0,
+        disabled: false,
+        mfa: Mfa::default(),
+        email_verified: upstream.email_verified,
+        email_verified_at: upstream.email_verified.then_some(now),
+        ldap_dn: None,
+        external_id: None,
+    };
+    users.create(&user).await?;
+    identities
+        .create(&FederatedIdentity {
+            id: ObjectId::new(),
+            user_id: user.id,
+            provider: provider.name.clone(),
+            subject: upstream.subject.clone(),
+            email: upstream.email.clone(),
+            linked_at: now,
+            last_login_at: Some(now),
+        })
+        .await?;
+
+    if !user.email_verified {
+        send_verification_email(cfg, one_time_tokens, mail, req, &user).await?;
+    }
+    Ok(user)
+}
+
+/// Links the upstream identity to the user who started the link request.
+async fn link(
+    users: &UserRepository,
+    identities: &FederatedIdentityRepository,
+    provider: &FederatedProvider,
+    upstream: &UpstreamIdentity,
+    user_id: &str,
+) -> Result<(), AppError> {
+    let user_id = ObjectId::parse_str(user_id)?;
+    users
+        .find_by_id(&user_id)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized(USER_NOT_FOUND.into()))?;
+
+    if let Some(existing) = identities.find(&provider.name, &upstream.subject).await? {
+        if existing.user_id == user_id {
+            return Ok(());
+        }
+        return Err(AppError::Conflict(IDENTITY_ALREADY_LINKED.into()));
+    }
+    if identities
+        .find_by_user(&user_id)
+        .await?
+        .iter()
+        .any(|i| i.provider == provider.name)
+    {
+        return Err(AppError::Conflict(PROVIDER_ALREADY_LINKED.into()));
+    }
+
+    identities
+        .create(&FederatedIdentity {
+            id: ObjectId::new(),
+            user_id,
+            provider: provider.name.clone(),
+            subject: upstream.subject.clone(),
+            email: upstream.email.clone(),
+            linked_at: DateTime::now(),
+            last_login_at: None,
+        })
+        .await
+}
+
+#[get("")]
+async fn get_providers(federation: Data<FederationClient>) -> HttpResponse {
+    let providers: Vec<FederatedProviderInfo> = federation
+        .providers()
+        .iter()
+        .map(|p| FederatedProviderInfo {
+            name: p.name.clone(),
+        })
+        .collect();
+
+    HttpResponse::Ok().json(Response {
+        msg: FEDERATED_PROVIDERS_FETCHED.into(),
+        data: Some(providers),
+    })
+}
+
+/// Sends the browser to the provider to sign in.
+#[get("/{provider}/start")]
+async fn start(
+    cfg: Data<AppConfig>,
+    federation: Data<FederationClient>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    provider: Path<String>,
+    query: Query<FederatedStartQuery>,
+) -> Result<HttpResponse, AppError> {
+    let provider = federation.provider(&provider)?;
+    let (url, cookie) = begin(
+        &cfg,
+        &federation,
+        &one_time_tokens,
+        provider,
+        None,
+        query.device.clone(),
+    )
+    .await?;
+
+    Ok(HttpResponse::Found()
+        .insert_header((LOCATION, url))
+        .cookie(cookie)
+        .finish())
+}
+
+/// Where the provider redirects back to. Completes a link request, or signs
+/// the user in exactly like `/auth/login` (including the MFA challenge),
+/// creating an account on first sign-in. The browser is then sent on to
+/// `FEDERATED_REDIRECT_URL` with the outcome in the url fragment, which never
+/// reaches a server: `token`, `refresh_token` and `expires_in`; `mfa_token`
+/// and `expires_in`; `linked`; or `error`.
+#[get("/{provider}/callback")]
+#[allow(clippy::too_many_arguments)]
+async fn callback(
+    req: HttpRequest,
+    cfg: Data<AppConfig>,
+    federation: Data<FederationClient>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    users: Data<UserRepository>,
+    identities: Data<FederatedIdentityRepository>,
+    sessions: Data<SessionRepository>,
+    refresh_store: Data<RefreshTokenStore>,
+    mail: Data<MailService>,
+    provider: Path<String>,
+    query: Query<FederatedCallbackQuery>,
+) -> HttpResponse {
+    let outcome: Result<Vec<(&str, String)>, AppError> = async {
+        let provider = federation.provider(&provider)?;
+        let state = query.state.as_deref().filter(|state| {
+            req.cookie(STATE_COOKIE)
+                .is_some_and(|cookie| secrets_match(cookie.value(), &hash_token(state)))
+        });
+        let pending: FederatedState = match state {
+            Some(state) => one_time_tokens
+                .take(STATE_PURPOSE, &hash_token(state))
+                .await?
+                .and_then(|value| serde_json::from_str(&value).ok()),
+            None => None,
+        }
+        .filter(|pending: &FederatedState| pending.provider == provider.name)
+        .ok_or_else(|| AppError::Unauthorized(INVALID_FEDERATED_STATE.into()))?;
+
+        if let Some(ref error) = query.error {
+            return Err(AppError::Unauthorized(format!(
+                "{}: {}",
+                FEDERATED_LOGIN_FAILED, error
+            )));
+        }
+        let code = query
+            .code
+            .as_deref()
+            .ok_or_else(|| AppError::BadRequest(FEDERATED_LOGIN_FAILED.into()))?;
+        let upstream = federation
+            .sign_in(provider, code, &pending.code_verifier, &pending.nonce)
+            .await?;
+
+        if let Some(ref user_id) = pending.link_user_id {
+            link(&users, &identities, provider, &upstream, user_id).await?;
+            return Ok(vec![("linked", provider.name.clone())]);
+        }
+
+        let user = find_or_create_user(
+            &req,
+            &cfg,
+            &users,
+            &identities,
+            &one_time_tokens,
+            &mail,
+            provider,
+            &upstream,
+        )
+        .await?;
+        ensure_can_sign_in(&cfg, &user)?;
+
+        if user.mfa.totp_enabled {
+            let challenge = mfa_challenge(&one_time_tokens, &user, pending.device, false).await?;
+            return Ok(vec![
+                ("mfa_token", challenge.mfa_token),
+                ("expires_in", challenge.expires_in.to_string()),
+            ]);
+        }
+
+        let token =
+            start_session(&cfg, &sessions, &refresh_store, &req, &user, pending.device).await?;
+        Ok(vec![
+            ("token", token.token),
+            ("refresh_token", token.refresh_token),
+            ("expires_in", token.expires_in.to_string()),
+        ])
+    }
+    .await;
+
+    let fragment = match outcome {
+        Ok(fragment) => fragment,
+        Err(error) => vec![("error", error_message(error))],
+    };
+    let mut removal = Cookie::build(STATE_COOKIE, "")
+        .path(STATE_COOKIE_PATH)
+        .finish();
+    removal.make_removal();
+
+    HttpResponse::Found()
+        .insert_header((
+            LOCATION,
+            format!(
+                "{}#{}",
+                cfg.federated_redirect_url,
+                serde_urlencoded::to_string(fragment).unwrap_or_default()
+            ),
+        ))
+        .cookie(removal)
+        .finish()
+}
+
+pub fn federated_scope() -> Scope {
+    Scope::new("/federated")
+        .service(get_providers)
+        .service(start)
+        .service(callback)
+}
+
+#[get("")]
+async fn get_identities(
+    user: AuthenticatedUser,
+    identities: Data<FederatedIdentityRepository>,
+) -> Result<HttpResponse, AppError> {
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    let infos: Vec<IdentityInfo> = identities
+        .find_by_user(&uid)
+        .await?
+        .into_iter()
+        .map(|i| IdentityInfo {
+            provider: i.provider,
+            subject: i.subject,
+            email: i.email,
+            linked_at: i.linked_at.timestamp_millis() / 1000,
+            last_login_at: i.last_login_at.map(|t| t.timestamp_millis() / 1000),
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: IDENTITIES_FETCHED.into(),
+        data: Some(infos),
+    }))
+}
+
+/// Starts linking a provider account. The client sends the browser to the
+/// returned url; the link is made when the provider redirects back.
+#[post("/{provider}")]
+async fn start_link(
+    user: AuthenticatedUser,
+    cfg: Data<AppConfig>,
+    federation: Data<FederationClient>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    provider: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let provider = federation.provider(&provider)?;
+    let (redirect_to, cookie) = begin(
+        &cfg,
+        &federation,
+        &one_time_tokens,
+        provider,
+        Some(user.user_id.clone()),
+        None,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().cookie(cookie).json(Response {
+        msg: IDENTITY_LINK_STARTED.into(),
+        data: Some(AuthorizationRedirect { redirect_to }),
+    }))
+}
+
+/// Unlinks a provider account, unless it is the user's only way to sign in.
+#[delete("/{provider}")]
+async fn unlink(
+    user: AuthenticatedUser,
+    users: Data<UserRepository>,
+    identities: Data<FederatedIdentityRepository>,
+    passkeys: Data<PasskeyRepository>,
+    provider: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    let account = users
+        .find_by_id(&uid)
+        .await?
+        .ok_or(AppError::Unauthorized(USER_NOT_FOUND.into()))?;
+
+    let linked = identities.find_by_user(&uid).await?;
+    if !linked.iter().any(|i| i.provider == *provider) {
+        return Err(AppError::NotFound(IDENTITY_NOT_FOUND.into()));
+    }
+    if account.password_hash.is_empty()
+        && linked.len() == 1
+        && passkeys.find_by_user(&uid).await?.is_empty()
+    {
+        return Err(AppError::BadRequest(LAST_SIGN_IN_METHOD.into()));
+    }
+    identities.delete(&uid, &provider).await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: IDENTITY_UNLINKED.into(),
+        data: None,
+    }))
+}
+
+/// Linked provider accounts, nested under `/user`.
+pub fn identity_scope() -> Scope {
+    Scope::new("/identities")
+        .service(get_identities)
+        .service(start_link)
+        .service(unlink)
+}
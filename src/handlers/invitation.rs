@@ -0,0 +1,247 @@
+use crate::auth::{RequirePermission, UsersRead, UsersWrite};
+use crate::config::app_config::AppConfig;
+use crate::constants::*;
+use crate::database::mongodb::{
+    InvitationRepository, RoleRepository, SessionRepository, UserRepository,
+};
+use crate::database::redis::RefreshTokenStore;
+use crate::errors::AppError;
+use crate::handlers::auth::start_session;
+use crate::handlers::role::check_roles;
+use crate::mail::{accept_language, MailService};
+use crate::models::invitation::Invitation;
+use crate::models::request::{AcceptInviteRequest, CreateInvitationRequest};
+use crate::models::response::{InvitationInfo, Response};
+use crate::models::user::{Mfa, User};
+use crate::utils::password::hash_password;
+use crate::utils::password_policy::check_password;
+use crate::utils::token::{decode_action_token, generate_action_token};
+use actix_web::web::{Data, Json, Path};
+use actix_web::{delete, get, post, HttpRequest, HttpResponse, Scope};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use time::OffsetDateTime;
+use validator::Validate;
+
+const INVITATION_PURPOSE: &str = "invitation";
+
+fn invitation_info(invitation: Invitation) -> InvitationInfo {
+    InvitationInfo {
+        id: invitation.id.to_hex(),
+        email: invitation.email,
+        role: invitation.role,
+        invited_by: invitation.invited_by.to_hex(),
+        expires_at: invitation.expires_at.timestamp_millis() / 1000,
+        created_at: invitation.created_at.timestamp_millis() / 1000,
+    }
+}
+
+/// Mails a signed invitation link. A newer invitation for the same address
+/// replaces any pending one.
+#[post("")]
+#[allow(clippy::too_many_arguments)]
+async fn create_invitation(
+    req: HttpRequest,
+    admin: RequirePermission<UsersWrite>,
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    role_repo: Data<RoleRepository>,
+    invitations: Data<InvitationRepository>,
+    mail: Data<MailService>,
+    payload: Json<CreateInvitationRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    if user_repo.find_by_email(&payload.email).await?.is_some() {
+        return Err(AppError::Conflict(EMAIL_ALREADY_EXISTS.into()));
+    }
+
+    // The invitation is sent in a person's name
+    let inviter_id = admin
+        .principal
+        .user_id()
+        .ok_or_else(|| AppError::Forbidden(USER_PRINCIPAL_REQUIRED.into()))?;
+    let inviter_id = ObjectId::parse_str(inviter_id)?;
+    let inviter = user_repo
+        .find_by_id(&inviter_id)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+
+    // Handing out a role is role management
+    if let Some(ref role) = payload.role {
+        if !role_repo
+            .grants(&inviter.roles, PERMISSION_ROLES_MANAGE)
+            .await?
+        {
+            return Err(AppError::Forbidden(PERMISSION_DENIED.into()));
+        }
+        check_roles(&role_repo, std::slice::from_ref(role)).await?;
+    }
+
+    let email = payload.email.to_lowercase();
+    invitations.delete_by_email(&email).await?;
+
+    let id = ObjectId::new();
+    let (token, jti) = generate_action_token(
+        &cfg,
+        INVITATION_PURPOSE,
+        &id.to_hex(),
+        &email,
+        INVITATION_TTL_SECONDS,
+    )?;
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let invitation = Invitation {
+        id,
+        email,
+        role: payload.role.clone(),
+        invited_by: inviter.id,
+        jti,
+        expires_at: DateTime::from_millis((now + INVITATION_TTL_SECONDS) * 1000),
+        created_at: DateTime::now(),
+    };
+    invitations.create(&invitation).await?;
+
+    mail.send(
+        &invitation.email,
+        "invitation",
+        accept_language(&req),
+        &[
+            ("inviter", &inviter.username),
+            (
+                "link",
+                &format!("{}/accept-invite?token={}", cfg.public_url, token),
+            ),
+            ("days", &(INVITATION_TTL_SECONDS / 86400).to_string()),
+        ],
+    )?;
+
+    Ok(HttpResponse::Created().json(Response {
+        msg: INVITATION_SENT.into(),
+        data: Some(invitation_info(invitation)),
+    }))
+}
+
+#[get("")]
+async fn get_invitations(
+    _admin: RequirePermission<UsersRead>,
+    invitations: Data<InvitationRepository>,
+) -> Result<HttpResponse, AppError> {
+    let infos: Vec<InvitationInfo> = invitations
+        .find_pending()
+        .await?
+        .into_iter()
+        .map(invitation_info)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: INVITATIONS_FETCHED.into(),
+        data: Some(infos),
+    }))
+}
+
+#[delete("/{id}")]
+async fn revoke_invitation(
+    _admin: RequirePermission<UsersWrite>,
+    invitations: Data<InvitationRepository>,
+    id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let invitation_id = ObjectId::parse_str(id.as_str())
+        .map_err(|_| AppError::BadRequest(INVALID_INVITATION_ID.into()))?;
+
+    if !invitations.delete(&invitation_id).await? {
+        return Err(AppError::NotFound(INVITATION_NOT_FOUND.into()));
+    }
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: INVITATION_REVOKED.into(),
+        data: None,
+    }))
+}
+
+/// Registers the invitee with a password of their choosing. The emailed link
+/// proves they own the address, so it starts out verified.
+#[post("/accept-invite")]
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn accept_invite(
+    req: HttpRequest,
+    user_repo: Data<UserRepository>,
+    role_repo: Data<RoleRepository>,
+    invitations: Data<InvitationRepository>,
+    sessions: Data<SessionRepository>,
+    refresh_store: Data<RefreshTokenStore>,
+    cfg: Data<AppConfig>,
+    payload: Json<AcceptInviteRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let invalid = || AppError::BadRequest(INVALID_INVITATION.into());
+    let claims =
+        decode_action_token(&cfg, INVITATION_PURPOSE, &payload.token).ok_or_else(invalid)?;
+    let invitation_id = ObjectId::parse_str(&claims.sub).map_err(|_| invalid())?;
+
+    if user_repo.find_by_email(&claims.email).await?.is_some() {
+        return Err(AppError::Conflict(EMAIL_ALREADY_EXISTS.into()));
+    }
+    check_password(
+        &cfg.password_policy,
+        &payload.password,
+        &[&claims.email, &payload.username],
+    )
+    .await?;
+
+    let invitation = invitations
+        .take(&invitation_id, &claims.jti)
+        .await?
+        .ok_or_else(invalid)?;
+
+    // The role may have been deleted since the invitation was sent
+    let mut roles = Vec::new();
+    if let Some(role) = invitation.role {
+        if role_repo.find_by_name(&role).await?.is_some() {
+            roles.push(role);
+        }
+    }
+
+    let user = User {
+        id: ObjectId::new(),
+        email: invitation.email,
+        username: payload.username.clone(),
+        password_hash: hash_password(&cfg.password_hashing, &payload.password).await?,
+        password_history: Vec::new(),
+        password_changed_at: Some(DateTime::now()),
+        roles,
+        token_version: 0,
+        disabled: false,
+        mfa: Mfa::default(),
+        email_verified: true,
+        email_verified_at: Some(DateTime::now()),
+        ldap_dn: None,
+        external_id: None,
+    };
+    user_repo.create(&user).await?;
+
+    let token = start_session(
+        &cfg,
+        &sessions,
+        &refresh_store,
+        &req,
+        &user,
+        payload.device.clone(),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(Response {
+        msg: INVITATION_ACCEPTED.into(),
+        data: Some(token),
+    }))
+}
+
+pub fn invitation_scope() -> Scope {
+    Scope::new("/invitations")
+        .service(create_invitation)
+        .service(get_invitations)
+        .service(revoke_invitation)
+}
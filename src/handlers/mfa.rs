@@ -0,0 +1,204 @@
+use crate::auth::AuthenticatedUser;
+use crate::config::app_config::AppConfig;
+use crate::constants::*;
+use crate::database::mongodb::UserRepository;
+use crate::errors::AppError;
+use crate::models::request::MfaCodeRequest;
+use crate::models::response::{MfaEnrollment, MfaStatus, RecoveryCodes, Response};
+use crate::models::user::User;
+use crate::utils::token::hash_token;
+use crate::utils::totp::{
+    build_totp, generate_recovery_codes, generate_secret, seal_secret, user_secret, verify_code,
+};
+use actix_web::web::{scope, Data, Json};
+use actix_web::{delete, get, post, HttpResponse, Scope};
+use mongodb::bson::oid::ObjectId;
+use validator::Validate;
+
+/// Accepts either a TOTP code or an unused recovery code, consuming the latter.
+pub(super) async fn verify_second_factor(
+    cfg: &AppConfig,
+    user_repo: &UserRepository,
+    user: &User,
+    code: &str,
+) -> Result<bool, AppError> {
+    let code = code.trim();
+
+    if let (true, Some(secret)) = (user.mfa.totp_enabled, user_secret(cfg, user)?) {
+        let totp = build_totp(&secret, &cfg.mfa_issuer, &user.email)?;
+        if let Some(step) = verify_code(&totp, code, user.mfa.totp_last_step) {
+            return user_repo.advance_totp_step(&user.id, step).await;
+        }
+    }
+
+    user_repo
+        .consume_recovery_code(&user.id, &hash_token(&code.to_lowercase()))
+        .await
+}
+
+fn hashed_recovery_codes(codes: &[String]) -> Vec<String> {
+    codes.iter().map(|c| hash_token(c)).collect()
+}
+
+async fn current_user(
+    user_repo: &UserRepository,
+    user: &AuthenticatedUser,
+) -> Result<User, AppError> {
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    user_repo
+        .find_by_id(&uid)
+        .await?
+        .ok_or(AppError::Unauthorized(USER_NOT_FOUND.into()))
+}
+
+#[get("")]
+async fn get_status(
+    user_repo: Data<UserRepository>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let current = current_user(&user_repo, &user).await?;
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: MFA_STATUS_FETCHED.into(),
+        data: Some(MfaStatus {
+            enabled: current.mfa.totp_enabled,
+            recovery_codes_remaining: current.mfa.recovery_codes.len(),
+        }),
+    }))
+}
+
+#[post("/totp")]
+async fn enroll_totp(
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let current = current_user(&user_repo, &user).await?;
+
+    if current.mfa.totp_enabled {
+        return Err(AppError::Conflict(MFA_ALREADY_ENABLED.into()));
+    }
+
+    let secret = generate_secret();
+    let totp = build_totp(&secret, &cfg.mfa_issuer, &current.email)?;
+    let (stored, encrypted) = seal_secret(&cfg, &current.id, &secret)?;
+    user_repo
+        .set_pending_totp(&current.id, &stored, encrypted)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: MFA_ENROLLMENT_STARTED.into(),
+        data: Some(MfaEnrollment {
+            secret,
+            otpauth_uri: totp.get_url(),
+        }),
+    }))
+}
+
+#[post("/totp/confirm")]
+async fn confirm_totp(
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    user: AuthenticatedUser,
+    payload: Json<MfaCodeRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let current = current_user(&user_repo, &user).await?;
+
+    if current.mfa.totp_enabled {
+        return Err(AppError::Conflict(MFA_ALREADY_ENABLED.into()));
+    }
+
+    let secret =
+        user_secret(&cfg, &current)?.ok_or_else(|| AppError::BadRequest(MFA_NOT_PENDING.into()))?;
+    let totp = build_totp(&secret, &cfg.mfa_issuer, &current.email)?;
+    let step = verify_code(&totp, payload.code.trim(), None)
+        .ok_or_else(|| AppError::Unauthorized(INVALID_MFA_CODE.into()))?;
+
+    let codes = generate_recovery_codes(RECOVERY_CODE_COUNT);
+    user_repo
+        .enable_totp(&current.id, step, &hashed_recovery_codes(&codes))
+        .await?;
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: MFA_ENABLED.into(),
+        data: Some(RecoveryCodes {
+            recovery_codes: codes,
+        }),
+    }))
+}
+
+#[post("/recovery-codes")]
+async fn regenerate_recovery_codes(
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    user: AuthenticatedUser,
+    payload: Json<MfaCodeRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let current = current_user(&user_repo, &user).await?;
+
+    if !current.mfa.totp_enabled {
+        return Err(AppError::BadRequest(MFA_NOT_ENABLED.into()));
+    }
+
+    if !verify_second_factor(&cfg, &user_repo, &current, &payload.code).await? {
+        return Err(AppError::Unauthorized(INVALID_MFA_CODE.into()));
+    }
+
+    let codes = generate_recovery_codes(RECOVERY_CODE_COUNT);
+    user_repo
+        .set_recovery_codes(&current.id, &hashed_recovery_codes(&codes))
+        .await?;
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: RECOVERY_CODES_REGENERATED.into(),
+        data: Some(RecoveryCodes {
+            recovery_codes: codes,
+        }),
+    }))
+}
+
+#[delete("")]
+async fn disable_mfa(
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    user: AuthenticatedUser,
+    payload: Json<MfaCodeRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let current = current_user(&user_repo, &user).await?;
+
+    if !current.mfa.totp_enabled {
+        return Err(AppError::BadRequest(MFA_NOT_ENABLED.into()));
+    }
+
+    if !verify_second_factor(&cfg, &user_repo, &current, &payload.code).await? {
+        return Err(AppError::Unauthorized(INVALID_MFA_CODE.into()));
+    }
+
+    user_repo.clear_mfa(&current.id).await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: MFA_DISABLED.into(),
+        data: None,
+    }))
+}
+
+pub fn mfa_scope() -> Scope {
+    scope("/mfa")
+        .service(get_status)
+        .service(enroll_totp)
+        .service(confirm_totp)
+        .service(regenerate_recovery_codes)
+        .service(disable_mfa)
+}
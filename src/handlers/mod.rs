@@ -1,9 +1,27 @@
 mod admin;
+mod api_key;
 mod auth;
+mod federated;
 mod health;
+mod invitation;
+mod mfa;
+mod oauth;
+mod oauth_client;
+mod organization;
+mod password_reset;
+mod role;
+mod scim;
+mod service_account;
 mod user;
+mod verification;
+mod webauthn;
+mod well_known;
 
 pub use admin::admin_scope;
 pub use auth::auth_scope;
 pub use health::health_check;
+pub use oauth::oauth_scope;
+pub use organization::org_scope;
+pub use scim::scim_scope;
 pub use user::user_scope;
+pub use well_known::{jwks, openid_configuration};
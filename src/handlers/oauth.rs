@@ -0,0 +1,780 @@
+use crate::auth::{
+    bearer_token, verify_service_token, verify_user_token, AuthenticatedUser, OAuthUser,
+};
+use crate::config::app_config::AppConfig;
+use crate::constants::*;
+use crate::database::mongodb::{
+    OAuthClientRepository, ServiceAccountRepository, SessionRepository, UserRepository,
+};
+use crate::database::redis::{OneTimeTokenStore, RefreshTokenStore, TokenBlacklist};
+use crate::errors::{AppError, OAuthError};
+use crate::handlers::auth::{ensure_can_sign_in, new_session, open_session, rotate_refresh_token};
+use crate::models::oauth_client::{ClientType, OAuthClient};
+use crate::models::request::{
+    AuthorizeDecisionRequest, AuthorizeRequest, TokenActionRequest, TokenRequest,
+};
+use crate::models::response::{
+    AuthorizationRedirect, OAuthToken, Response, Token, TokenIntrospection,
+};
+use crate::models::service_account::ServiceAccount;
+use crate::utils::token::{
+    decode_token, generate_id_token, generate_opaque_token, generate_service_token, hash_token,
+    secrets_match, IdentityClaims, PrincipalKind,
+};
+use actix_web::http::header::{AUTHORIZATION, CACHE_CONTROL, LOCATION, PRAGMA};
+use actix_web::web::{Data, Form, Json, Query};
+use actix_web::{get, post, route, HttpRequest, HttpResponse, Scope};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+
+const CODE_PURPOSE: &str = "oauth_code";
+const CONSENT_PAGE: &str = include_str!("../../templates/oauth/consent.html");
+
+/// What an authorization code stands for, kept in Redis until it is redeemed.
+#[derive(Serialize, Deserialize)]
+struct AuthorizationCode {
+    client_id: String,
+    user_id: String,
+    redirect_uri: Option<String>, // as sent in the authorization request
+    scope: String,
+    code_challenge: String,
+    nonce: Option<String>,
+}
+
+/// Resolves the client and the redirect uri to answer on. Failures here are
+/// shown to the user instead of being redirected, since the uri is untrusted.
+async fn resolve_client(
+    clients: &OAuthClientRepository,
+    request: &AuthorizeRequest,
+) -> Result<(OAuthClient, String), OAuthError> {
+    let client_id = request
+        .client_id
+        .as_deref()
+        .ok_or_else(|| OAuthError::new("invalid_request", "client_id is required"))?;
+    let client = clients
+        .find_by_client_id(client_id)
+        .await?
+        .ok_or_else(|| OAuthError::new("invalid_request", OAUTH_CLIENT_NOT_FOUND))?;
+
+    let redirect_uri = match request.redirect_uri {
+        Some(ref uri) if client.redirect_uris.contains(uri) => uri.clone(),
+        Some(_) => {
+            return Err(OAuthError::new(
+                "invalid_request",
+                "redirect_uri is not registered for this client",
+            ))
+        }
+        None if client.redirect_uris.len() == 1 => client.redirect_uris[0].clone(),
+        None => {
+            return Err(OAuthError::new(
+                "invalid_request",
+                "redirect_uri is required",
+            ))
+        }
+    };
+
+    Ok((client, redirect_uri))
+}
+
+/// Checks the remaining parameters and returns the granted scopes and the
+/// PKCE challenge. Only S256 challenges are accepted, and `openid` only when
+/// id tokens can be signed with an asymmetric key.
+fn check_request(
+    cfg: &AppConfig,
+    client: &OAuthClient,
+    request: &AuthorizeRequest,
+) -> Result<(Vec<String>, String), OAuthError> {
+    if request.response_type.as_deref() != Some("code") {
+        return Err(OAuthError::new(
+            "unsupported_response_type",
+            "response_type must be code",
+        ));
+    }
+
+    let code_challenge = request
+        .code_challenge
+        .clone()
+        .ok_or_else(|| OAuthError::new("invalid_request", "code_challenge is required"))?;
+    if request.code_challenge_method.as_deref() != Some("S256") {
+        return Err(OAuthError::new(
+            "invalid_request",
+            "code_challenge_method must be S256",
+        ));
+    }
+    if code_challenge.len() != 43
+        || !code_challenge
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    {
+        return Err(OAuthError::new("invalid_request", "invalid code_challenge"));
+    }
+
+    let mut scopes: Vec<String> = Vec::new();
+    for scope in request
+        .scope
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+    {
+        if !client.scopes.iter().any(|s| s == scope) {
+            return Err(OAuthError::new(
+                "invalid_scope",
+                format!("{}: {}", UNKNOWN_SCOPE, scope),
+            ));
+        }
+        if !scopes.iter().any(|s| s == scope) {
+            scopes.push(scope.to_string());
+        }
+    }
+    if scopes.is_empty() {
+        scopes = client.scopes.clone();
+    }
+    if !cfg.jwt_keys.is_asymmetric() && scopes.iter().any(|s| s == "openid") {
+        return Err(OAuthError::new("invalid_scope", OIDC_UNAVAILABLE));
+    }
+
+    Ok((scopes, code_challenge))
+}
+
+/// Appends query parameters to a registered redirect uri.
+fn with_query(uri: &str, params: &[(&str, &str)]) -> String {
+    let query = serde_urlencoded::to_string(params).unwrap_or_default();
+    let separator = if uri.contains('?') { '&' } else { '?' };
+    format!("{}{}{}", uri, separator, query)
+}
+
+fn error_redirect(uri: &str, error: &OAuthError, state: Option<&str>) -> String {
+    let mut params = vec![
+        ("error", error.error),
+        ("error_description", error.description.as_str()),
+    ];
+    if let Some(state) = state {
+        params.push(("state", state));
+    }
+    with_query(uri, &params)
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn scope_description(scope: &str) -> &str {
+    match scope {
+        "openid" => "Confirm your identity",
+        "profile" => "See your username",
+        "email" => "See your email address",
+        other => other,
+    }
+}
+
+fn consent_page(client: &OAuthClient, scopes: &[String], request: &AuthorizeRequest) -> String {
+    let scopes: String = scopes
+        .iter()
+        .map(|scope| format!("<li>{}</li>", escape_html(scope_description(scope))))
+        .collect();
+    // The request is echoed back on consent; escaping `<` keeps it inside
+    // its script element
+    let request = serde_json::to_string(request)
+        .unwrap_or_default()
+        .replace('<', "\\u003c");
+
+    CONSENT_PAGE
+        .replace("{{ client_name }}", &escape_html(&client.name))
+        .replace("{{ scopes }}", &scopes)
+        .replace("{{ request }}", &request)
+}
+
+/// Serves the sign-in and consent page for a valid authorization request.
+#[get("/authorize")]
+async fn authorize(
+    cfg: Data<AppConfig>,
+    clients: Data<OAuthClientRepository>,
+    query: Query<AuthorizeRequest>,
+) -> Result<HttpResponse, OAuthError> {
+    let (client, redirect_uri) = resolve_client(&clients, &query).await?;
+
+    let scopes = match check_request(&cfg, &client, &query) {
+        Ok((scopes, _)) => scopes,
+        Err(e) => {
+            return Ok(HttpResponse::Found()
+                .insert_header((
+                    LOCATION,
+                    error_redirect(&redirect_uri, &e, query.state.as_deref()),
+                ))
+                .finish())
+        }
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .insert_header(("X-Frame-Options", "DENY"))
+        .body(consent_page(&client, &scopes, &query)))
+}
+
+/// Records the signed-in user's decision and tells the page where to send
+/// the browser: back to the client with either a code or an error.
+#[post("/authorize")]
+async fn decide(
+    user: AuthenticatedUser,
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    clients: Data<OAuthClientRepository>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    payload: Json<AuthorizeDecisionRequest>,
+) -> Result<HttpResponse, AppError> {
+    // Client tokens are refused by the extractor, so a client can't approve
+    // requests on the user's behalf
+    let request = &payload.request;
+    let (client, redirect_uri) = resolve_client(&clients, request)
+        .await
+        .map_err(|e| AppError::BadRequest(e.description))?;
+    let state = request.state.as_deref();
+
+    let redirect_to = match check_request(&cfg, &client, request) {
+        Err(e) => error_redirect(&redirect_uri, &e, state),
+        Ok(_) if !payload.approve => error_redirect(
+            &redirect_uri,
+            &OAuthError::new("access_denied", "the user denied the request"),
+            state,
+        ),
+        Ok((scopes, code_challenge)) => {
+            let user_id = ObjectId::parse_str(&user.user_id)?;
+            let account = user_repo
+                .find_by_id(&user_id)
+                .await?
+                .ok_or_else(|| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+            ensure_can_sign_in(&cfg, &account)?;
+
+            let code = generate_opaque_token();
+            let grant = AuthorizationCode {
+                client_id: client.client_id,
+                user_id: user.user_id,
+                redirect_uri: request.redirect_uri.clone(),
+                scope: scopes.join(" "),
+                code_challenge,
+                nonce: request.nonce.clone(),
+            };
+            one_time_tokens
+                .issue(
+                    CODE_PURPOSE,
+                    &hash_token(&code),
+                    &serde_json::to_string(&grant).map_err(|_| AppError::Internal)?,
+                    OAUTH_CODE_TTL_SECONDS,
+                )
+                .await?;
+
+            let mut params = vec![("code", code.as_str())];
+            if let Some(state) = state {
+                params.push(("state", state));
+            }
+            with_query(&redirect_uri, &params)
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: AUTHORIZATION_DECIDED.into(),
+        data: Some(AuthorizationRedirect { redirect_to }),
+    }))
+}
+
+fn invalid_client() -> OAuthError {
+    OAuthError::new("invalid_client", "client authentication failed")
+}
+
+/// The client id and secret from HTTP Basic credentials or the form body.
+fn presented_credentials(
+    req: &HttpRequest,
+    client_id: Option<&String>,
+    client_secret: Option<&String>,
+) -> Result<(String, Option<String>), OAuthError> {
+    let basic = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Basic "))
+        .and_then(|v| STANDARD.decode(v.trim()).ok())
+        .and_then(|v| String::from_utf8(v).ok())
+        .and_then(|v| {
+            v.split_once(':')
+                .map(|(id, secret)| (id.to_string(), secret.to_string()))
+        });
+    match basic {
+        Some((id, secret)) => Ok((id, Some(secret))),
+        None => Ok((
+            client_id.cloned().ok_or_else(invalid_client)?,
+            client_secret.cloned(),
+        )),
+    }
+}
+
+/// Identifies the OAuth client making the request. Confidential clients must
+/// present their secret; public ones rely on PKCE.
+async fn authenticate_client(
+    req: &HttpRequest,
+    clients: &OAuthClientRepository,
+    client_id: Option<&String>,
+    client_secret: Option<&String>,
+) -> Result<OAuthClient, OAuthError> {
+    let (client_id, secret) = presented_credentials(req, client_id, client_secret)?;
+    let client = clients
+        .find_by_client_id(&client_id)
+        .await?
+        .ok_or_else(invalid_client)?;
+
+    if client.client_type == ClientType::Confidential {
+        match (secret, &client.secret_hash) {
+            (Some(secret), Some(hash)) if secrets_match(&hash_token(&secret), hash) => {}
+            _ => return Err(invalid_client()),
+        }
+    }
+
+    Ok(client)
+}
+
+fn token_response(token: OAuthToken) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .insert_header((PRAGMA, "no-cache"))
+        .json(token)
+}
+
+/// Identifies a service account by its client id and secret.
+async fn authenticate_service_account(
+    req: &HttpRequest,
+    accounts: &ServiceAccountRepository,
+    client_id: Option<&String>,
+    client_secret: Option<&String>,
+) -> Result<ServiceAccount, OAuthError> {
+    let (client_id, secret) = presented_credentials(req, client_id, client_secret)?;
+    let account = accounts
+        .find_by_client_id(&client_id)
+        .await?
+        .ok_or_else(invalid_client)?;
+    let secret_matches =
+        secret.is_some_and(|s| secrets_match(&hash_token(&s), &account.secret_hash));
+    if account.disabled || !secret_matches {
+        return Err(invalid_client());
+    }
+
+    Ok(account)
+}
+
+/// Issues a token to a service account authenticating with its own
+/// credentials (RFC 6749 section 4.4). No refresh token is issued.
+async fn client_credentials_grant(
+    req: &HttpRequest,
+    cfg: &AppConfig,
+    accounts: &ServiceAccountRepository,
+    form: &TokenRequest,
+) -> Result<HttpResponse, OAuthError> {
+    let account = authenticate_service_account(
+        req,
+        accounts,
+        form.client_id.as_ref(),
+        form.client_secret.as_ref(),
+    )
+    .await?;
+
+    let mut scopes: Vec<&str> = Vec::new();
+    for scope in form.scope.as_deref().unwrap_or_default().split_whitespace() {
+        if !account.scopes.iter().any(|s| s == scope) {
+            return Err(OAuthError::new(
+                "invalid_scope",
+                format!("{}: {}", UNKNOWN_SCOPE, scope),
+            ));
+        }
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    let scope = if scopes.is_empty() {
+        account.scopes.join(" ")
+    } else {
+        scopes.join(" ")
+    };
+
+    accounts.record_use(&account.id).await?;
+    Ok(token_response(OAuthToken {
+        access_token: generate_service_token(cfg, &account, &scope)?,
+        token_type: "Bearer",
+        expires_in: cfg.access_token_exp_minutes * 60,
+        refresh_token: None,
+        scope: Some(scope),
+        id_token: None,
+    }))
+}
+
+fn verify_code_challenge(verifier: &str, challenge: &str) -> bool {
+    (43..=128).contains(&verifier.len())
+        && verifier
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-._~".contains(&b))
+        && URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())) == challenge
+}
+
+/// Tokens issued for a redeemed authorization code.
+struct CodeGrant {
+    token: Token,
+    scope: String,
+    id_token: Option<String>,
+}
+
+/// Redeems an authorization code for a new session bound to the client, plus
+/// an id token when `openid` was granted.
+#[allow(clippy::too_many_arguments)]
+async fn exchange_code(
+    req: &HttpRequest,
+    cfg: &AppConfig,
+    user_repo: &UserRepository,
+    sessions: &SessionRepository,
+    refresh_store: &RefreshTokenStore,
+    one_time_tokens: &OneTimeTokenStore,
+    client: &OAuthClient,
+    form: &TokenRequest,
+) -> Result<CodeGrant, OAuthError> {
+    let code = form
+        .code
+        .as_deref()
+        .ok_or_else(|| OAuthError::new("invalid_request", "code is required"))?;
+    let verifier = form
+        .code_verifier
+        .as_deref()
+        .ok_or_else(|| OAuthError::new("invalid_request", "code_verifier is required"))?;
+    let invalid = || OAuthError::new("invalid_grant", "invalid or expired authorization code");
+
+    let grant: AuthorizationCode = one_time_tokens
+        .take(CODE_PURPOSE, &hash_token(code))
+        .await?
+        .and_then(|value| serde_json::from_str(&value).ok())
+        .ok_or_else(invalid)?;
+
+    if grant.client_id != client.client_id
+        || grant.redirect_uri != form.redirect_uri
+        || !verify_code_challenge(verifier, &grant.code_challenge)
+    {
+        return Err(invalid());
+    }
+
+    let user_id = ObjectId::parse_str(&grant.user_id).map_err(|_| invalid())?;
+    let user = user_repo.find_by_id(&user_id).await?.ok_or_else(invalid)?;
+    ensure_can_sign_in(cfg, &user)?;
+
+    let mut session = new_session(cfg, req, &user, Some(client.name.clone()));
+    session.client_id = Some(client.client_id.clone());
+    session.scope = Some(grant.scope.clone());
+    let token = open_session(cfg, sessions, refresh_store, &user, &session).await?;
+
+    let id_token = if grant.scope.split_whitespace().any(|s| s == "openid") {
+        Some(generate_id_token(
+            cfg,
+            &user,
+            &client.client_id,
+            &grant.scope,
+            grant.nonce.as_deref(),
+        )?)
+    } else {
+        None
+    };
+
+    Ok(CodeGrant {
+        token,
+        scope: grant.scope,
+        id_token,
+    })
+}
+
+#[post("/token")]
+#[allow(clippy::too_many_arguments)]
+async fn issue_token(
+    req: HttpRequest,
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    sessions: Data<SessionRepository>,
+    refresh_store: Data<RefreshTokenStore>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    clients: Data<OAuthClientRepository>,
+    accounts: Data<ServiceAccountRepository>,
+    form: Form<TokenRequest>,
+) -> Result<HttpResponse, OAuthError> {
+    // Service accounts are not OAuth clients and have a grant of their own
+    if form.grant_type.as_deref() == Some("client_credentials") {
+        return client_credentials_grant(&req, &cfg, &accounts, &form).await;
+    }
+
+    let client = authenticate_client(
+        &req,
+        &clients,
+        form.client_id.as_ref(),
+        form.client_secret.as_ref(),
+    )
+    .await?;
+
+    let (token, scope, id_token) = match form.grant_type.as_deref() {
+        Some("authorization_code") => {
+            let grant = exchange_code(
+                &req,
+                &cfg,
+                &user_repo,
+                &sessions,
+                &refresh_store,
+                &one_time_tokens,
+                &client,
+                &form,
+            )
+            .await?;
+            (grant.token, Some(grant.scope), grant.id_token)
+        }
+        Some("refresh_token") => {
+            let refresh_token = form
+                .refresh_token
+                .as_deref()
+                .ok_or_else(|| OAuthError::new("invalid_request", "refresh_token is required"))?;
+            let (token, session) = rotate_refresh_token(
+                &cfg,
+                &user_repo,
+                &sessions,
+                &refresh_store,
+                refresh_token,
+                Some(&client.client_id),
+            )
+            .await?;
+            (token, session.scope, None)
+        }
+        Some(_) => {
+            return Err(OAuthError::new(
+                "unsupported_grant_type",
+                "grant_type must be authorization_code, refresh_token or client_credentials",
+            ))
+        }
+        None => return Err(OAuthError::new("invalid_request", "grant_type is required")),
+    };
+
+    Ok(token_response(OAuthToken {
+        access_token: token.token,
+        token_type: "Bearer",
+        expires_in: token.expires_in,
+        refresh_token: Some(token.refresh_token),
+        scope,
+        id_token,
+    }))
+}
+
+/// Returns the claims the access token's scopes release about its user.
+#[route("/userinfo", method = "GET", method = "POST")]
+async fn userinfo(
+    user: OAuthUser,
+    user_repo: Data<UserRepository>,
+) -> Result<HttpResponse, OAuthError> {
+    let scope = user.scope;
+    if !scope.split_whitespace().any(|s| s == "openid") {
+        return Err(OAuthError::new(
+            "insufficient_scope",
+            "the access token was not granted the openid scope",
+        ));
+    }
+
+    let user_id = ObjectId::parse_str(&user.user_id)
+        .map_err(|_| OAuthError::new("invalid_token", AUTH_REQUIRED))?;
+    let account = user_repo
+        .find_by_id(&user_id)
+        .await?
+        .ok_or_else(|| OAuthError::new("invalid_token", AUTH_REQUIRED))?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .json(IdentityClaims::for_scope(&account, &scope)))
+}
+
+/// Checks an access token on behalf of a resource server, applying the same
+/// blacklist, token version and session checks as our own endpoints.
+async fn introspect_access_token(
+    cfg: &AppConfig,
+    blacklist: &TokenBlacklist,
+    user_repo: &UserRepository,
+    sessions: &SessionRepository,
+    accounts: &ServiceAccountRepository,
+    token: &str,
+) -> Result<TokenIntrospection, AppError> {
+    let verified = match decode_token(cfg, token) {
+        Ok(claims) if claims.kind == PrincipalKind::Service => {
+            verify_service_token(cfg, Some(blacklist), accounts, token).await
+        }
+        Ok(_) => verify_user_token(cfg, Some(blacklist), user_repo, sessions, token).await,
+        Err(e) => Err(e),
+    };
+
+    let claims = match verified {
+        Ok(claims) => claims,
+        // A database or Redis failure is not an answer about the token
+        Err(e @ (AppError::Database(_) | AppError::Redis(_) | AppError::Internal)) => {
+            return Err(e)
+        }
+        Err(_) => return Ok(TokenIntrospection::default()),
+    };
+
+    Ok(TokenIntrospection {
+        active: true,
+        scope: claims.scope,
+        client_id: claims.client_id,
+        token_type: Some("Bearer"),
+        exp: Some(claims.exp),
+        iat: Some(claims.iat),
+        sub: Some(claims.sub),
+        iss: Some(cfg.oidc_issuer.clone()),
+        kind: Some(claims.kind),
+        sid: claims.sid,
+        org: claims.org,
+    })
+}
+
+/// Token introspection (RFC 7662) for service accounts holding the
+/// `tokens:introspect` scope, authenticated by a client credentials token or
+/// by their id and secret. Only access tokens are reported as active.
+#[post("/introspect")]
+#[allow(clippy::too_many_arguments)]
+async fn introspect(
+    req: HttpRequest,
+    cfg: Data<AppConfig>,
+    blacklist: Data<TokenBlacklist>,
+    user_repo: Data<UserRepository>,
+    sessions: Data<SessionRepository>,
+    accounts: Data<ServiceAccountRepository>,
+    form: Form<TokenActionRequest>,
+) -> Result<HttpResponse, OAuthError> {
+    let allowed = match bearer_token(&req) {
+        Some(token) => verify_service_token(&cfg, Some(&blacklist), &accounts, &token)
+            .await
+            .map_err(|_| OAuthError::new("invalid_token", AUTH_REQUIRED))?
+            .scope
+            .unwrap_or_default()
+            .split_whitespace()
+            .any(|s| s == PERMISSION_TOKENS_INTROSPECT),
+        None => authenticate_service_account(
+            &req,
+            &accounts,
+            form.client_id.as_ref(),
+            form.client_secret.as_ref(),
+        )
+        .await?
+        .scopes
+        .iter()
+        .any(|s| s == PERMISSION_TOKENS_INTROSPECT),
+    };
+    if !allowed {
+        return Err(OAuthError::new(
+            "insufficient_scope",
+            format!("{}: {}", PERMISSION_DENIED, PERMISSION_TOKENS_INTROSPECT),
+        ));
+    }
+
+    let token = form
+        .token
+        .as_deref()
+        .ok_or_else(|| OAuthError::new("invalid_request", "token is required"))?;
+    let introspection =
+        introspect_access_token(&cfg, &blacklist, &user_repo, &sessions, &accounts, token).await?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .json(introspection))
+}
+
+/// Token revocation (RFC 7009) for OAuth clients and service accounts. An
+/// access token is blacklisted until it expires; a refresh token ends its
+/// session. Unknown or already invalid tokens are not an error.
+#[post("/revoke")]
+#[allow(clippy::too_many_arguments)]
+async fn revoke(
+    req: HttpRequest,
+    cfg: Data<AppConfig>,
+    blacklist: Data<TokenBlacklist>,
+    sessions: Data<SessionRepository>,
+    refresh_store: Data<RefreshTokenStore>,
+    clients: Data<OAuthClientRepository>,
+    accounts: Data<ServiceAccountRepository>,
+    form: Form<TokenActionRequest>,
+) -> Result<HttpResponse, OAuthError> {
+    let (client_id, _) =
+        presented_credentials(&req, form.client_id.as_ref(), form.client_secret.as_ref())?;
+    let caller = if clients.find_by_client_id(&client_id).await?.is_some() {
+        authenticate_client(
+            &req,
+            &clients,
+            form.client_id.as_ref(),
+            form.client_secret.as_ref(),
+        )
+        .await?
+        .client_id
+    } else {
+        authenticate_service_account(
+            &req,
+            &accounts,
+            form.client_id.as_ref(),
+            form.client_secret.as_ref(),
+        )
+        .await?
+        .client_id
+    };
+
+    let token = form
+        .token
+        .as_deref()
+        .ok_or_else(|| OAuthError::new("invalid_request", "token is required"))?;
+    let not_yours = || {
+        OAuthError::new(
+            "unauthorized_client",
+            "the token was not issued to this client",
+        )
+    };
+
+    if let Ok(claims) = decode_token(&cfg, token) {
+        if claims.client_id.as_deref() != Some(caller.as_str()) {
+            return Err(not_yours());
+        }
+        let exp_seconds = claims.exp as i64 - OffsetDateTime::now_utc().unix_timestamp();
+        if exp_seconds > 0 {
+            blacklist.add_token(token, exp_seconds).await?;
+        }
+    } else if let Some(record) = refresh_store.find(&hash_token(token)).await? {
+        let (Ok(session_id), Ok(user_id)) = (
+            ObjectId::parse_str(&record.family_id),
+            ObjectId::parse_str(&record.user_id),
+        ) else {
+            return Err(OAuthError::from(AppError::Internal));
+        };
+        match sessions.find(&session_id, &user_id).await? {
+            Some(session) if session.client_id.as_deref() != Some(caller.as_str()) => {
+                return Err(not_yours())
+            }
+            Some(_) => {
+                sessions.delete(&session_id, &user_id).await?;
+                refresh_store.revoke_family(&record.family_id).await?;
+            }
+            None => refresh_store.revoke_family(&record.family_id).await?,
+        }
+    }
+
+    Ok(HttpResponse::Ok()
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .finish())
+}
+
+pub fn oauth_scope() -> Scope {
+    Scope::new("/oauth")
+        .service(authorize)
+        .service(decide)
+        .service(issue_token)
+        .service(userinfo)
+        .service(introspect)
+        .service(revoke)
+}
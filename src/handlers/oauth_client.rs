@@ -0,0 +1,212 @@
+use crate::auth::{ClientsManage, RequirePermission};
+use crate::constants::*;
+use crate::database::mongodb::{OAuthClientRepository, SessionRepository};
+use crate::errors::AppError;
+use crate::models::oauth_client::{ClientType, OAuthClient};
+use crate::models::request::{CreateOAuthClientRequest, UpdateOAuthClientRequest};
+use crate::models::response::{OAuthClientCredentials, OAuthClientInfo, Response};
+use crate::utils::token::{generate_opaque_token, hash_token};
+use actix_web::web::{Data, Json, Path};
+use actix_web::{delete, get, post, put, HttpResponse, Scope};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use validator::Validate;
+
+fn client_info(client: OAuthClient) -> OAuthClientInfo {
+    OAuthClientInfo {
+        client_id: client.client_id,
+        name: client.name,
+        client_type: client.client_type,
+        redirect_uris: client.redirect_uris,
+        scopes: client.scopes,
+        created_at: client.created_at.timestamp_millis() / 1000,
+    }
+}
+
+/// Redirect uris are compared verbatim, so they must be absolute (custom
+/// schemes are allowed for native apps) and carry no fragment.
+fn check_redirect_uris(uris: &[String]) -> Result<(), AppError> {
+    let valid = |uri: &str| {
+        let Some((scheme, rest)) = uri.split_once(':') else {
+            return false;
+        };
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+            && !rest.is_empty()
+            && !uri.contains('#')
+            && !uri.contains(char::is_whitespace)
+    };
+
+    match uris.iter().find(|uri| !valid(uri)) {
+        Some(uri) => Err(AppError::BadRequest(format!(
+            "{}: {}",
+            INVALID_REDIRECT_URI, uri
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn check_scopes(scopes: &[String]) -> Result<(), AppError> {
+    match scopes.iter().find(|s| !OAUTH_SCOPES.contains(&s.as_str())) {
+        Some(s) => Err(AppError::BadRequest(format!("{}: {}", UNKNOWN_SCOPE, s))),
+        None => Ok(()),
+    }
+}
+
+async fn find_client(
+    clients: &OAuthClientRepository,
+    client_id: &str,
+) -> Result<OAuthClient, AppError> {
+    clients
+        .find_by_client_id(client_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(OAUTH_CLIENT_NOT_FOUND.into()))
+}
+
+#[get("")]
+async fn get_clients(
+    _admin: RequirePermission<ClientsManage>,
+    clients: Data<OAuthClientRepository>,
+) -> Result<HttpResponse, AppError> {
+    let infos: Vec<OAuthClientInfo> = clients
+        .find_all()
+        .await?
+        .into_iter()
+        .map(client_info)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: OAUTH_CLIENTS_FETCHED.into(),
+        data: Some(infos),
+    }))
+}
+
+/// Registers a client. The secret of a confidential client is returned once
+/// and only its hash is stored.
+#[post("")]
+async fn create_client(
+    _admin: RequirePermission<ClientsManage>,
+    clients: Data<OAuthClientRepository>,
+    payload: Json<CreateOAuthClientRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+    check_redirect_uris(&payload.redirect_uris)?;
+    check_scopes(&payload.scopes)?;
+
+    let secret = match payload.client_type {
+        ClientType::Confidential => Some(generate_opaque_token()),
+        ClientType::Public => None,
+    };
+    let client = OAuthClient {
+        id: ObjectId::new(),
+        client_id: generate_opaque_token()[..32].to_string(),
+        name: payload.name.clone(),
+        client_type: payload.client_type,
+        secret_hash: secret.as_deref().map(hash_token),
+        redirect_uris: payload.redirect_uris.clone(),
+        scopes: payload.scopes.clone(),
+        created_at: DateTime::now(),
+    };
+    clients.create(&client).await?;
+
+    Ok(HttpResponse::Created().json(Response {
+        msg: OAUTH_CLIENT_CREATED.into(),
+        data: Some(OAuthClientCredentials {
+            client: client_info(client),
+            client_secret: secret,
+        }),
+    }))
+}
+
+#[put("/{client_id}")]
+async fn update_client(
+    _admin: RequirePermission<ClientsManage>,
+    clients: Data<OAuthClientRepository>,
+    client_id: Path<String>,
+    payload: Json<UpdateOAuthClientRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+    if let Some(ref uris) = payload.redirect_uris {
+        check_redirect_uris(uris)?;
+    }
+    if let Some(ref scopes) = payload.scopes {
+        check_scopes(scopes)?;
+    }
+
+    find_client(&clients, &client_id).await?;
+    clients
+        .update(
+            &client_id,
+            payload.name.as_deref(),
+            payload.redirect_uris.as_deref(),
+            payload.scopes.as_deref(),
+        )
+        .await?;
+
+    let client = find_client(&clients, &client_id).await?;
+    Ok(HttpResponse::Ok().json(Response {
+        msg: OAUTH_CLIENT_UPDATED.into(),
+        data: Some(client_info(client)),
+    }))
+}
+
+/// Replaces the secret of a confidential client; the old one stops working
+/// immediately.
+#[post("/{client_id}/secret")]
+async fn rotate_secret(
+    _admin: RequirePermission<ClientsManage>,
+    clients: Data<OAuthClientRepository>,
+    client_id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let client = find_client(&clients, &client_id).await?;
+    if client.client_type == ClientType::Public {
+        return Err(AppError::BadRequest(PUBLIC_CLIENT_SECRET.into()));
+    }
+
+    let secret = generate_opaque_token();
+    clients
+        .set_secret_hash(&client.client_id, &hash_token(&secret))
+        .await?;
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: OAUTH_CLIENT_SECRET_ROTATED.into(),
+        data: Some(OAuthClientCredentials {
+            client: client_info(client),
+            client_secret: Some(secret),
+        }),
+    }))
+}
+
+/// Deletes the client and ends every session it was granted.
+#[delete("/{client_id}")]
+async fn delete_client(
+    _admin: RequirePermission<ClientsManage>,
+    clients: Data<OAuthClientRepository>,
+    sessions: Data<SessionRepository>,
+    client_id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    if !clients.delete(&client_id).await? {
+        return Err(AppError::NotFound(OAUTH_CLIENT_NOT_FOUND.into()));
+    }
+    sessions.delete_by_client(&client_id).await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: OAUTH_CLIENT_DELETED.into(),
+        data: None,
+    }))
+}
+
+pub fn oauth_client_scope() -> Scope {
+    Scope::new("/oauth-clients")
+        .service(get_clients)
+        .service(create_client)
+        .service(update_client)
+        .service(rotate_secret)
+        .service(delete_client)
+}
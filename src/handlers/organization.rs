@@ -0,0 +1,488 @@
+use crate::auth::{AuthenticatedUser, OrgMember};
+use crate::config::app_config::AppConfig;
+use crate::constants::*;
+use crate::database::mongodb::{
+    MembershipRepository, OrgInvitationRepository, OrganizationRepository, SessionRepository,
+    UserRepository,
+};
+use crate::errors::AppError;
+use crate::mail::{accept_language, MailService};
+use crate::models::organization::{Membership, OrgInvitation, OrgRole, Organization};
+use crate::models::request::{
+    AcceptOrgInvitationRequest, CreateOrgInvitationRequest, CreateOrganizationRequest,
+    SwitchOrganizationRequest, UpdateMemberRequest,
+};
+use crate::models::response::{
+    AccessToken, MemberInfo, OrgInvitationInfo, OrganizationInfo, Response,
+};
+use crate::utils::token::{generate_opaque_token, generate_token, hash_token};
+use actix_web::web::{Data, Json, Path};
+use actix_web::{delete, get, post, put, HttpRequest, HttpResponse, Scope};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use time::OffsetDateTime;
+use validator::Validate;
+
+fn organization_info(org: Organization, role: OrgRole, active: bool) -> OrganizationInfo {
+    OrganizationInfo {
+        id: org.id.to_hex(),
+        name: org.name,
+        role,
+        active,
+        created_at: org.created_at.timestamp_millis() / 1000,
+    }
+}
+
+fn invitation_info(invitation: OrgInvitation) -> OrgInvitationInfo {
+    OrgInvitationInfo {
+        id: invitation.id.to_hex(),
+        email: invitation.email,
+        role: invitation.role,
+        expires_at: invitation.expires_at.timestamp_millis() / 1000,
+        created_at: invitation.created_at.timestamp_millis() / 1000,
+    }
+}
+
+/// Only owners may touch owner memberships or hand out the owner role.
+fn ensure_can_assign(
+    member: &OrgMember,
+    current: OrgRole,
+    new: Option<OrgRole>,
+) -> Result<(), AppError> {
+    member.ensure_manager()?;
+    if (current == OrgRole::Owner || new == Some(OrgRole::Owner)) && member.role != OrgRole::Owner {
+        return Err(AppError::Forbidden(ORG_PERMISSION_DENIED.into()));
+    }
+    Ok(())
+}
+
+fn parse_user_id(id: &str) -> Result<ObjectId, AppError> {
+    ObjectId::parse_str(id).map_err(|_| AppError::BadRequest(INVALID_USER_ID.into()))
+}
+
+#[get("")]
+async fn list_organizations(
+    user: AuthenticatedUser,
+    orgs: Data<OrganizationRepository>,
+    memberships: Data<MembershipRepository>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = ObjectId::parse_str(&user.user_id)?;
+    let memberships = memberships.find_by_user(&user_id).await?;
+    let org_ids: Vec<ObjectId> = memberships.iter().map(|m| m.org_id).collect();
+
+    let infos: Vec<OrganizationInfo> = orgs
+        .find_by_ids(&org_ids)
+        .await?
+        .into_iter()
+        .filter_map(|org| {
+            let role = memberships.iter().find(|m| m.org_id == org.id)?.role;
+            let active = user.org_id.as_deref() == Some(org.id.to_hex().as_str());
+            Some(organization_info(org, role, active))
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: ORGANIZATIONS_FETCHED.into(),
+        data: Some(infos),
+    }))
+}
+
+#[post("")]
+async fn create_organization(
+    user: AuthenticatedUser,
+    orgs: Data<OrganizationRepository>,
+    memberships: Data<MembershipRepository>,
+    payload: Json<CreateOrganizationRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let user_id = ObjectId::parse_str(&user.user_id)?;
+    let now = DateTime::now();
+    let org = Organization {
+        id: ObjectId::new(),
+        name: payload.name.clone(),
+        created_by: user_id,
+        created_at: now,
+    };
+    orgs.create(&org).await?;
+    memberships
+        .create(&Membership {
+            id: ObjectId::new(),
+            org_id: org.id,
+            user_id,
+            role: OrgRole::Owner,
+            joined_at: now,
+        })
+        .await?;
+
+    Ok(HttpResponse::Created().json(Response {
+        msg: ORGANIZATION_CREATED.into(),
+        data: Some(organization_info(org, OrgRole::Owner, false)),
+    }))
+}
+
+/// Selects the active organization for the current session and returns an
+/// access token carrying it. Refreshed tokens keep the selection.
+#[post("/switch")]
+async fn switch_organization(
+    user: AuthenticatedUser,
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    sessions: Data<SessionRepository>,
+    memberships: Data<MembershipRepository>,
+    payload: Json<SwitchOrganizationRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = ObjectId::parse_str(&user.user_id)?;
+    let session_id = ObjectId::parse_str(&user.session.id)?;
+
+    let org_id = match payload.org_id {
+        Some(ref org_id) => {
+            let org_id = ObjectId::parse_str(org_id)
+                .map_err(|_| AppError::BadRequest(INVALID_ORGANIZATION_ID.into()))?;
+            memberships
+                .find(&org_id, &user_id)
+                .await?
+                .ok_or_else(|| AppError::Forbidden(NOT_ORG_MEMBER.into()))?;
+            Some(org_id)
+        }
+        None => None,
+    };
+
+    let account = user_repo
+        .find_by_id(&user_id)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+
+    sessions
+        .set_org(&session_id, &user_id, org_id.as_ref())
+        .await?;
+    let session = sessions
+        .find(&session_id, &user_id)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+    let token = generate_token(&cfg, &session, account.token_version)?;
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: ORGANIZATION_SWITCHED.into(),
+        data: Some(AccessToken {
+            token,
+            expires_in: cfg.access_token_exp_minutes * 60,
+        }),
+    }))
+}
+
+/// Joins the organization named by an emailed invitation. The invitation is
+/// bound to the address it was sent to.
+#[post("/invitations/accept")]
+async fn accept_invitation(
+    user: AuthenticatedUser,
+    user_repo: Data<UserRepository>,
+    orgs: Data<OrganizationRepository>,
+    memberships: Data<MembershipRepository>,
+    invitations: Data<OrgInvitationRepository>,
+    payload: Json<AcceptOrgInvitationRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let invalid = || AppError::BadRequest(INVALID_INVITATION.into());
+    let user_id = ObjectId::parse_str(&user.user_id)?;
+    let account = user_repo
+        .find_by_id(&user_id)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+
+    let invitation = invitations
+        .find_by_token_hash(&hash_token(&payload.token))
+        .await?
+        .ok_or_else(invalid)?;
+    if invitation.email != account.email.to_lowercase() {
+        return Err(invalid());
+    }
+    let org = orgs
+        .find_by_id(&invitation.org_id)
+        .await?
+        .ok_or_else(invalid)?;
+
+    if memberships.find(&org.id, &user_id).await?.is_some() {
+        invitations.delete(&org.id, &invitation.id).await?;
+        return Err(AppError::Conflict(ALREADY_ORG_MEMBER.into()));
+    }
+    if !invitations.delete(&org.id, &invitation.id).await? {
+        // accepted concurrently
+        return Err(invalid());
+    }
+
+    memberships
+        .create(&Membership {
+            id: ObjectId::new(),
+            org_id: org.id,
+            user_id,
+            role: invitation.role,
+            joined_at: DateTime::now(),
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: ORG_INVITATION_ACCEPTED.into(),
+        data: Some(organization_info(org, invitation.role, false)),
+    }))
+}
+
+#[get("/current")]
+async fn current_organization(
+    member: OrgMember,
+    orgs: Data<OrganizationRepository>,
+) -> Result<HttpResponse, AppError> {
+    let org = orgs
+        .find_by_id(&member.org_id)
+        .await?
+        .ok_or_else(|| AppError::Forbidden(NOT_ORG_MEMBER.into()))?;
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: ORGANIZATION_FETCHED.into(),
+        data: Some(organization_info(org, member.role, true)),
+    }))
+}
+
+#[delete("/current")]
+async fn delete_organization(
+    member: OrgMember,
+    orgs: Data<OrganizationRepository>,
+    memberships: Data<MembershipRepository>,
+    invitations: Data<OrgInvitationRepository>,
+    sessions: Data<SessionRepository>,
+) -> Result<HttpResponse, AppError> {
+    member.ensure_owner()?;
+
+    invitations.delete_by_org(&member.org_id).await?;
+    memberships.delete_by_org(&member.org_id).await?;
+    sessions.clear_org(&member.org_id, None).await?;
+    orgs.delete(&member.org_id).await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: ORGANIZATION_DELETED.into(),
+        data: None,
+    }))
+}
+
+#[get("/current/members")]
+async fn list_members(
+    member: OrgMember,
+    user_repo: Data<UserRepository>,
+    memberships: Data<MembershipRepository>,
+) -> Result<HttpResponse, AppError> {
+    let memberships = memberships.find_by_org(&member.org_id).await?;
+    let user_ids: Vec<ObjectId> = memberships.iter().map(|m| m.user_id).collect();
+    let users = user_repo.find_by_ids(&user_ids).await?;
+
+    let infos: Vec<MemberInfo> = memberships
+        .into_iter()
+        .filter_map(|m| {
+            let user = users.iter().find(|u| u.id == m.user_id)?;
+            Some(MemberInfo {
+                user_id: m.user_id.to_hex(),
+                email: user.email.clone(),
+                username: user.username.clone(),
+                role: m.role,
+                joined_at: m.joined_at.timestamp_millis() / 1000,
+            })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: MEMBERS_FETCHED.into(),
+        data: Some(infos),
+    }))
+}
+
+#[put("/current/members/{user_id}")]
+async fn update_member(
+    member: OrgMember,
+    memberships: Data<MembershipRepository>,
+    user_id: Path<String>,
+    payload: Json<UpdateMemberRequest>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = parse_user_id(&user_id)?;
+    let target = memberships
+        .find(&member.org_id, &user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(MEMBER_NOT_FOUND.into()))?;
+
+    ensure_can_assign(&member, target.role, Some(payload.role))?;
+
+    if target.role == OrgRole::Owner
+        && payload.role != OrgRole::Owner
+        && memberships.count_owners(&member.org_id).await? <= 1
+    {
+        return Err(AppError::BadRequest(LAST_ORG_OWNER.into()));
+    }
+
+    memberships
+        .update_role(&member.org_id, &user_id, payload.role)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: MEMBER_UPDATED.into(),
+        data: None,
+    }))
+}
+
+/// Removes a member. Any member may remove themselves to leave.
+#[delete("/current/members/{user_id}")]
+async fn remove_member(
+    member: OrgMember,
+    memberships: Data<MembershipRepository>,
+    sessions: Data<SessionRepository>,
+    user_id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = parse_user_id(&user_id)?;
+    let target = memberships
+        .find(&member.org_id, &user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(MEMBER_NOT_FOUND.into()))?;
+
+    if user_id != member.user_id {
+        ensure_can_assign(&member, target.role, None)?;
+    }
+
+    if target.role == OrgRole::Owner && memberships.count_owners(&member.org_id).await? <= 1 {
+        return Err(AppError::BadRequest(LAST_ORG_OWNER.into()));
+    }
+
+    memberships.delete(&member.org_id, &user_id).await?;
+    sessions.clear_org(&member.org_id, Some(&user_id)).await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: MEMBER_REMOVED.into(),
+        data: None,
+    }))
+}
+
+#[get("/current/invitations")]
+async fn list_invitations(
+    member: OrgMember,
+    invitations: Data<OrgInvitationRepository>,
+) -> Result<HttpResponse, AppError> {
+    member.ensure_manager()?;
+
+    let infos: Vec<OrgInvitationInfo> = invitations
+        .find_by_org(&member.org_id)
+        .await?
+        .into_iter()
+        .map(invitation_info)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: ORG_INVITATIONS_FETCHED.into(),
+        data: Some(infos),
+    }))
+}
+
+#[post("/current/invitations")]
+#[allow(clippy::too_many_arguments)]
+async fn create_invitation(
+    req: HttpRequest,
+    member: OrgMember,
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    orgs: Data<OrganizationRepository>,
+    memberships: Data<MembershipRepository>,
+    invitations: Data<OrgInvitationRepository>,
+    mail: Data<MailService>,
+    payload: Json<CreateOrgInvitationRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+    ensure_can_assign(&member, OrgRole::Member, Some(payload.role))?;
+
+    let org = orgs
+        .find_by_id(&member.org_id)
+        .await?
+        .ok_or_else(|| AppError::Forbidden(NOT_ORG_MEMBER.into()))?;
+    let inviter = user_repo
+        .find_by_id(&member.user_id)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+
+    if let Some(existing) = user_repo.find_by_email(&payload.email).await? {
+        if memberships.find(&org.id, &existing.id).await?.is_some() {
+            return Err(AppError::Conflict(ALREADY_ORG_MEMBER.into()));
+        }
+    }
+
+    let token = generate_opaque_token();
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let invitation = OrgInvitation {
+        id: ObjectId::new(),
+        org_id: org.id,
+        email: payload.email.to_lowercase(),
+        role: payload.role,
+        token_hash: hash_token(&token),
+        invited_by: member.user_id,
+        expires_at: DateTime::from_millis((now + ORG_INVITATION_TTL_SECONDS) * 1000),
+        created_at: DateTime::now(),
+    };
+    invitations.create(&invitation).await?;
+
+    mail.send(
+        &payload.email,
+        "org_invitation",
+        accept_language(&req),
+        &[
+            ("org", &org.name),
+            ("inviter", &inviter.username),
+            (
+                "link",
+                &format!("{}/orgs/accept-invite?token={}", cfg.public_url, token),
+            ),
+            ("days", &(ORG_INVITATION_TTL_SECONDS / 86400).to_string()),
+        ],
+    )?;
+
+    Ok(HttpResponse::Created().json(Response {
+        msg: ORG_INVITATION_SENT.into(),
+        data: Some(invitation_info(invitation)),
+    }))
+}
+
+#[delete("/current/invitations/{id}")]
+async fn revoke_invitation(
+    member: OrgMember,
+    invitations: Data<OrgInvitationRepository>,
+    id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    member.ensure_manager()?;
+
+    let invitation_id = ObjectId::parse_str(id.as_str())
+        .map_err(|_| AppError::BadRequest(INVALID_INVITATION_ID.into()))?;
+
+    if !invitations.delete(&member.org_id, &invitation_id).await? {
+        return Err(AppError::NotFound(INVITATION_NOT_FOUND.into()));
+    }
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: ORG_INVITATION_REVOKED.into(),
+        data: None,
+    }))
+}
+
+pub fn org_scope() -> Scope {
+    Scope::new("/orgs")
+        .service(list_organizations)
+        .service(create_organization)
+        .service(switch_organization)
+        .service(accept_invitation)
+        .service(current_organization)
+        .service(delete_organization)
+        .service(list_members)
+        .service(update_member)
+        .service(remove_member)
+        .service(list_invitations)
+        .service(create_invitation)
+        .service(revoke_invitation)
+}
@@ -0,0 +1,169 @@
+use crate::config::app_config::AppConfig;
+use crate::constants::*;
+use crate::database::mongodb::{SessionRepository, UserRepository};
+use crate::database::redis::{OneTimeTokenStore, RefreshTokenStore};
+use crate::errors::AppError;
+use crate::handlers::auth::sign_out_everywhere;
+use crate::mail::{accept_language, MailService};
+use crate::models::request::{ForgotPasswordRequest, ResetPasswordRequest};
+use crate::models::response::Response;
+use crate::utils::password::hash_password;
+use crate::utils::password_policy::{check_password_change, set_password_hash};
+use crate::utils::token::{generate_opaque_token, hash_token};
+use actix_web::web::{scope, Data, Json};
+use actix_web::{post, HttpRequest, HttpResponse, Scope};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+const PASSWORD_RESET_PURPOSE: &str = "password_reset";
+const PASSWORD_RESET_THROTTLE_PURPOSE: &str = "password_reset_throttle";
+
+#[derive(Serialize, Deserialize)]
+struct PasswordResetState {
+    user_id: String,
+    email: String,
+    token_version: i32,
+}
+
+/// Mails a reset link to the local account with this address, at most once
+/// per `PASSWORD_RESET_REQUEST_INTERVAL_SECONDS`. Directory accounts, unknown
+/// addresses and throttled requests get the same reply, without a mail.
+#[post("/forgot")]
+async fn forgot_password(
+    req: HttpRequest,
+    user_repo: Data<UserRepository>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    mail: Data<MailService>,
+    cfg: Data<AppConfig>,
+    payload: Json<ForgotPasswordRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    // Directory accounts change their password in the directory
+    let user = user_repo
+        .find_by_email(&payload.email)
+        .await?
+        .filter(|user| user.ldap_dn.is_none());
+    if let Some(user) = user {
+        let throttle_key = user.id.to_hex();
+        let throttled = one_time_tokens
+            .peek(PASSWORD_RESET_THROTTLE_PURPOSE, &throttle_key)
+            .await?
+            .is_some();
+
+        if !throttled {
+            one_time_tokens
+                .issue(
+                    PASSWORD_RESET_THROTTLE_PURPOSE,
+                    &throttle_key,
+                    "1",
+                    PASSWORD_RESET_REQUEST_INTERVAL_SECONDS,
+                )
+                .await?;
+
+            let reset_token = generate_opaque_token();
+            let state = PasswordResetState {
+                user_id: user.id.to_hex(),
+                email: user.email.clone(),
+                token_version: user.token_version,
+            };
+            one_time_tokens
+                .issue_for_user(
+                    PASSWORD_RESET_PURPOSE,
+                    &hash_token(&reset_token),
+                    &serde_json::to_string(&state).map_err(|_| AppError::Internal)?,
+                    PASSWORD_RESET_TTL_SECONDS,
+                    &state.user_id,
+                )
+                .await?;
+
+            mail.send(
+                &user.email,
+                "password_reset",
+                accept_language(&req),
+                &[
+                    ("username", &user.username),
+                    (
+                        "link",
+                        &format!("{}/reset-password?token={}", cfg.public_url, reset_token),
+                    ),
+                    ("minutes", &(PASSWORD_RESET_TTL_SECONDS / 60).to_string()),
+                ],
+            )?;
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: PASSWORD_RESET_REQUESTED.into(),
+        data: None,
+    }))
+}
+
+#[post("/reset")]
+async fn reset_password(
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    sessions: Data<SessionRepository>,
+    refresh_store: Data<RefreshTokenStore>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    payload: Json<ResetPasswordRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let invalid = || AppError::BadRequest(INVALID_RESET_TOKEN.into());
+    let token_hash = hash_token(&payload.token);
+    let state: PasswordResetState = one_time_tokens
+        .peek(PASSWORD_RESET_PURPOSE, &token_hash)
+        .await?
+        .and_then(|v| serde_json::from_str(&v).ok())
+        .ok_or_else(invalid)?;
+
+    let user_id = ObjectId::parse_str(&state.user_id).map_err(|_| invalid())?;
+    let mut user = user_repo.find_by_id(&user_id).await?.ok_or_else(invalid)?;
+
+    // The link went to the address on file when it was requested, and a
+    // completed reset bumps the token version, retiring any other links
+    if user.email != state.email
+        || user.token_version != state.token_version
+        || user.ldap_dn.is_some()
+    {
+        return Err(invalid());
+    }
+
+    // Checked before the link is used up, so a rejected password can be
+    // replaced with another one
+    check_password_change(&cfg.password_policy, &user, &payload.new_password).await?;
+    if one_time_tokens
+        .take(PASSWORD_RESET_PURPOSE, &token_hash)
+        .await?
+        .is_none()
+    {
+        return Err(invalid());
+    }
+
+    let new_hash = hash_password(&cfg.password_hashing, &payload.new_password).await?;
+    set_password_hash(&cfg.password_policy, &mut user, new_hash);
+    user_repo.update_password(&user).await?;
+    sign_out_everywhere(&user_repo, &sessions, &refresh_store, &user).await?;
+
+    // Receiving the reset link proves ownership of the address
+    if !user.email_verified {
+        user_repo.mark_email_verified(&user_id, &user.email).await?;
+    }
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: PASSWORD_RESET_SUCCESS.into(),
+        data: None,
+    }))
+}
+
+pub fn password_reset_scope() -> Scope {
+    scope("/password")
+        .service(forgot_password)
+        .service(reset_password)
+}
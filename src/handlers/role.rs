@@ -0,0 +1,188 @@
+use crate::auth::{RequirePermission, RolesManage};
+use crate::constants::*;
+use crate::database::mongodb::{RoleRepository, UserRepository};
+use crate::errors::AppError;
+use crate::models::request::{CreateRoleRequest, UpdateRoleRequest};
+use crate::models::response::{Response, RoleInfo};
+use crate::models::role::Role;
+use actix_web::web::{Data, Json, Path};
+use actix_web::{delete, get, post, put, HttpResponse, Scope};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use validator::Validate;
+
+fn role_info(role: Role) -> RoleInfo {
+    RoleInfo {
+        name: role.name,
+        description: role.description,
+        permissions: role.permissions,
+        built_in: role.built_in,
+    }
+}
+
+fn check_permissions(permissions: &[String]) -> Result<(), AppError> {
+    match permissions
+        .iter()
+        .find(|p| !ALL_PERMISSIONS.contains(&p.as_str()))
+    {
+        Some(p) => Err(AppError::BadRequest(format!(
+            "{}: {}",
+            UNKNOWN_PERMISSION, p
+        ))),
+        None => Ok(()),
+    }
+}
+
+pub(super) fn valid_role_name(name: &str) -> bool {
+    (1..=32).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-_:".contains(c))
+}
+
+/// Fails unless every name refers to an existing role.
+pub(super) async fn check_roles(
+    role_repo: &RoleRepository,
+    names: &[String],
+) -> Result<(), AppError> {
+    let found = role_repo.find_by_names(names).await?;
+    match names
+        .iter()
+        .find(|name| !found.iter().any(|role| &role.name == *name))
+    {
+        Some(name) => Err(AppError::BadRequest(format!(
+            "{}: {}",
+            ROLE_NOT_FOUND, name
+        ))),
+        None => Ok(()),
+    }
+}
+
+async fn find_custom_role(role_repo: &RoleRepository, name: &str) -> Result<Role, AppError> {
+    let role = role_repo
+        .find_by_name(name)
+        .await?
+        .ok_or_else(|| AppError::NotFound(ROLE_NOT_FOUND.into()))?;
+
+    if role.built_in {
+        return Err(AppError::Forbidden(BUILTIN_ROLE_IMMUTABLE.into()));
+    }
+
+    Ok(role)
+}
+
+#[get("")]
+async fn get_roles(
+    _admin: RequirePermission<RolesManage>,
+    role_repo: Data<RoleRepository>,
+) -> Result<HttpResponse, AppError> {
+    let roles = role_repo.find_all().await?;
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: ROLES_FETCHED.into(),
+        data: Some(roles.into_iter().map(role_info).collect::<Vec<_>>()),
+    }))
+}
+
+#[post("")]
+async fn create_role(
+    _admin: RequirePermission<RolesManage>,
+    role_repo: Data<RoleRepository>,
+    payload: Json<CreateRoleRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    if !valid_role_name(&payload.name) {
+        return Err(AppError::BadRequest(INVALID_ROLE_NAME.into()));
+    }
+    check_permissions(&payload.permissions)?;
+
+    if role_repo.find_by_name(&payload.name).await?.is_some() {
+        return Err(AppError::Conflict(ROLE_ALREADY_EXISTS.into()));
+    }
+
+    let mut permissions = payload.permissions.clone();
+    permissions.sort();
+    permissions.dedup();
+
+    let role = Role {
+        id: ObjectId::new(),
+        name: payload.name.clone(),
+        description: payload.description.clone(),
+        permissions,
+        built_in: false,
+        created_at: DateTime::now(),
+    };
+    role_repo.create(&role).await?;
+
+    Ok(HttpResponse::Created().json(Response {
+        msg: ROLE_CREATED.into(),
+        data: Some(role_info(role)),
+    }))
+}
+
+#[put("/{name}")]
+async fn update_role(
+    _admin: RequirePermission<RolesManage>,
+    role_repo: Data<RoleRepository>,
+    name: Path<String>,
+    payload: Json<UpdateRoleRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    find_custom_role(&role_repo, &name).await?;
+
+    let permissions = match payload.permissions {
+        Some(ref permissions) => {
+            check_permissions(permissions)?;
+            let mut permissions = permissions.clone();
+            permissions.sort();
+            permissions.dedup();
+            Some(permissions)
+        }
+        None => None,
+    };
+
+    role_repo
+        .update(
+            &name,
+            payload.description.as_deref(),
+            permissions.as_deref(),
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: ROLE_UPDATED.into(),
+        data: None,
+    }))
+}
+
+#[delete("/{name}")]
+async fn delete_role(
+    _admin: RequirePermission<RolesManage>,
+    role_repo: Data<RoleRepository>,
+    user_repo: Data<UserRepository>,
+    name: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    find_custom_role(&role_repo, &name).await?;
+
+    role_repo.delete(&name).await?;
+    user_repo.remove_role(&name).await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: ROLE_DELETED.into(),
+        data: None,
+    }))
+}
+
+pub fn role_scope() -> Scope {
+    Scope::new("/roles")
+        .service(get_roles)
+        .service(create_role)
+        .service(update_role)
+        .service(delete_role)
+}
@@ -0,0 +1,1072 @@
+use crate::auth::ScimClient;
+use crate::config::app_config::AppConfig;
+use crate::constants::*;
+use crate::database::mongodb::{
+    ApiKeyRepository, FederatedIdentityRepository, MembershipRepository, PasskeyRepository,
+    RoleRepository, SessionRepository, UserRepository,
+};
+use crate::database::redis::{OneTimeTokenStore, RefreshTokenStore};
+use crate::errors::{AppError, ScimError};
+use crate::handlers::admin::delete_account;
+use crate::handlers::role::valid_role_name;
+use crate::models::role::Role;
+use crate::models::scim::{
+    ScimEmail, ScimGroup, ScimGroupRequest, ScimListQuery, ScimListResponse, ScimMember,
+    ScimMemberRef, ScimMeta, ScimPatchOperation, ScimPatchRequest, ScimUser, ScimUserRequest,
+};
+use crate::models::user::{fit_username, Mfa, User};
+use crate::utils::password::hash_password;
+use crate::utils::password_policy::{check_password_change, set_password_hash};
+use crate::utils::scim_filter::{self, normalize_path, CompareOp, Field, Filter};
+use actix_web::http::header::LOCATION;
+use actix_web::http::StatusCode;
+use actix_web::web::{Data, Json, JsonConfig, Path, Query, QueryConfig};
+use actix_web::{delete, get, patch, post, put, HttpResponse, Scope};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{DateTime, Document};
+use serde::Serialize;
+use serde_json::{json, Value};
+use validator::ValidateEmail;
+
+fn scim_response<T: Serialize>(status: StatusCode, body: &T) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type(SCIM_CONTENT_TYPE)
+        .json(body)
+}
+
+fn location(cfg: &AppConfig, resource: &str, id: &ObjectId) -> String {
+    format!("{}/scim/v2/{}/{}", cfg.public_url, resource, id.to_hex())
+}
+
+fn rfc3339(time: DateTime) -> String {
+    time.try_to_rfc3339_string().unwrap_or_default()
+}
+
+fn not_found() -> ScimError {
+    ScimError::new(StatusCode::NOT_FOUND, SCIM_RESOURCE_NOT_FOUND)
+}
+
+fn invalid_value() -> ScimError {
+    ScimError::bad_request("invalidValue", INVALID_SCIM_VALUE)
+}
+
+/// Resource ids are ObjectIds, so anything else names no resource.
+fn resource_id(id: &str) -> Result<ObjectId, ScimError> {
+    ObjectId::parse_str(id).map_err(|_| not_found())
+}
+
+/// The query and window of a list request.
+struct Page {
+    filter: Document,
+    start_index: i64,
+    count: i64,
+}
+
+impl Page {
+    fn from_query(
+        query: &ScimListQuery,
+        field: fn(&str) -> Option<Field>,
+    ) -> Result<Self, ScimError> {
+        let filter = match query.filter.as_deref() {
+            Some(filter) => scim_filter::parse(filter)
+                .and_then(|f| f.to_document(&field))
+                .map_err(|e| {
+                    ScimError::bad_request(
+                        "invalidFilter",
+                        format!("{}: {}", INVALID_SCIM_FILTER, e),
+                    )
+                })?,
+            None => Document::new(),
+        };
+
+        Ok(Self {
+            filter,
+            start_index: query.start_index.unwrap_or(1).max(1),
+            count: query
+                .count
+                .unwrap_or(SCIM_DEFAULT_PAGE_SIZE)
+                .clamp(0, SCIM_MAX_PAGE_SIZE),
+        })
+    }
+
+    fn skip(&self) -> u64 {
+        (self.start_index - 1) as u64
+    }
+
+    fn respond<T: Serialize>(&self, total: u64, resources: Vec<T>) -> HttpResponse {
+        scim_response(
+            StatusCode::OK,
+            &ScimListResponse {
+                schemas: [SCIM_SCHEMA_LIST_RESPONSE],
+                total_results: total,
+                start_index: self.start_index,
+                items_per_page: resources.len(),
+                resources,
+            },
+        )
+    }
+}
+
+fn user_field(path: &str) -> Option<Field> {
+    match path {
+        "id" => Some(Field::Id),
+        "username" | "emails" | "emails.value" => Some(Field::Text("email")),
+        "displayname" => Some(Field::Text("username")),
+        "externalid" => Some(Field::Text("external_id")),
+        "active" => Some(Field::Flag("disabled", true)),
+        _ => None,
+    }
+}
+
+fn group_field(path: &str) -> Option<Field> {
+    match path {
+        "id" => Some(Field::Id),
+        "displayname" => Some(Field::Text("name")),
+        _ => None,
+    }
+}
+
+fn member(cfg: &AppConfig, resource: &str, id: &ObjectId, display: &str) -> ScimMember {
+    ScimMember {
+        value: id.to_hex(),
+        display: display.into(),
+        reference: location(cfg, resource, id),
+    }
+}
+
+fn user_resource(cfg: &AppConfig, roles: &[Role], user: User) -> ScimUser {
+    ScimUser {
+        schemas: [SCIM_SCHEMA_USER],
+        id: user.id.to_hex(),
+        external_id: user.external_id,
+        user_name: user.email.clone(),
+        display_name: user.username,
+        emails: vec![ScimEmail {
+            value: user.email,
+            primary: true,
+        }],
+        active: !user.disabled,
+        groups: roles
+            .iter()
+            .filter(|r| user.roles.contains(&r.name))
+            .map(|r| member(cfg, "Groups", &r.id, &r.name))
+            .collect(),
+        meta: ScimMeta {
+            resource_type: "User",
+            created: rfc3339(user.id.timestamp()),
+            location: location(cfg, "Users", &user.id),
+        },
+    }
+}
+
+/// Renders users with their groups, looking up all of their roles at once.
+async fn user_resources(
+    cfg: &AppConfig,
+    role_repo: &RoleRepository,
+    users: Vec<User>,
+) -> Result<Vec<ScimUser>, ScimError> {
+    let mut names: Vec<String> = users.iter().flat_map(|u| u.roles.clone()).collect();
+    names.sort();
+    names.dedup();
+    let roles = role_repo.find_by_names(&names).await?;
+
+    Ok(users
+        .into_iter()
+        .map(|user| user_resource(cfg, &roles, user))
+        .collect())
+}
+
+async fn group_resource(
+    cfg: &AppConfig,
+    user_repo: &UserRepository,
+    role: Role,
+    with_members: bool,
+) -> Result<ScimGroup, ScimError> {
+    let members = if with_members {
+        let users = user_repo.find_by_role(&role.name).await?;
+        Some(
+            users
+                .iter()
+                .map(|u| member(cfg, "Users", &u.id, &u.username))
+                .collect(),
+        )
+    } else {
+        None
+    };
+
+    Ok(ScimGroup {
+        schemas: [SCIM_SCHEMA_GROUP],
+        id: role.id.to_hex(),
+        members,
+        meta: ScimMeta {
+            resource_type: "Group",
+            created: rfc3339(role.created_at),
+            location: location(cfg, "Groups", &role.id),
+        },
+        display_name: role.name,
+    })
+}
+
+// Listing members is the expensive part of a group, so clients that only
+// need names ask for it to be left out
+fn excludes_members(query: &ScimListQuery) -> bool {
+    query
+        .excluded_attributes
+        .as_deref()
+        .is_some_and(|a| a.split(',').any(|a| normalize_path(a) == "members"))
+}
+
+async fn find_user(user_repo: &UserRepository, id: &str) -> Result<User, ScimError> {
+    user_repo
+        .find_by_id(&resource_id(id)?)
+        .await?
+        .ok_or_else(not_found)
+}
+
+async fn find_group(role_repo: &RoleRepository, id: &str) -> Result<Role, ScimError> {
+    role_repo
+        .find_by_id(&resource_id(id)?)
+        .await?
+        .ok_or_else(not_found)
+}
+
+fn string_value(value: &Value) -> Result<&str, ScimError> {
+    value.as_str().ok_or_else(invalid_value)
+}
+
+fn bool_value(value: &Value) -> Result<bool, ScimError> {
+    match value {
+        Value::Bool(flag) => Ok(*flag),
+        // Some clients send booleans as "True" and "False"
+        Value::String(s) if s.eq_ignore_ascii_case("true") => Ok(true),
+        Value::String(s) if s.eq_ignore_ascii_case("false") => Ok(false),
+        _ => Err(invalid_value()),
+    }
+}
+
+fn email_value(value: &str) -> Result<String, ScimError> {
+    if !value.validate_email() {
+        return Err(ScimError::bad_request(
+            "invalidValue",
+            SCIM_USER_NAME_REQUIRED,
+        ));
+    }
+    Ok(value.to_string())
+}
+
+/// Sets one attribute for an add or replace. Attributes this server does not
+/// store, such as `name` or `title`, are ignored. A new password is handed
+/// back to be hashed once all operations have been applied.
+fn set_user_attribute(
+    user: &mut User,
+    password: &mut Option<String>,
+    path: &str,
+    value: &Value,
+) -> Result<(), ScimError> {
+    match normalize_path(path).as_str() {
+        "username" => user.email = email_value(string_value(value)?)?,
+        "emails" => {
+            let emails = value.as_array().ok_or_else(invalid_value)?;
+            let email = emails
+                .iter()
+                .find(|e| e["primary"] == Value::Bool(true))
+                .or_else(|| emails.first())
+                .ok_or_else(invalid_value)?;
+            user.email = email_value(string_value(&email["value"])?)?;
+        }
+        path if path.starts_with("emails[") && path.ends_with("].value") => {
+            user.email = email_value(string_value(value)?)?
+        }
+        "displayname" => user.username = fit_username(string_value(value)?),
+        "externalid" => user.external_id = value.as_str().map(String::from),
+        "active" => user.disabled = !bool_value(value)?,
+        "password" => *password = Some(string_value(value)?.to_string()),
+        _ => {}
+    }
+    Ok(())
+}
+
+fn apply_user_operation(
+    user: &mut User,
+    password: &mut Option<String>,
+    operation: &ScimPatchOperation,
+) -> Result<(), ScimError> {
+    let op = operation.op.to_ascii_lowercase();
+    match (op.as_str(), operation.path.as_deref()) {
+        ("add" | "replace", Some(path)) => set_user_attribute(
+            user,
+            password,
+            path,
+            operation.value.as_ref().unwrap_or(&Value::Null),
+        ),
+        ("add" | "replace", None) => {
+            let attributes = operation
+                .value
+                .as_ref()
+                .and_then(Value::as_object)
+                .ok_or_else(invalid_value)?;
+            for (name, value) in attributes {
+                set_user_attribute(user, password, name, value)?;
+            }
+            Ok(())
+        }
+        ("remove", Some(path)) => match normalize_path(path).as_str() {
+            "externalid" => {
+                user.external_id = None;
+                Ok(())
+            }
+            "username" | "emails" | "displayname" | "active" | "password" => Err(
+                ScimError::bad_request("mutability", format!("{} cannot be removed", path)),
+            ),
+            _ => Ok(()),
+        },
+        ("remove", None) => Err(ScimError::bad_request("noTarget", INVALID_SCIM_PATH)),
+        _ => Err(ScimError::bad_request(
+            "invalidSyntax",
+            INVALID_SCIM_OPERATION,
+        )),
+    }
+}
+
+/// Writes a provisioning change. Disabling an account signs it out everywhere
+/// by bumping its token version and ending its sessions.
+async fn save_user(
+    user_repo: &UserRepository,
+    sessions: &SessionRepository,
+    before: &User,
+    mut user: User,
+) -> Result<User, ScimError> {
+    if user.email != before.email {
+        if user_repo.find_by_email(&user.email).await?.is_some() {
+            return Err(AppError::Conflict(EMAIL_ALREADY_EXISTS.into()).into());
+        }
+        // The identity provider vouches for the addresses it provisions
+        user.email_verified = true;
+        user.email_verified_at = Some(DateTime::now());
+    }
+    if user.disabled && !before.disabled {
+        user.token_version += 1;
+        sessions.delete_by_user(&user.id).await?;
+    }
+
+    user_repo.replace(&user).await?;
+    Ok(user)
+}
+
+/// Sets a password sent by the client once it passes the password policy.
+async fn set_password(cfg: &AppConfig, user: &mut User, password: &str) -> Result<(), ScimError> {
+    check_password_change(&cfg.password_policy, user, password).await?;
+    let hash = hash_password(&cfg.password_hashing, password).await?;
+    set_password_hash(&cfg.password_policy, user, hash);
+    Ok(())
+}
+
+fn check_patch_schema(payload: &ScimPatchRequest) -> Result<(), ScimError> {
+    if !payload.schemas.iter().any(|s| s == SCIM_SCHEMA_PATCH_OP) {
+        return Err(ScimError::bad_request(
+            "invalidSyntax",
+            format!("schemas must contain {}", SCIM_SCHEMA_PATCH_OP),
+        ));
+    }
+    Ok(())
+}
+
+#[get("/Users")]
+async fn list_users(
+    _client: ScimClient,
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    role_repo: Data<RoleRepository>,
+    query: Query<ScimListQuery>,
+) -> Result<HttpResponse, ScimError> {
+    let page = Page::from_query(&query, user_field)?;
+
+    let total = user_repo.count(page.filter.clone()).await?;
+    // MongoDB treats a limit of zero as no limit
+    let users = if page.count == 0 {
+        Vec::new()
+    } else {
+        user_repo
+            .find_page(page.filter.clone(), page.skip(), page.count)
+            .await?
+    };
+
+    Ok(page.respond(total, user_resources(&cfg, &role_repo, users).await?))
+}
+
+#[get("/Users/{id}")]
+async fn get_user(
+    _client: ScimClient,
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    role_repo: Data<RoleRepository>,
+    id: Path<String>,
+) -> Result<HttpResponse, ScimError> {
+    let user = find_user(&user_repo, &id).await?;
+    let resource = user_resources(&cfg, &role_repo, vec![user])
+        .await?
+        .remove(0);
+
+    Ok(scim_response(StatusCode::OK, &resource))
+}
+
+#[post("/Users")]
+async fn create_user(
+    _client: ScimClient,
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    payload: Json<ScimUserRequest>,
+) -> Result<HttpResponse, ScimError> {
+    let email = email_value(&payload.user_name)?;
+    if user_repo.find_by_email(&email).await?.is_some() {
+        return Err(AppError::Conflict(EMAIL_ALREADY_EXISTS.into()).into());
+    }
+
+    let display_name = payload
+        .display_name
+        .as_deref()
+        .unwrap_or_else(|| email.split('@').next().unwrap_or_default());
+
+    let mut user = User {
+        id: ObjectId::new(),
+        username: fit_username(display_name),
+        password_hash: String::new(),
+        password_history: Vec::new(),
+        password_changed_at: None,
+        roles: Vec::new(),
+        token_version: 0,
+        disabled: !payload.active.unwrap_or(true),
+        mfa: Mfa::default(),
+        email_verified: true,
+        email_verified_at: Some(DateTime::now()),
+        ldap_dn: None,
+        external_id: payload.external_id.clone(),
+        email,
+    };
+    // Without a password the user signs in through federation or LDAP
+    if let Some(password) = payload.password.as_deref() {
+        set_password(&cfg, &mut user, password).await?;
+    }
+    user_repo.create(&user).await?;
+
+    let resource = user_resource(&cfg, &[], user);
+    Ok(HttpResponse::Created()
+        .content_type(SCIM_CONTENT_TYPE)
+        .insert_header((LOCATION, resource.meta.location.clone()))
+        .json(resource))
+}
+
+#[allow(clippy::too_many_arguments)]
+#[put("/Users/{id}")]
+async fn replace_user(
+    _client: ScimClient,
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    role_repo: Data<RoleRepository>,
+    sessions: Data<SessionRepository>,
+    id: Path<String>,
+    payload: Json<ScimUserRequest>,
+) -> Result<HttpResponse, ScimError> {
+    let before = find_user(&user_repo, &id).await?;
+
+    let mut user = before.clone();
+    user.email = email_value(&payload.user_name)?;
+    if let Some(display_name) = payload.display_name.as_deref() {
+        user.username = fit_username(display_name);
+    }
+    user.external_id = payload.external_id.clone();
+    user.disabled = !payload.active.unwrap_or(true);
+    if let Some(password) = payload.password.as_deref() {
+        set_password(&cfg, &mut user, password).await?;
+    }
+
+    let user = save_user(&user_repo, &sessions, &before, user).await?;
+    let resource = user_resources(&cfg, &role_repo, vec![user])
+        .await?
+        .remove(0);
+
+    Ok(scim_response(StatusCode::OK, &resource))
+}
+
+#[allow(clippy::too_many_arguments)]
+#[patch("/Users/{id}")]
+async fn patch_user(
+    _client: ScimClient,
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    role_repo: Data<RoleRepository>,
+    sessions: Data<SessionRepository>,
+    id: Path<String>,
+    payload: Json<ScimPatchRequest>,
+) -> Result<HttpResponse, ScimError> {
+    check_patch_schema(&payload)?;
+    let before = find_user(&user_repo, &id).await?;
+
+    let mut user = before.clone();
+    let mut password = None;
+    for operation in &payload.operations {
+        apply_user_operation(&mut user, &mut password, operation)?;
+    }
+    if let Some(password) = password {
+        set_password(&cfg, &mut user, &password).await?;
+    }
+
+    let user = save_user(&user_repo, &sessions, &before, user).await?;
+    let resource = user_resources(&cfg, &role_repo, vec![user])
+        .await?
+        .remove(0);
+
+    Ok(scim_response(StatusCode::OK, &resource))
+}
+
+#[delete("/Users/{id}")]
+#[allow(clippy::too_many_arguments)]
+async fn delete_user(
+    _client: ScimClient,
+    user_repo: Data<UserRepository>,
+    memberships: Data<MembershipRepository>,
+    api_keys: Data<ApiKeyRepository>,
+    identities: Data<FederatedIdentityRepository>,
+    passkeys: Data<PasskeyRepository>,
+    sessions: Data<SessionRepository>,
+    refresh_store: Data<RefreshTokenStore>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    id: Path<String>,
+) -> Result<HttpResponse, ScimError> {
+    let user = find_user(&user_repo, &id).await?;
+
+    delete_account(
+        &user_repo,
+        &memberships,
+        &api_keys,
+        &identities,
+        &passkeys,
+        &sessions,
+        &refresh_store,
+        &one_time_tokens,
+        &user.id,
+    )
+    .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+fn parse_members(refs: &[ScimMemberRef]) -> Result<Vec<ObjectId>, ScimError> {
+    let mut ids = Vec::with_capacity(refs.len());
+    for member in refs {
+        let id = ObjectId::parse_str(&member.value).map_err(|_| {
+            ScimError::bad_request(
+                "invalidValue",
+                format!("{}: {}", USER_NOT_FOUND, member.value),
+            )
+        })?;
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
+
+fn member_values(value: Option<&Value>) -> Result<Vec<ObjectId>, ScimError> {
+    let refs: Vec<ScimMemberRef> = serde_json::from_value(value.cloned().unwrap_or(Value::Null))
+        .map_err(|_| invalid_value())?;
+    parse_members(&refs)
+}
+
+/// Fails unless every id belongs to an existing user.
+async fn check_members(user_repo: &UserRepository, ids: &[ObjectId]) -> Result<(), ScimError> {
+    let found = user_repo.find_by_ids(ids).await?;
+    if let Some(missing) = ids.iter().find(|id| !found.iter().any(|u| u.id == **id)) {
+        return Err(ScimError::bad_request(
+            "invalidValue",
+            format!("{}: {}", USER_NOT_FOUND, missing.to_hex()),
+        ));
+    }
+    Ok(())
+}
+
+/// Reads the member id out of a path like `members[value eq "..."]`.
+fn member_filter(path: &str) -> Result<ObjectId, ScimError> {
+    let invalid = || ScimError::bad_request("invalidFilter", INVALID_SCIM_PATH);
+
+    let filter = path
+        .strip_prefix("members[")
+        .and_then(|p| p.strip_suffix(']'))
+        .ok_or_else(invalid)?;
+    match scim_filter::parse(filter).map_err(|_| invalid())? {
+        Filter::Compare(attribute, CompareOp::Eq, Value::String(id))
+            if normalize_path(&attribute) == "value" =>
+        {
+            ObjectId::parse_str(&id).map_err(|_| invalid())
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Applies one PATCH operation to the group's name and member list.
+/// Nothing is written until every operation has been applied.
+fn apply_group_operation(
+    name: &mut String,
+    members: &mut Vec<ObjectId>,
+    operation: &ScimPatchOperation,
+) -> Result<(), ScimError> {
+    let op = operation.op.to_ascii_lowercase();
+    let path = operation.path.as_deref().map(normalize_path);
+    let value = operation.value.as_ref();
+
+    match (op.as_str(), path.as_deref()) {
+        ("add" | "replace", None) => {
+            let attributes = value.and_then(Value::as_object).ok_or_else(invalid_value)?;
+            for (attribute, value) in attributes {
+                match normalize_path(attribute).as_str() {
+                    "displayname" => *name = string_value(value)?.to_string(),
+                    "members" if op == "add" => add_members(members, member_values(Some(value))?),
+                    "members" => *members = member_values(Some(value))?,
+                    _ => {}
+                }
+            }
+        }
+        ("add" | "replace", Some("displayname")) => {
+            *name = string_value(value.unwrap_or(&Value::Null))?.to_string()
+        }
+        ("add", Some("members")) => add_members(members, member_values(value)?),
+        ("replace", Some("members")) => *members = member_values(value)?,
+        // Some clients name the members to remove in the value
+        ("remove", Some("members")) => match value {
+            Some(value) => {
+                let removed = member_values(Some(value))?;
+                members.retain(|id| !removed.contains(id));
+            }
+            None => members.clear(),
+        },
+        ("remove", Some(path)) if path.starts_with("members[") => {
+            let removed = member_filter(path)?;
+            members.retain(|id| *id != removed);
+        }
+        ("add" | "replace" | "remove", _) => {
+            return Err(ScimError::bad_request("invalidPath", INVALID_SCIM_PATH))
+        }
+        _ => {
+            return Err(ScimError::bad_request(
+                "invalidSyntax",
+                INVALID_SCIM_OPERATION,
+            ))
+        }
+    }
+    Ok(())
+}
+
+fn add_members(members: &mut Vec<ObjectId>, added: Vec<ObjectId>) {
+    for id in added {
+        if !members.contains(&id) {
+            members.push(id);
+        }
+    }
+}
+
+/// Renames the role behind a group and brings its members in line with
+/// `members`. Built-in roles keep their names.
+async fn save_group(
+    role_repo: &RoleRepository,
+    user_repo: &UserRepository,
+    role: &Role,
+    name: &str,
+    members: &[ObjectId],
+) -> Result<(), ScimError> {
+    let current: Vec<ObjectId> = user_repo
+        .find_by_role(&role.name)
+        .await?
+        .iter()
+        .map(|u| u.id)
+        .collect();
+    let added: Vec<ObjectId> = members
+        .iter()
+        .filter(|id| !current.contains(id))
+        .copied()
+        .collect();
+    let removed: Vec<ObjectId> = current
+        .iter()
+        .filter(|id| !members.contains(id))
+        .copied()
+        .collect();
+    check_members(user_repo, &added).await?;
+
+    if name != role.name {
+        if role.built_in {
+            return Err(AppError::Forbidden(BUILTIN_ROLE_IMMUTABLE.into()).into());
+        }
+        if !valid_role_name(name) {
+            return Err(ScimError::bad_request("invalidValue", INVALID_ROLE_NAME));
+        }
+        if role_repo.find_by_name(name).await?.is_some() {
+            return Err(AppError::Conflict(ROLE_ALREADY_EXISTS.into()).into());
+        }
+        role_repo.rename(&role.id, name).await?;
+        user_repo.rename_role(&role.name, name).await?;
+    }
+
+    user_repo.add_role_to(&added, name).await?;
+    user_repo.remove_role_from(&removed, name).await?;
+    Ok(())
+}
+
+#[get("/Groups")]
+async fn list_groups(
+    _client: ScimClient,
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    role_repo: Data<RoleRepository>,
+    query: Query<ScimListQuery>,
+) -> Result<HttpResponse, ScimError> {
+    let page = Page::from_query(&query, group_field)?;
+
+    let total = role_repo.count(page.filter.clone()).await?;
+    let roles = if page.count == 0 {
+        Vec::new()
+    } else {
+        role_repo
+            .find_page(page.filter.clone(), page.skip(), page.count)
+            .await?
+    };
+
+    let with_members = !excludes_members(&query);
+    let mut groups = Vec::with_capacity(roles.len());
+    for role in roles {
+        groups.push(group_resource(&cfg, &user_repo, role, with_members).await?);
+    }
+
+    Ok(page.respond(total, groups))
+}
+
+#[get("/Groups/{id}")]
+async fn get_group(
+    _client: ScimClient,
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    role_repo: Data<RoleRepository>,
+    id: Path<String>,
+    query: Query<ScimListQuery>,
+) -> Result<HttpResponse, ScimError> {
+    let role = find_group(&role_repo, &id).await?;
+    let group = group_resource(&cfg, &user_repo, role, !excludes_members(&query)).await?;
+
+    Ok(scim_response(StatusCode::OK, &group))
+}
+
+/// Groups are roles. A provisioned group starts without permissions; an
+/// administrator grants them through `/admin/roles`.
+#[post("/Groups")]
+async fn create_group(
+    _client: ScimClient,
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    role_repo: Data<RoleRepository>,
+    payload: Json<ScimGroupRequest>,
+) -> Result<HttpResponse, ScimError> {
+    if !valid_role_name(&payload.display_name) {
+        return Err(ScimError::bad_request("invalidValue", INVALID_ROLE_NAME));
+    }
+    if role_repo
+        .find_by_name(&payload.display_name)
+        .await?
+        .is_some()
+    {
+        return Err(AppError::Conflict(ROLE_ALREADY_EXISTS.into()).into());
+    }
+    let members = parse_members(&payload.members)?;
+    check_members(&user_repo, &members).await?;
+
+    let role = Role {
+        id: ObjectId::new(),
+        name: payload.display_name.clone(),
+        description: None,
+        permissions: Vec::new(),
+        built_in: false,
+        created_at: DateTime::now(),
+    };
+    role_repo.create(&role).await?;
+    user_repo.add_role_to(&members, &role.name).await?;
+
+    let group = group_resource(&cfg, &user_repo, role, true).await?;
+    Ok(HttpResponse::Created()
+        .content_type(SCIM_CONTENT_TYPE)
+        .insert_header((LOCATION, group.meta.location.clone()))
+        .json(group))
+}
+
+#[put("/Groups/{id}")]
+async fn replace_group(
+    _client: ScimClient,
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    role_repo: Data<RoleRepository>,
+    id: Path<String>,
+    payload: Json<ScimGroupRequest>,
+) -> Result<HttpResponse, ScimError> {
+    let role = find_group(&role_repo, &id).await?;
+    let members = parse_members(&payload.members)?;
+
+    save_group(
+        &role_repo,
+        &user_repo,
+        &role,
+        &payload.display_name,
+        &members,
+    )
+    .await?;
+
+    let role = find_group(&role_repo, &id).await?;
+    let group = group_resource(&cfg, &user_repo, role, true).await?;
+    Ok(scim_response(StatusCode::OK, &group))
+}
+
+#[patch("/Groups/{id}")]
+async fn patch_group(
+    _client: ScimClient,
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    role_repo: Data<RoleRepository>,
+    id: Path<String>,
+    payload: Json<ScimPatchRequest>,
+) -> Result<HttpResponse, ScimError> {
+    check_patch_schema(&payload)?;
+    let role = find_group(&role_repo, &id).await?;
+
+    let mut name = role.name.clone();
+    let mut members: Vec<ObjectId> = user_repo
+        .find_by_role(&role.name)
+        .await?
+        .iter()
+        .map(|u| u.id)
+        .collect();
+    for operation in &payload.operations {
+        apply_group_operation(&mut name, &mut members, operation)?;
+    }
+
+    save_group(&role_repo, &user_repo, &role, &name, &members).await?;
+
+    let role = find_group(&role_repo, &id).await?;
+    let group = group_resource(&cfg, &user_repo, role, true).await?;
+    Ok(scim_response(StatusCode::OK, &group))
+}
+
+#[delete("/Groups/{id}")]
+async fn delete_group(
+    _client: ScimClient,
+    user_repo: Data<UserRepository>,
+    role_repo: Data<RoleRepository>,
+    id: Path<String>,
+) -> Result<HttpResponse, ScimError> {
+    let role = find_group(&role_repo, &id).await?;
+    if role.built_in {
+        return Err(AppError::Forbidden(BUILTIN_ROLE_IMMUTABLE.into()).into());
+    }
+
+    role_repo.delete(&role.name).await?;
+    user_repo.remove_role(&role.name).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/ServiceProviderConfig")]
+async fn service_provider_config(
+    _client: ScimClient,
+    cfg: Data<AppConfig>,
+) -> Result<HttpResponse, ScimError> {
+    Ok(scim_response(
+        StatusCode::OK,
+        &json!({
+            "schemas": [SCIM_SCHEMA_SERVICE_PROVIDER_CONFIG],
+            "patch": { "supported": true },
+            "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+            "filter": { "supported": true, "maxResults": SCIM_MAX_PAGE_SIZE },
+            "changePassword": { "supported": true },
+            "sort": { "supported": false },
+            "etag": { "supported": false },
+            "authenticationSchemes": [{
+                "type": "oauthbearertoken",
+                "name": "Bearer token",
+                "description": "The token configured in SCIM_TOKEN",
+                "primary": true,
+            }],
+            "meta": {
+                "resourceType": "ServiceProviderConfig",
+                "location": format!("{}/scim/v2/ServiceProviderConfig", cfg.public_url),
+            },
+        }),
+    ))
+}
+
+fn resource_types(cfg: &AppConfig) -> Vec<Value> {
+    [
+        ("User", "/Users", SCIM_SCHEMA_USER),
+        ("Group", "/Groups", SCIM_SCHEMA_GROUP),
+    ]
+    .into_iter()
+    .map(|(name, endpoint, schema)| {
+        json!({
+            "schemas": [SCIM_SCHEMA_RESOURCE_TYPE],
+            "id": name,
+            "name": name,
+            "endpoint": endpoint,
+            "schema": schema,
+            "meta": {
+                "resourceType": "ResourceType",
+                "location": format!("{}/scim/v2/ResourceTypes/{}", cfg.public_url, name),
+            },
+        })
+    })
+    .collect()
+}
+
+#[get("/ResourceTypes")]
+async fn get_resource_types(
+    _client: ScimClient,
+    cfg: Data<AppConfig>,
+) -> Result<HttpResponse, ScimError> {
+    let resources = resource_types(&cfg);
+    Ok(scim_response(
+        StatusCode::OK,
+        &ScimListResponse {
+            schemas: [SCIM_SCHEMA_LIST_RESPONSE],
+            total_results: resources.len() as u64,
+            start_index: 1,
+            items_per_page: resources.len(),
+            resources,
+        },
+    ))
+}
+
+fn attribute(name: &str, kind: &str, required: bool, mutability: &str, uniqueness: &str) -> Value {
+    json!({
+        "name": name,
+        "type": kind,
+        "multiValued": false,
+        "required": required,
+        "caseExact": false,
+        "mutability": mutability,
+        "returned": "default",
+        "uniqueness": uniqueness,
+    })
+}
+
+fn multi_valued(name: &str, mutability: &str, sub_attributes: Vec<Value>) -> Value {
+    json!({
+        "name": name,
+        "type": "complex",
+        "multiValued": true,
+        "required": false,
+        "mutability": mutability,
+        "returned": "default",
+        "subAttributes": sub_attributes,
+    })
+}
+
+/// The attributes this server stores, described as RFC 7643 schemas.
+fn schemas(cfg: &AppConfig) -> Vec<Value> {
+    let reference = |mutability: &str| {
+        vec![
+            attribute("value", "string", false, mutability, "none"),
+            attribute("display", "string", false, "readOnly", "none"),
+            attribute("$ref", "reference", false, mutability, "none"),
+        ]
+    };
+    let user = json!({
+        "id": SCIM_SCHEMA_USER,
+        "name": "User",
+        "description": "User Account",
+        "attributes": [
+            attribute("userName", "string", true, "readWrite", "server"),
+            attribute("displayName", "string", false, "readWrite", "none"),
+            attribute("externalId", "string", false, "readWrite", "none"),
+            attribute("active", "boolean", false, "readWrite", "none"),
+            attribute("password", "string", false, "writeOnly", "none"),
+            multi_valued("emails", "readWrite", vec![
+                attribute("value", "string", false, "readWrite", "server"),
+                attribute("primary", "boolean", false, "readWrite", "none"),
+            ]),
+            multi_valued("groups", "readOnly", reference("readOnly")),
+        ],
+    });
+    let group = json!({
+        "id": SCIM_SCHEMA_GROUP,
+        "name": "Group",
+        "description": "Group",
+        "attributes": [
+            attribute("displayName", "string", true, "readWrite", "server"),
+            multi_valued("members", "readWrite", reference("immutable")),
+        ],
+    });
+
+    [user, group]
+        .into_iter()
+        .map(|mut schema| {
+            schema["schemas"] = json!([SCIM_SCHEMA_SCHEMA]);
+            schema["meta"] = json!({
+                "resourceType": "Schema",
+                "location": format!("{}/scim/v2/Schemas/{}", cfg.public_url, schema["id"].as_str().unwrap_or_default()),
+            });
+            schema
+        })
+        .collect()
+}
+
+#[get("/Schemas")]
+async fn get_schemas(_client: ScimClient, cfg: Data<AppConfig>) -> Result<HttpResponse, ScimError> {
+    let resources = schemas(&cfg);
+    Ok(scim_response(
+        StatusCode::OK,
+        &ScimListResponse {
+            schemas: [SCIM_SCHEMA_LIST_RESPONSE],
+            total_results: resources.len() as u64,
+            start_index: 1,
+            items_per_page: resources.len(),
+            resources,
+        },
+    ))
+}
+
+#[get("/Schemas/{id}")]
+async fn get_schema(
+    _client: ScimClient,
+    cfg: Data<AppConfig>,
+    id: Path<String>,
+) -> Result<HttpResponse, ScimError> {
+    let schema = schemas(&cfg)
+        .into_iter()
+        .find(|s| s["id"] == id.as_str())
+        .ok_or_else(not_found)?;
+
+    Ok(scim_response(StatusCode::OK, &schema))
+}
+
+/// SCIM 2.0 provisioning (RFC 7644) for identity providers such as Okta and
+/// Entra ID, authenticated with the token from `SCIM_TOKEN`.
+pub fn scim_scope() -> Scope {
+    Scope::new("/scim/v2")
+        .app_data(
+            JsonConfig::default().error_handler(|e, _| {
+                ScimError::bad_request("invalidSyntax", e.to_string()).into()
+            }),
+        )
+        .app_data(
+            QueryConfig::default()
+                .error_handler(|e, _| ScimError::bad_request("invalidValue", e.to_string()).into()),
+        )
+        .service(list_users)
+        .service(get_user)
+        .service(create_user)
+        .service(replace_user)
+        .service(patch_user)
+        .service(delete_user)
+        .service(list_groups)
+        .service(get_group)
+        .service(create_group)
+        .service(replace_group)
+        .service(patch_group)
+        .service(delete_group)
+        .service(service_provider_config)
+        .service(get_resource_types)
+        .service(get_schemas)
+        .service(get_schema)
+}
@@ -0,0 +1,228 @@
+use crate::auth::{ClientsManage, Principal, RequirePermission};
+use crate::constants::*;
+use crate::database::mongodb::{RoleRepository, ServiceAccountRepository, UserRepository};
+use crate::errors::AppError;
+use crate::models::request::{CreateServiceAccountRequest, UpdateServiceAccountRequest};
+use crate::models::response::{Response, ServiceAccountCredentials, ServiceAccountInfo};
+use crate::models::service_account::ServiceAccount;
+use crate::utils::token::{generate_opaque_token, hash_token};
+use actix_web::web::{Data, Json, Path};
+use actix_web::{delete, get, post, put, HttpResponse, Scope};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use validator::Validate;
+
+fn account_info(account: ServiceAccount) -> ServiceAccountInfo {
+    ServiceAccountInfo {
+        client_id: account.client_id,
+        name: account.name,
+        scopes: account.scopes,
+        disabled: account.disabled,
+        created_at: account.created_at.timestamp_millis() / 1000,
+        last_used_at: account.last_used_at.map(|t| t.timestamp_millis() / 1000),
+    }
+}
+
+/// Service account scopes are permissions. A principal may only hand out
+/// permissions it holds itself, so managing service accounts can't be used to
+/// escalate.
+async fn check_grantable(
+    principal: &Principal,
+    user_repo: &UserRepository,
+    role_repo: &RoleRepository,
+    scopes: &[String],
+) -> Result<(), AppError> {
+    if let Some(scope) = scopes
+        .iter()
+        .find(|s| !ALL_PERMISSIONS.contains(&s.as_str()))
+    {
+        return Err(AppError::BadRequest(format!(
+            "{}: {}",
+            UNKNOWN_PERMISSION, scope
+        )));
+    }
+
+    match principal {
+        Principal::User(user_id) => {
+            let object_id = ObjectId::parse_str(user_id)?;
+            let user = user_repo
+                .find_by_id(&object_id)
+                .await?
+                .ok_or_else(|| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+            for scope in scopes {
+                if !role_repo.grants(&user.roles, scope).await? {
+                    return Err(AppError::Forbidden(SCOPE_NOT_GRANTABLE.into()));
+                }
+            }
+        }
+        Principal::Service(service) => {
+            if !scopes.iter().all(|s| service.has_scope(s)) {
+                return Err(AppError::Forbidden(SCOPE_NOT_GRANTABLE.into()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn ensure_not_self(principal: &Principal, client_id: &str) -> Result<(), AppError> {
+    match principal {
+        Principal::Service(service) if service.client_id == client_id => {
+            Err(AppError::Forbidden(OWN_SERVICE_ACCOUNT.into()))
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn find_account(
+    accounts: &ServiceAccountRepository,
+    client_id: &str,
+) -> Result<ServiceAccount, AppError> {
+    accounts
+        .find_by_client_id(client_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(SERVICE_ACCOUNT_NOT_FOUND.into()))
+}
+
+#[get("")]
+async fn get_service_accounts(
+    _admin: RequirePermission<ClientsManage>,
+    accounts: Data<ServiceAccountRepository>,
+) -> Result<HttpResponse, AppError> {
+    let infos: Vec<ServiceAccountInfo> = accounts
+        .find_all()
+        .await?
+        .into_iter()
+        .map(account_info)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: SERVICE_ACCOUNTS_FETCHED.into(),
+        data: Some(infos),
+    }))
+}
+
+/// Creates a service account. Its secret is returned once and only its hash
+/// is stored.
+#[post("")]
+async fn create_service_account(
+    admin: RequirePermission<ClientsManage>,
+    user_repo: Data<UserRepository>,
+    role_repo: Data<RoleRepository>,
+    accounts: Data<ServiceAccountRepository>,
+    payload: Json<CreateServiceAccountRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+    check_grantable(&admin.principal, &user_repo, &role_repo, &payload.scopes).await?;
+
+    let secret = generate_opaque_token();
+    let account = ServiceAccount {
+        id: ObjectId::new(),
+        client_id: generate_opaque_token()[..32].to_string(),
+        name: payload.name.clone(),
+        secret_hash: hash_token(&secret),
+        scopes: payload.scopes.clone(),
+        disabled: false,
+        token_version: 0,
+        created_at: DateTime::now(),
+        last_used_at: None,
+    };
+    accounts.create(&account).await?;
+
+    Ok(HttpResponse::Created().json(Response {
+        msg: SERVICE_ACCOUNT_CREATED.into(),
+        data: Some(ServiceAccountCredentials {
+            account: account_info(account),
+            client_secret: secret,
+        }),
+    }))
+}
+
+/// Renames, rescopes, disables or re-enables a service account. Changing the
+/// scopes or disabling it revokes its outstanding tokens.
+#[put("/{client_id}")]
+async fn update_service_account(
+    admin: RequirePermission<ClientsManage>,
+    user_repo: Data<UserRepository>,
+    role_repo: Data<RoleRepository>,
+    accounts: Data<ServiceAccountRepository>,
+    client_id: Path<String>,
+    payload: Json<UpdateServiceAccountRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+    ensure_not_self(&admin.principal, &client_id)?;
+    if let Some(ref scopes) = payload.scopes {
+        check_grantable(&admin.principal, &user_repo, &role_repo, scopes).await?;
+    }
+
+    find_account(&accounts, &client_id).await?;
+    accounts
+        .update(
+            &client_id,
+            payload.name.as_deref(),
+            payload.scopes.as_deref(),
+            payload.disabled,
+        )
+        .await?;
+
+    let account = find_account(&accounts, &client_id).await?;
+    Ok(HttpResponse::Ok().json(Response {
+        msg: SERVICE_ACCOUNT_UPDATED.into(),
+        data: Some(account_info(account)),
+    }))
+}
+
+/// Replaces the secret; the old one and every token issued with it stop
+/// working immediately.
+#[post("/{client_id}/secret")]
+async fn rotate_service_account_secret(
+    admin: RequirePermission<ClientsManage>,
+    accounts: Data<ServiceAccountRepository>,
+    client_id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    ensure_not_self(&admin.principal, &client_id)?;
+    let account = find_account(&accounts, &client_id).await?;
+
+    let secret = generate_opaque_token();
+    accounts
+        .rotate_secret(&account.client_id, &hash_token(&secret))
+        .await?;
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: SERVICE_ACCOUNT_SECRET_ROTATED.into(),
+        data: Some(ServiceAccountCredentials {
+            account: account_info(account),
+            client_secret: secret,
+        }),
+    }))
+}
+
+#[delete("/{client_id}")]
+async fn delete_service_account(
+    admin: RequirePermission<ClientsManage>,
+    accounts: Data<ServiceAccountRepository>,
+    client_id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    ensure_not_self(&admin.principal, &client_id)?;
+    if !accounts.delete(&client_id).await? {
+        return Err(AppError::NotFound(SERVICE_ACCOUNT_NOT_FOUND.into()));
+    }
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: SERVICE_ACCOUNT_DELETED.into(),
+        data: None,
+    }))
+}
+
+pub fn service_account_scope() -> Scope {
+    Scope::new("/service-accounts")
+        .service(get_service_accounts)
+        .service(create_service_account)
+        .service(update_service_account)
+        .service(rotate_service_account_secret)
+        .service(delete_service_account)
+}
@@ -1,21 +1,31 @@
-use crate::auth::AuthenticatedUser;
+use crate::auth::{AuthenticatedUser, PasswordChanger, UserOrApiKey};
+use crate::config::app_config::AppConfig;
 use crate::constants::*;
-use crate::database::mongodb::UserRepository;
+use crate::database::mongodb::{SessionRepository, UserRepository};
+use crate::database::redis::{OneTimeTokenStore, RefreshTokenStore};
 use crate::errors::AppError;
+use crate::handlers::api_key::api_key_scope;
+use crate::handlers::auth::{ensure_can_sign_in, sign_out_everywhere, start_session};
+use crate::handlers::federated::identity_scope;
+use crate::handlers::mfa::mfa_scope;
+use crate::handlers::verification::send_verification_email;
+use crate::handlers::webauthn::passkey_scope;
+use crate::mail::MailService;
 use crate::models::request::{UpdateEmailRequest, UpdatePasswordRequest, UpdateUsernameRequest};
-use crate::models::response::{AboutMe, Response};
+use crate::models::response::{AboutMe, Response, SessionInfo};
 use crate::utils::password::{hash_password, verify_password};
-use actix_web::web::{scope, Data, Json};
-use actix_web::{get, put, HttpResponse};
+use crate::utils::password_policy::{check_password_change, set_password_hash};
+use actix_web::web::{scope, Data, Json, Path};
+use actix_web::{delete, get, put, HttpRequest, HttpResponse};
 use mongodb::bson::oid::ObjectId;
 use validator::Validate;
 
 #[get("/me")]
 pub async fn get_me(
     user_repo: Data<UserRepository>,
-    user: AuthenticatedUser,
+    user: UserOrApiKey,
 ) -> Result<HttpResponse, AppError> {
-    let uid = ObjectId::parse_str(&user.user_id)?;
+    let uid = ObjectId::parse_str(user.user_id())?;
     let user_doc = user_repo
         .find_by_id(&uid)
         .await?
@@ -26,13 +36,18 @@ pub async fn get_me(
         data: Some(AboutMe {
             email: user_doc.email,
             username: user_doc.username,
+            email_verified: user_doc.email_verified,
         }),
     }))
 }
 
 #[put("/email")]
 async fn update_email(
+    req: HttpRequest,
     user_repo: Data<UserRepository>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    mail: Data<MailService>,
+    cfg: Data<AppConfig>,
     user: AuthenticatedUser,
     payload: Json<UpdateEmailRequest>,
 ) -> Result<HttpResponse, AppError> {
@@ -47,6 +62,10 @@ async fn update_email(
     let uid = ObjectId::parse_str(&user.user_id)?;
     user_repo.update_email(&uid, &payload.email).await?;
 
+    if let Some(updated) = user_repo.find_by_id(&uid).await? {
+        send_verification_email(&cfg, &one_time_tokens, &mail, &req, &updated).await?;
+    }
+
     Ok(HttpResponse::Ok().json(Response::<()> {
         msg: EMAIL_UPDATED.into(),
         data: None,
@@ -72,30 +91,133 @@ async fn update_username(
     }))
 }
 
+/// Also completes a login held back by an expired password, which then
+/// continues with a new session.
 #[put("/password")]
+#[allow(clippy::too_many_arguments)]
 async fn update_password(
+    req: HttpRequest,
+    cfg: Data<AppConfig>,
     user_repo: Data<UserRepository>,
-    user: AuthenticatedUser,
+    sessions: Data<SessionRepository>,
+    refresh_store: Data<RefreshTokenStore>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    changer: PasswordChanger,
     payload: Json<UpdatePasswordRequest>,
 ) -> Result<HttpResponse, AppError> {
     payload
         .validate()
         .map_err(|e| AppError::BadRequest(e.to_string()))?;
 
-    let uid = ObjectId::parse_str(&user.user_id)?;
-    let current = user_repo
+    let uid = ObjectId::parse_str(changer.user_id())?;
+    let mut current = user_repo
         .find_by_id(&uid)
         .await?
         .ok_or(AppError::Unauthorized(USER_NOT_FOUND.into()))?;
+    if current.ldap_dn.is_some() {
+        return Err(AppError::BadRequest(PASSWORD_MANAGED_BY_DIRECTORY.into()));
+    }
 
     verify_password(&current.password_hash, &payload.old_password)
+        .await
         .map_err(|_| AppError::Unauthorized(INVALID_OLD_PASSWORD.into()))?;
+    check_password_change(&cfg.password_policy, &current, &payload.new_password).await?;
 
-    let new_hash = hash_password(&payload.new_password)?;
-    user_repo.update_password(&uid, &new_hash).await?;
+    let pending = match changer {
+        PasswordChanger::User(_) => None,
+        PasswordChanger::Expired { ref pending, .. } => {
+            ensure_can_sign_in(&cfg, &current)?;
+            Some(pending.device.clone())
+        }
+    };
 
-    Ok(HttpResponse::Ok().json(Response::<()> {
+    let new_hash = hash_password(&cfg.password_hashing, &payload.new_password).await?;
+    changer.consume(&one_time_tokens).await?;
+    set_password_hash(&cfg.password_policy, &mut current, new_hash);
+    user_repo.update_password(&current).await?;
+
+    let Some(device) = pending else {
+        return Ok(HttpResponse::Ok().json(Response::<()> {
+            msg: PASSWORD_UPDATED.into(),
+            data: None,
+        }));
+    };
+
+    // The second factor was checked before the password change token was issued
+    let token = start_session(&cfg, &sessions, &refresh_store, &req, &current, device).await?;
+    Ok(HttpResponse::Ok().json(Response {
         msg: PASSWORD_UPDATED.into(),
+        data: Some(token),
+    }))
+}
+
+#[get("/sessions")]
+async fn get_sessions(
+    sessions: Data<SessionRepository>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    let session_infos: Vec<SessionInfo> = sessions
+        .find_by_user(&uid)
+        .await?
+        .into_iter()
+        .map(|s| SessionInfo {
+            current: user.session.id == s.id.to_hex(),
+            id: s.id.to_hex(),
+            device: s.device,
+            ip: s.ip,
+            user_agent: s.user_agent,
+            created_at: s.created_at.timestamp_millis() / 1000,
+            last_seen_at: s.last_seen_at.timestamp_millis() / 1000,
+            client_id: s.client_id,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: SESSIONS_FETCHED.into(),
+        data: Some(session_infos),
+    }))
+}
+
+#[delete("/sessions/{id}")]
+async fn revoke_session(
+    sessions: Data<SessionRepository>,
+    refresh_store: Data<RefreshTokenStore>,
+    user: AuthenticatedUser,
+    id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    let session_id = ObjectId::parse_str(id.as_str())
+        .map_err(|_| AppError::BadRequest(INVALID_SESSION_ID.into()))?;
+
+    if !sessions.delete(&session_id, &uid).await? {
+        return Err(AppError::NotFound(SESSION_NOT_FOUND.into()));
+    }
+    refresh_store.revoke_family(&session_id.to_hex()).await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: SESSION_REVOKED.into(),
+        data: None,
+    }))
+}
+
+#[delete("/sessions")]
+async fn revoke_all_sessions(
+    user_repo: Data<UserRepository>,
+    sessions: Data<SessionRepository>,
+    refresh_store: Data<RefreshTokenStore>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    let current = user_repo
+        .find_by_id(&uid)
+        .await?
+        .ok_or(AppError::Unauthorized(USER_NOT_FOUND.into()))?;
+
+    sign_out_everywhere(&user_repo, &sessions, &refresh_store, &current).await?;
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: ALL_SESSIONS_REVOKED.into(),
         data: None,
     }))
 }
@@ -106,4 +228,11 @@ pub fn user_scope() -> actix_web::Scope {
         .service(update_email)
         .service(update_username)
         .service(update_password)
+        .service(get_sessions)
+        .service(revoke_session)
+        .service(revoke_all_sessions)
+        .service(mfa_scope())
+        .service(passkey_scope())
+        .service(api_key_scope())
+        .service(identity_scope())
 }
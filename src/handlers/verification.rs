@@ -0,0 +1,139 @@
+use crate::config::app_config::AppConfig;
+use crate::constants::*;
+use crate::database::mongodb::UserRepository;
+use crate::database::redis::OneTimeTokenStore;
+use crate::errors::AppError;
+use crate::mail::{accept_language, MailService};
+use crate::models::request::{ResendVerificationRequest, VerifyEmailRequest};
+use crate::models::response::Response;
+use crate::models::user::User;
+use crate::utils::token::{decode_action_token, generate_action_token};
+use actix_web::web::{Data, Json};
+use actix_web::{post, HttpRequest, HttpResponse};
+use mongodb::bson::oid::ObjectId;
+use validator::Validate;
+
+const EMAIL_VERIFICATION_PURPOSE: &str = "email_verification";
+const EMAIL_VERIFICATION_RESEND_PURPOSE: &str = "email_verification_resend";
+
+/// Mails the user a link to verify their current address.
+pub(super) async fn send_verification_email(
+    cfg: &AppConfig,
+    one_time_tokens: &OneTimeTokenStore,
+    mail: &MailService,
+    req: &HttpRequest,
+    user: &User,
+) -> Result<(), AppError> {
+    let (token, jti) = generate_action_token(
+        cfg,
+        EMAIL_VERIFICATION_PURPOSE,
+        &user.id.to_hex(),
+        &user.email,
+        EMAIL_VERIFICATION_TTL_SECONDS,
+    )?;
+    one_time_tokens
+        .issue_for_user(
+            EMAIL_VERIFICATION_PURPOSE,
+            &jti,
+            &user.id.to_hex(),
+            EMAIL_VERIFICATION_TTL_SECONDS,
+            &user.id.to_hex(),
+        )
+        .await?;
+
+    mail.send(
+        &user.email,
+        "verify_email",
+        accept_language(req),
+        &[
+            ("username", &user.username),
+            (
+                "link",
+                &format!("{}/verify-email?token={}", cfg.public_url, token),
+            ),
+            (
+                "hours",
+                &(EMAIL_VERIFICATION_TTL_SECONDS / 3600).to_string(),
+            ),
+        ],
+    )
+}
+
+#[post("/verify-email")]
+async fn verify_email(
+    user_repo: Data<UserRepository>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    cfg: Data<AppConfig>,
+    payload: Json<VerifyEmailRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let invalid = || AppError::BadRequest(INVALID_VERIFICATION_TOKEN.into());
+    let claims = decode_action_token(&cfg, EMAIL_VERIFICATION_PURPOSE, &payload.token)
+        .ok_or_else(invalid)?;
+
+    if one_time_tokens
+        .take(EMAIL_VERIFICATION_PURPOSE, &claims.jti)
+        .await?
+        .is_none()
+    {
+        return Err(invalid());
+    }
+
+    let user_id = ObjectId::parse_str(&claims.sub).map_err(|_| invalid())?;
+    if !user_repo
+        .mark_email_verified(&user_id, &claims.email)
+        .await?
+    {
+        return Err(invalid());
+    }
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: EMAIL_VERIFIED.into(),
+        data: None,
+    }))
+}
+
+/// Sends a new link when the address belongs to an unverified account that
+/// hasn't had one in the last `EMAIL_VERIFICATION_RESEND_SECONDS`. Whether a
+/// mail went out is not reported back.
+#[post("/verify-email/resend")]
+async fn resend_verification(
+    req: HttpRequest,
+    user_repo: Data<UserRepository>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    mail: Data<MailService>,
+    cfg: Data<AppConfig>,
+    payload: Json<ResendVerificationRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    if let Some(user) = user_repo.find_by_email(&payload.email).await? {
+        let throttle_key = user.id.to_hex();
+        let throttled = one_time_tokens
+            .peek(EMAIL_VERIFICATION_RESEND_PURPOSE, &throttle_key)
+            .await?
+            .is_some();
+
+        if !user.email_verified && !throttled {
+            one_time_tokens
+                .issue(
+                    EMAIL_VERIFICATION_RESEND_PURPOSE,
+                    &throttle_key,
+                    "1",
+                    EMAIL_VERIFICATION_RESEND_SECONDS,
+                )
+                .await?;
+            send_verification_email(&cfg, &one_time_tokens, &mail, &req, &user).await?;
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: VERIFICATION_EMAIL_SENT.into(),
+        data: None,
+    }))
+}
@@ -0,0 +1,355 @@
+use crate::auth::AuthenticatedUser;
+use crate::config::app_config::AppConfig;
+use crate::constants::*;
+use crate::database::mongodb::{PasskeyRepository, SessionRepository, UserRepository};
+use crate::database::redis::{OneTimeTokenStore, RefreshTokenStore};
+use crate::errors::AppError;
+use crate::handlers::auth::start_session;
+use crate::models::passkey::Passkey;
+use crate::models::request::{
+    PasskeyLoginRequest, PasskeyLoginStartRequest, PasskeyRegisterRequest,
+};
+use crate::models::response::{
+    AuthenticatorSelection, CredentialDescriptor, PasskeyCreationOptions, PasskeyInfo,
+    PasskeyRequestOptions, PasskeyUser, PubKeyCredParam, RelyingParty, Response,
+};
+use crate::utils::token::hash_token;
+use crate::utils::webauthn::{
+    decode, encode, generate_challenge, parse_attestation_object, parse_authenticator_data,
+    public_key_algorithm, verify_client_data, verify_signature, COSE_ALG_EDDSA, COSE_ALG_ES256,
+};
+use actix_web::web::{scope, Data, Json, Path};
+use actix_web::{delete, get, post, HttpRequest, HttpResponse, Scope};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use validator::Validate;
+
+const REGISTRATION_PURPOSE: &str = "webauthn_registration";
+const AUTHENTICATION_PURPOSE: &str = "webauthn_authentication";
+
+fn user_verification(cfg: &AppConfig) -> &'static str {
+    if cfg.webauthn_require_user_verification {
+        "required"
+    } else {
+        "preferred"
+    }
+}
+
+fn descriptors(passkeys: Vec<Passkey>) -> Vec<CredentialDescriptor> {
+    passkeys
+        .into_iter()
+        .map(|p| CredentialDescriptor {
+            kind: "public-key",
+            id: p.credential_id,
+        })
+        .collect()
+}
+
+#[post("/register/start")]
+async fn start_registration(
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    passkeys: Data<PasskeyRepository>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    let current = user_repo
+        .find_by_id(&uid)
+        .await?
+        .ok_or(AppError::Unauthorized(USER_NOT_FOUND.into()))?;
+
+    let challenge = generate_challenge();
+    one_time_tokens
+        .issue_for_user(
+            REGISTRATION_PURPOSE,
+            &hash_token(&challenge),
+            &user.user_id,
+            WEBAUTHN_CHALLENGE_TTL_SECONDS,
+            &user.user_id,
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: PASSKEY_CHALLENGE_CREATED.into(),
+        data: Some(PasskeyCreationOptions {
+            challenge,
+            rp: RelyingParty {
+                id: cfg.webauthn_rp_id.clone(),
+                name: cfg.webauthn_rp_name.clone(),
+            },
+            user: PasskeyUser {
+                id: encode(&uid.bytes()),
+                name: current.email,
+                display_name: current.username,
+            },
+            pub_key_cred_params: [COSE_ALG_ES256, COSE_ALG_EDDSA]
+                .into_iter()
+                .map(|alg| PubKeyCredParam {
+                    kind: "public-key",
+                    alg,
+                })
+                .collect(),
+            timeout: WEBAUTHN_CHALLENGE_TTL_SECONDS * 1000,
+            attestation: "none",
+            authenticator_selection: AuthenticatorSelection {
+                resident_key: "preferred",
+                user_verification: user_verification(&cfg),
+            },
+            exclude_credentials: descriptors(passkeys.find_by_user(&uid).await?),
+        }),
+    }))
+}
+
+#[post("/register/finish")]
+async fn finish_registration(
+    cfg: Data<AppConfig>,
+    passkeys: Data<PasskeyRepository>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    user: AuthenticatedUser,
+    payload: Json<PasskeyRegisterRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let client_data_json = decode(&payload.client_data_json)?;
+    let challenge = verify_client_data(&cfg.webauthn_origin, &client_data_json, "webauthn.create")?;
+
+    let owner = one_time_tokens
+        .take(REGISTRATION_PURPOSE, &hash_token(&challenge))
+        .await?;
+    if owner.as_deref() != Some(user.user_id.as_str()) {
+        return Err(AppError::BadRequest(INVALID_PASSKEY_CHALLENGE.into()));
+    }
+
+    let auth_data = parse_attestation_object(&decode(&payload.attestation_object)?)?;
+    let parsed = parse_authenticator_data(
+        &cfg.webauthn_rp_id,
+        cfg.webauthn_require_user_verification,
+        &auth_data,
+    )?;
+    let (credential_id, public_key) = match (parsed.credential_id, parsed.public_key) {
+        (Some(id), Some(key)) => (id, key),
+        _ => return Err(AppError::BadRequest(INVALID_PASSKEY.into())),
+    };
+
+    if decode(&payload.id)? != credential_id {
+        return Err(AppError::BadRequest(INVALID_PASSKEY.into()));
+    }
+    public_key_algorithm(&public_key)?;
+
+    let credential_id = encode(&credential_id);
+    if passkeys
+        .find_by_credential_id(&credential_id)
+        .await?
+        .is_some()
+    {
+        return Err(AppError::Conflict(PASSKEY_ALREADY_REGISTERED.into()));
+    }
+
+    let passkey = Passkey {
+        id: ObjectId::new(),
+        user_id: ObjectId::parse_str(&user.user_id)?,
+        credential_id,
+        public_key: encode(&public_key),
+        sign_count: parsed.sign_count as i64,
+        name: payload.name.clone(),
+        created_at: DateTime::now(),
+        last_used_at: None,
+    };
+    passkeys.create(&passkey).await?;
+
+    Ok(HttpResponse::Created().json(Response::<()> {
+        msg: PASSKEY_REGISTERED.into(),
+        data: None,
+    }))
+}
+
+#[post("/login/start")]
+async fn start_login(
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    passkeys: Data<PasskeyRepository>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    payload: Json<PasskeyLoginStartRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    // With an email address the challenge is bound to that account; an
+    // unknown address gets an id no passkey belongs to. Without one, any
+    // discoverable credential may answer it.
+    let (bound_to, allow_credentials) = match payload.email {
+        Some(ref email) => match user_repo.find_by_email(email).await? {
+            Some(user) => (
+                user.id.to_hex(),
+                descriptors(passkeys.find_by_user(&user.id).await?),
+            ),
+            None => (ObjectId::new().to_hex(), Vec::new()),
+        },
+        None => (String::new(), Vec::new()),
+    };
+
+    let challenge = generate_challenge();
+    one_time_tokens
+        .issue(
+            AUTHENTICATION_PURPOSE,
+            &hash_token(&challenge),
+            &bound_to,
+            WEBAUTHN_CHALLENGE_TTL_SECONDS,
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: PASSKEY_CHALLENGE_CREATED.into(),
+        data: Some(PasskeyRequestOptions {
+            challenge,
+            rp_id: cfg.webauthn_rp_id.clone(),
+            timeout: WEBAUTHN_CHALLENGE_TTL_SECONDS * 1000,
+            user_verification: user_verification(&cfg),
+            allow_credentials,
+        }),
+    }))
+}
+
+#[post("/login/finish")]
+#[allow(clippy::too_many_arguments)]
+async fn finish_login(
+    req: HttpRequest,
+    cfg: Data<AppConfig>,
+    user_repo: Data<UserRepository>,
+    sessions: Data<SessionRepository>,
+    refresh_store: Data<RefreshTokenStore>,
+    passkeys: Data<PasskeyRepository>,
+    one_time_tokens: Data<OneTimeTokenStore>,
+    payload: Json<PasskeyLoginRequest>,
+) -> Result<HttpResponse, AppError> {
+    payload
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let client_data_json = decode(&payload.client_data_json)?;
+    let challenge = verify_client_data(&cfg.webauthn_origin, &client_data_json, "webauthn.get")?;
+
+    let bound_to = one_time_tokens
+        .take(AUTHENTICATION_PURPOSE, &hash_token(&challenge))
+        .await?
+        .ok_or_else(|| AppError::Unauthorized(INVALID_PASSKEY_CHALLENGE.into()))?;
+
+    let passkey = passkeys
+        .find_by_credential_id(&encode(&decode(&payload.id)?))
+        .await?
+        .ok_or_else(|| AppError::Unauthorized(INVALID_PASSKEY.into()))?;
+
+    // A challenge issued for an account only signs that account in
+    if !bound_to.is_empty() && bound_to != passkey.user_id.to_hex() {
+        return Err(AppError::Unauthorized(INVALID_PASSKEY_CHALLENGE.into()));
+    }
+
+    if let Some(ref user_handle) = payload.user_handle {
+        if decode(user_handle)? != passkey.user_id.bytes() {
+            return Err(AppError::Unauthorized(INVALID_PASSKEY.into()));
+        }
+    }
+
+    let auth_data = decode(&payload.authenticator_data)?;
+    let parsed = parse_authenticator_data(
+        &cfg.webauthn_rp_id,
+        cfg.webauthn_require_user_verification,
+        &auth_data,
+    )?;
+    verify_signature(
+        &decode(&passkey.public_key)?,
+        &auth_data,
+        &client_data_json,
+        &decode(&payload.signature)?,
+    )
+    .map_err(|_| AppError::Unauthorized(INVALID_PASSKEY.into()))?;
+
+    // A non-increasing counter indicates a cloned authenticator.
+    let sign_count = parsed.sign_count as i64;
+    if (sign_count != 0 || passkey.sign_count != 0) && sign_count <= passkey.sign_count {
+        return Err(AppError::Unauthorized(INVALID_PASSKEY.into()));
+    }
+
+    passkeys.record_use(&passkey.id, sign_count).await?;
+
+    let user = user_repo
+        .find_by_id(&passkey.user_id)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized(INVALID_PASSKEY.into()))?;
+
+    let token = start_session(
+        &cfg,
+        &sessions,
+        &refresh_store,
+        &req,
+        &user,
+        payload.device.clone(),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(Response {
+        msg: LOGIN_SUCCESS.into(),
+        data: Some(token),
+    }))
+}
+
+#[get("")]
+async fn get_passkeys(
+    passkeys: Data<PasskeyRepository>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AppError> {
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    let passkey_infos: Vec<PasskeyInfo> = passkeys
+        .find_by_user(&uid)
+        .await?
+        .into_iter()
+        .map(|p| PasskeyInfo {
+            id: p.id.to_hex(),
+            name: p.name,
+            created_at: p.created_at.timestamp_millis() / 1000,
+            last_used_at: p.last_used_at.map(|t| t.timestamp_millis() / 1000),
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(Response {
+        msg: PASSKEYS_FETCHED.into(),
+        data: Some(passkey_infos),
+    }))
+}
+
+#[delete("/{id}")]
+async fn delete_passkey(
+    passkeys: Data<PasskeyRepository>,
+    user: AuthenticatedUser,
+    id: Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let uid = ObjectId::parse_str(&user.user_id)?;
+    let passkey_id = ObjectId::parse_str(id.as_str())
+        .map_err(|_| AppError::BadRequest(INVALID_PASSKEY_ID.into()))?;
+
+    if !passkeys.delete(&passkey_id, &uid).await? {
+        return Err(AppError::NotFound(PASSKEY_NOT_FOUND.into()));
+    }
+
+    Ok(HttpResponse::Ok().json(Response::<()> {
+        msg: PASSKEY_DELETED.into(),
+        data: None,
+    }))
+}
+
+pub fn webauthn_scope() -> Scope {
+    scope("/webauthn")
+        .service(start_registration)
+        .service(finish_registration)
+        .service(start_login)
+        .service(finish_login)
+}
+
+pub fn passkey_scope() -> Scope {
+    scope("/passkeys")
+        .service(get_passkeys)
+        .service(delete_passkey)
+}
@@ -0,0 +1,49 @@
+use crate::config::app_config::AppConfig;
+use crate::constants::{OAUTH_SCOPES, OIDC_UNAVAILABLE};
+use crate::errors::AppError;
+use actix_web::web::Data;
+use actix_web::{get, HttpResponse};
+use serde_json::json;
+
+#[get("/.well-known/jwks.json")]
+async fn jwks(cfg: Data<AppConfig>) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(("Cache-Control", "public, max-age=300"))
+        .json(cfg.jwt_keys.jwks())
+}
+
+/// OpenID Connect discovery document (OpenID Connect Discovery 1.0). Not
+/// served with an HMAC keyring, whose id tokens clients couldn't verify.
+#[get("/.well-known/openid-configuration")]
+async fn openid_configuration(cfg: Data<AppConfig>) -> Result<HttpResponse, AppError> {
+    if !cfg.jwt_keys.is_asymmetric() {
+        return Err(AppError::NotFound(OIDC_UNAVAILABLE.into()));
+    }
+
+    let issuer = &cfg.oidc_issuer;
+    Ok(HttpResponse::Ok()
+        .insert_header(("Cache-Control", "public, max-age=300"))
+        .json(json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{}/oauth/authorize", issuer),
+            "token_endpoint": format!("{}/oauth/token", issuer),
+            "userinfo_endpoint": format!("{}/oauth/userinfo", issuer),
+            "introspection_endpoint": format!("{}/oauth/introspect", issuer),
+            "revocation_endpoint": format!("{}/oauth/revoke", issuer),
+            "jwks_uri": format!("{}/.well-known/jwks.json", issuer),
+            "scopes_supported": OAUTH_SCOPES,
+            "response_types_supported": ["code"],
+            "grant_types_supported": ["authorization_code", "refresh_token", "client_credentials"],
+            "subject_types_supported": ["public"],
+            "id_token_signing_alg_values_supported": [cfg.jwt_keys.current().algorithm],
+            "token_endpoint_auth_methods_supported":
+                ["client_secret_basic", "client_secret_post", "none"],
+            "introspection_endpoint_auth_methods_supported":
+                ["client_secret_basic", "client_secret_post", "bearer"],
+            "revocation_endpoint_auth_methods_supported":
+                ["client_secret_basic", "client_secret_post", "none"],
+            "code_challenge_methods_supported": ["S256"],
+            "claims_supported":
+                ["iss", "sub", "aud", "exp", "iat", "nonce", "email", "email_verified", "name"],
+        })))
+}
@@ -0,0 +1,65 @@
+use crate::errors::AppError;
+use crate::mail::{Email, Mailer};
+use async_trait::async_trait;
+use mongodb::bson::oid::ObjectId;
+use std::path::PathBuf;
+use time::format_description::well_known::Rfc2822;
+use time::OffsetDateTime;
+
+/// Writes each message as an `.eml` file into an outbox directory, for local
+/// development and testing.
+pub struct FileMailer {
+    dir: PathBuf,
+    from: String,
+}
+
+impl FileMailer {
+    pub fn new(dir: &str, from: &str) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+            from: from.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: &Email) -> Result<(), AppError> {
+        let date = OffsetDateTime::now_utc()
+            .format(&Rfc2822)
+            .map_err(|_| AppError::Internal)?;
+        let message = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}\r\n",
+            self.from, email.to, email.subject, date, email.body
+        );
+
+        // ObjectIds sort by creation time, which keeps the outbox in send order
+        let path = self.dir.join(format!("{}.eml", ObjectId::new().to_hex()));
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        tokio::fs::write(&path, message).await.map_err(|e| {
+            tracing::error!("Failed to write mail to {}: {}", path.display(), e);
+            AppError::Internal
+        })?;
+
+        tracing::info!("Mail to {} written to {}", email.to, path.display());
+        Ok(())
+    }
+}
+
+/// Drops messages, logging only who they were for. Bodies carry live
+/// verification and reset links, so they never reach the log.
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: &Email) -> Result<(), AppError> {
+        tracing::warn!(
+            "Mail to {} not delivered (MAIL_TRANSPORT=log): {}",
+            email.to,
+            email.subject
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+pub mod file;
+pub mod queue;
+pub mod smtp;
+pub mod templates;
+
+use crate::config::app_config::AppConfig;
+use crate::config::mail_config::MailTransport;
+use crate::errors::AppError;
+use async_trait::async_trait;
+use queue::MailQueue;
+use std::sync::Arc;
+use templates::MailTemplates;
+
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// A mail transport.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<(), AppError>;
+}
+
+pub fn build_transport(cfg: &AppConfig) -> Result<Arc<dyn Mailer>, String> {
+    Ok(match cfg.mail_transport {
+        MailTransport::Log => {
+            tracing::warn!("MAIL_TRANSPORT=log: mail is discarded and never delivered");
+            Arc::new(file::LogMailer)
+        }
+        MailTransport::File(ref dir) => Arc::new(file::FileMailer::new(dir, &cfg.mail_from)),
+        MailTransport::Smtp(ref smtp) => Arc::new(smtp::SmtpMailer::new(smtp, &cfg.mail_from)?),
+    })
+}
+
+/// Renders templated mail and hands it to the delivery queue.
+#[derive(Clone)]
+pub struct MailService {
+    templates: Arc<MailTemplates>,
+    queue: MailQueue,
+}
+
+impl MailService {
+    pub fn new(cfg: &AppConfig) -> Result<Self, String> {
+        let templates =
+            MailTemplates::load(cfg.mail_template_dir.as_deref(), &cfg.mail_default_locale)?;
+        let transport = build_transport(cfg)?;
+
+        Ok(Self {
+            templates: Arc::new(templates),
+            queue: MailQueue::start(transport),
+        })
+    }
+
+    /// Queues the `template` mail to `to`, localized for `accept_language`.
+    pub fn send(
+        &self,
+        to: &str,
+        template: &str,
+        accept_language: Option<&str>,
+        vars: &[(&str, &str)],
+    ) -> Result<(), AppError> {
+        let (subject, body) = self
+            .templates
+            .render(template, accept_language, vars)
+            .ok_or_else(|| {
+                tracing::error!("Missing mail template {}", template);
+                AppError::Internal
+            })?;
+
+        self.queue.enqueue(Email {
+            to: to.into(),
+            subject,
+            body,
+        });
+        Ok(())
+    }
+}
+
+/// The raw `Accept-Language` header of a request, used to localize mail.
+pub fn accept_language(req: &actix_web::HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(actix_web::http::header::ACCEPT_LANGUAGE)
+        .and_then(|h| h.to_str().ok())
+}
@@ -0,0 +1,64 @@
+use crate::constants::MAIL_RETRY_DELAYS_SECONDS;
+use crate::mail::{Email, Mailer};
+use actix_web::rt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+struct QueuedEmail {
+    email: Email,
+    attempt: usize,
+}
+
+/// Delivers mail in the background so requests never wait on the transport.
+/// Failed deliveries are re-queued after each delay in
+/// `MAIL_RETRY_DELAYS_SECONDS` and dropped with an error once those run out.
+/// The queue lives in memory, so undelivered mail is lost on restart.
+#[derive(Clone)]
+pub struct MailQueue {
+    sender: UnboundedSender<QueuedEmail>,
+}
+
+impl MailQueue {
+    /// Starts the delivery worker. Must be called from within the runtime.
+    pub fn start(transport: Arc<dyn Mailer>) -> Self {
+        let (sender, mut receiver) = unbounded_channel::<QueuedEmail>();
+        let retry_sender = sender.clone();
+
+        rt::spawn(async move {
+            while let Some(queued) = receiver.recv().await {
+                if transport.send(&queued.email).await.is_ok() {
+                    continue;
+                }
+
+                let Some(delay) = MAIL_RETRY_DELAYS_SECONDS.get(queued.attempt) else {
+                    tracing::error!(
+                        "Giving up on mail to {} after {} attempts",
+                        queued.email.to,
+                        queued.attempt + 1
+                    );
+                    continue;
+                };
+
+                tracing::warn!("Mail to {} failed, retrying in {}s", queued.email.to, delay);
+                let retry_sender = retry_sender.clone();
+                let delay = Duration::from_secs(*delay);
+                rt::spawn(async move {
+                    rt::time::sleep(delay).await;
+                    let _ = retry_sender.send(QueuedEmail {
+                        email: queued.email,
+                        attempt: queued.attempt + 1,
+                    });
+                });
+            }
+        });
+
+        Self { sender }
+    }
+
+    pub fn enqueue(&self, email: Email) {
+        if self.sender.send(QueuedEmail { email, attempt: 0 }).is_err() {
+            tracing::error!("Mail queue is closed, dropping mail");
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use crate::config::mail_config::{SmtpConfig, SmtpTls};
+use crate::errors::AppError;
+use crate::mail::{Email, Mailer};
+use async_trait::async_trait;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(cfg: &SmtpConfig, from: &str) -> Result<Self, String> {
+        let from = from
+            .parse()
+            .map_err(|e| format!("invalid mail sender {}: {}", from, e))?;
+
+        let mut builder = match cfg.tls {
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&cfg.host),
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&cfg.host),
+            SmtpTls::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                &cfg.host,
+            )),
+        }
+        .map_err(|e| format!("invalid SMTP host {}: {}", cfg.host, e))?;
+
+        if let Some(port) = cfg.port {
+            builder = builder.port(port);
+        }
+        if let (Some(username), Some(password)) = (&cfg.username, &cfg.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: &Email) -> Result<(), AppError> {
+        let to = email.to.parse().map_err(|e| {
+            tracing::error!("Invalid mail recipient {}: {}", email.to, e);
+            AppError::Internal
+        })?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(&email.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(email.body.clone())
+            .map_err(|e| {
+                tracing::error!("Failed to build mail to {}: {}", email.to, e);
+                AppError::Internal
+            })?;
+
+        self.transport.send(message).await.map_err(|e| {
+            tracing::warn!("SMTP delivery to {} failed: {}", email.to, e);
+            AppError::Internal
+        })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+struct Template {
+    subject: String,
+    body: String,
+}
+
+/// Built-in templates as `(locale, name, subject, body)`.
+const BUILTIN: &[(&str, &str, &str, &str)] = &[
+    (
+        "en",
+        "verify_email",
+        include_str!("../../templates/mail/en/verify_email.subject.txt"),
+        include_str!("../../templates/mail/en/verify_email.body.txt"),
+    ),
+    (
+        "en",
+        "password_reset",
+        include_str!("../../templates/mail/en/password_reset.subject.txt"),
+        include_str!("../../templates/mail/en/password_reset.body.txt"),
+    ),
+    (
+        "en",
+        "org_invitation",
+        include_str!("../../templates/mail/en/org_invitation.subject.txt"),
+        include_str!("../../templates/mail/en/org_invitation.body.txt"),
+    ),
+    (
+        "en",
+        "invitation",
+        include_str!("../../templates/mail/en/invitation.subject.txt"),
+        include_str!("../../templates/mail/en/invitation.body.txt"),
+    ),
+    (
+        "zh-CN",
+        "verify_email",
+        include_str!("../../templates/mail/zh-CN/verify_email.subject.txt"),
+        include_str!("../../templates/mail/zh-CN/verify_email.body.txt"),
+    ),
+    (
+        "zh-CN",
+        "password_reset",
+        include_str!("../../templates/mail/zh-CN/password_reset.subject.txt"),
+        include_str!("../../templates/mail/zh-CN/password_reset.body.txt"),
+    ),
+    (
+        "zh-CN",
+        "org_invitation",
+        include_str!("../../templates/mail/zh-CN/org_invitation.subject.txt"),
+        include_str!("../../templates/mail/zh-CN/org_invitation.body.txt"),
+    ),
+    (
+        "zh-CN",
+        "invitation",
+        include_str!("../../templates/mail/zh-CN/invitation.subject.txt"),
+        include_str!("../../templates/mail/zh-CN/invitation.body.txt"),
+    ),
+];
+
+/// Mail templates keyed by locale and name. Each template is a subject and a
+/// plain text body with `{{ name }}` placeholders.
+pub struct MailTemplates {
+    templates: HashMap<(String, String), Template>,
+    default_locale: String,
+}
+
+impl MailTemplates {
+    /// Loads the built-in templates, overridden by any
+    /// `{dir}/{locale}/{name}.subject.txt` and `{name}.body.txt` pairs found
+    /// in `override_dir`.
+    pub fn load(override_dir: Option<&str>, default_locale: &str) -> Result<Self, String> {
+        let mut templates = HashMap::new();
+        for (locale, name, subject, body) in BUILTIN {
+            templates.insert(
+                (locale.to_lowercase(), name.to_string()),
+                Template {
+                    subject: subject.trim().into(),
+                    body: body.to_string(),
+                },
+            );
+        }
+
+        if let Some(dir) = override_dir {
+            let locales = fs::read_dir(dir)
+                .map_err(|e| format!("failed to read mail templates in {}: {}", dir, e))?;
+            for locale_dir in locales.flatten().filter(|e| e.path().is_dir()) {
+                let locale = locale_dir.file_name().to_string_lossy().to_lowercase();
+                for file in fs::read_dir(locale_dir.path())
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                {
+                    let file_name = file.file_name().to_string_lossy().into_owned();
+                    if let Some(name) = file_name.strip_suffix(".subject.txt") {
+                        let body_path = locale_dir.path().join(format!("{}.body.txt", name));
+                        templates.insert(
+                            (locale.clone(), name.into()),
+                            Template {
+                                subject: read(&file.path())?.trim().into(),
+                                body: read(&body_path)?,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        let default_locale = default_locale.to_lowercase();
+        if !templates
+            .keys()
+            .any(|(locale, _)| *locale == default_locale)
+        {
+            return Err(format!("no mail templates for locale {}", default_locale));
+        }
+
+        Ok(Self {
+            templates,
+            default_locale,
+        })
+    }
+
+    /// Renders `name` in the first available locale of `accept_language` (an
+    /// `Accept-Language` value), falling back to a bare language match and
+    /// then the default locale. Returns the subject and body.
+    pub fn render(
+        &self,
+        name: &str,
+        accept_language: Option<&str>,
+        vars: &[(&str, &str)],
+    ) -> Option<(String, String)> {
+        let requested = accept_language
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|tag| tag.split(';').next())
+            .map(|tag| tag.trim().to_lowercase())
+            .filter(|tag| !tag.is_empty() && tag != "*");
+
+        let template = requested
+            .flat_map(|tag| {
+                let language = tag.split('-').next().unwrap_or_default().to_string();
+                [tag, language]
+            })
+            .find_map(|tag| self.find(&tag, name))
+            .or_else(|| self.get(&self.default_locale, name))?;
+
+        Some((
+            substitute(&template.subject, vars),
+            substitute(&template.body, vars),
+        ))
+    }
+
+    fn get(&self, locale: &str, name: &str) -> Option<&Template> {
+        self.templates.get(&(locale.into(), name.into()))
+    }
+
+    /// Looks up an exact locale, or for a bare language any of its regional
+    /// variants (e.g. `zh` matches `zh-cn`).
+    fn find(&self, tag: &str, name: &str) -> Option<&Template> {
+        if let Some(template) = self.get(tag, name) {
+            return Some(template);
+        }
+        if tag.contains('-') {
+            return None;
+        }
+
+        self.templates
+            .iter()
+            .filter(|((locale, n), _)| n == name && locale.split('-').next() == Some(tag))
+            .min_by_key(|((locale, _), _)| locale.clone())
+            .map(|(_, template)| template)
+    }
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path)
+        .map_err(|e| format!("failed to read mail template {}: {}", path.display(), e))
+}
+
+fn substitute(template: &str, vars: &[(&str, &str)]) -> String {
+    vars.iter()
+        .fold(template.to_string(), |text, (key, value)| {
+            text.replace(&format!("{{{{ {} }}}}", key), value)
+                .replace(&format!("{{{{{}}}}}", key), value)
+        })
+}
@@ -3,17 +3,40 @@ mod config;
 mod constants;
 mod database;
 mod errors;
+mod federation;
 mod handlers;
+mod mail;
+mod middleware;
 mod models;
 mod utils;
 
+use crate::auth::Authenticators;
 use crate::config::app_config::AppConfig;
 use crate::config::rustls_config::load_rustls_config;
-use crate::database::mongodb::{init_mongodb, UserRepository};
-use crate::database::redis::{init_redis, TokenBlacklist};
-use crate::handlers::{admin_scope, auth_scope, health_check, user_scope};
+use crate::constants::{ALL_PERMISSIONS, BUILTIN_ADMIN_ROLE, JWT_KEYRING_REFRESH_SECONDS};
+use crate::database::mongodb::{
+    init_mongodb, ApiKeyRepository, FederatedIdentityRepository, InvitationRepository,
+    MembershipRepository, OAuthClientRepository, OrgInvitationRepository, OrganizationRepository,
+    PasskeyRepository, RoleRepository, ServiceAccountRepository, SessionRepository,
+    SigningKeyRepository, UserRepository,
+};
+use crate::database::redis::{
+    init_redis, LoginAttemptStore, OneTimeTokenStore, RateLimitStore, RefreshTokenStore,
+    TokenBlacklist,
+};
+use crate::federation::FederationClient;
+use crate::handlers::{
+    admin_scope, auth_scope, health_check, jwks, oauth_scope, openid_configuration, org_scope,
+    scim_scope, user_scope,
+};
+use crate::mail::MailService;
+use crate::middleware::rate_limit::{rate_limit, RateLimitGroup, RateLimiter};
+use crate::utils::token::{encrypt_stored_keys, reload_keyring};
+use crate::utils::totp::encrypt_stored_secrets;
 use actix_cors::Cors;
+use actix_web::middleware::from_fn;
 use actix_web::{web::Data, App, HttpServer};
+use std::time::Duration;
 use tracing::{info, warn};
 use tracing_actix_web::TracingLogger;
 use tracing_subscriber::{fmt::layer, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
@@ -43,7 +66,88 @@ async fn main() -> Result<(), std::io::Error> {
         .expect("Failed to connect to Redis");
 
     let user_repo = UserRepository::new(&db);
-    let blacklist = TokenBlacklist::new(redis_conn);
+    let session_repo = SessionRepository::new(&db);
+    let passkey_repo = PasskeyRepository::new(&db);
+    let signing_key_repo = SigningKeyRepository::new(&db);
+    let role_repo = RoleRepository::new(&db);
+    let org_repo = OrganizationRepository::new(&db);
+    let membership_repo = MembershipRepository::new(&db);
+    let org_invitation_repo = OrgInvitationRepository::new(&db);
+    let invitation_repo = InvitationRepository::new(&db);
+    let oauth_client_repo = OAuthClientRepository::new(&db);
+    let service_account_repo = ServiceAccountRepository::new(&db);
+    let api_key_repo = ApiKeyRepository::new(&db);
+    let federated_identity_repo = FederatedIdentityRepository::new(&db);
+    let blacklist = TokenBlacklist::new(redis_conn.clone());
+    let refresh_store = RefreshTokenStore::new(redis_conn.clone());
+    let one_time_tokens = OneTimeTokenStore::new(redis_conn.clone());
+    let login_attempts = LoginAttemptStore::new(redis_conn.clone());
+    let rate_limiter = RateLimiter::new(RateLimitStore::new(redis_conn));
+    let mail = MailService::new(&cfg).expect("Failed to set up mail delivery");
+    let federation = FederationClient::new(&cfg).expect("Failed to set up federated sign-in");
+    let authenticators = Authenticators::new(&cfg, &user_repo);
+
+    info!("Creating indexes...");
+    user_repo
+        .ensure_indexes()
+        .await
+        .expect("Failed to create user indexes");
+    session_repo
+        .ensure_indexes(Duration::from_secs(
+            cfg.refresh_token_exp_days as u64 * 24 * 60 * 60,
+        ))
+        .await
+        .expect("Failed to create session indexes");
+
+    info!("Preparing roles...");
+    role_repo
+        .ensure_built_in(BUILTIN_ADMIN_ROLE, &ALL_PERMISSIONS)
+        .await
+        .expect("Failed to create built-in roles");
+    let migrated = user_repo
+        .migrate_admin_flag()
+        .await
+        .expect("Failed to migrate admin users");
+    if migrated > 0 {
+        info!(
+            "Moved {} admin users to the '{}' role",
+            migrated, BUILTIN_ADMIN_ROLE
+        );
+    }
+
+    let encrypted = encrypt_stored_secrets(&cfg, &user_repo)
+        .await
+        .expect("Failed to encrypt stored TOTP secrets");
+    if encrypted > 0 {
+        info!("Encrypted {} stored TOTP secrets", encrypted);
+    }
+
+    info!("Loading JWT signing keys...");
+    let encrypted = encrypt_stored_keys(&cfg, &signing_key_repo)
+        .await
+        .expect("Failed to encrypt stored JWT signing keys");
+    if encrypted > 0 {
+        info!("Encrypted {} stored JWT signing keys", encrypted);
+    }
+    reload_keyring(&cfg, &signing_key_repo)
+        .await
+        .expect("Failed to load JWT signing keys");
+
+    // Pick up keys rotated by other instances
+    {
+        let cfg = cfg.clone();
+        let signing_key_repo = signing_key_repo.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval =
+                actix_web::rt::time::interval(Duration::from_secs(JWT_KEYRING_REFRESH_SECONDS));
+            loop {
+                interval.tick().await;
+                if let Err(e) = reload_keyring(&cfg, &signing_key_repo).await {
+                    warn!("Failed to refresh JWT signing keys: {}", e);
+                }
+            }
+        });
+    }
 
     let host = cfg.host.clone();
     let port = cfg.port;
@@ -56,11 +160,47 @@ async fn main() -> Result<(), std::io::Error> {
             .wrap(TracingLogger::default())
             .app_data(Data::new(cfg.clone()))
             .app_data(Data::new(user_repo.clone()))
+            .app_data(Data::new(session_repo.clone()))
+            .app_data(Data::new(passkey_repo.clone()))
+            .app_data(Data::new(signing_key_repo.clone()))
+            .app_data(Data::new(role_repo.clone()))
+            .app_data(Data::new(org_repo.clone()))
+            .app_data(Data::new(membership_repo.clone()))
+            .app_data(Data::new(org_invitation_repo.clone()))
+            .app_data(Data::new(invitation_repo.clone()))
+            .app_data(Data::new(oauth_client_repo.clone()))
+            .app_data(Data::new(service_account_repo.clone()))
+            .app_data(Data::new(api_key_repo.clone()))
+            .app_data(Data::new(federated_identity_repo.clone()))
             .app_data(Data::new(blacklist.clone()))
+            .app_data(Data::new(refresh_store.clone()))
+            .app_data(Data::new(one_time_tokens.clone()))
+            .app_data(Data::new(login_attempts.clone()))
+            .app_data(Data::new(rate_limiter.clone()))
+            .app_data(Data::new(mail.clone()))
+            .app_data(Data::new(federation.clone()))
+            .app_data(Data::new(authenticators.clone()))
             .service(health_check)
-            .service(auth_scope())
-            .service(user_scope())
-            .service(admin_scope())
+            .service(jwks)
+            .service(openid_configuration)
+            .service(auth_scope().wrap(from_fn(|req, next| {
+                rate_limit(RateLimitGroup::Auth, req, next)
+            })))
+            .service(oauth_scope().wrap(from_fn(|req, next| {
+                rate_limit(RateLimitGroup::Auth, req, next)
+            })))
+            .service(user_scope().wrap(from_fn(|req, next| {
+                rate_limit(RateLimitGroup::User, req, next)
+            })))
+            .service(org_scope().wrap(from_fn(|req, next| {
+                rate_limit(RateLimitGroup::User, req, next)
+            })))
+            .service(admin_scope().wrap(from_fn(|req, next| {
+                rate_limit(RateLimitGroup::Admin, req, next)
+            })))
+            .service(scim_scope().wrap(from_fn(|req, next| {
+                rate_limit(RateLimitGroup::Admin, req, next)
+            })))
     });
 
     let server = match (&ssl_cert_path, &ssl_key_path) {
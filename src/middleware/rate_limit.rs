@@ -0,0 +1,299 @@
+use crate::auth::api_key;
+use crate::config::app_config::AppConfig;
+use crate::config::rate_limit_config::{RateLimitAlgorithm, RateLimitPolicy};
+use crate::constants::RATE_LIMITED;
+use crate::database::mongodb::ApiKeyRepository;
+use crate::database::redis::{RateLimitDecision, RateLimitStore};
+use crate::errors::AppError;
+use crate::utils::client_addr::client_ip;
+use crate::utils::token::{decode_token, hash_token};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use actix_web::middleware::Next;
+use actix_web::web::Data;
+use actix_web::{Error, HttpRequest, ResponseError};
+use mongodb::bson::DateTime;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use time::OffsetDateTime;
+
+const MAX_LOCAL_ENTRIES: usize = 10_000;
+
+#[derive(Clone, Copy)]
+pub enum RateLimitGroup {
+    Auth,
+    User,
+    Admin,
+}
+
+impl RateLimitGroup {
+    fn name(self) -> &'static str {
+        match self {
+            RateLimitGroup::Auth => "auth",
+            RateLimitGroup::User => "user",
+            RateLimitGroup::Admin => "admin",
+        }
+    }
+
+    fn policy(self, cfg: &AppConfig) -> Option<RateLimitPolicy> {
+        match self {
+            RateLimitGroup::Auth => cfg.rate_limits.auth,
+            RateLimitGroup::User => cfg.rate_limits.user,
+            RateLimitGroup::Admin => cfg.rate_limits.admin,
+        }
+    }
+}
+
+enum LocalState {
+    Bucket {
+        tokens: f64,
+        ts: u64,
+    },
+    Window {
+        window: u64,
+        previous: u64,
+        current: u64,
+    },
+}
+
+struct LocalEntry {
+    state: LocalState,
+    expires_at: u64,
+}
+
+/// Counts requests in Redis so limits hold across instances, falling back to
+/// per-instance counters while Redis is unreachable.
+#[derive(Clone)]
+pub struct RateLimiter {
+    store: RateLimitStore,
+    fallback: Arc<Mutex<HashMap<String, LocalEntry>>>,
+}
+
+impl RateLimiter {
+    pub fn new(store: RateLimitStore) -> Self {
+        Self {
+            store,
+            fallback: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub async fn hit(&self, key: &str, policy: &RateLimitPolicy) -> RateLimitDecision {
+        match self.store.hit(key, policy).await {
+            Ok(decision) => decision,
+            Err(e) => {
+                tracing::warn!("Rate limit store unavailable, using local counters: {}", e);
+                self.hit_local(key, policy)
+            }
+        }
+    }
+
+    /// Same algorithms as the Redis scripts in `database::redis`.
+    fn hit_local(&self, key: &str, policy: &RateLimitPolicy) -> RateLimitDecision {
+        let now = (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as u64;
+        let limit = policy.limit;
+        let window_ms = policy.window_seconds * 1000;
+
+        let mut entries = self.fallback.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= MAX_LOCAL_ENTRIES {
+            entries.retain(|_, entry| entry.expires_at > now);
+        }
+
+        match policy.algorithm {
+            RateLimitAlgorithm::TokenBucket => {
+                let rate = limit as f64 / window_ms as f64;
+                let entry = entries.entry(key.into()).or_insert(LocalEntry {
+                    state: LocalState::Bucket {
+                        tokens: limit as f64,
+                        ts: now,
+                    },
+                    expires_at: 0,
+                });
+                let (mut tokens, ts) = match entry.state {
+                    LocalState::Bucket { tokens, ts } => (tokens, ts),
+                    _ => (limit as f64, now),
+                };
+                tokens = (tokens + now.saturating_sub(ts) as f64 * rate).min(limit as f64);
+
+                let (allowed, retry_after_ms) = if tokens >= 1.0 {
+                    tokens -= 1.0;
+                    (true, 0)
+                } else {
+                    (false, ((1.0 - tokens) / rate).ceil() as u64)
+                };
+                entry.state = LocalState::Bucket { tokens, ts: now };
+                entry.expires_at = now + window_ms;
+
+                RateLimitDecision {
+                    allowed,
+                    remaining: tokens.floor() as u64,
+                    reset_ms: ((limit as f64 - tokens) / rate).ceil() as u64,
+                    retry_after_ms,
+                }
+            }
+            RateLimitAlgorithm::SlidingWindow => {
+                let window = now / window_ms;
+                let into_window = now - window * window_ms;
+                let elapsed = into_window as f64 / window_ms as f64;
+                let until_next = window_ms - into_window;
+
+                let entry = entries.entry(key.into()).or_insert(LocalEntry {
+                    state: LocalState::Window {
+                        window,
+                        previous: 0,
+                        current: 0,
+                    },
+                    expires_at: 0,
+                });
+                let (previous, current) = match entry.state {
+                    LocalState::Window {
+                        window: w,
+                        previous,
+                        current,
+                    } if w == window => (previous, current),
+                    LocalState::Window {
+                        window: w, current, ..
+                    } if w + 1 == window => (current, 0),
+                    _ => (0, 0),
+                };
+
+                let estimate = previous as f64 * (1.0 - elapsed) + current as f64;
+                if estimate + 1.0 > limit as f64 {
+                    let mut wait = until_next;
+                    if current < limit && previous > 0 {
+                        let needed = 1.0 - (limit - current - 1) as f64 / previous as f64;
+                        wait = ((needed - elapsed) * window_ms as f64).ceil() as u64;
+                    }
+                    entry.state = LocalState::Window {
+                        window,
+                        previous,
+                        current,
+                    };
+                    return RateLimitDecision {
+                        allowed: false,
+                        remaining: 0,
+                        reset_ms: until_next,
+                        retry_after_ms: wait.max(1),
+                    };
+                }
+
+                entry.state = LocalState::Window {
+                    window,
+                    previous,
+                    current: current + 1,
+                };
+                entry.expires_at = now + window_ms * 2;
+
+                RateLimitDecision {
+                    allowed: true,
+                    remaining: (limit as f64 - estimate - 1.0).floor() as u64,
+                    reset_ms: until_next,
+                    retry_after_ms: 0,
+                }
+            }
+        }
+    }
+}
+
+/// Authenticated clients are limited per user or API key, everyone else per
+/// address. A key is only counted on its own once it is known to exist, so
+/// made-up keys can't buy fresh limits.
+async fn client_subject(
+    cfg: &AppConfig,
+    keys: Option<&ApiKeyRepository>,
+    req: &HttpRequest,
+) -> String {
+    let user_id = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .and_then(|token| decode_token(cfg, token).ok())
+        .map(|claims| claims.sub);
+
+    let key_id = match (&user_id, api_key(req), keys) {
+        (None, Some(key), Some(keys)) => keys
+            .find_by_hash(&hash_token(&key))
+            .await
+            .ok()
+            .flatten()
+            .filter(|k| k.expires_at.is_none_or(|t| t > DateTime::now()))
+            .map(|k| k.id.to_hex()),
+        _ => None,
+    };
+
+    match (user_id, key_id) {
+        (Some(user_id), _) => format!("user:{}", user_id),
+        (None, Some(key_id)) => format!("key:{}", key_id),
+        (None, None) => format!(
+            "ip:{}",
+            client_ip(&cfg.trusted_proxies, req)
+                .map(|ip| ip.to_string())
+                .unwrap_or_else(|| "unknown".into())
+        ),
+    }
+}
+
+fn seconds(ms: u64) -> u64 {
+    ms.div_ceil(1000)
+}
+
+fn insert_headers(headers: &mut HeaderMap, policy: &RateLimitPolicy, decision: &RateLimitDecision) {
+    let values = [
+        ("ratelimit-limit", policy.limit.to_string()),
+        ("ratelimit-remaining", decision.remaining.to_string()),
+        ("ratelimit-reset", seconds(decision.reset_ms).to_string()),
+        (
+            "ratelimit-policy",
+            format!("{};w={}", policy.limit, policy.window_seconds),
+        ),
+    ];
+    for (name, value) in values {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(HeaderName::from_static(name), value);
+        }
+    }
+}
+
+/// Limits requests to a route group. Each route is counted separately per
+/// client; wrap a scope with
+/// `from_fn(|req, next| rate_limit(RateLimitGroup::Auth, req, next))`.
+pub async fn rate_limit<B: MessageBody>(
+    group: RateLimitGroup,
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let cfg = req.app_data::<Data<AppConfig>>().cloned();
+    let limiter = req.app_data::<Data<RateLimiter>>().cloned();
+    let keys = req.app_data::<Data<ApiKeyRepository>>().cloned();
+    let (Some(cfg), Some(limiter)) = (cfg, limiter) else {
+        return next.call(req).await.map(|res| res.map_into_left_body());
+    };
+    let Some(policy) = group.policy(&cfg) else {
+        return next.call(req).await.map(|res| res.map_into_left_body());
+    };
+
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| req.path().to_string());
+    let key = format!(
+        "{}:{} {}:{}",
+        group.name(),
+        req.method(),
+        route,
+        client_subject(&cfg, keys.as_ref().map(|k| k.get_ref()), req.request()).await
+    );
+    let decision = limiter.hit(&key, &policy).await;
+
+    if !decision.allowed {
+        let retry_after = seconds(decision.retry_after_ms).max(1) as i64;
+        let mut response =
+            AppError::TooManyRequests(RATE_LIMITED.into(), retry_after).error_response();
+        insert_headers(response.headers_mut(), &policy, &decision);
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    let mut res = next.call(req).await?;
+    insert_headers(res.headers_mut(), &policy, &decision);
+    Ok(res.map_into_left_body())
+}
@@ -0,0 +1,19 @@
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+/// A long-lived credential a user creates for scripts and CI jobs. It acts as
+/// the user, limited to its scopes on administrative endpoints.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiKey {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub user_id: ObjectId,
+    pub name: String,
+    pub prefix: String, // first characters of the key, to tell keys apart
+    pub key_hash: String,
+    pub scopes: Vec<String>, // permissions the key may exercise
+    pub expires_at: Option<DateTime>,
+    pub created_at: DateTime,
+    pub last_used_at: Option<DateTime>,
+}
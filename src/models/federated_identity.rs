@@ -0,0 +1,17 @@
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+/// An account at an upstream OpenID Connect provider linked to a user. The
+/// provider and its `sub` claim identify it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FederatedIdentity {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub user_id: ObjectId,
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>, // as reported by the provider when linked
+    pub linked_at: DateTime,
+    pub last_login_at: Option<DateTime>,
+}
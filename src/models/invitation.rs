@@ -0,0 +1,15 @@
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Invitation {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub email: String, // lowercased
+    pub role: Option<String>,
+    pub invited_by: ObjectId,
+    pub jti: String, // id of the signed token mailed to the invitee
+    pub expires_at: DateTime,
+    pub created_at: DateTime,
+}
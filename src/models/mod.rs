@@ -1,3 +1,14 @@
+pub mod api_key;
+pub mod federated_identity;
+pub mod invitation;
+pub mod oauth_client;
+pub mod organization;
+pub mod passkey;
 pub mod request;
 pub mod response;
+pub mod role;
+pub mod scim;
+pub mod service_account;
+pub mod session;
+pub mod signing_key;
 pub mod user;
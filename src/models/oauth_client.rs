@@ -0,0 +1,23 @@
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientType {
+    Public,       // cannot keep a secret (SPAs, native apps), relies on PKCE
+    Confidential, // authenticates to the token endpoint with its secret
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OAuthClient {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub client_id: String,
+    pub name: String,
+    pub client_type: ClientType,
+    pub secret_hash: Option<String>, // confidential clients only
+    pub redirect_uris: Vec<String>,  // matched exactly
+    pub scopes: Vec<String>,         // scopes the client may request
+    pub created_at: DateTime,
+}
@@ -0,0 +1,49 @@
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Organization {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub name: String,
+    pub created_by: ObjectId,
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OrgRole {
+    Owner,
+    Admin,
+    Member,
+}
+
+impl OrgRole {
+    pub fn can_manage_members(self) -> bool {
+        matches!(self, OrgRole::Owner | OrgRole::Admin)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Membership {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub org_id: ObjectId,
+    pub user_id: ObjectId,
+    pub role: OrgRole,
+    pub joined_at: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrgInvitation {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub org_id: ObjectId,
+    pub email: String, // lowercased
+    pub role: OrgRole,
+    pub token_hash: String,
+    pub invited_by: ObjectId,
+    pub expires_at: DateTime,
+    pub created_at: DateTime,
+}
@@ -0,0 +1,16 @@
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Passkey {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub user_id: ObjectId,
+    pub credential_id: String, // base64url
+    pub public_key: String,    // base64url COSE_Key
+    pub sign_count: i64,
+    pub name: Option<String>,
+    pub created_at: DateTime,
+    pub last_used_at: Option<DateTime>,
+}
@@ -1,4 +1,6 @@
-use serde::Deserialize;
+use crate::models::oauth_client::ClientType;
+use crate::models::organization::OrgRole;
+use serde::{Deserialize, Serialize};
 use validator::Validate;
 
 #[derive(Debug, Deserialize, Validate)]
@@ -7,8 +9,9 @@ pub struct RegisterRequest {
     pub email: String,
     #[validate(length(min = 3, max = 30, message = "username must be 3-30 characters"))]
     pub username: String,
-    #[validate(length(min = 8, message = "password must be at least 8 characters"))]
     pub password: String,
+    #[validate(length(max = 100, message = "device name must be at most 100 characters"))]
+    pub device: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -16,6 +19,86 @@ pub struct LoginRequest {
     #[validate(email(message = "invalid email format"))]
     pub email: String,
     pub password: String,
+    #[validate(length(max = 100, message = "device name must be at most 100 characters"))]
+    pub device: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct RefreshRequest {
+    #[validate(length(min = 1, message = "refresh token is required"))]
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct VerifyEmailRequest {
+    #[validate(length(min = 1, message = "verification token is required"))]
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ResendVerificationRequest {
+    #[validate(email(message = "invalid email format"))]
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ForgotPasswordRequest {
+    #[validate(email(message = "invalid email format"))]
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ResetPasswordRequest {
+    #[validate(length(min = 1, message = "reset token is required"))]
+    pub token: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct MfaCodeRequest {
+    #[validate(length(min = 1, message = "code is required"))]
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct MfaVerifyRequest {
+    #[validate(length(min = 1, message = "mfa token is required"))]
+    pub mfa_token: String,
+    #[validate(length(min = 1, message = "code is required"))]
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct PasskeyRegisterRequest {
+    #[validate(length(min = 1, message = "credential id is required"))]
+    pub id: String,
+    #[validate(length(min = 1, message = "client data is required"))]
+    pub client_data_json: String,
+    #[validate(length(min = 1, message = "attestation object is required"))]
+    pub attestation_object: String,
+    #[validate(length(max = 100, message = "passkey name must be at most 100 characters"))]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct PasskeyLoginStartRequest {
+    #[validate(email(message = "invalid email format"))]
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct PasskeyLoginRequest {
+    #[validate(length(min = 1, message = "credential id is required"))]
+    pub id: String,
+    #[validate(length(min = 1, message = "client data is required"))]
+    pub client_data_json: String,
+    #[validate(length(min = 1, message = "authenticator data is required"))]
+    pub authenticator_data: String,
+    #[validate(length(min = 1, message = "signature is required"))]
+    pub signature: String,
+    pub user_handle: Option<String>,
+    #[validate(length(max = 100, message = "device name must be at most 100 characters"))]
+    pub device: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -33,7 +116,6 @@ pub struct UpdateUsernameRequest {
 #[derive(Debug, Deserialize, Validate)]
 pub struct UpdatePasswordRequest {
     pub old_password: String,
-    #[validate(length(min = 8, message = "password must be at least 8 characters"))]
     pub new_password: String,
 }
 
@@ -43,9 +125,9 @@ pub struct CreateUserRequest {
     pub email: String,
     #[validate(length(min = 3, max = 30, message = "username must be 3-30 characters"))]
     pub username: String,
-    #[validate(length(min = 8, message = "password must be at least 8 characters"))]
     pub password: String,
-    pub is_admin: bool,
+    #[serde(default)]
+    pub roles: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -54,11 +136,179 @@ pub struct UpdateUserRequest {
     pub email: Option<String>,
     #[validate(length(min = 3, max = 30, message = "username must be 3-30 characters"))]
     pub username: Option<String>,
-    #[validate(length(min = 8, message = "password must be at least 8 characters"))]
     pub password: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
-pub struct SetRoleRequest {
-    pub is_admin: bool,
+pub struct SetUserRolesRequest {
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateRoleRequest {
+    #[validate(length(min = 1, max = 32, message = "role name must be 1-32 characters"))]
+    pub name: String,
+    #[validate(length(max = 200, message = "description must be at most 200 characters"))]
+    pub description: Option<String>,
+    pub permissions: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateRoleRequest {
+    #[validate(length(max = 200, message = "description must be at most 200 characters"))]
+    pub description: Option<String>,
+    pub permissions: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateOrganizationRequest {
+    #[validate(length(min = 2, max = 64, message = "name must be 2-64 characters"))]
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SwitchOrganizationRequest {
+    pub org_id: Option<String>, // null leaves the active organization
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateMemberRequest {
+    pub role: OrgRole,
+}
+
+fn default_org_role() -> OrgRole {
+    OrgRole::Member
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateOrgInvitationRequest {
+    #[validate(email(message = "invalid email format"))]
+    pub email: String,
+    #[serde(default = "default_org_role")]
+    pub role: OrgRole,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct AcceptOrgInvitationRequest {
+    #[validate(length(min = 1, message = "invitation token is required"))]
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateInvitationRequest {
+    #[validate(email(message = "invalid email format"))]
+    pub email: String,
+    pub role: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct AcceptInviteRequest {
+    #[validate(length(min = 1, message = "invitation token is required"))]
+    pub token: String,
+    #[validate(length(min = 3, max = 30, message = "username must be 3-30 characters"))]
+    pub username: String,
+    pub password: String,
+    #[validate(length(max = 100, message = "device name must be at most 100 characters"))]
+    pub device: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateOAuthClientRequest {
+    #[validate(length(min = 1, max = 64, message = "name must be 1-64 characters"))]
+    pub name: String,
+    pub client_type: ClientType,
+    #[validate(length(min = 1, message = "at least one redirect uri is required"))]
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateOAuthClientRequest {
+    #[validate(length(min = 1, max = 64, message = "name must be 1-64 characters"))]
+    pub name: Option<String>,
+    #[validate(length(min = 1, message = "at least one redirect uri is required"))]
+    pub redirect_uris: Option<Vec<String>>,
+    pub scopes: Option<Vec<String>>,
+}
+
+/// Parameters of an authorization request (RFC 6749 section 4.1.1, RFC 7636,
+/// OpenID Connect Core section 3.1.2.1).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuthorizeRequest {
+    pub response_type: Option<String>,
+    pub client_id: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub nonce: Option<String>, // OpenID Connect, echoed in the id token
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuthorizeDecisionRequest {
+    #[serde(flatten)]
+    pub request: AuthorizeRequest,
+    pub approve: bool,
+}
+
+/// Form body of the token endpoint (RFC 6749 sections 4.1.3, 4.4.2 and 6).
+#[derive(Debug, Deserialize)]
+pub struct TokenRequest {
+    pub grant_type: Option<String>,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    pub refresh_token: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub scope: Option<String>, // client_credentials only, narrows the granted scopes
+}
+
+/// Form body of the introspection (RFC 7662) and revocation (RFC 7009)
+/// endpoints. A `token_type_hint` is accepted but not needed: access tokens
+/// are JWTs and refresh tokens are opaque.
+#[derive(Debug, Deserialize)]
+pub struct TokenActionRequest {
+    pub token: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateServiceAccountRequest {
+    #[validate(length(min = 1, max = 64, message = "name must be 1-64 characters"))]
+    pub name: String,
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateServiceAccountRequest {
+    #[validate(length(min = 1, max = 64, message = "name must be 1-64 characters"))]
+    pub name: Option<String>,
+    pub scopes: Option<Vec<String>>,
+    pub disabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateApiKeyRequest {
+    #[validate(length(min = 1, max = 64, message = "name must be 1-64 characters"))]
+    pub name: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    #[validate(range(min = 1, max = 365, message = "expires_in_days must be 1-365"))]
+    pub expires_in_days: Option<i64>, // never expires when omitted
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FederatedStartQuery {
+    pub device: Option<String>,
+}
+
+/// Query of the redirect back from an upstream provider.
+#[derive(Debug, Deserialize)]
+pub struct FederatedCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
 }
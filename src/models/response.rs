@@ -1,3 +1,6 @@
+use crate::models::oauth_client::ClientType;
+use crate::models::organization::OrgRole;
+use crate::utils::token::PrincipalKind;
 use serde::Serialize;
 
 #[derive(Debug, Serialize)]
@@ -7,15 +10,131 @@ pub struct Response<T> {
     pub data: Option<T>,
 }
 
+/// A password policy rule that a new password breaks.
+#[derive(Debug, Clone, Serialize)]
+pub struct PasswordViolation {
+    pub code: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PasswordPolicyErrors {
+    pub violations: Vec<PasswordViolation>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct Token {
     pub token: String,
+    pub refresh_token: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccessToken {
+    pub token: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MfaChallenge {
+    pub mfa_token: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PasswordChangeChallenge {
+    pub password_change_token: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MfaStatus {
+    pub enabled: bool,
+    pub recovery_codes_remaining: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MfaEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RelyingParty {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyUser {
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PubKeyCredParam {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub alg: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub id: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+    pub resident_key: &'static str,
+    pub user_verification: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyCreationOptions {
+    pub challenge: String,
+    pub rp: RelyingParty,
+    pub user: PasskeyUser,
+    pub pub_key_cred_params: Vec<PubKeyCredParam>,
+    pub timeout: i64,
+    pub attestation: &'static str,
+    pub authenticator_selection: AuthenticatorSelection,
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyRequestOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub timeout: i64,
+    pub user_verification: &'static str,
+    pub allow_credentials: Vec<CredentialDescriptor>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PasskeyInfo {
+    pub id: String,
+    pub name: Option<String>,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct AboutMe {
     pub email: String,
     pub username: String,
+    pub email_verified: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -23,5 +142,187 @@ pub struct UserInfo {
     pub id: String,
     pub email: String,
     pub username: String,
-    pub is_admin: bool,
+    pub roles: Vec<String>,
+    pub email_verified: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub device: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: i64,
+    pub last_seen_at: i64,
+    pub current: bool,
+    pub client_id: Option<String>, // OAuth client the session was granted to
+}
+
+#[derive(Debug, Serialize)]
+pub struct SigningKeyInfo {
+    pub kid: String,
+    pub algorithm: String,
+    pub activates_at: i64,
+    pub created_at: i64,
+    pub status: String, // pending, current, retired or expired
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoleInfo {
+    pub name: String,
+    pub description: Option<String>,
+    pub permissions: Vec<String>,
+    pub built_in: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrganizationInfo {
+    pub id: String,
+    pub name: String,
+    pub role: OrgRole,
+    pub active: bool,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MemberInfo {
+    pub user_id: String,
+    pub email: String,
+    pub username: String,
+    pub role: OrgRole,
+    pub joined_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrgInvitationInfo {
+    pub id: String,
+    pub email: String,
+    pub role: OrgRole,
+    pub expires_at: i64,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InvitationInfo {
+    pub id: String,
+    pub email: String,
+    pub role: Option<String>,
+    pub invited_by: String,
+    pub expires_at: i64,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OAuthClientInfo {
+    pub client_id: String,
+    pub name: String,
+    pub client_type: ClientType,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OAuthClientCredentials {
+    #[serde(flatten)]
+    pub client: OAuthClientInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>, // only shown when created or rotated
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthorizationRedirect {
+    pub redirect_to: String,
+}
+
+/// Token endpoint response (RFC 6749 section 5.1), sent without the usual
+/// envelope.
+#[derive(Debug, Serialize)]
+pub struct OAuthToken {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>, // not issued to service accounts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>, // when the openid scope was granted
+}
+
+/// Introspection response (RFC 7662 section 2.2), sent without the usual
+/// envelope. Only `active` is present for a token that is not.
+#[derive(Debug, Default, Serialize)]
+pub struct TokenIntrospection {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<PrincipalKind>, // user or service
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub org: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ServiceAccountInfo {
+    pub client_id: String,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub disabled: bool,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ServiceAccountCredentials {
+    #[serde(flatten)]
+    pub account: ServiceAccountInfo,
+    pub client_secret: String, // only shown when created or rotated
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<i64>,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiKeyCredentials {
+    #[serde(flatten)]
+    pub api_key: ApiKeyInfo,
+    pub key: String, // only shown when created
+}
+
+#[derive(Debug, Serialize)]
+pub struct FederatedProviderInfo {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IdentityInfo {
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub linked_at: i64,
+    pub last_login_at: Option<i64>,
 }
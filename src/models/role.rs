@@ -0,0 +1,14 @@
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Role {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub name: String, // referenced from User.roles
+    pub description: Option<String>,
+    pub permissions: Vec<String>,
+    pub built_in: bool,
+    pub created_at: DateTime,
+}
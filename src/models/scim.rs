@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// SCIM uses its own camelCase wire format (RFC 7643), so these are kept
+// apart from the envelope-based request and response types.
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimListQuery {
+    pub filter: Option<String>,
+    pub start_index: Option<i64>, // 1-based
+    pub count: Option<i64>,
+    pub excluded_attributes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimUserRequest {
+    pub user_name: String, // the user's email address
+    pub external_id: Option<String>,
+    pub display_name: Option<String>,
+    pub active: Option<bool>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScimMemberRef {
+    pub value: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimGroupRequest {
+    pub display_name: String,
+    #[serde(default)]
+    pub members: Vec<ScimMemberRef>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScimPatchRequest {
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    #[serde(rename = "Operations")]
+    pub operations: Vec<ScimPatchOperation>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScimPatchOperation {
+    pub op: String,
+    pub path: Option<String>,
+    pub value: Option<Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimMeta {
+    pub resource_type: &'static str,
+    pub created: String,
+    pub location: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScimEmail {
+    pub value: String,
+    pub primary: bool,
+}
+
+/// A user in a group, or a group in a user's `groups`.
+#[derive(Debug, Serialize)]
+pub struct ScimMember {
+    pub value: String,
+    pub display: String,
+    #[serde(rename = "$ref")]
+    pub reference: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimUser {
+    pub schemas: [&'static str; 1],
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    pub user_name: String,
+    pub display_name: String,
+    pub emails: Vec<ScimEmail>,
+    pub active: bool,
+    pub groups: Vec<ScimMember>,
+    pub meta: ScimMeta,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimGroup {
+    pub schemas: [&'static str; 1],
+    pub id: String,
+    pub display_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub members: Option<Vec<ScimMember>>, // omitted with excludedAttributes=members
+    pub meta: ScimMeta,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimListResponse<T> {
+    pub schemas: [&'static str; 1],
+    pub total_results: u64,
+    pub start_index: i64,
+    pub items_per_page: usize,
+    #[serde(rename = "Resources")]
+    pub resources: Vec<T>,
+}
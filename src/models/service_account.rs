@@ -0,0 +1,18 @@
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+/// A machine identity authenticating with the client credentials grant.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServiceAccount {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub client_id: String,
+    pub name: String,
+    pub secret_hash: String,
+    pub scopes: Vec<String>, // permissions granted to the account
+    pub disabled: bool,
+    pub token_version: i32, // bumped to revoke every issued token
+    pub created_at: DateTime,
+    pub last_used_at: Option<DateTime>,
+}
@@ -0,0 +1,21 @@
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Session {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub user_id: ObjectId,
+    pub device: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: DateTime,
+    pub last_seen_at: DateTime,
+    #[serde(default)]
+    pub org_id: Option<ObjectId>, // active organization
+    #[serde(default)]
+    pub client_id: Option<String>, // OAuth client the session was granted to
+    #[serde(default)]
+    pub scope: Option<String>, // scopes granted to that client
+}
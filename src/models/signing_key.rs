@@ -0,0 +1,16 @@
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SigningKey {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub kid: String,
+    pub algorithm: String,
+    pub material: String, // HMAC secret or PKCS#8 PEM private key
+    #[serde(default)]
+    pub encrypted: bool, // material sealed with SIGNING_KEY_ENCRYPTION_KEY
+    pub activates_at: DateTime,
+    pub created_at: DateTime,
+}
@@ -1,4 +1,5 @@
 use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -7,7 +8,49 @@ pub struct User {
     pub id: ObjectId,
     pub email: String,
     pub username: String,
-    pub password_hash: String,
-    pub is_admin: bool,
+    pub password_hash: String, // empty for accounts created by federated sign-in
+    #[serde(default)]
+    pub password_history: Vec<String>, // previous hashes, most recent first
+    #[serde(default)]
+    pub password_changed_at: Option<DateTime>,
+    #[serde(default)]
+    pub roles: Vec<String>,
     pub token_version: i32,
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub mfa: Mfa,
+    // accounts created before verification existed are treated as verified
+    #[serde(default = "default_email_verified")]
+    pub email_verified: bool,
+    #[serde(default)]
+    pub email_verified_at: Option<DateTime>,
+    #[serde(default)]
+    pub ldap_dn: Option<String>, // set for accounts provisioned from the LDAP directory
+    #[serde(default)]
+    pub external_id: Option<String>, // the provisioning client's id, from SCIM
+}
+
+fn default_email_verified() -> bool {
+    true
+}
+
+/// Fits a name taken from an external account to the 3-30 character
+/// username rule.
+pub fn fit_username(name: &str) -> String {
+    let mut username: String = name.trim().chars().take(30).collect();
+    while username.chars().count() < 3 {
+        username.push('_');
+    }
+    username
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Mfa {
+    pub totp_secret: Option<String>,
+    #[serde(default)]
+    pub totp_secret_encrypted: bool, // sealed with SIGNING_KEY_ENCRYPTION_KEY
+    pub totp_enabled: bool,
+    pub totp_last_step: Option<i64>,
+    pub recovery_codes: Vec<String>, // sha256 hashes
 }
@@ -0,0 +1,107 @@
+use actix_web::HttpRequest;
+use ipnet::IpNet;
+use std::net::IpAddr;
+
+/// Parses a comma separated list of proxy addresses and CIDR ranges.
+pub fn parse_trusted_proxies(value: &str) -> Result<Vec<IpNet>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            entry
+                .parse::<IpNet>()
+                .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| format!("invalid address or range: {}", entry))
+        })
+        .collect()
+}
+
+/// The address of the client behind the request. `X-Forwarded-For` is only
+/// believed when the connection comes from a trusted proxy, and is then read
+/// from the right, so entries a client prepends to it are ignored.
+pub fn client_ip(trusted_proxies: &[IpNet], req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let trusted = |ip: &IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+    if !trusted(&peer) {
+        return Some(peer);
+    }
+
+    let forwarded: Vec<IpAddr> = req
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .map(|entry| entry.trim().parse().ok())
+        .collect::<Option<_>>()
+        .unwrap_or_default();
+
+    let mut client = peer;
+    for ip in forwarded.into_iter().rev() {
+        client = ip;
+        if !trusted(&ip) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn request(peer: &str, forwarded: Option<&str>) -> HttpRequest {
+        let mut req = TestRequest::default().peer_addr(format!("{}:4000", peer).parse().unwrap());
+        if let Some(forwarded) = forwarded {
+            req = req.insert_header(("X-Forwarded-For", forwarded));
+        }
+        req.to_http_request()
+    }
+
+    fn ip(value: &str) -> Option<IpAddr> {
+        Some(value.parse().unwrap())
+    }
+
+    #[test]
+    fn forwarded_header_is_ignored_without_trusted_proxies() {
+        let req = request("203.0.113.7", Some("198.51.100.1"));
+        assert_eq!(client_ip(&[], &req), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn forwarded_header_is_ignored_from_untrusted_peers() {
+        let proxies = parse_trusted_proxies("10.0.0.0/8").unwrap();
+        let req = request("203.0.113.7", Some("198.51.100.1"));
+        assert_eq!(client_ip(&proxies, &req), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn trusted_proxies_are_skipped_from_the_right() {
+        let proxies = parse_trusted_proxies("10.0.0.1, 10.1.0.0/16").unwrap();
+
+        let req = request("10.0.0.1", Some("198.51.100.1"));
+        assert_eq!(client_ip(&proxies, &req), ip("198.51.100.1"));
+
+        // The client supplied the first entry itself
+        let req = request("10.0.0.1", Some("1.2.3.4, 198.51.100.1, 10.1.2.3"));
+        assert_eq!(client_ip(&proxies, &req), ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn malformed_forwarded_header_falls_back_to_the_peer() {
+        let proxies = parse_trusted_proxies("10.0.0.1").unwrap();
+        let req = request("10.0.0.1", Some("unknown, 198.51.100.1"));
+        assert_eq!(client_ip(&proxies, &req), ip("10.0.0.1"));
+
+        let req = request("10.0.0.1", None);
+        assert_eq!(client_ip(&proxies, &req), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn trusted_proxies_must_parse() {
+        assert_eq!(parse_trusted_proxies("").unwrap(), Vec::new());
+        assert!(parse_trusted_proxies("10.0.0.0/8, ::1").is_ok());
+        assert!(parse_trusted_proxies("proxy.internal").is_err());
+    }
+}
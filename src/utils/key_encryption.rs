@@ -0,0 +1,108 @@
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rand_core::{OsRng, RngCore};
+
+const NONCE_LEN: usize = 12;
+
+/// Key-encryption key protecting private key material stored in the
+/// database. Whoever can read the database but not the environment learns
+/// nothing about the keys; whoever holds both can sign tokens.
+#[derive(Clone)]
+pub struct KeyEncryptionKey([u8; 32]);
+
+impl KeyEncryptionKey {
+    /// Parses a base64 encoded 256-bit key.
+    pub fn from_base64(value: &str) -> Result<Self, String> {
+        let bytes = STANDARD
+            .decode(value.trim())
+            .map_err(|_| "must be base64")?;
+        let key = bytes.try_into().map_err(|_| "must be 32 bytes")?;
+        Ok(Self(key))
+    }
+
+    /// Encrypts `material` with AES-256-GCM. `context` (the key id) is
+    /// authenticated too, so ciphertexts can't be swapped between records.
+    pub fn seal(&self, material: &str, context: &str) -> Result<String, String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = Aes256Gcm::new(&self.0.into())
+            .encrypt(
+                &Nonce::from(nonce),
+                Payload {
+                    msg: material.as_bytes(),
+                    aad: context.as_bytes(),
+                },
+            )
+            .map_err(|_| "encryption failed")?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok(STANDARD.encode(sealed))
+    }
+
+    /// Reverses [`seal`](Self::seal), failing if the key, the context or the
+    /// ciphertext differ.
+    pub fn open(&self, sealed: &str, context: &str) -> Result<String, String> {
+        let sealed = STANDARD.decode(sealed).map_err(|_| "not base64")?;
+        let nonce: [u8; NONCE_LEN] = sealed
+            .get(..NONCE_LEN)
+            .and_then(|nonce| nonce.try_into().ok())
+            .ok_or("ciphertext too short")?;
+        let ciphertext = &sealed[NONCE_LEN..];
+        let material = Aes256Gcm::new(&self.0.into())
+            .decrypt(
+                &Nonce::from(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: context.as_bytes(),
+                },
+            )
+            .map_err(|_| "decryption failed")?;
+        String::from_utf8(material).map_err(|_| "decryption failed".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kek(byte: u8) -> KeyEncryptionKey {
+        KeyEncryptionKey::from_base64(&STANDARD.encode([byte; 32])).unwrap()
+    }
+
+    #[test]
+    fn sealed_material_round_trips() {
+        let sealed = kek(1).seal("secret", "kid-1").unwrap();
+        assert!(!sealed.contains("secret"));
+        assert_eq!(kek(1).open(&sealed, "kid-1").unwrap(), "secret");
+    }
+
+    #[test]
+    fn sealing_is_randomized() {
+        assert_ne!(
+            kek(1).seal("secret", "kid-1").unwrap(),
+            kek(1).seal("secret", "kid-1").unwrap()
+        );
+    }
+
+    #[test]
+    fn wrong_key_context_or_ciphertext_fails() {
+        let sealed = kek(1).seal("secret", "kid-1").unwrap();
+        assert!(kek(2).open(&sealed, "kid-1").is_err());
+        assert!(kek(1).open(&sealed, "kid-2").is_err());
+
+        let mut bytes = STANDARD.decode(&sealed).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(kek(1).open(&STANDARD.encode(bytes), "kid-1").is_err());
+        assert!(kek(1).open("AAAA", "kid-1").is_err());
+    }
+
+    #[test]
+    fn keys_must_be_256_bits() {
+        assert!(KeyEncryptionKey::from_base64(&STANDARD.encode([0u8; 16])).is_err());
+        assert!(KeyEncryptionKey::from_base64("not base64!").is_err());
+    }
+}
@@ -1,2 +1,9 @@
+pub mod client_addr;
+pub mod key_encryption;
 pub mod password;
+pub mod password_policy;
+pub mod password_strength;
+pub mod scim_filter;
 pub mod token;
+pub mod totp;
+pub mod webauthn;
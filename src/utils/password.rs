@@ -1,19 +1,192 @@
-use crate::constants::INVALID_CREDENTIALS;
+use crate::config::password_config::PasswordHashing;
+use crate::constants::{INVALID_CREDENTIALS, PASSWORD_TOO_LONG_FOR_BCRYPT};
 use crate::errors::AppError;
-use bcrypt::{DEFAULT_COST, hash, verify};
+use actix_web::web;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use bcrypt::{non_truncating_hash, verify, BcryptError};
 
-pub fn hash_password(plain: &str) -> Result<String, AppError> {
-    hash(plain, DEFAULT_COST).map_err(|_| AppError::Internal)
+fn hash_blocking(policy: &PasswordHashing, plain: &str) -> Result<String, AppError> {
+    match *policy {
+        PasswordHashing::Argon2id {
+            memory_kib,
+            iterations,
+            parallelism,
+        } => {
+            let params = Params::new(memory_kib, iterations, parallelism, None)
+                .map_err(|_| AppError::Internal)?;
+            let salt = SaltString::generate(&mut OsRng);
+            Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                .hash_password(plain.as_bytes(), &salt)
+                .map(|hash| hash.to_string())
+                .map_err(|_| AppError::Internal)
+        }
+        PasswordHashing::Bcrypt { cost } => non_truncating_hash(plain, cost).map_err(|e| match e {
+            BcryptError::Truncation(_) => AppError::BadRequest(PASSWORD_TOO_LONG_FOR_BCRYPT.into()),
+            _ => AppError::Internal,
+        }),
+    }
 }
 
-pub fn verify_password(hash: &str, plain: &str) -> Result<(), AppError> {
-    verify(plain, hash)
-        .map_err(|_| AppError::Unauthorized(INVALID_CREDENTIALS.into()))
-        .and_then(|ok| {
-            if ok {
-                Ok(())
-            } else {
-                Err(AppError::Unauthorized(INVALID_CREDENTIALS.into()))
-            }
+fn verify_blocking(hash: &str, plain: &str) -> bool {
+    if hash.starts_with("$argon2") {
+        // The algorithm and parameters are read from the hash itself
+        PasswordHash::new(hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(plain.as_bytes(), &hash)
+                .is_ok()
         })
+    } else {
+        verify(plain, hash).unwrap_or(false)
+    }
+}
+
+/// Hashes with the configured policy into a PHC string. Hashing is slow by
+/// design, so it runs on the blocking thread pool instead of the worker.
+pub async fn hash_password(policy: &PasswordHashing, plain: &str) -> Result<String, AppError> {
+    let policy = policy.clone();
+    let plain = plain.to_string();
+    web::block(move || hash_blocking(&policy, &plain))
+        .await
+        .map_err(|_| AppError::Internal)?
+}
+
+/// Accepts argon2 and bcrypt hashes regardless of the configured policy.
+/// Accounts without a password never match.
+pub async fn verify_password(hash: &str, plain: &str) -> Result<(), AppError> {
+    let matches = !hash.is_empty() && {
+        let hash = hash.to_string();
+        let plain = plain.to_string();
+        web::block(move || verify_blocking(&hash, &plain))
+            .await
+            .map_err(|_| AppError::Internal)?
+    };
+
+    if matches {
+        Ok(())
+    } else {
+        Err(AppError::Unauthorized(INVALID_CREDENTIALS.into()))
+    }
+}
+
+/// Whether `hash` was made with another algorithm or other parameters than
+/// `policy` asks for.
+pub fn needs_rehash(policy: &PasswordHashing, hash: &str) -> bool {
+    match *policy {
+        PasswordHashing::Argon2id {
+            memory_kib,
+            iterations,
+            parallelism,
+        } => match PasswordHash::new(hash) {
+            Ok(parsed) if parsed.algorithm == argon2::ARGON2ID_IDENT => Params::try_from(&parsed)
+                .map_or(true, |params| {
+                    params.m_cost() != memory_kib
+                        || params.t_cost() != iterations
+                        || params.p_cost() != parallelism
+                }),
+            _ => true,
+        },
+        // `$2b$12$...`
+        PasswordHashing::Bcrypt { cost } => {
+            !hash.starts_with("$2") || hash.get(4..6).and_then(|c| c.parse().ok()) != Some(cost)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARGON2: PasswordHashing = PasswordHashing::Argon2id {
+        memory_kib: 1024,
+        iterations: 1,
+        parallelism: 1,
+    };
+    const BCRYPT: PasswordHashing = PasswordHashing::Bcrypt { cost: 4 };
+
+    #[test]
+    fn hashes_verify_with_either_algorithm() {
+        for policy in [ARGON2, BCRYPT] {
+            let hash = hash_blocking(&policy, "correct horse").unwrap();
+            assert!(verify_blocking(&hash, "correct horse"), "{:?}", policy);
+            assert!(!verify_blocking(&hash, "correct horsE"), "{:?}", policy);
+            assert!(!needs_rehash(&policy, &hash), "{:?}", policy);
+        }
+
+        // Hashes from other bcrypt implementations
+        let legacy = bcrypt::hash_with_result("correct horse", 4)
+            .unwrap()
+            .format_for_version(bcrypt::Version::TwoY);
+        assert!(legacy.starts_with("$2y$04$"));
+        assert!(verify_blocking(&legacy, "correct horse"));
+        assert!(!verify_blocking("not a hash", "correct horse"));
+    }
+
+    #[test]
+    fn changed_argon2_parameters_need_a_rehash() {
+        let hash = hash_blocking(&ARGON2, "correct horse").unwrap();
+        for policy in [
+            PasswordHashing::Argon2id {
+                memory_kib: 2048,
+                iterations: 1,
+                parallelism: 1,
+            },
+            PasswordHashing::Argon2id {
+                memory_kib: 1024,
+                iterations: 2,
+                parallelism: 1,
+            },
+            PasswordHashing::Argon2id {
+                memory_kib: 1024,
+                iterations: 1,
+                parallelism: 2,
+            },
+            BCRYPT,
+        ] {
+            assert!(needs_rehash(&policy, &hash), "{:?}", policy);
+        }
+
+        // Other argon2 variants and unparsable hashes are replaced too
+        let argon2i = Argon2::new(
+            Algorithm::Argon2i,
+            Version::V0x13,
+            Params::new(1024, 1, 1, None).unwrap(),
+        )
+        .hash_password(b"correct horse", &SaltString::generate(&mut OsRng))
+        .unwrap()
+        .to_string();
+        assert!(verify_blocking(&argon2i, "correct horse"));
+        assert!(needs_rehash(&ARGON2, &argon2i));
+        assert!(needs_rehash(&ARGON2, "$argon2id$garbage"));
+    }
+
+    #[test]
+    fn changed_bcrypt_cost_needs_a_rehash() {
+        let hash = hash_blocking(&BCRYPT, "correct horse").unwrap();
+        assert!(hash.starts_with("$2b$04$"));
+        assert!(needs_rehash(&PasswordHashing::Bcrypt { cost: 5 }, &hash));
+        assert!(needs_rehash(&ARGON2, &hash));
+
+        let argon2 = hash_blocking(&ARGON2, "correct horse").unwrap();
+        assert!(needs_rehash(&BCRYPT, &argon2));
+    }
+
+    #[test]
+    fn bcrypt_refuses_to_truncate() {
+        let long = "x".repeat(73);
+        assert!(matches!(
+            hash_blocking(&BCRYPT, &long),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(hash_blocking(&ARGON2, &long).is_ok());
+    }
+
+    #[actix_web::test]
+    async fn accounts_without_a_password_never_match() {
+        assert!(verify_password("", "").await.is_err());
+        let hash = hash_password(&ARGON2, "correct horse").await.unwrap();
+        assert!(verify_password(&hash, "correct horse").await.is_ok());
+        assert!(verify_password(&hash, "").await.is_err());
+    }
 }
@@ -0,0 +1,349 @@
+use crate::config::password_config::PasswordPolicy;
+use crate::errors::AppError;
+use crate::models::response::PasswordViolation;
+use crate::models::user::User;
+use crate::utils::password::verify_password;
+use crate::utils::password_strength;
+use mongodb::bson::DateTime;
+use sha1::{Digest, Sha1};
+use std::io::ErrorKind;
+use std::path::Path;
+use tracing::error;
+
+fn violation(code: &'static str, message: impl Into<String>) -> PasswordViolation {
+    PasswordViolation {
+        code,
+        message: message.into(),
+    }
+}
+
+/// Looks the password up in Have I Been Pwned range files: `<PREFIX>.txt`
+/// lists `SUFFIX:COUNT` for the SHA-1 hashes starting with that five digit
+/// prefix, as returned by the range API or saved by the official downloader.
+async fn is_breached(dir: &Path, password: &str) -> Result<bool, AppError> {
+    let digest = hex::encode_upper(Sha1::digest(password.as_bytes()));
+    let (prefix, suffix) = digest.split_at(5);
+    let path = dir.join(format!("{}.txt", prefix));
+
+    let ranges = match tokio::fs::read_to_string(&path).await {
+        Ok(ranges) => ranges,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => {
+            error!("Failed to read {}: {}", path.display(), e);
+            return Err(AppError::Internal);
+        }
+    };
+
+    // Padding entries have a count of 0
+    Ok(ranges
+        .lines()
+        .filter_map(|line| line.trim().split_once(':'))
+        .any(|(hash, count)| hash.eq_ignore_ascii_case(suffix) && count.trim() != "0"))
+}
+
+async fn find_violations(
+    policy: &PasswordPolicy,
+    password: &str,
+    personal: &[&str],
+) -> Result<Vec<PasswordViolation>, AppError> {
+    let mut violations = Vec::new();
+
+    let length = password.chars().count();
+    if length < policy.min_length {
+        violations.push(violation(
+            "too_short",
+            format!("password must be at least {} characters", policy.min_length),
+        ));
+    }
+    if length > policy.max_length {
+        violations.push(violation(
+            "too_long",
+            format!("password must be at most {} characters", policy.max_length),
+        ));
+    }
+
+    let classes = [
+        password.chars().any(char::is_lowercase),
+        password.chars().any(char::is_uppercase),
+        password.chars().any(|c| c.is_ascii_digit()),
+        password.chars().any(|c| !c.is_alphanumeric()),
+    ];
+    if classes.iter().filter(|c| **c).count() < policy.min_classes {
+        violations.push(violation(
+            "missing_classes",
+            format!(
+                "password must mix at least {} of lowercase, uppercase, digits and symbols",
+                policy.min_classes
+            ),
+        ));
+    }
+
+    let lower = password.to_lowercase();
+    let contains_personal = personal
+        .iter()
+        .flat_map(|p| [*p, p.split('@').next().unwrap_or_default()])
+        .any(|p| p.chars().count() >= 3 && lower.contains(&p.to_lowercase()));
+    if contains_personal {
+        violations.push(violation(
+            "personal_info",
+            "password must not contain your email address or username",
+        ));
+    }
+
+    // An overlong password is rejected anyway, so it isn't worth estimating
+    if length <= policy.max_length {
+        if password_strength::score(password, personal) < policy.min_score {
+            violations.push(violation("too_weak", "password is too easy to guess"));
+        }
+        if let Some(ref dir) = policy.breached_dir {
+            if is_breached(dir, password).await? {
+                violations.push(violation(
+                    "breached",
+                    "password has appeared in a data breach, choose another one",
+                ));
+            }
+        }
+    }
+
+    Ok(violations)
+}
+
+fn into_result(violations: Vec<PasswordViolation>) -> Result<(), AppError> {
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::PasswordPolicy(violations))
+    }
+}
+
+/// Checks a new password against the policy and reports every rule it
+/// breaks. `personal` holds the account's email address and username.
+pub async fn check_password(
+    policy: &PasswordPolicy,
+    password: &str,
+    personal: &[&str],
+) -> Result<(), AppError> {
+    into_result(find_violations(policy, password, personal).await?)
+}
+
+/// Whether the password is the user's current one or one of the previous
+/// ones the history remembers.
+async fn is_reused(policy: &PasswordPolicy, user: &User, password: &str) -> Result<bool, AppError> {
+    let recent = std::iter::once(&user.password_hash)
+        .chain(&user.password_history)
+        .take(policy.history);
+    for hash in recent {
+        match verify_password(hash, password).await {
+            Ok(()) => return Ok(true),
+            Err(AppError::Unauthorized(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(false)
+}
+
+/// Checks a password replacing the user's current one: on top of the rules
+/// for any new password, it must not be one of their recent passwords.
+pub async fn check_password_change(
+    policy: &PasswordPolicy,
+    user: &User,
+    password: &str,
+) -> Result<(), AppError> {
+    let mut violations = find_violations(policy, password, &[&user.email, &user.username]).await?;
+
+    if is_reused(policy, user, password).await? {
+        let message = match policy.history {
+            1 => "password must differ from the current one".to_string(),
+            n => format!("password must differ from your last {} passwords", n),
+        };
+        violations.push(violation("reused", message));
+    }
+
+    into_result(violations)
+}
+
+/// Swaps in a new password hash, moving the old one into the history. Only
+/// as many old hashes are kept as the history needs.
+pub fn set_password_hash(policy: &PasswordPolicy, user: &mut User, hash: String) {
+    let old = std::mem::replace(&mut user.password_hash, hash);
+    if !old.is_empty() {
+        user.password_history.insert(0, old);
+    }
+    // Together with the current hash, these cover the last `history` passwords
+    let keep = policy.history.saturating_sub(1);
+    user.password_history.truncate(keep);
+    user.password_changed_at = Some(DateTime::now());
+}
+
+/// Whether the password is older than the policy allows. Accounts without a
+/// recorded change count from their creation; directory and federated
+/// accounts have no password here to expire.
+pub fn password_expired(policy: &PasswordPolicy, user: &User) -> bool {
+    if policy.max_age_days == 0 || user.password_hash.is_empty() || user.ldap_dn.is_some() {
+        return false;
+    }
+    let changed_at = user
+        .password_changed_at
+        .unwrap_or_else(|| user.id.timestamp());
+    let age_ms = DateTime::now().timestamp_millis() - changed_at.timestamp_millis();
+    age_ms >= policy.max_age_days * 24 * 60 * 60 * 1000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::password_config::PasswordHashing;
+    use crate::models::user::Mfa;
+    use crate::utils::password::hash_password;
+    use mongodb::bson::oid::ObjectId;
+    use std::path::PathBuf;
+
+    const HASHING: PasswordHashing = PasswordHashing::Argon2id {
+        memory_kib: 1024,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 10,
+            max_length: 64,
+            min_classes: 3,
+            min_score: 0,
+            breached_dir: None,
+            history: 3,
+            max_age_days: 0,
+        }
+    }
+
+    fn user() -> User {
+        User {
+            id: ObjectId::new(),
+            email: "alice@example.com".into(),
+            username: "alice".into(),
+            password_hash: String::new(),
+            password_history: Vec::new(),
+            password_changed_at: None,
+            roles: Vec::new(),
+            token_version: 0,
+            disabled: false,
+            mfa: Mfa::default(),
+            email_verified: true,
+            email_verified_at: None,
+            ldap_dn: None,
+            external_id: None,
+        }
+    }
+
+    fn codes(result: Result<(), AppError>) -> Vec<&'static str> {
+        match result {
+            Ok(()) => Vec::new(),
+            Err(AppError::PasswordPolicy(violations)) => {
+                violations.into_iter().map(|v| v.code).collect()
+            }
+            Err(e) => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    /// A directory holding one range file, removed when dropped.
+    struct Ranges(PathBuf);
+
+    impl Ranges {
+        fn new(prefix: &str, contents: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("pwned-ranges-{}", ObjectId::new()));
+            std::fs::create_dir(&dir).unwrap();
+            std::fs::write(dir.join(format!("{}.txt", prefix)), contents).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for Ranges {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[actix_web::test]
+    async fn length_and_classes_are_enforced() {
+        let policy = policy();
+        let check = |password: &'static str| check_password(&policy, password, &[]);
+
+        assert_eq!(codes(check("Tr0ub4dor&3").await), Vec::<&str>::new());
+        assert_eq!(codes(check("Tr0ub4&").await), ["too_short"]);
+        assert_eq!(codes(check("troubadours").await), ["missing_classes"]);
+        assert_eq!(
+            codes(check("trouba").await),
+            ["too_short", "missing_classes"]
+        );
+        // Any three of the four classes will do, and length counts characters
+        assert_eq!(codes(check("troubadour-42").await), Vec::<&str>::new());
+        assert_eq!(codes(check("Ünïcödé-Pässwörd").await), Vec::<&str>::new());
+        assert_eq!(codes(check("Äb1!Äb1!Ä").await), ["too_short"]);
+        let long = check_password(&policy, &"Ab1!".repeat(17), &[]).await;
+        assert_eq!(codes(long), ["too_long"]);
+    }
+
+    #[actix_web::test]
+    async fn personal_information_is_refused() {
+        let policy = policy();
+        let result = check_password(&policy, "Alice-2024-xyz", &["alice@example.com", "al"]).await;
+        assert_eq!(codes(result), ["personal_info"]);
+    }
+
+    #[actix_web::test]
+    async fn recent_passwords_cannot_be_reused() {
+        let policy = policy();
+        let mut user = user();
+        for password in [
+            "First-pass-1",
+            "Second-pass-2",
+            "Third-pass-3",
+            "Fourth-pass-4",
+        ] {
+            let hash = hash_password(&HASHING, password).await.unwrap();
+            set_password_hash(&policy, &mut user, hash);
+        }
+        // The current hash plus two older ones cover the last three passwords
+        assert_eq!(user.password_history.len(), 2);
+
+        for reused in ["Fourth-pass-4", "Third-pass-3", "Second-pass-2"] {
+            let result = check_password_change(&policy, &user, reused).await;
+            assert_eq!(codes(result), ["reused"], "{}", reused);
+        }
+        let result = check_password_change(&policy, &user, "First-pass-1").await;
+        assert_eq!(codes(result), Vec::<&str>::new());
+
+        let no_history = PasswordPolicy {
+            history: 0,
+            ..policy
+        };
+        let result = check_password_change(&no_history, &user, "Fourth-pass-4").await;
+        assert_eq!(codes(result), Vec::<&str>::new());
+    }
+
+    #[actix_web::test]
+    async fn range_responses_are_parsed() {
+        // SHA-1 of "password" is 5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8
+        let ranges = Ranges::new(
+            "5BAA6",
+            "003D68EB55068C33ACE09247EE4C639306B:3\r\n\
+             1e4c9b93f3f0682250b6cf8331b7ee68fd8:10434004\r\n\
+             01330C689E5D64F660D6947A93AD634EF8F:0\r\n",
+        );
+        assert!(is_breached(&ranges.0, "password").await.unwrap());
+        assert!(!is_breached(&ranges.0, "password1").await.unwrap());
+
+        // Padding entries have a count of 0
+        let padded = Ranges::new("5BAA6", "1E4C9B93F3F0682250B6CF8331B7EE68FD8:0\n");
+        assert!(!is_breached(&padded.0, "password").await.unwrap());
+
+        let policy = PasswordPolicy {
+            min_length: 8,
+            min_classes: 1,
+            breached_dir: Some(ranges.0.clone()),
+            ..policy()
+        };
+        let result = check_password(&policy, "password", &[]).await;
+        assert_eq!(codes(result), ["breached"]);
+    }
+}
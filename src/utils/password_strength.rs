@@ -0,0 +1,308 @@
+// A password strength estimate modelled on zxcvbn: the password is covered by
+// the cheapest sequence of guessable patterns (common passwords, the user's
+// own details, keyboard runs, sequences, repeats and dates), and the number of
+// guesses that takes is mapped onto a score from 0 to 4.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use time::OffsetDateTime;
+
+// Ordered by popularity, so a password's line number is its rank
+const COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+const KEYBOARD_ROWS: [&str; 4] = ["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+// Guesses per character not covered by any pattern
+const BRUTEFORCE_CARDINALITY: f64 = 10.0;
+const MIN_SINGLE_CHAR_GUESSES: f64 = 10.0;
+const MIN_MULTI_CHAR_GUESSES: f64 = 50.0;
+const MIN_YEAR_SPACE: i32 = 20;
+
+// Upper bounds on the guesses for scores 0 to 3
+const SCORE_THRESHOLDS: [f64; 4] = [1e3 + 5.0, 1e6 + 5.0, 1e8 + 5.0, 1e10 + 5.0];
+
+/// A pattern covering `chars[start..end]`.
+struct Match {
+    start: usize,
+    end: usize,
+    guesses: f64,
+}
+
+fn common_ranks() -> &'static HashMap<&'static str, usize> {
+    static RANKS: OnceLock<HashMap<&'static str, usize>> = OnceLock::new();
+    RANKS.get_or_init(|| {
+        COMMON_PASSWORDS
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .enumerate()
+            .map(|(i, word)| (word, i + 1))
+            .collect()
+    })
+}
+
+fn unleet(c: char) -> char {
+    match c {
+        '4' | '@' => 'a',
+        '8' => 'b',
+        '3' => 'e',
+        '6' | '9' => 'g',
+        '1' | '!' | '|' => 'i',
+        '0' => 'o',
+        '5' | '$' => 's',
+        '7' | '+' => 't',
+        '2' => 'z',
+        c => c,
+    }
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    (0..k).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+/// How many ways the word could have been capitalized.
+fn case_variations(word: &[char]) -> f64 {
+    let upper = word.iter().filter(|c| c.is_uppercase()).count();
+    let lower = word.iter().filter(|c| c.is_lowercase()).count();
+    let capitalized = word.first().is_some_and(|c| c.is_uppercase());
+    let trailing = word.last().is_some_and(|c| c.is_uppercase());
+
+    if upper == 0 {
+        return 1.0;
+    }
+    if lower == 0 || (upper == 1 && (capitalized || trailing)) {
+        return 2.0;
+    }
+    (1..=upper.min(lower))
+        .map(|k| binomial(upper + lower, k))
+        .sum()
+}
+
+fn dictionary_matches(chars: &[char], ranks: &dyn Fn(&str) -> Option<usize>) -> Vec<Match> {
+    let lower: Vec<char> = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+    let mut matches = Vec::new();
+
+    for start in 0..lower.len() {
+        for end in start + 3..=lower.len() {
+            let word: String = lower[start..end].iter().collect();
+            let unleeted: String = word.chars().map(unleet).collect();
+            let reversed: String = word.chars().rev().collect();
+
+            let candidates = [(&word, 1.0), (&reversed, 2.0), (&unleeted, 2.0)];
+            let best = candidates
+                .iter()
+                .filter_map(|(candidate, factor)| ranks(candidate).map(|r| r as f64 * factor))
+                .min_by(f64::total_cmp);
+            if let Some(guesses) = best {
+                matches.push(Match {
+                    start,
+                    end,
+                    guesses: guesses * case_variations(&chars[start..end]),
+                });
+            }
+        }
+    }
+    matches
+}
+
+/// Runs like `qwerty`, `asdf` or `0987` along a keyboard row.
+fn keyboard_matches(chars: &[char]) -> Vec<Match> {
+    let lower: Vec<char> = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+    let mut matches = Vec::new();
+
+    for row in KEYBOARD_ROWS {
+        let reversed: String = row.chars().rev().collect();
+        for (line, factor) in [(row.to_string(), 1.0), (reversed, 2.0)] {
+            for start in 0..lower.len() {
+                let mut end = start + 1;
+                while end < lower.len() {
+                    let run: String = lower[start..=end].iter().collect();
+                    if !line.contains(&run) {
+                        break;
+                    }
+                    end += 1;
+                }
+                if end - start >= 3 {
+                    matches.push(Match {
+                        start,
+                        end,
+                        guesses: 40.0 * (end - start) as f64 * factor,
+                    });
+                }
+            }
+        }
+    }
+    matches
+}
+
+/// Runs with a constant step such as `abc`, `7531` or `zyx`.
+fn sequence_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    let mut start = 0;
+
+    while start + 2 < chars.len() {
+        let delta = chars[start + 1] as i64 - chars[start] as i64;
+        let mut end = start + 2;
+        while end < chars.len() && chars[end] as i64 - chars[end - 1] as i64 == delta {
+            end += 1;
+        }
+
+        if end - start >= 3 && delta != 0 && delta.abs() <= 5 {
+            let first = chars[start];
+            let base = if "aAzZ019".contains(first) {
+                4.0
+            } else if first.is_ascii_digit() {
+                10.0
+            } else {
+                26.0
+            };
+            let direction = if delta < 0 { 2.0 } else { 1.0 };
+            matches.push(Match {
+                start,
+                end,
+                guesses: base * direction * (end - start) as f64,
+            });
+            start = end - 1;
+        } else {
+            start += 1;
+        }
+    }
+    matches
+}
+
+/// Blocks repeated back to back, such as `aaaa` or `abcabc`.
+fn repeat_matches(chars: &[char], user_inputs: &[String]) -> Vec<Match> {
+    let mut matches = Vec::new();
+
+    for size in 1..=chars.len() / 2 {
+        let mut start = 0;
+        while start + 2 * size <= chars.len() {
+            let block = &chars[start..start + size];
+            let mut end = start + size;
+            while end + size <= chars.len() && &chars[end..end + size] == block {
+                end += size;
+            }
+
+            let count = (end - start) / size;
+            if count >= 2 && end - start >= 3 {
+                let block: String = block.iter().collect();
+                matches.push(Match {
+                    start,
+                    end,
+                    guesses: estimate_guesses(&block, user_inputs) * count as f64,
+                });
+                start = end;
+            } else {
+                start += 1;
+            }
+        }
+    }
+    matches
+}
+
+/// Years and dates in the `yyyymmdd`, `ddmmyyyy` or `mmddyyyy` forms.
+fn date_matches(chars: &[char]) -> Vec<Match> {
+    let this_year = OffsetDateTime::now_utc().year();
+    let year_space = |year: i32| (year - this_year).abs().max(MIN_YEAR_SPACE) as f64;
+    let number = |digits: &[char]| -> i32 {
+        digits
+            .iter()
+            .fold(0, |n, d| n * 10 + d.to_digit(10).unwrap_or(0) as i32)
+    };
+    let plausible_year = |year: i32| (1900..=2099).contains(&year);
+    let plausible_date = |a: i32, b: i32| (1..=12).contains(&a) && (1..=31).contains(&b);
+
+    let mut matches = Vec::new();
+    for start in 0..chars.len() {
+        for len in [4, 8] {
+            let end = start + len;
+            if end > chars.len() || !chars[start..end].iter().all(char::is_ascii_digit) {
+                continue;
+            }
+            let digits = &chars[start..end];
+
+            if len == 4 && plausible_year(number(digits)) {
+                matches.push(Match {
+                    start,
+                    end,
+                    guesses: year_space(number(digits)),
+                });
+            }
+            if len == 8 {
+                let leading = number(&digits[..4]);
+                let trailing = number(&digits[4..]);
+                let (x, y) = (number(&digits[..2]), number(&digits[2..4]));
+                let (m, d) = (number(&digits[4..6]), number(&digits[6..]));
+
+                let year = if plausible_year(leading) && plausible_date(m, d) {
+                    Some(leading)
+                } else if plausible_year(trailing) && (plausible_date(x, y) || plausible_date(y, x))
+                {
+                    Some(trailing)
+                } else {
+                    None
+                };
+                if let Some(year) = year {
+                    matches.push(Match {
+                        start,
+                        end,
+                        guesses: 365.0 * year_space(year),
+                    });
+                }
+            }
+        }
+    }
+    matches
+}
+
+fn estimate_guesses(password: &str, user_inputs: &[String]) -> f64 {
+    let chars: Vec<char> = password.chars().collect();
+    let ranks = |word: &str| {
+        // The user's own details are tried before any list
+        user_inputs
+            .iter()
+            .position(|input| input == word)
+            .map(|i| i + 1)
+            .or_else(|| common_ranks().get(word).copied())
+    };
+
+    let mut matches = dictionary_matches(&chars, &ranks);
+    matches.extend(keyboard_matches(&chars));
+    matches.extend(sequence_matches(&chars));
+    matches.extend(repeat_matches(&chars, user_inputs));
+    matches.extend(date_matches(&chars));
+
+    // best[i] is the fewest guesses covering the first i characters
+    let mut best = vec![f64::INFINITY; chars.len() + 1];
+    best[0] = 1.0;
+    for end in 1..=chars.len() {
+        best[end] = best[end - 1] * BRUTEFORCE_CARDINALITY;
+        for m in matches.iter().filter(|m| m.end == end) {
+            let floor = if m.end - m.start == 1 {
+                MIN_SINGLE_CHAR_GUESSES
+            } else {
+                MIN_MULTI_CHAR_GUESSES
+            };
+            best[end] = best[end].min(best[m.start] * m.guesses.max(floor));
+        }
+    }
+    best[chars.len()]
+}
+
+/// Scores a password from 0 (too guessable) to 4 (very unguessable).
+/// `user_inputs` are details such as the email address and username that an
+/// attacker would try first.
+pub fn score(password: &str, user_inputs: &[&str]) -> u8 {
+    let user_inputs: Vec<String> = user_inputs
+        .iter()
+        .flat_map(|input| input.split(|c: char| !c.is_alphanumeric()))
+        .filter(|part| part.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .collect();
+
+    let guesses = estimate_guesses(password, &user_inputs);
+    SCORE_THRESHOLDS
+        .iter()
+        .position(|threshold| guesses < *threshold)
+        .unwrap_or(SCORE_THRESHOLDS.len()) as u8
+}
@@ -0,0 +1,393 @@
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Bson, Document, Regex};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Co,
+    Sw,
+    Ew,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+/// A parsed SCIM filter (RFC 7644 section 3.4.2.2). Complex attribute
+/// filters such as `emails[type eq "work"]` are not supported.
+#[derive(Debug)]
+pub enum Filter {
+    Compare(String, CompareOp, Value),
+    Present(String),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+}
+
+/// Where a SCIM attribute is stored.
+pub enum Field {
+    Id,
+    Text(&'static str),       // compared case-insensitively
+    Flag(&'static str, bool), // field name, stored inverted
+}
+
+/// Lowercases an attribute path and drops its schema prefix, e.g.
+/// `urn:ietf:params:scim:schemas:core:2.0:User:userName` becomes `username`.
+pub fn normalize_path(path: &str) -> String {
+    let path = path.trim().to_ascii_lowercase();
+    if path.starts_with("urn:") {
+        path.rsplit(':').next().unwrap_or_default().to_string()
+    } else {
+        path
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Token {
+    Open,
+    Close,
+    Str(String),
+    Word(String),
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                let mut escaped = false;
+                let mut end = None;
+                for (i, c) in chars.by_ref() {
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => {
+                            end = Some(i);
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                let end = end.ok_or("unterminated string")?;
+                // Values are JSON strings, escapes included
+                let value = serde_json::from_str(&input[start..=end])
+                    .map_err(|_| format!("invalid string {}", &input[start..=end]))?;
+                tokens.push(Token::Str(value));
+            }
+            _ => {
+                let mut end = input.len();
+                while let Some(&(i, c)) = chars.peek() {
+                    if c.is_whitespace() || "()\"".contains(c) {
+                        end = i;
+                        break;
+                    }
+                    chars.next();
+                }
+                tokens.push(Token::Word(input[start..end].to_string()));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.pos), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn or(&mut self) -> Result<Filter, String> {
+        let mut filter = self.and()?;
+        while self.at_keyword("or") {
+            self.pos += 1;
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, String> {
+        let mut filter = self.unary()?;
+        while self.at_keyword("and") {
+            self.pos += 1;
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+        Ok(filter)
+    }
+
+    fn group(&mut self) -> Result<Filter, String> {
+        if !matches!(self.next(), Some(Token::Open)) {
+            return Err("expected '('".into());
+        }
+        let filter = self.or()?;
+        if !matches!(self.next(), Some(Token::Close)) {
+            return Err("expected ')'".into());
+        }
+        Ok(filter)
+    }
+
+    fn unary(&mut self) -> Result<Filter, String> {
+        if self.at_keyword("not") {
+            self.pos += 1;
+            return Ok(Filter::Not(Box::new(self.group()?)));
+        }
+        if matches!(self.tokens.get(self.pos), Some(Token::Open)) {
+            return self.group();
+        }
+
+        let path = match self.next() {
+            Some(Token::Word(path)) if !path.contains('[') => path,
+            Some(Token::Word(path)) => return Err(format!("unsupported attribute {}", path)),
+            _ => return Err("expected an attribute".into()),
+        };
+        let operator = match self.next() {
+            Some(Token::Word(operator)) => operator.to_ascii_lowercase(),
+            _ => return Err(format!("expected an operator after {}", path)),
+        };
+        let op = match operator.as_str() {
+            "pr" => return Ok(Filter::Present(path)),
+            "eq" => CompareOp::Eq,
+            "ne" => CompareOp::Ne,
+            "co" => CompareOp::Co,
+            "sw" => CompareOp::Sw,
+            "ew" => CompareOp::Ew,
+            "gt" => CompareOp::Gt,
+            "ge" => CompareOp::Ge,
+            "lt" => CompareOp::Lt,
+            "le" => CompareOp::Le,
+            _ => return Err(format!("unknown operator {}", operator)),
+        };
+        let value = match self.next() {
+            Some(Token::Str(value)) => Value::String(value),
+            Some(Token::Word(word)) => match word.to_ascii_lowercase().as_str() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                "null" => Value::Null,
+                _ => serde_json::from_str::<serde_json::Number>(&word)
+                    .map(Value::Number)
+                    .map_err(|_| format!("invalid value {}", word))?,
+            },
+            _ => return Err(format!("expected a value after {}", operator)),
+        };
+
+        Ok(Filter::Compare(path, op, value))
+    }
+}
+
+pub fn parse(input: &str) -> Result<Filter, String> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+    };
+    let filter = parser.or()?;
+    if parser.pos != parser.tokens.len() {
+        return Err("unexpected input after the filter".into());
+    }
+    Ok(filter)
+}
+
+fn regex_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.^$|?*+()[]{}/".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn insensitive(pattern: String) -> Bson {
+    Bson::RegularExpression(Regex {
+        pattern,
+        options: "i".into(),
+    })
+}
+
+fn compare(path: &str, field: Field, op: CompareOp, value: &Value) -> Result<Document, String> {
+    match field {
+        Field::Id => {
+            let id = value
+                .as_str()
+                .ok_or_else(|| format!("{} must be compared with a string", path))?;
+            // An id that is not an ObjectId matches nothing
+            let ids: Vec<ObjectId> = ObjectId::parse_str(id).into_iter().collect();
+            match op {
+                CompareOp::Eq => Ok(doc! { "_id": { "$in": ids } }),
+                CompareOp::Ne => Ok(doc! { "_id": { "$nin": ids } }),
+                _ => Err(format!("{} only supports eq and ne", path)),
+            }
+        }
+        Field::Flag(name, inverted) => {
+            let flag = value
+                .as_bool()
+                .ok_or_else(|| format!("{} must be compared with a boolean", path))?;
+            let stored = match op {
+                CompareOp::Eq => flag != inverted,
+                CompareOp::Ne => flag == inverted,
+                _ => return Err(format!("{} only supports eq and ne", path)),
+            };
+            // Documents written before the flag existed count as false
+            Ok(if stored {
+                doc! { name: true }
+            } else {
+                doc! { name: { "$ne": true } }
+            })
+        }
+        Field::Text(name) => {
+            let text = value
+                .as_str()
+                .ok_or_else(|| format!("{} must be compared with a string", path))?;
+            let escaped = regex_escape(text);
+            Ok(match op {
+                CompareOp::Eq => doc! { name: insensitive(format!("^{}$", escaped)) },
+                CompareOp::Ne => doc! { name: { "$not": insensitive(format!("^{}$", escaped)) } },
+                CompareOp::Co => doc! { name: insensitive(escaped) },
+                CompareOp::Sw => doc! { name: insensitive(format!("^{}", escaped)) },
+                CompareOp::Ew => doc! { name: insensitive(format!("{}$", escaped)) },
+                CompareOp::Gt => doc! { name: { "$gt": text } },
+                CompareOp::Ge => doc! { name: { "$gte": text } },
+                CompareOp::Lt => doc! { name: { "$lt": text } },
+                CompareOp::Le => doc! { name: { "$lte": text } },
+            })
+        }
+    }
+}
+
+impl Filter {
+    /// Translates the filter into a MongoDB query. `field` maps a normalized
+    /// attribute path to where the attribute is stored.
+    pub fn to_document(&self, field: &dyn Fn(&str) -> Option<Field>) -> Result<Document, String> {
+        let resolve = |path: &str| {
+            field(&normalize_path(path)).ok_or_else(|| format!("unsupported attribute {}", path))
+        };
+
+        match self {
+            Filter::And(a, b) => {
+                Ok(doc! { "$and": [a.to_document(field)?, b.to_document(field)?] })
+            }
+            Filter::Or(a, b) => Ok(doc! { "$or": [a.to_document(field)?, b.to_document(field)?] }),
+            Filter::Not(f) => Ok(doc! { "$nor": [f.to_document(field)?] }),
+            Filter::Present(path) => Ok(match resolve(path)? {
+                Field::Text(name) => doc! { name: { "$exists": true, "$nin": [Bson::Null, ""] } },
+                Field::Id | Field::Flag(..) => doc! {},
+            }),
+            Filter::Compare(path, op, value) => compare(path, resolve(path)?, *op, value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_field(path: &str) -> Option<Field> {
+        match path {
+            "id" => Some(Field::Id),
+            "username" => Some(Field::Text("email")),
+            "displayname" => Some(Field::Text("username")),
+            "active" => Some(Field::Flag("disabled", true)),
+            _ => None,
+        }
+    }
+
+    fn query(filter: &str) -> Result<Document, String> {
+        parse(filter)?.to_document(&user_field)
+    }
+
+    fn exactly(name: &str, text: &str) -> Document {
+        doc! { name: insensitive(format!("^{}$", text)) }
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let alice = exactly("email", "alice");
+        let bob = exactly("email", "bob");
+        let active = doc! { "disabled": { "$ne": true } };
+
+        assert_eq!(
+            query(r#"userName eq "alice" or userName eq "bob" and active eq true"#).unwrap(),
+            doc! { "$or": [alice.clone(), { "$and": [bob.clone(), active.clone()] }] }
+        );
+        assert_eq!(
+            query(r#"(userName eq "alice" or userName eq "bob") and active eq true"#).unwrap(),
+            doc! { "$and": [{ "$or": [alice.clone(), bob.clone()] }, active.clone()] }
+        );
+        assert_eq!(
+            query(r#"not (userName eq "alice") AND active eq true"#).unwrap(),
+            doc! { "$and": [{ "$nor": [alice] }, active] }
+        );
+        // `not` applies to a parenthesized filter only
+        assert!(parse(r#"not userName eq "alice""#).is_err());
+    }
+
+    #[test]
+    fn strings_are_json_quoted() {
+        assert_eq!(
+            query(r#"displayName eq "say \"hi\" (now)""#).unwrap(),
+            exactly("username", r#"say "hi" \(now\)"#)
+        );
+        assert_eq!(
+            query(r#"userName sw "a.b*""#).unwrap(),
+            doc! { "email": insensitive(r"^a\.b\*".into()) }
+        );
+        assert!(parse(r#"userName eq "alice"#).is_err());
+        assert!(parse(r#"userName eq "bad \q escape""#).is_err());
+        assert!(parse("userName eq alice").is_err());
+        assert!(parse(r#"userName eq "alice" )"#).is_err());
+    }
+
+    #[test]
+    fn unsupported_operators_are_refused() {
+        assert!(parse(r#"userName like "alice""#).is_err());
+        assert!(parse(r#"emails[type eq "work"]"#).is_err());
+        assert!(parse("userName").is_err());
+        assert!(query(r#"id sw "507f""#).is_err());
+        assert!(query("active gt true").is_err());
+        assert!(query("active eq 1").is_err());
+        assert!(query("userName eq true").is_err());
+    }
+
+    #[test]
+    fn only_allowed_attributes_are_queried() {
+        assert!(query(r#"password eq "secret""#).is_err());
+        assert!(query(r#"password_hash pr"#).is_err());
+        assert!(query(r#"userName eq "alice" or roles eq "admin""#).is_err());
+
+        assert_eq!(
+            query(r#"urn:ietf:params:scim:schemas:core:2.0:User:userName eq "alice""#).unwrap(),
+            exactly("email", "alice")
+        );
+        assert_eq!(query("active eq false").unwrap(), doc! { "disabled": true });
+        assert_eq!(
+            query(r#"id eq "not-an-id""#).unwrap(),
+            doc! { "_id": { "$in": Vec::<ObjectId>::new() } }
+        );
+    }
+}
@@ -1,14 +1,38 @@
 use crate::config::app_config::AppConfig;
-use crate::constants::AUTH_REQUIRED;
+use crate::config::jwt_keys::{JwtKey, Keyring};
+use crate::constants::{AUTH_REQUIRED, OIDC_UNAVAILABLE, SIGNING_KEY_ENCRYPTION_KEY};
+use crate::database::mongodb::SigningKeyRepository;
 use crate::errors::AppError;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use crate::models::service_account::ServiceAccount;
+use crate::models::session::Session;
+use crate::models::signing_key::SigningKey;
+use crate::models::user::User;
+use jsonwebtoken::{decode, decode_header, encode, Header, Validation};
+use rand_core::{OsRng, RngCore};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
 use time::{Duration, OffsetDateTime};
 
 fn default_claims_ver() -> i32 {
     0
 }
 
+/// Whether a token was issued to a person or to a service account.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PrincipalKind {
+    #[default]
+    User,
+    Service,
+}
+
+impl PrincipalKind {
+    fn is_user(&self) -> bool {
+        *self == PrincipalKind::User
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String, // user id
@@ -16,35 +40,319 @@ pub struct Claims {
     pub iat: usize, // issued at
     #[serde(default = "default_claims_ver")]
     pub ver: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>, // session id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org: Option<String>, // active organization id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>, // space separated, OAuth clients only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "PrincipalKind::is_user")]
+    pub kind: PrincipalKind,
 }
 
-pub fn generate_token(cfg: &AppConfig, user_id: &str, token_version: i32) -> Result<String, AppError> {
+/// Issues an access token for the session's user, carrying the session's
+/// active organization and OAuth grant.
+pub fn generate_token(
+    cfg: &AppConfig,
+    session: &Session,
+    token_version: i32,
+) -> Result<String, AppError> {
     let now = OffsetDateTime::now_utc().unix_timestamp() as usize;
-    let exp =
-        (OffsetDateTime::now_utc() + Duration::hours(cfg.jwt_exp_hours)).unix_timestamp() as usize;
+    let exp = (OffsetDateTime::now_utc() + Duration::minutes(cfg.access_token_exp_minutes))
+        .unix_timestamp() as usize;
     let claims = Claims {
-        sub: user_id.into(),
+        sub: session.user_id.to_hex(),
         exp,
         iat: now,
         ver: token_version,
+        sid: Some(session.id.to_hex()),
+        org: session.org_id.map(|org| org.to_hex()),
+        scope: session.scope.clone(),
+        client_id: session.client_id.clone(),
+        kind: PrincipalKind::User,
+    };
+    let key = cfg.jwt_keys.current();
+    let mut header = Header::new(key.algorithm);
+    header.kid = Some(key.kid.clone());
+    encode(&header, &claims, &key.encoding).map_err(|_| AppError::Internal)
+}
+
+/// Issues an access token for a service account carrying the granted scopes.
+pub fn generate_service_token(
+    cfg: &AppConfig,
+    account: &ServiceAccount,
+    scope: &str,
+) -> Result<String, AppError> {
+    let now = OffsetDateTime::now_utc();
+    let claims = Claims {
+        sub: account.id.to_hex(),
+        exp: (now + Duration::minutes(cfg.access_token_exp_minutes)).unix_timestamp() as usize,
+        iat: now.unix_timestamp() as usize,
+        ver: account.token_version,
+        sid: None,
+        org: None,
+        scope: Some(scope.into()),
+        client_id: Some(account.client_id.clone()),
+        kind: PrincipalKind::Service,
     };
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(cfg.jwt_secret.as_bytes()),
-    )
-    .map_err(|_| AppError::Internal)
+    let key = cfg.jwt_keys.current();
+    let mut header = Header::new(key.algorithm);
+    header.kid = Some(key.kid.clone());
+    encode(&header, &claims, &key.encoding).map_err(|_| AppError::Internal)
+}
+
+/// Picks the verification key named by the token's `kid` header.
+fn verification_key(keys: &Keyring, token: &str) -> Option<JwtKey> {
+    let header = decode_header(token)
+        .map_err(|e| tracing::warn!("Token header decode error: {:?}", e))
+        .ok()?;
+
+    // Tokens issued before key ids were introduced carry no kid and can only
+    // have been signed with the bootstrap key, which stops verifying them once
+    // rotation has retired it.
+    let kid = header.kid.as_deref().unwrap_or(&keys.bootstrap().kid);
+    let key = keys.find(kid);
+    if key.is_none() {
+        tracing::warn!("Token signed with unknown or retired key id: {}", kid);
+    }
+    key
 }
 
 pub fn decode_token(cfg: &AppConfig, token: &str) -> Result<Claims, AppError> {
-    decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(cfg.jwt_secret.as_bytes()),
-        &Validation::default(),
-    )
-    .map(|data| data.claims)
-    .map_err(|e| {
-        tracing::warn!("Token decode error: {:?}", e);
-        AppError::Unauthorized(AUTH_REQUIRED.into())
-    })
+    let key = verification_key(&cfg.jwt_keys, token)
+        .ok_or_else(|| AppError::Unauthorized(AUTH_REQUIRED.into()))?;
+
+    decode::<Claims>(token, &key.decoding, &Validation::new(key.algorithm))
+        .map(|data| data.claims)
+        .map_err(|e| {
+            tracing::warn!("Token decode error: {:?}", e);
+            AppError::Unauthorized(AUTH_REQUIRED.into())
+        })
+}
+
+/// Claims of a single-purpose token sent out of band, e.g. in an email link.
+/// The purpose is carried as the audience so these tokens are never accepted
+/// as access tokens. Single use is enforced by the caller through `jti`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActionClaims {
+    pub sub: String, // user id
+    pub aud: String, // purpose
+    pub email: String,
+    pub jti: String,
+    pub exp: usize,
+    pub iat: usize,
+}
+
+/// Issues a signed action token, returning it along with its `jti`.
+pub fn generate_action_token(
+    cfg: &AppConfig,
+    purpose: &str,
+    user_id: &str,
+    email: &str,
+    exp_seconds: i64,
+) -> Result<(String, String), AppError> {
+    let now = OffsetDateTime::now_utc();
+    let jti = generate_opaque_token();
+    let claims = ActionClaims {
+        sub: user_id.into(),
+        aud: purpose.into(),
+        email: email.into(),
+        jti: jti.clone(),
+        exp: (now + Duration::seconds(exp_seconds)).unix_timestamp() as usize,
+        iat: now.unix_timestamp() as usize,
+    };
+    let key = cfg.jwt_keys.current();
+    let mut header = Header::new(key.algorithm);
+    header.kid = Some(key.kid.clone());
+    let token = encode(&header, &claims, &key.encoding).map_err(|_| AppError::Internal)?;
+    Ok((token, jti))
+}
+
+pub fn decode_action_token(cfg: &AppConfig, purpose: &str, token: &str) -> Option<ActionClaims> {
+    let key = verification_key(&cfg.jwt_keys, token)?;
+    let mut validation = Validation::new(key.algorithm);
+    validation.set_audience(&[purpose]);
+
+    decode::<ActionClaims>(token, &key.decoding, &validation)
+        .map(|data| data.claims)
+        .map_err(|e| tracing::warn!("Action token decode error: {:?}", e))
+        .ok()
+}
+
+/// Standard OpenID Connect claims about a user, limited to what the granted
+/// scopes allow: `email` releases the address, `profile` the name.
+#[derive(Debug, Serialize)]
+pub struct IdentityClaims {
+    pub sub: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl IdentityClaims {
+    pub fn for_scope(user: &User, scope: &str) -> Self {
+        let granted = |name: &str| scope.split_whitespace().any(|s| s == name);
+        let email = granted("email");
+        Self {
+            sub: user.id.to_hex(),
+            email: email.then(|| user.email.clone()),
+            email_verified: email.then_some(user.email_verified),
+            name: granted("profile").then(|| user.username.clone()),
+        }
+    }
+}
+
+/// Claims of an OpenID Connect id token. The audience is the client, so
+/// these tokens are never accepted as access tokens.
+#[derive(Debug, Serialize)]
+struct IdClaims<'a> {
+    iss: &'a str,
+    aud: &'a str,
+    exp: usize,
+    iat: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nonce: Option<&'a str>,
+    #[serde(flatten)]
+    identity: IdentityClaims,
+}
+
+/// Issues an id token for `client_id` describing the user as far as `scope`
+/// permits. Never signed with an HMAC secret, which the client can't have.
+pub fn generate_id_token(
+    cfg: &AppConfig,
+    user: &User,
+    client_id: &str,
+    scope: &str,
+    nonce: Option<&str>,
+) -> Result<String, AppError> {
+    if !cfg.jwt_keys.is_asymmetric() {
+        return Err(AppError::BadRequest(OIDC_UNAVAILABLE.into()));
+    }
+    let now = OffsetDateTime::now_utc();
+    let claims = IdClaims {
+        iss: &cfg.oidc_issuer,
+        aud: client_id,
+        exp: (now + Duration::minutes(cfg.access_token_exp_minutes)).unix_timestamp() as usize,
+        iat: now.unix_timestamp() as usize,
+        nonce,
+        identity: IdentityClaims::for_scope(user, scope),
+    };
+    let key = cfg.jwt_keys.current();
+    let mut header = Header::new(key.algorithm);
+    header.kid = Some(key.kid.clone());
+    encode(&header, &claims, &key.encoding).map_err(|_| AppError::Internal)
+}
+
+/// Generates a random opaque token (e.g. a refresh token) encoded as hex.
+pub fn generate_opaque_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Compares secrets, or digests of them, in constant time.
+pub fn secrets_match(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+/// Hashes an opaque token so that only its digest is ever persisted.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Reloads rotated signing keys from the database into the keyring.
+pub async fn reload_keyring(
+    cfg: &AppConfig,
+    signing_key_repo: &SigningKeyRepository,
+) -> Result<(), AppError> {
+    let keys = signing_key_repo
+        .find_all()
+        .await?
+        .into_iter()
+        .filter_map(|record| {
+            match stored_material(cfg, &record).and_then(|material| {
+                JwtKey::from_material(&record.algorithm, &material, &record.kid)
+            }) {
+                Ok(key) => Some((key, record.activates_at.timestamp_millis() / 1000)),
+                Err(e) => {
+                    tracing::error!("Skipping unusable signing key {}: {}", record.kid, e);
+                    None
+                }
+            }
+        })
+        .collect();
+
+    cfg.jwt_keys.set_rotated(keys);
+    Ok(())
+}
+
+fn stored_material(cfg: &AppConfig, record: &SigningKey) -> Result<String, String> {
+    if !record.encrypted {
+        return Ok(record.material.clone());
+    }
+    cfg.signing_key_encryption_key
+        .as_ref()
+        .ok_or_else(|| format!("{} is not set", SIGNING_KEY_ENCRYPTION_KEY))?
+        .open(&record.material, &record.kid)
+}
+
+/// Encrypts signing keys persisted before `SIGNING_KEY_ENCRYPTION_KEY` was
+/// configured, returning how many were converted.
+pub async fn encrypt_stored_keys(
+    cfg: &AppConfig,
+    signing_key_repo: &SigningKeyRepository,
+) -> Result<u64, AppError> {
+    let Some(kek) = cfg.signing_key_encryption_key.as_ref() else {
+        return Ok(0);
+    };
+
+    let mut encrypted = 0;
+    for record in signing_key_repo.find_all().await? {
+        if record.encrypted {
+            continue;
+        }
+        let sealed = kek.seal(&record.material, &record.kid).map_err(|e| {
+            tracing::error!("Failed to encrypt signing key {}: {}", record.kid, e);
+            AppError::Internal
+        })?;
+        signing_key_repo.seal_material(&record.id, &sealed).await?;
+        encrypted += 1;
+    }
+    Ok(encrypted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::Algorithm;
+
+    const GRACE_SECONDS: i64 = 600;
+
+    fn kid_less_token(key: &JwtKey) -> String {
+        let claims = serde_json::json!({ "sub": "user", "exp": 4_000_000_000u64 });
+        encode(&Header::new(key.algorithm), &claims, &key.encoding).unwrap()
+    }
+
+    #[test]
+    fn kid_less_tokens_expire_with_the_bootstrap_key() {
+        let bootstrap = JwtKey::from_secret(b"old-jwt-secret", None);
+        let token = kid_less_token(&bootstrap);
+        let keys = Keyring::new(bootstrap, GRACE_SECONDS);
+        assert!(verification_key(&keys, &token).is_some());
+
+        // Superseded, but tokens it signed are still within the grace window
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let (rotated, _) = JwtKey::generate(Algorithm::HS256).unwrap();
+        keys.set_rotated(vec![(rotated.clone(), now - GRACE_SECONDS + 60)]);
+        assert!(verification_key(&keys, &token).is_some());
+
+        // Retired: whoever still holds the old secret can't mint tokens
+        keys.set_rotated(vec![(rotated, now - GRACE_SECONDS - 60)]);
+        assert!(verification_key(&keys, &token).is_none());
+    }
 }
@@ -0,0 +1,124 @@
+use crate::config::app_config::AppConfig;
+use crate::constants::SIGNING_KEY_ENCRYPTION_KEY;
+use crate::database::mongodb::UserRepository;
+use crate::errors::AppError;
+use crate::models::user::User;
+use crate::utils::token::secrets_match;
+use mongodb::bson::oid::ObjectId;
+use rand_core::{OsRng, RngCore};
+use time::OffsetDateTime;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+const TOTP_DIGITS: usize = 6;
+const TOTP_SKEW: u8 = 1;
+const TOTP_STEP: u64 = 30;
+// 128 bits, so the unsalted digests stored for them can't be brute-forced
+const RECOVERY_CODE_BYTES: usize = 16;
+
+pub fn generate_secret() -> String {
+    Secret::generate_secret().to_encoded().to_string()
+}
+
+/// Prepares a new secret for storage: sealed with `SIGNING_KEY_ENCRYPTION_KEY`
+/// when it is configured, returned with whether it was. The user id is
+/// authenticated along, so a sealed secret can't be copied to another account.
+pub fn seal_secret(
+    cfg: &AppConfig,
+    user_id: &ObjectId,
+    secret: &str,
+) -> Result<(String, bool), AppError> {
+    match cfg.signing_key_encryption_key {
+        Some(ref kek) => kek
+            .seal(secret, &user_id.to_hex())
+            .map(|sealed| (sealed, true))
+            .map_err(|e| {
+                tracing::error!("Failed to encrypt TOTP secret of {}: {}", user_id, e);
+                AppError::Internal
+            }),
+        None => Ok((secret.to_string(), false)),
+    }
+}
+
+/// The user's TOTP secret, pending or enabled, decrypted if need be.
+pub fn user_secret(cfg: &AppConfig, user: &User) -> Result<Option<String>, AppError> {
+    let Some(ref stored) = user.mfa.totp_secret else {
+        return Ok(None);
+    };
+    if !user.mfa.totp_secret_encrypted {
+        return Ok(Some(stored.clone()));
+    }
+
+    cfg.signing_key_encryption_key
+        .as_ref()
+        .ok_or_else(|| format!("{} is not set", SIGNING_KEY_ENCRYPTION_KEY))
+        .and_then(|kek| kek.open(stored, &user.id.to_hex()))
+        .map(Some)
+        .map_err(|e| {
+            tracing::error!("Failed to decrypt TOTP secret of {}: {}", user.id, e);
+            AppError::Internal
+        })
+}
+
+/// Encrypts TOTP secrets stored before `SIGNING_KEY_ENCRYPTION_KEY` was
+/// configured, returning how many were converted.
+pub async fn encrypt_stored_secrets(
+    cfg: &AppConfig,
+    user_repo: &UserRepository,
+) -> Result<u64, AppError> {
+    if cfg.signing_key_encryption_key.is_none() {
+        return Ok(0);
+    }
+
+    let mut encrypted = 0;
+    for user in user_repo.find_plaintext_totp_secrets().await? {
+        let Some(ref secret) = user.mfa.totp_secret else {
+            continue;
+        };
+        let (sealed, _) = seal_secret(cfg, &user.id, secret)?;
+        user_repo
+            .seal_totp_secret(&user.id, secret, &sealed)
+            .await?;
+        encrypted += 1;
+    }
+    Ok(encrypted)
+}
+
+pub fn build_totp(secret: &str, issuer: &str, account: &str) -> Result<TOTP, AppError> {
+    let bytes = Secret::Encoded(secret.into())
+        .to_bytes()
+        .map_err(|_| AppError::Internal)?;
+    TOTP::new(
+        Algorithm::SHA1,
+        TOTP_DIGITS,
+        TOTP_SKEW,
+        TOTP_STEP,
+        bytes,
+        Some(issuer.into()),
+        account.into(),
+    )
+    .map_err(|_| AppError::Internal)
+}
+
+/// Checks `code` against the current time step and its neighbours, returning the
+/// matched step. Steps at or before `last_step` are rejected to prevent replay.
+pub fn verify_code(totp: &TOTP, code: &str, last_step: Option<i64>) -> Option<i64> {
+    let now = OffsetDateTime::now_utc().unix_timestamp() as u64;
+    let current = now / TOTP_STEP;
+    let skew = TOTP_SKEW as u64;
+
+    (current.saturating_sub(skew)..=current + skew)
+        .filter(|step| last_step.is_none_or(|last| *step as i64 > last))
+        .find(|step| secrets_match(&totp.generate(step * TOTP_STEP), code))
+        .map(|step| step as i64)
+}
+
+pub fn generate_recovery_codes(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|_| {
+            let mut bytes = [0u8; RECOVERY_CODE_BYTES];
+            OsRng.fill_bytes(&mut bytes);
+            let code = hex::encode(bytes);
+            [&code[..8], &code[8..16], &code[16..24], &code[24..]].join("-")
+        })
+        .collect()
+}
@@ -0,0 +1,425 @@
+use crate::constants::INVALID_PASSKEY;
+use crate::errors::AppError;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ciborium::value::Value;
+use p256::ecdsa::signature::Verifier;
+use rand_core::{OsRng, RngCore};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+pub const COSE_ALG_ES256: i64 = -7;
+pub const COSE_ALG_EDDSA: i64 = -8;
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+pub struct AuthenticatorData {
+    pub sign_count: u32,
+    pub credential_id: Option<Vec<u8>>,
+    pub public_key: Option<Vec<u8>>, // COSE_Key
+}
+
+fn invalid() -> AppError {
+    AppError::BadRequest(INVALID_PASSKEY.into())
+}
+
+pub fn encode(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+pub fn decode(value: &str) -> Result<Vec<u8>, AppError> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| invalid())
+}
+
+pub fn generate_challenge() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    encode(&bytes)
+}
+
+/// Validates `clientDataJSON` for the given ceremony type and returns the
+/// challenge it was signed over.
+pub fn verify_client_data(
+    origin: &str,
+    client_data_json: &[u8],
+    expected_type: &str,
+) -> Result<String, AppError> {
+    let client_data: ClientData =
+        serde_json::from_slice(client_data_json).map_err(|_| invalid())?;
+
+    if client_data.kind != expected_type || client_data.origin != origin {
+        return Err(invalid());
+    }
+
+    Ok(client_data.challenge)
+}
+
+/// Extracts `authData` from a CBOR attestation object. Only the `none`
+/// attestation format is expected from passkeys, so attestation statements
+/// are not verified.
+pub fn parse_attestation_object(attestation_object: &[u8]) -> Result<Vec<u8>, AppError> {
+    let value: Value = ciborium::from_reader(attestation_object).map_err(|_| invalid())?;
+    value
+        .as_map()
+        .and_then(|map| {
+            map.iter()
+                .find(|(k, _)| k.as_text() == Some("authData"))
+                .and_then(|(_, v)| v.as_bytes().cloned())
+        })
+        .ok_or_else(invalid)
+}
+
+/// Parses `authenticatorData` scoped to `rp_id`. The user must have been
+/// present, and verified too when `require_user_verification` is set.
+pub fn parse_authenticator_data(
+    rp_id: &str,
+    require_user_verification: bool,
+    auth_data: &[u8],
+) -> Result<AuthenticatorData, AppError> {
+    if auth_data.len() < 37 {
+        return Err(invalid());
+    }
+
+    let rp_id_hash = Sha256::digest(rp_id.as_bytes());
+    if auth_data[..32] != rp_id_hash[..] {
+        return Err(invalid());
+    }
+
+    let flags = auth_data[32];
+    if flags & FLAG_USER_PRESENT == 0
+        || (require_user_verification && flags & FLAG_USER_VERIFIED == 0)
+    {
+        return Err(invalid());
+    }
+
+    let sign_count =
+        u32::from_be_bytes([auth_data[33], auth_data[34], auth_data[35], auth_data[36]]);
+
+    if flags & FLAG_ATTESTED_CREDENTIAL_DATA == 0 {
+        return Ok(AuthenticatorData {
+            sign_count,
+            credential_id: None,
+            public_key: None,
+        });
+    }
+
+    // aaguid (16 bytes) followed by a big-endian credential id length
+    let rest = auth_data.get(37 + 16..).ok_or_else(invalid)?;
+    if rest.len() < 2 {
+        return Err(invalid());
+    }
+    let id_len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+    let credential_id = rest.get(2..2 + id_len).ok_or_else(invalid)?.to_vec();
+
+    let mut key_bytes = rest.get(2 + id_len..).ok_or_else(invalid)?;
+    let total = key_bytes.len();
+    let _: Value = ciborium::from_reader(&mut key_bytes).map_err(|_| invalid())?;
+    let public_key = rest[2 + id_len..2 + id_len + (total - key_bytes.len())].to_vec();
+
+    Ok(AuthenticatorData {
+        sign_count,
+        credential_id: Some(credential_id),
+        public_key: Some(public_key),
+    })
+}
+
+fn cose_field(map: &[(Value, Value)], label: i64) -> Option<&Value> {
+    map.iter()
+        .find(|(k, _)| k.as_integer().map(i128::from) == Some(label as i128))
+        .map(|(_, v)| v)
+}
+
+/// Returns the COSE algorithm of a credential public key, rejecting keys we
+/// cannot verify.
+pub fn public_key_algorithm(public_key: &[u8]) -> Result<i64, AppError> {
+    let value: Value = ciborium::from_reader(public_key).map_err(|_| invalid())?;
+    let map = value.as_map().ok_or_else(invalid)?;
+    let alg = cose_field(map, 3)
+        .and_then(|v| v.as_integer())
+        .and_then(|v| i64::try_from(v).ok())
+        .ok_or_else(invalid)?;
+
+    match alg {
+        COSE_ALG_ES256 | COSE_ALG_EDDSA => Ok(alg),
+        _ => Err(invalid()),
+    }
+}
+
+/// Verifies an assertion signature over `authenticatorData || SHA-256(clientDataJSON)`.
+pub fn verify_signature(
+    public_key: &[u8],
+    auth_data: &[u8],
+    client_data_json: &[u8],
+    signature: &[u8],
+) -> Result<(), AppError> {
+    let value: Value = ciborium::from_reader(public_key).map_err(|_| invalid())?;
+    let map = value.as_map().ok_or_else(invalid)?;
+    let bytes = |label| {
+        cose_field(map, label)
+            .and_then(|v| v.as_bytes())
+            .ok_or_else(invalid)
+    };
+
+    let mut message = auth_data.to_vec();
+    message.extend_from_slice(&Sha256::digest(client_data_json));
+
+    match public_key_algorithm(public_key)? {
+        COSE_ALG_ES256 => {
+            let (x, y) = (bytes(-2)?, bytes(-3)?);
+            if x.len() != 32 || y.len() != 32 {
+                return Err(invalid());
+            }
+            let mut point = vec![0x04];
+            point.extend_from_slice(x);
+            point.extend_from_slice(y);
+            let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(&point).map_err(|_| invalid())?;
+            let signature = p256::ecdsa::Signature::from_der(signature).map_err(|_| invalid())?;
+            key.verify(&message, &signature).map_err(|_| invalid())
+        }
+        COSE_ALG_EDDSA => {
+            let x: [u8; 32] = bytes(-2)?.as_slice().try_into().map_err(|_| invalid())?;
+            let key = ed25519_dalek::VerifyingKey::from_bytes(&x).map_err(|_| invalid())?;
+            let signature =
+                ed25519_dalek::Signature::from_slice(signature).map_err(|_| invalid())?;
+            key.verify(&message, &signature).map_err(|_| invalid())
+        }
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ciborium::value::Integer;
+    use p256::ecdsa::signature::Signer;
+
+    const RP_ID: &str = "example.com";
+    const ORIGIN: &str = "https://example.com";
+    const CREDENTIAL_ID: &[u8] = b"credential-1";
+
+    // A software authenticator with fixed keys, so every run signs the same way
+    enum Authenticator {
+        Es256(p256::ecdsa::SigningKey),
+        EdDsa(ed25519_dalek::SigningKey),
+    }
+
+    impl Authenticator {
+        fn es256() -> Self {
+            Self::Es256(p256::ecdsa::SigningKey::from_bytes(&[7u8; 32].into()).unwrap())
+        }
+
+        fn eddsa() -> Self {
+            Self::EdDsa(ed25519_dalek::SigningKey::from_bytes(&[9u8; 32]))
+        }
+
+        fn cose_key(&self) -> Vec<u8> {
+            let int = |v: i64| Value::Integer(Integer::from(v));
+            let map = match self {
+                Self::Es256(key) => {
+                    let point = key.verifying_key().to_encoded_point(false);
+                    vec![
+                        (int(1), int(2)),
+                        (int(3), int(COSE_ALG_ES256)),
+                        (int(-1), int(1)),
+                        (int(-2), Value::Bytes(point.x().unwrap().to_vec())),
+                        (int(-3), Value::Bytes(point.y().unwrap().to_vec())),
+                    ]
+                }
+                Self::EdDsa(key) => vec![
+                    (int(1), int(1)),
+                    (int(3), int(COSE_ALG_EDDSA)),
+                    (int(-1), int(6)),
+                    (
+                        int(-2),
+                        Value::Bytes(key.verifying_key().to_bytes().to_vec()),
+                    ),
+                ],
+            };
+            let mut out = Vec::new();
+            ciborium::into_writer(&Value::Map(map), &mut out).unwrap();
+            out
+        }
+
+        fn sign(&self, auth_data: &[u8], client_data_json: &[u8]) -> Vec<u8> {
+            let mut message = auth_data.to_vec();
+            message.extend_from_slice(&Sha256::digest(client_data_json));
+            match self {
+                Self::Es256(key) => {
+                    let signature: p256::ecdsa::Signature = key.sign(&message);
+                    signature.to_der().as_bytes().to_vec()
+                }
+                Self::EdDsa(key) => key.sign(&message).to_bytes().to_vec(),
+            }
+        }
+    }
+
+    fn auth_data(rp_id: &str, flags: u8, sign_count: u32, attested: Option<&[u8]>) -> Vec<u8> {
+        let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&sign_count.to_be_bytes());
+        if let Some(cose_key) = attested {
+            data.extend_from_slice(&[0u8; 16]);
+            data.extend_from_slice(&(CREDENTIAL_ID.len() as u16).to_be_bytes());
+            data.extend_from_slice(CREDENTIAL_ID);
+            data.extend_from_slice(cose_key);
+        }
+        data
+    }
+
+    fn attestation_object(auth_data: Vec<u8>) -> Vec<u8> {
+        let value = Value::Map(vec![
+            (Value::Text("fmt".into()), Value::Text("none".into())),
+            (Value::Text("attStmt".into()), Value::Map(Vec::new())),
+            (Value::Text("authData".into()), Value::Bytes(auth_data)),
+        ]);
+        let mut out = Vec::new();
+        ciborium::into_writer(&value, &mut out).unwrap();
+        out
+    }
+
+    fn client_data(kind: &str, challenge: &str, origin: &str) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "type": kind,
+            "challenge": challenge,
+            "origin": origin,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn registration_yields_credential_and_key() {
+        let authenticator = Authenticator::es256();
+        let cose_key = authenticator.cose_key();
+        let object = attestation_object(auth_data(
+            RP_ID,
+            FLAG_USER_PRESENT | FLAG_ATTESTED_CREDENTIAL_DATA,
+            0,
+            Some(&cose_key),
+        ));
+
+        let data = parse_attestation_object(&object).unwrap();
+        let parsed = parse_authenticator_data(RP_ID, false, &data).unwrap();
+
+        assert_eq!(parsed.credential_id.as_deref(), Some(CREDENTIAL_ID));
+        assert_eq!(parsed.public_key.as_deref(), Some(cose_key.as_slice()));
+        assert_eq!(public_key_algorithm(&cose_key).unwrap(), COSE_ALG_ES256);
+    }
+
+    #[test]
+    fn authenticator_data_for_another_rp_is_rejected() {
+        let data = auth_data("evil.example", FLAG_USER_PRESENT, 1, None);
+        assert!(parse_authenticator_data(RP_ID, false, &data).is_err());
+    }
+
+    #[test]
+    fn user_presence_is_required() {
+        let data = auth_data(RP_ID, FLAG_USER_VERIFIED, 1, None);
+        assert!(parse_authenticator_data(RP_ID, false, &data).is_err());
+    }
+
+    #[test]
+    fn user_verification_is_enforced_when_required() {
+        let present = auth_data(RP_ID, FLAG_USER_PRESENT, 1, None);
+        assert!(parse_authenticator_data(RP_ID, false, &present).is_ok());
+        assert!(parse_authenticator_data(RP_ID, true, &present).is_err());
+
+        let verified = auth_data(RP_ID, FLAG_USER_PRESENT | FLAG_USER_VERIFIED, 5, None);
+        let parsed = parse_authenticator_data(RP_ID, true, &verified).unwrap();
+        assert_eq!(parsed.sign_count, 5);
+        assert!(parsed.credential_id.is_none());
+    }
+
+    #[test]
+    fn truncated_authenticator_data_is_rejected() {
+        let data = auth_data(RP_ID, FLAG_USER_PRESENT, 1, None);
+        assert!(parse_authenticator_data(RP_ID, false, &data[..36]).is_err());
+
+        let attested = auth_data(
+            RP_ID,
+            FLAG_USER_PRESENT | FLAG_ATTESTED_CREDENTIAL_DATA,
+            0,
+            Some(&[]),
+        );
+        assert!(parse_authenticator_data(RP_ID, false, &attested).is_err());
+    }
+
+    #[test]
+    fn client_data_checks_type_and_origin() {
+        let json = client_data("webauthn.get", "abc", ORIGIN);
+        assert_eq!(
+            verify_client_data(ORIGIN, &json, "webauthn.get").unwrap(),
+            "abc"
+        );
+        assert!(verify_client_data(ORIGIN, &json, "webauthn.create").is_err());
+        assert!(verify_client_data("https://evil.example", &json, "webauthn.get").is_err());
+        assert!(verify_client_data(ORIGIN, b"not json", "webauthn.get").is_err());
+    }
+
+    #[test]
+    fn assertions_verify_for_supported_algorithms() {
+        for authenticator in [Authenticator::es256(), Authenticator::eddsa()] {
+            let data = auth_data(RP_ID, FLAG_USER_PRESENT, 2, None);
+            let json = client_data("webauthn.get", "abc", ORIGIN);
+            let signature = authenticator.sign(&data, &json);
+
+            assert!(verify_signature(&authenticator.cose_key(), &data, &json, &signature).is_ok());
+        }
+    }
+
+    #[test]
+    fn tampered_assertions_are_rejected() {
+        for authenticator in [Authenticator::es256(), Authenticator::eddsa()] {
+            let key = authenticator.cose_key();
+            let data = auth_data(RP_ID, FLAG_USER_PRESENT, 2, None);
+            let json = client_data("webauthn.get", "abc", ORIGIN);
+            let signature = authenticator.sign(&data, &json);
+
+            let other_json = client_data("webauthn.get", "xyz", ORIGIN);
+            assert!(verify_signature(&key, &data, &other_json, &signature).is_err());
+
+            let other_data = auth_data(RP_ID, FLAG_USER_PRESENT, 3, None);
+            assert!(verify_signature(&key, &other_data, &json, &signature).is_err());
+
+            let mut bad = signature.clone();
+            let last = bad.len() - 1;
+            bad[last] ^= 1;
+            assert!(verify_signature(&key, &data, &json, &bad).is_err());
+        }
+    }
+
+    #[test]
+    fn signatures_from_another_key_are_rejected() {
+        let data = auth_data(RP_ID, FLAG_USER_PRESENT, 2, None);
+        let json = client_data("webauthn.get", "abc", ORIGIN);
+        let signature = Authenticator::EdDsa(ed25519_dalek::SigningKey::from_bytes(&[1u8; 32]))
+            .sign(&data, &json);
+
+        let key = Authenticator::eddsa().cose_key();
+        assert!(verify_signature(&key, &data, &json, &signature).is_err());
+    }
+
+    #[test]
+    fn unsupported_algorithms_are_rejected() {
+        let rs256 = Value::Map(vec![
+            (Value::Integer(1.into()), Value::Integer(3.into())),
+            (Value::Integer(3.into()), Value::Integer((-257).into())),
+        ]);
+        let mut key = Vec::new();
+        ciborium::into_writer(&rs256, &mut key).unwrap();
+
+        assert!(public_key_algorithm(&key).is_err());
+        assert!(verify_signature(&key, b"", b"", b"").is_err());
+    }
+}